@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+/// Groups of the S3 ACL grants
+const ALL_USERS_URI: &str = "http://acs.amazonaws.com/groups/global/AllUsers";
+const AUTHENTICATED_USERS_URI: &str = "http://acs.amazonaws.com/groups/global/AuthenticatedUsers";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Grantee {
+    AllUsers,
+    AuthenticatedUsers,
+    /// Owner of the source object, the destination object is owned by the destination user
+    Owner,
+    /// Canonical user ID, email address or group URI
+    Other(String),
+}
+
+impl Grantee {
+    pub fn from_uri(uri: &str) -> Grantee {
+        match uri {
+            ALL_USERS_URI => Grantee::AllUsers,
+            AUTHENTICATED_USERS_URI => Grantee::AuthenticatedUsers,
+            _ => Grantee::Other(uri.to_string()),
+        }
+    }
+}
+
+/// Grant of a source object ACL
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectGrant {
+    pub grantee: Grantee,
+    /// READ, WRITE, READ_ACP, WRITE_ACP or FULL_CONTROL
+    pub permission: String,
+}
+
+/// Canned ACL set on destination objects, by the ACL rules or from the source ACL
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DestinationAcl {
+    Private,
+    PublicRead,
+    PublicReadWrite,
+    AuthenticatedRead,
+}
+
+impl DestinationAcl {
+    pub fn is_public(&self) -> bool {
+        matches!(
+            self,
+            DestinationAcl::PublicRead | DestinationAcl::PublicReadWrite
+        )
+    }
+
+    /// Value of the x-amz-acl header
+    pub fn canned(&self) -> &'static str {
+        match self {
+            DestinationAcl::Private => "private",
+            DestinationAcl::PublicRead => "public-read",
+            DestinationAcl::PublicReadWrite => "public-read-write",
+            DestinationAcl::AuthenticatedRead => "authenticated-read",
+        }
+    }
+
+    /// Canned ACL giving the same permissions to the groups as the grants. The grants which
+    /// can't be expressed with a canned ACL, e.g. to other users, are returned to be reported
+    pub fn from_grants(grants: &[ObjectGrant]) -> (DestinationAcl, Vec<String>) {
+        let has_grant = |grantee: &Grantee, permission: &str| {
+            grants.iter().any(|grant| {
+                &grant.grantee == grantee
+                    && (grant.permission == permission || grant.permission == "FULL_CONTROL")
+            })
+        };
+
+        let acl = if has_grant(&Grantee::AllUsers, "READ") {
+            if has_grant(&Grantee::AllUsers, "WRITE") {
+                DestinationAcl::PublicReadWrite
+            } else {
+                DestinationAcl::PublicRead
+            }
+        } else if has_grant(&Grantee::AuthenticatedUsers, "READ") {
+            DestinationAcl::AuthenticatedRead
+        } else {
+            DestinationAcl::Private
+        };
+
+        let unmapped = grants
+            .iter()
+            .filter(|grant| match (&grant.grantee, acl) {
+                (Grantee::Owner, _) => false,
+                (Grantee::AllUsers, DestinationAcl::PublicRead) => grant.permission != "READ",
+                (Grantee::AllUsers, DestinationAcl::PublicReadWrite) => {
+                    grant.permission != "READ" && grant.permission != "WRITE"
+                }
+                (Grantee::AuthenticatedUsers, DestinationAcl::AuthenticatedRead) => {
+                    grant.permission != "READ"
+                }
+                // Public objects are readable by the authenticated users too
+                (Grantee::AuthenticatedUsers, acl) if acl.is_public() => grant.permission != "READ",
+                _ => true,
+            })
+            .map(|grant| format!("{:?}:{}", grant.grantee, grant.permission))
+            .collect();
+
+        (acl, unmapped)
+    }
+}
+
+impl TryFrom<&str> for DestinationAcl {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "private" => Ok(DestinationAcl::Private),
+            "public-read" => Ok(DestinationAcl::PublicRead),
+            "public-read-write" => Ok(DestinationAcl::PublicReadWrite),
+            "authenticated-read" => Ok(DestinationAcl::AuthenticatedRead),
+            _ => Err(format!(
+                "Unknown ACL {}. Valid values are: private, public-read, public-read-write, authenticated-read",
+                value
+            )),
+        }
+    }
+}
+
+/// `<tag key>=<tag value>:<acl>`, e.g. `visibility=public:public-read`
+#[derive(Debug, Clone)]
+pub struct AclRule {
+    tag_key: String,
+    tag_value: String,
+    acl: DestinationAcl,
+}
+
+impl TryFrom<&str> for AclRule {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (tag, acl) = value.rsplit_once(':').ok_or(format!(
+            "Invalid ACL rule {}. Expected <tag key>=<tag value>:<acl>",
+            value
+        ))?;
+        let (tag_key, tag_value) = tag.split_once('=').ok_or(format!(
+            "Invalid ACL rule {}. Expected <tag key>=<tag value>:<acl>",
+            value
+        ))?;
+
+        Ok(AclRule {
+            tag_key: tag_key.to_string(),
+            tag_value: tag_value.to_string(),
+            acl: DestinationAcl::try_from(acl)?,
+        })
+    }
+}
+
+/// Derives the destination ACL of an object from its source tags instead of the source ACL.
+/// Rules are evaluated in order and the first matching rule wins
+#[derive(Debug, Clone)]
+pub struct AclRules {
+    pub rules: Vec<AclRule>,
+    /// ACL used when no rule matches. If not set, the source ACL is kept
+    pub default: Option<DestinationAcl>,
+}
+
+impl AclRules {
+    /// Returns `None` if no rule matched the tags
+    pub fn matching_acl(&self, tags: &HashMap<String, String>) -> Option<DestinationAcl> {
+        self.rules
+            .iter()
+            .find(|rule| tags.get(&rule.tag_key) == Some(&rule.tag_value))
+            .map(|rule| rule.acl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(grantee: Grantee, permission: &str) -> ObjectGrant {
+        ObjectGrant {
+            grantee,
+            permission: permission.to_string(),
+        }
+    }
+
+    #[test]
+    fn grants_are_mapped_to_their_canned_acl() {
+        let owner = grant(Grantee::Owner, "FULL_CONTROL");
+        let cases = [
+            (vec![owner.clone()], DestinationAcl::Private),
+            (
+                vec![owner.clone(), grant(Grantee::AllUsers, "READ")],
+                DestinationAcl::PublicRead,
+            ),
+            (
+                vec![
+                    owner.clone(),
+                    grant(Grantee::AllUsers, "READ"),
+                    grant(Grantee::AllUsers, "WRITE"),
+                ],
+                DestinationAcl::PublicReadWrite,
+            ),
+            (
+                vec![owner, grant(Grantee::AuthenticatedUsers, "READ")],
+                DestinationAcl::AuthenticatedRead,
+            ),
+        ];
+
+        for (grants, expected) in cases {
+            let (acl, unmapped) = DestinationAcl::from_grants(&grants);
+            assert_eq!(acl, expected, "{:?}", grants);
+            assert!(unmapped.is_empty(), "{:?}", unmapped);
+            assert_eq!(DestinationAcl::try_from(acl.canned()), Ok(acl));
+        }
+    }
+
+    #[test]
+    fn grants_without_canned_acl_are_reported() {
+        let (acl, unmapped) = DestinationAcl::from_grants(&[
+            grant(Grantee::from_uri(ALL_USERS_URI), "READ"),
+            grant(Grantee::AllUsers, "READ_ACP"),
+            grant(Grantee::from_uri("user@example.com"), "WRITE"),
+        ]);
+
+        assert_eq!(acl, DestinationAcl::PublicRead);
+        assert_eq!(
+            unmapped,
+            ["AllUsers:READ_ACP", "Other(\"user@example.com\"):WRITE"]
+        );
+    }
+
+    #[test]
+    fn full_control_of_all_users_is_public_read_write() {
+        let (acl, unmapped) =
+            DestinationAcl::from_grants(&[grant(Grantee::AllUsers, "FULL_CONTROL")]);
+
+        assert_eq!(acl, DestinationAcl::PublicReadWrite);
+        assert_eq!(unmapped, ["AllUsers:FULL_CONTROL"]);
+    }
+}
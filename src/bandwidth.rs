@@ -0,0 +1,107 @@
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{Future, Stream};
+use tokio::time::{Instant, Sleep};
+
+/// Global upload rate limit shared by all the sync threads. Each uploaded chunk reserves its
+/// share of the bandwidth: the next chunk of the stream is only read once the reserved time
+/// is over. Unused bandwidth isn't saved for later, so there is no burst after an idle period
+#[derive(Debug)]
+pub struct BandwidthLimit {
+    bytes_per_second: u64,
+    /// Time until which the bandwidth is already reserved
+    reserved_until: Mutex<Instant>,
+}
+
+impl BandwidthLimit {
+    pub fn new(bytes_per_second: u64) -> BandwidthLimit {
+        BandwidthLimit {
+            bytes_per_second,
+            reserved_until: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Returns the time until which the stream reserving these bytes must wait
+    fn reserve(&self, bytes: usize) -> Instant {
+        let duration = Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+        let mut reserved_until = self.reserved_until.lock().unwrap();
+        *reserved_until = std::cmp::max(*reserved_until, Instant::now()) + duration;
+        *reserved_until
+    }
+
+    pub fn throttle<S>(self: &Arc<Self>, stream: S) -> ThrottledStream<S> {
+        ThrottledStream {
+            inner: Box::pin(stream),
+            limit: self.clone(),
+            sleep: None,
+        }
+    }
+}
+
+/// Body stream waiting for its reserved bandwidth between two chunks
+pub struct ThrottledStream<S> {
+    inner: Pin<Box<S>>,
+    limit: Arc<BandwidthLimit>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> Stream for ThrottledStream<S>
+where
+    S: Stream<Item = Result<Bytes, std::io::Error>>,
+{
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(sleep) = self.sleep.as_mut() {
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.sleep = None;
+        }
+
+        let poll = self.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(bytes))) = &poll {
+            let reserved_until = self.limit.reserve(bytes.len());
+            self.sleep = Some(Box::pin(tokio::time::sleep_until(reserved_until)));
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    const MB: usize = 1_000_000;
+
+    fn body(megabytes: usize) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+        futures::stream::iter((0..megabytes).map(|_| Ok(Bytes::from(vec![0; MB]))))
+    }
+
+    #[tokio::test]
+    async fn limit_is_shared_by_the_throttled_streams() {
+        let limit = Arc::new(BandwidthLimit::new(5 * MB as u64));
+        let start = Instant::now();
+
+        let (first, second) = futures::join!(
+            limit.throttle(body(5)).count(),
+            limit.throttle(body(5)).count()
+        );
+
+        let elapsed = start.elapsed();
+        assert_eq!(first + second, 10);
+        assert!(
+            elapsed >= Duration::from_millis(1900) && elapsed < Duration::from_millis(2500),
+            "10 MB took {:?} at 5 MB/s",
+            elapsed
+        );
+    }
+}
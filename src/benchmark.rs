@@ -0,0 +1,321 @@
+use std::{collections::HashMap, pin::Pin, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use bytesize::ByteSize;
+use futures::Stream;
+use tracing::{event, Level};
+
+use crate::{
+    acl_rules::ObjectGrant,
+    heartbeat::SyncProgress,
+    provider::{
+        Provider, ProviderObject, ProviderObjectMetadata, ProviderResponse,
+        ProviderResponseStreamChunk,
+    },
+    radosgw::{
+        uploader::{ObjectPriority, Uploader, UploaderConfiguration},
+        RadosGW,
+    },
+    storage_class::StorageClassRules,
+};
+
+const THREADS: [usize; 4] = [4, 8, 16, 32];
+const CHUNK_SIZES_MB: [usize; 3] = [8, 16, 32];
+const SYNTHETIC_BODY_CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct BenchmarkConfiguration {
+    pub run_id: String,
+    pub destination_endpoint: String,
+    pub destination_access_key: String,
+    pub destination_secret_key: String,
+    pub scratch_bucket: String,
+    pub object_size: usize,
+    /// Data uploaded for each threads / chunk size combination
+    pub total_size: usize,
+}
+
+#[derive(Debug)]
+struct BenchmarkResult {
+    threads: usize,
+    chunk_size: usize,
+    bytes_per_second: f64,
+    failures: usize,
+}
+
+/// Source serving objects filled with zeroes, so the benchmark only measures the destination
+#[derive(Debug, Clone)]
+pub struct SyntheticProvider {}
+
+#[derive(Debug)]
+struct SyntheticResponse {
+    size: usize,
+}
+
+impl SyntheticResponse {
+    fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>> {
+        let full_chunks = self.size / SYNTHETIC_BODY_CHUNK_SIZE;
+        let last_chunk = self.size % SYNTHETIC_BODY_CHUNK_SIZE;
+        let chunk = Bytes::from(vec![0u8; SYNTHETIC_BODY_CHUNK_SIZE]);
+
+        Box::pin(futures::stream::iter(
+            std::iter::repeat(chunk.clone())
+                .take(full_chunks)
+                .chain((last_chunk > 0).then(|| chunk.slice(0..last_chunk)))
+                .map(Ok),
+        ))
+    }
+}
+
+impl ProviderResponse for SyntheticResponse {
+    fn status(&self) -> u16 {
+        200
+    }
+
+    fn is_chunked(&self) -> bool {
+        false
+    }
+
+    fn body(&mut self) -> Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>> {
+        self.stream()
+    }
+
+    fn body_chunked(
+        &mut self,
+        chunk_size: usize,
+    ) -> Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>> {
+        Box::pin(ProviderResponseStreamChunk::new(self.stream(), chunk_size))
+    }
+}
+
+#[async_trait]
+impl Provider for SyntheticProvider {
+    async fn get_buckets(&self) -> anyhow::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+    fn list_objects(
+        &self,
+        _max_keys: Option<usize>,
+        _start_after: Option<String>,
+    ) -> Pin<Box<dyn Stream<Item = anyhow::Result<Vec<ProviderObject>>> + '_>> {
+        Box::pin(futures::stream::empty())
+    }
+    async fn get_object_metadata(
+        &self,
+        object: &ProviderObject,
+    ) -> anyhow::Result<ProviderObjectMetadata> {
+        Ok(ProviderObjectMetadata {
+            acl_public: false,
+            acl: None,
+            last_modified: None,
+            etag: None,
+            content_type: Some("application/octet-stream".to_string()),
+            content_length: object.get_size() as usize,
+            cache_control: None,
+            content_disposition: None,
+            content_encoding: None,
+            content_language: None,
+            content_md5: None,
+            expires: None,
+            website_redirect_location: None,
+            server_side_encryption: None,
+            ssekms_key_id: None,
+            truncated_from: None,
+            source_bucket: None,
+            source_etag: None,
+            tags: HashMap::new(),
+            storage_class: None,
+        })
+    }
+    async fn get_object(
+        &self,
+        object: &ProviderObject,
+    ) -> anyhow::Result<Box<dyn ProviderResponse>> {
+        Ok(Box::new(SyntheticResponse {
+            size: object.get_size() as usize,
+        }))
+    }
+    async fn get_object_range(
+        &self,
+        object: &ProviderObject,
+        start: u64,
+        length: u64,
+    ) -> anyhow::Result<Box<dyn ProviderResponse>> {
+        Ok(Box::new(SyntheticResponse {
+            size: std::cmp::min(object.get_size().saturating_sub(start), length) as usize,
+        }))
+    }
+    async fn get_object_if_none_match(
+        &self,
+        object: &ProviderObject,
+        _etag: &str,
+    ) -> anyhow::Result<Option<Box<dyn ProviderResponse>>> {
+        self.get_object(object).await.map(Some)
+    }
+    async fn get_object_tags(
+        &self,
+        _object: &ProviderObject,
+    ) -> anyhow::Result<HashMap<String, String>> {
+        Ok(HashMap::new())
+    }
+
+    async fn get_object_acl(&self, _object: &ProviderObject) -> anyhow::Result<Vec<ObjectGrant>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Uploads synthetic objects to a scratch bucket with several threads / chunk size combinations
+/// and prints the fastest one. Uploaded objects are always deleted.
+pub async fn run_benchmark(conf: BenchmarkConfiguration) -> anyhow::Result<()> {
+    let radosgw_client = RadosGW::new(
+        Some(conf.destination_endpoint.clone()),
+        None,
+        conf.destination_access_key.clone(),
+        conf.destination_secret_key.clone(),
+        Some(conf.scratch_bucket.clone()),
+    );
+
+    let mut results = Vec::new();
+    for threads in THREADS {
+        for chunk_size in CHUNK_SIZES_MB.map(|chunk_size| chunk_size * 1024 * 1024) {
+            let objects_count = std::cmp::max(threads, conf.total_size / conf.object_size);
+            let objects = (0..objects_count)
+                .map(|index| {
+                    ProviderObject::new(
+                        format!(
+                            "cellar-migration-benchmark/{}/{}-{}/{}",
+                            conf.run_id, threads, chunk_size, index
+                        ),
+                        conf.object_size as u64,
+                    )
+                })
+                .collect::<Vec<ProviderObject>>();
+
+            event!(
+                Level::INFO,
+                "Benchmark | Uploading {} objects of {} with {} threads and a chunk size of {}",
+                objects_count,
+                ByteSize(conf.object_size as u64),
+                threads,
+                ByteSize(chunk_size as u64)
+            );
+
+            let mut uploader = Uploader::new(
+                Box::new(SyntheticProvider {}),
+                radosgw_client.clone(),
+                objects.clone(),
+                Vec::new(),
+                UploaderConfiguration {
+                    threads,
+                    multipart_threshold: chunk_size as u64,
+                    multipart_part_size: chunk_size,
+                    multipart_budget: None,
+                    multipart_uploads: Vec::new(),
+                    small_object_threshold: None,
+                    small_object_threads: 0,
+                    delete_threads: threads,
+                    priority: ObjectPriority::Listing,
+                    source_etag_cache: None,
+                    checksum_manifest: None,
+                    state_file: None,
+                    object_report: None,
+                    content_dedup: None,
+                    acl_rules: None,
+                    preserve_acl: false,
+                    preserve_tags: false,
+                    storage_classes: StorageClassRules::default(),
+                    infer_content_type: false,
+                    object_acls_disabled: false,
+                    progress: Arc::new(SyncProgress::default()),
+                    truncate_bytes: None,
+                    source_bucket: None,
+                    metadata_filter: None,
+                    key_filter: None,
+                    encryption_mirror: None,
+                    server_side_encryption: None,
+                    sse_kms_key_id: None,
+                    timing_trace: None,
+                    object_timings: None,
+                    fail_fast: false,
+                    circuit_breaker: None,
+                    run_window: None,
+                    bandwidth_limits: Vec::new(),
+                    verify_checksum: false,
+                    thread_budget: None,
+                    max_retries: 0,
+                    retry_base_delay: Duration::ZERO,
+                    simulated_failures: None,
+                },
+            );
+
+            let start = std::time::Instant::now();
+            let sync_results = uploader.sync().await;
+            let elapsed = start.elapsed();
+
+            let failures = sync_results
+                .into_iter()
+                .map(|result| match result {
+                    Ok(result) => result.sync_results.iter().filter(|r| r.is_err()).count(),
+                    Err(_) => 0,
+                })
+                .sum::<usize>();
+
+            cleanup(&radosgw_client, objects).await;
+
+            let uploaded = (objects_count - failures) * conf.object_size;
+            results.push(BenchmarkResult {
+                threads,
+                chunk_size,
+                bytes_per_second: uploaded as f64 / elapsed.as_secs_f64(),
+                failures,
+            });
+        }
+    }
+
+    for result in &results {
+        event!(
+            Level::INFO,
+            "Benchmark | threads={}, chunk_size={}: {}/s ({} failed uploads)",
+            result.threads,
+            ByteSize(result.chunk_size as u64),
+            ByteSize(result.bytes_per_second as u64),
+            result.failures
+        );
+    }
+
+    let best = results
+        .iter()
+        .filter(|result| result.failures == 0)
+        .max_by(|a, b| a.bytes_per_second.total_cmp(&b.bytes_per_second));
+
+    match best {
+        Some(best) => event!(
+            Level::INFO,
+            "Benchmark | Recommended options: --threads {} --multipart-chunk-size-mb {} ({}/s)",
+            best.threads,
+            best.chunk_size / 1024 / 1024,
+            ByteSize(best.bytes_per_second as u64)
+        ),
+        None => event!(
+            Level::ERROR,
+            "Benchmark | Every combination had failed uploads, no recommendation can be made"
+        ),
+    }
+
+    Ok(())
+}
+
+async fn cleanup(radosgw_client: &RadosGW, objects: Vec<ProviderObject>) {
+    for object in objects {
+        let key = object.get_key();
+        if let Err(error) = radosgw_client.delete_object(object).await {
+            event!(
+                Level::WARN,
+                "Benchmark | Failed to delete scratch object {}: {:?}",
+                key,
+                error
+            );
+        }
+    }
+}
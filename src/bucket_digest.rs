@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use ring::digest;
+use tracing::{event, Level};
+
+use crate::{
+    migrate::{get_bucket_providers, BucketMigrationConfiguration},
+    provider::{list_all_objects, ProviderObject},
+};
+
+#[derive(Debug, PartialEq)]
+struct DigestEntry {
+    size: u64,
+    /// Multipart ETags depend on the chunk size used to upload the object, they can't be compared
+    etag: Option<String>,
+}
+
+/// Hash of the (key, size, ETag) of every object of a bucket. Two buckets with the same digest
+/// have the same objects, without downloading any of them
+#[derive(Debug)]
+pub struct BucketDigest {
+    pub digest: String,
+    entries: BTreeMap<String, DigestEntry>,
+}
+
+impl BucketDigest {
+    /// The digest doesn't depend on the order of the objects
+    pub fn new(objects: &[ProviderObject]) -> BucketDigest {
+        let entries = objects
+            .iter()
+            .map(|object| {
+                let etag = (!object.is_multipart())
+                    .then(|| object.get_etag().trim_matches('"').to_string());
+                (
+                    object.get_key(),
+                    DigestEntry {
+                        size: object.get_size(),
+                        etag,
+                    },
+                )
+            })
+            .collect::<BTreeMap<String, DigestEntry>>();
+
+        let mut context = digest::Context::new(&digest::SHA256);
+        for (key, entry) in &entries {
+            context.update(key.as_bytes());
+            context.update(b"\0");
+            context.update(entry.size.to_string().as_bytes());
+            context.update(b"\0");
+            context.update(entry.etag.as_deref().unwrap_or("").as_bytes());
+            context.update(b"\n");
+        }
+
+        let digest = context
+            .finish()
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        BucketDigest { digest, entries }
+    }
+
+    /// Keys missing on one side or whose size or ETag differ, sorted
+    pub fn differing_keys(&self, other: &BucketDigest) -> Vec<String> {
+        let mut keys = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| other.entries.get(*key) != Some(entry))
+            .map(|(key, _)| key.clone())
+            .chain(
+                other
+                    .entries
+                    .keys()
+                    .filter(|key| !self.entries.contains_key(*key))
+                    .cloned(),
+            )
+            .collect::<Vec<String>>();
+        keys.sort();
+        keys
+    }
+}
+
+/// Lists both buckets entirely and compares their digests. Returns the differing keys, empty
+/// if the buckets match
+pub async fn compare_bucket_digests(
+    conf: &BucketMigrationConfiguration,
+) -> anyhow::Result<Vec<String>> {
+    let (source_provider, destination_provider) = get_bucket_providers(conf);
+
+    let source_digest = BucketDigest::new(&list_all_objects(&*source_provider).await?);
+    let destination_digest = BucketDigest::new(&list_all_objects(&*destination_provider).await?);
+
+    event!(
+        Level::INFO,
+        "Bucket {} | Source digest: {} ({} objects), destination digest: {} ({} objects)",
+        conf.source_bucket,
+        source_digest.digest,
+        source_digest.entries.len(),
+        destination_digest.digest,
+        destination_digest.entries.len()
+    );
+
+    if source_digest.digest == destination_digest.digest {
+        return Ok(Vec::new());
+    }
+
+    Ok(source_digest.differing_keys(&destination_digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn object(key: &str, size: u64, etag: &str) -> ProviderObject {
+        ProviderObject::planned(key.to_string(), size, etag.to_string(), Utc::now(), None)
+    }
+
+    #[test]
+    fn digest_of_identical_listings() {
+        let source = BucketDigest::new(&[object("a", 1, "\"aa\""), object("b", 2, "\"bb\"")]);
+        let destination = BucketDigest::new(&[object("b", 2, "bb"), object("a", 1, "aa")]);
+        assert_eq!(source.digest, destination.digest);
+        assert!(source.differing_keys(&destination).is_empty());
+    }
+
+    #[test]
+    fn digest_ignores_multipart_etags() {
+        let source = BucketDigest::new(&[object("a", 1, "aa-2")]);
+        let destination = BucketDigest::new(&[object("a", 1, "bb-3")]);
+        assert_eq!(source.digest, destination.digest);
+    }
+
+    #[test]
+    fn differing_keys_of_different_listings() {
+        let source = BucketDigest::new(&[
+            object("same", 1, "aa"),
+            object("size", 1, "aa"),
+            object("etag", 1, "aa"),
+            object("source-only", 1, "aa"),
+        ]);
+        let destination = BucketDigest::new(&[
+            object("same", 1, "aa"),
+            object("size", 2, "aa"),
+            object("etag", 1, "bb"),
+            object("destination-only", 1, "aa"),
+        ]);
+        assert_ne!(source.digest, destination.digest);
+        assert_eq!(
+            source.differing_keys(&destination),
+            ["destination-only", "etag", "size", "source-only"]
+        );
+    }
+
+    #[test]
+    fn digest_of_empty_listings() {
+        assert_eq!(BucketDigest::new(&[]).digest, BucketDigest::new(&[]).digest);
+        assert_ne!(
+            BucketDigest::new(&[]).digest,
+            BucketDigest::new(&[object("a", 0, "aa")]).digest
+        );
+    }
+}
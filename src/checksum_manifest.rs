@@ -0,0 +1,78 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::Context;
+use base64::Engine;
+
+/// MD5 checksums of the source objects known beforehand, e.g. from an inventory. Each line is
+/// `<bucket>\t<md5>\t<key>`, the key comes last as it may contain tabs. The MD5 is either hex
+/// or base64 encoded. Known checksums are sent as Content-MD5 so the destination verifies the
+/// uploaded body, objects missing from the manifest keep the Content-MD5 of the source
+#[derive(Debug)]
+pub struct ChecksumManifest {
+    /// Base64 encoded, as expected by Content-MD5
+    checksums: HashMap<String, String>,
+}
+
+impl ChecksumManifest {
+    pub fn open(path: &Path, bucket: &str) -> anyhow::Result<ChecksumManifest> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open checksum manifest {}", path.display()))?;
+
+        let mut checksums = HashMap::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let mut parts = line.splitn(3, '\t');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(entry_bucket), Some(md5), Some(key)) => {
+                    if entry_bucket == bucket {
+                        let md5 = content_md5(md5).with_context(|| {
+                            format!(
+                                "Invalid MD5 {} on line {} of checksum manifest {}",
+                                md5,
+                                index + 1,
+                                path.display()
+                            )
+                        })?;
+                        checksums.insert(key.to_string(), md5);
+                    }
+                }
+                _ if line.is_empty() => {}
+                _ => anyhow::bail!(
+                    "Invalid line {} of checksum manifest {}. Expected <bucket>\\t<md5>\\t<key>",
+                    index + 1,
+                    path.display()
+                ),
+            }
+        }
+
+        Ok(ChecksumManifest { checksums })
+    }
+
+    pub fn content_md5(&self, key: &str) -> Option<&str> {
+        self.checksums.get(key).map(|md5| md5.as_str())
+    }
+}
+
+/// Base64 encoded MD5 from a hex or base64 encoded MD5
+fn content_md5(md5: &str) -> anyhow::Result<String> {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let bytes = if md5.len() == 32 && md5.is_ascii() {
+        (0..md5.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&md5[index..index + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()?
+    } else {
+        engine.decode(md5)?
+    };
+
+    if bytes.len() != 16 {
+        anyhow::bail!("MD5 should be 16 bytes long, got {}", bytes.len());
+    }
+
+    Ok(engine.encode(bytes))
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tracing::{event, Level};
+
+/// The error rate isn't meaningful on a handful of requests
+const MIN_REQUESTS: usize = 20;
+/// While probing, a single object is dispatched per interval
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfiguration {
+    /// Ratio (0..1) of destination 5xx responses over the window above which the breaker opens
+    pub threshold: f64,
+    pub window: Duration,
+    pub cool_down: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CircuitBreakerStatus {
+    Closed,
+    /// No object is dispatched until the end of the cool down
+    Open { until: Instant },
+    /// Objects are dispatched one by one until a destination request succeeds
+    Probing { next_probe: Instant },
+}
+
+#[derive(Debug)]
+struct CircuitBreakerState {
+    status: CircuitBreakerStatus,
+    /// Time of the destination responses of the window and whether they were a 5xx
+    responses: VecDeque<(Instant, bool)>,
+}
+
+/// Pauses the dispatch of new objects while the destination answers too many 5xx, shared by all
+/// the sync threads of the run
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    conf: CircuitBreakerConfiguration,
+    state: Mutex<CircuitBreakerState>,
+    activations: AtomicUsize,
+}
+
+impl CircuitBreaker {
+    pub fn new(conf: CircuitBreakerConfiguration) -> CircuitBreaker {
+        CircuitBreaker {
+            conf,
+            state: Mutex::new(CircuitBreakerState {
+                status: CircuitBreakerStatus::Closed,
+                responses: VecDeque::new(),
+            }),
+            activations: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of times the breaker opened
+    pub fn activations(&self) -> usize {
+        self.activations.load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, server_error: bool) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        match state.status {
+            CircuitBreakerStatus::Open { .. } => {}
+            CircuitBreakerStatus::Probing { .. } if server_error => self.open(&mut state, now),
+            CircuitBreakerStatus::Probing { .. } => {
+                event!(
+                    Level::INFO,
+                    "Circuit breaker | Destination recovered, resuming full concurrency"
+                );
+                state.status = CircuitBreakerStatus::Closed;
+                state.responses.clear();
+            }
+            CircuitBreakerStatus::Closed => {
+                state.responses.push_back((now, server_error));
+                while let Some(&(time, _)) = state.responses.front() {
+                    if now.duration_since(time) <= self.conf.window {
+                        break;
+                    }
+                    state.responses.pop_front();
+                }
+
+                let errors = state.responses.iter().filter(|(_, error)| *error).count();
+                if state.responses.len() >= MIN_REQUESTS
+                    && errors as f64 / state.responses.len() as f64 > self.conf.threshold
+                {
+                    self.open(&mut state, now);
+                }
+            }
+        }
+    }
+
+    fn open(&self, state: &mut CircuitBreakerState, now: Instant) {
+        let activations = self.activations.fetch_add(1, Ordering::Relaxed) + 1;
+        event!(
+            Level::WARN,
+            "Circuit breaker | Too many destination 5xx errors, pausing new objects for {:?} (activation #{})",
+            self.conf.cool_down,
+            activations
+        );
+        state.status = CircuitBreakerStatus::Open {
+            until: now + self.conf.cool_down,
+        };
+        state.responses.clear();
+    }
+
+    /// Returns once a new object can be dispatched
+    pub async fn wait(&self) {
+        loop {
+            let sleep = {
+                let now = Instant::now();
+                let mut state = self.state.lock().unwrap();
+                match state.status {
+                    CircuitBreakerStatus::Closed => return,
+                    CircuitBreakerStatus::Open { until } if now < until => until - now,
+                    CircuitBreakerStatus::Open { .. } => {
+                        event!(
+                            Level::INFO,
+                            "Circuit breaker | Cool down over, probing the destination"
+                        );
+                        state.status = CircuitBreakerStatus::Probing {
+                            next_probe: now + PROBE_INTERVAL,
+                        };
+                        return;
+                    }
+                    CircuitBreakerStatus::Probing { next_probe } if now < next_probe => {
+                        next_probe - now
+                    }
+                    CircuitBreakerStatus::Probing { .. } => {
+                        state.status = CircuitBreakerStatus::Probing {
+                            next_probe: now + PROBE_INTERVAL,
+                        };
+                        return;
+                    }
+                }
+            };
+
+            tokio::time::sleep(sleep).await;
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::provider::ProviderObject;
+
+/// Index of the content uploaded during the run, shared by all the buckets. Source objects
+/// having the same content as an object already uploaded are copied server side from it
+/// instead of being transferred again. The content is identified by the source ETag and size,
+/// only for objects whose ETag is their MD5: multipart ETags depend on the part size
+#[derive(Debug, Default)]
+pub struct ContentDedup {
+    /// (ETag, size) to the copy source (`<bucket>/<url encoded key>`) of the uploaded object
+    uploaded: Mutex<HashMap<(String, u64), String>>,
+    copied_objects: AtomicUsize,
+    saved_bytes: AtomicU64,
+}
+
+impl ContentDedup {
+    fn content_id(object: &ProviderObject) -> Option<(String, u64)> {
+        (!object.is_multipart()).then(|| {
+            (
+                object.get_etag().trim_matches('"').to_string(),
+                object.get_size(),
+            )
+        })
+    }
+
+    /// Copy source of an object uploaded during the run with the same content
+    pub fn find(&self, object: &ProviderObject) -> Option<String> {
+        let content_id = ContentDedup::content_id(object)?;
+        self.uploaded.lock().unwrap().get(&content_id).cloned()
+    }
+
+    /// Called once an object is uploaded. The first uploaded object of a content is kept
+    pub fn record_upload(&self, object: &ProviderObject, copy_source: String) {
+        if let Some(content_id) = ContentDedup::content_id(object) {
+            self.uploaded
+                .lock()
+                .unwrap()
+                .entry(content_id)
+                .or_insert(copy_source);
+        }
+    }
+
+    pub fn record_copy(&self, object: &ProviderObject) {
+        self.copied_objects.fetch_add(1, Ordering::Relaxed);
+        self.saved_bytes.fetch_add(object.get_size(), Ordering::Relaxed);
+    }
+
+    pub fn copied_objects(&self) -> usize {
+        self.copied_objects.load(Ordering::Relaxed)
+    }
+
+    pub fn saved_bytes(&self) -> u64 {
+        self.saved_bytes.load(Ordering::Relaxed)
+    }
+}
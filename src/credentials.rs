@@ -0,0 +1,188 @@
+use std::{collections::HashMap, path::PathBuf};
+
+/// Access and secret keys of a named profile of the AWS shared credentials or config files,
+/// used instead of the keys given on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileCredentials {
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+impl ProfileCredentials {
+    /// Looks for the profile in the credentials file, then in the config file, like the AWS CLI.
+    /// Their paths can be overridden with AWS_SHARED_CREDENTIALS_FILE and AWS_CONFIG_FILE
+    pub fn load(profile: &str) -> Result<ProfileCredentials, String> {
+        let files = [(credentials_file(), false), (config_file(), true)];
+
+        for (path, is_config) in files {
+            let path = match path {
+                Some(path) if path.exists() => path,
+                _ => continue,
+            };
+            let content = std::fs::read_to_string(&path)
+                .map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+
+            if let Some(keys) = parse_profiles(&content, is_config).get(profile) {
+                return ProfileCredentials::from_keys(keys).map_err(|missing| {
+                    format!(
+                        "Profile {} of {} has no {}",
+                        profile,
+                        path.display(),
+                        missing
+                    )
+                });
+            }
+        }
+
+        Err(format!(
+            "Profile {} not found in the AWS credentials and config files",
+            profile
+        ))
+    }
+
+    /// Returns the name of the missing key
+    fn from_keys(keys: &HashMap<String, String>) -> Result<ProfileCredentials, &'static str> {
+        let get = |name: &'static str| {
+            keys.get(name)
+                .filter(|value| !value.is_empty())
+                .cloned()
+                .ok_or(name)
+        };
+
+        Ok(ProfileCredentials {
+            access_key: get("aws_access_key_id")?,
+            secret_key: get("aws_secret_access_key")?,
+        })
+    }
+}
+
+fn home_file(path: &str) -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(path))
+}
+
+fn credentials_file() -> Option<PathBuf> {
+    std::env::var_os("AWS_SHARED_CREDENTIALS_FILE")
+        .map(PathBuf::from)
+        .or_else(|| home_file(".aws/credentials"))
+}
+
+fn config_file() -> Option<PathBuf> {
+    std::env::var_os("AWS_CONFIG_FILE")
+        .map(PathBuf::from)
+        .or_else(|| home_file(".aws/config"))
+}
+
+/// Keys of each profile of an INI file. The profiles of the config file are named
+/// `[profile <name>]`, except the default one
+fn parse_profiles(content: &str, is_config: bool) -> HashMap<String, HashMap<String, String>> {
+    let mut profiles: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(section) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            let section = section.trim();
+            current = match (is_config, section.strip_prefix("profile ")) {
+                (true, Some(name)) => Some(name.trim().to_string()),
+                (true, None) if section == "default" => Some(section.to_string()),
+                (true, None) => None,
+                (false, _) => Some(section.to_string()),
+            };
+            continue;
+        }
+
+        if let (Some(profile), Some((key, value))) = (&current, line.split_once('=')) {
+            profiles
+                .entry(profile.clone())
+                .or_default()
+                .insert(key.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    profiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CREDENTIALS: &str = "
+[default]
+aws_access_key_id = default-access
+aws_secret_access_key = default-secret
+
+# Source of the migration
+[source]
+AWS_ACCESS_KEY_ID=source-access
+aws_secret_access_key=source-secret
+
+[incomplete]
+aws_access_key_id = incomplete-access
+";
+
+    fn credentials(
+        profile: &str,
+        is_config: bool,
+        content: &str,
+    ) -> Result<ProfileCredentials, &'static str> {
+        ProfileCredentials::from_keys(&parse_profiles(content, is_config)[profile])
+    }
+
+    #[test]
+    fn keys_of_each_profile() {
+        assert_eq!(
+            credentials("default", false, CREDENTIALS),
+            Ok(ProfileCredentials {
+                access_key: "default-access".to_string(),
+                secret_key: "default-secret".to_string(),
+            })
+        );
+        assert_eq!(
+            credentials("source", false, CREDENTIALS),
+            Ok(ProfileCredentials {
+                access_key: "source-access".to_string(),
+                secret_key: "source-secret".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn profile_missing_a_key() {
+        assert_eq!(
+            credentials("incomplete", false, CREDENTIALS),
+            Err("aws_secret_access_key")
+        );
+    }
+
+    #[test]
+    fn profiles_of_the_config_file_are_prefixed() {
+        let config = "
+[default]
+aws_access_key_id = default-access
+aws_secret_access_key = default-secret
+
+[profile destination]
+aws_access_key_id = destination-access
+aws_secret_access_key = destination-secret
+
+[sso-session company]
+sso_region = us-east-1
+";
+        let profiles = parse_profiles(config, true);
+
+        let mut names: Vec<&String> = profiles.keys().collect();
+        names.sort();
+        assert_eq!(names, ["default", "destination"]);
+        assert_eq!(
+            credentials("destination", true, config).map(|keys| keys.access_key),
+            Ok("destination-access".to_string())
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use std::net::Ipv4Addr;
+
+use chrono::{DateTime, Utc};
+
+/// Names of the destination buckets when no template is given
+const DEFAULT_TEMPLATE: &str = "{prefix}{bucket}";
+const PLACEHOLDERS: [&str; 3] = ["{bucket}", "{prefix}", "{date}"];
+
+/// Name of the destination bucket of each source bucket, from `--destination-bucket`,
+/// `--destination-bucket-prefix` and `--destination-bucket-template`
+#[derive(Debug, Clone)]
+pub struct DestinationBucketNames {
+    /// Replaces the source bucket name in `{bucket}`
+    bucket: Option<String>,
+    /// With its trailing dash, empty without prefix
+    prefix: String,
+    template: Option<String>,
+    /// UTC day the process started, as YYYYMMDD, so all the passes of a continuous run keep the
+    /// same buckets
+    date: String,
+}
+
+impl DestinationBucketNames {
+    pub fn new(
+        bucket: Option<String>,
+        prefix: String,
+        template: Option<String>,
+        started_at: DateTime<Utc>,
+    ) -> Result<DestinationBucketNames, String> {
+        if let Some(template) = &template {
+            let remaining = PLACEHOLDERS
+                .iter()
+                .fold(template.clone(), |remaining, placeholder| {
+                    remaining.replace(placeholder, "")
+                });
+            if remaining.contains('{') || remaining.contains('}') {
+                return Err(format!(
+                    "Invalid destination bucket template {}. Valid placeholders are: {}",
+                    template,
+                    PLACEHOLDERS.join(", ")
+                ));
+            }
+        }
+
+        Ok(DestinationBucketNames {
+            bucket,
+            prefix,
+            template,
+            date: started_at.format("%Y%m%d").to_string(),
+        })
+    }
+
+    pub fn name(&self, source_bucket: &str) -> String {
+        self.template
+            .as_deref()
+            .unwrap_or(DEFAULT_TEMPLATE)
+            .replace("{bucket}", self.bucket.as_deref().unwrap_or(source_bucket))
+            .replace("{prefix}", &self.prefix)
+            .replace("{date}", &self.date)
+    }
+
+    /// Only the names rendered from a template are checked, some destinations accept names
+    /// that don't follow the S3 rules and existing migrations may rely on them
+    pub fn check_names(&self, source_buckets: &[String]) -> Result<(), String> {
+        if self.template.is_none() {
+            return Ok(());
+        }
+
+        for source_bucket in source_buckets {
+            let name = self.name(source_bucket);
+            check_bucket_name(&name).map_err(|reason| {
+                format!(
+                    "Invalid destination bucket name {} for source bucket {}: {}",
+                    name, source_bucket, reason
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// S3 bucket naming rules: 3 to 63 lowercase letters, digits, dots and dashes, starting and
+/// ending with a letter or digit, without consecutive dots and not formatted as an IP address
+fn check_bucket_name(name: &str) -> Result<(), String> {
+    if name.len() < 3 || name.len() > 63 {
+        return Err(format!("{} characters, expected 3 to 63", name.len()));
+    }
+
+    if let Some(invalid) = name
+        .chars()
+        .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '.' || *c == '-'))
+    {
+        return Err(format!(
+            "invalid character '{}', only lowercase letters, digits, dots and dashes are allowed",
+            invalid
+        ));
+    }
+
+    let is_alphanumeric = |c: Option<char>| matches!(c, Some(c) if c.is_ascii_alphanumeric());
+    if !is_alphanumeric(name.chars().next()) || !is_alphanumeric(name.chars().last()) {
+        return Err("should start and end with a letter or a digit".to_string());
+    }
+
+    if name.contains("..") {
+        return Err("should not contain consecutive dots".to_string());
+    }
+
+    if name.parse::<Ipv4Addr>().is_ok() {
+        return Err("should not be formatted as an IP address".to_string());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn names(
+        bucket: Option<&str>,
+        prefix: &str,
+        template: Option<&str>,
+    ) -> Result<DestinationBucketNames, String> {
+        DestinationBucketNames::new(
+            bucket.map(str::to_string),
+            prefix.to_string(),
+            template.map(str::to_string),
+            Utc.with_ymd_and_hms(2024, 3, 9, 23, 59, 0).unwrap(),
+        )
+    }
+
+    #[test]
+    fn name_without_template() {
+        assert_eq!(names(None, "", None).unwrap().name("photos"), "photos");
+        assert_eq!(
+            names(None, "cellar-", None).unwrap().name("photos"),
+            "cellar-photos"
+        );
+        assert_eq!(
+            names(Some("archive"), "", None).unwrap().name("photos"),
+            "archive"
+        );
+    }
+
+    #[test]
+    fn name_from_a_template() {
+        let bucket_names = names(None, "cellar-", Some("{prefix}{bucket}-migrated")).unwrap();
+        assert_eq!(bucket_names.name("photos"), "cellar-photos-migrated");
+
+        let bucket_names = names(Some("archive"), "", Some("{bucket}-{date}")).unwrap();
+        assert_eq!(bucket_names.name("photos"), "archive-20240309");
+    }
+
+    #[test]
+    fn template_with_an_unknown_placeholder() {
+        let error = names(None, "", Some("{bucket}-{month}")).unwrap_err();
+        assert!(error.contains("{bucket}-{month}"), "{}", error);
+    }
+
+    #[test]
+    fn template_names_are_checked() {
+        let bucket_names = names(None, "", Some("{bucket}-{date}")).unwrap();
+        assert!(bucket_names.check_names(&["photos".to_string()]).is_ok());
+
+        let error = bucket_names
+            .check_names(&["Photos".to_string()])
+            .unwrap_err();
+        assert!(error.contains("Photos-20240309"), "{}", error);
+    }
+
+    #[test]
+    fn names_without_template_are_not_checked() {
+        let bucket_names = names(None, "", None).unwrap();
+        assert!(bucket_names.check_names(&["Photos".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn bucket_name_rules() {
+        assert!(check_bucket_name("photos.2024-backup").is_ok());
+        assert!(check_bucket_name("ab").is_err());
+        assert!(check_bucket_name(&"a".repeat(64)).is_err());
+        assert!(check_bucket_name("photos_backup").is_err());
+        assert!(check_bucket_name("-photos").is_err());
+        assert!(check_bucket_name("photos.").is_err());
+        assert!(check_bucket_name("photos..backup").is_err());
+        assert!(check_bucket_name("192.168.1.10").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::provider::ProviderObjectMetadata;
+
+const SSE_KMS: &str = "aws:kms";
+const SSE_S3: &str = "AES256";
+
+/// Mirrors the server side encryption of each source object on its destination object, instead
+/// of leaving all the destination objects unencrypted. KMS keys of the source don't exist on the
+/// destination, they are mapped to destination keys
+#[derive(Debug, Default)]
+pub struct EncryptionMirror {
+    /// Source KMS key id to destination KMS key id
+    kms_keys: HashMap<String, String>,
+    /// Used for the source KMS keys which aren't mapped
+    default_kms_key: Option<String>,
+    /// Objects whose encryption differs from their source object, with the reason
+    unfaithful: Mutex<Vec<String>>,
+}
+
+impl EncryptionMirror {
+    pub fn new(kms_keys: HashMap<String, String>, default_kms_key: Option<String>) -> Self {
+        EncryptionMirror {
+            kms_keys,
+            default_kms_key,
+            unfaithful: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Parses a `<source key id>=<destination key id>` mapping
+    pub fn parse_kms_key_mapping(value: &str) -> Result<(String, String), String> {
+        match value.split_once('=') {
+            Some((source, destination)) if !source.is_empty() && !destination.is_empty() => {
+                Ok((source.to_string(), destination.to_string()))
+            }
+            _ => Err(format!(
+                "Invalid KMS key mapping {}. Expected <source key id>=<destination key id>",
+                value
+            )),
+        }
+    }
+
+    /// Sets the destination encryption from the source one. Returns the reason when it can't be
+    /// mirrored faithfully, the object is then recorded for the end of run report
+    pub fn apply(&self, key: &str, metadata: &mut ProviderObjectMetadata) -> Option<String> {
+        if metadata.server_side_encryption.as_deref() != Some(SSE_KMS) {
+            // Unencrypted or SSE-S3, both are mirrored as is
+            metadata.ssekms_key_id = None;
+            return None;
+        }
+
+        // Without a key id, the source uses the AWS managed key of the account
+        let source_key = metadata
+            .ssekms_key_id
+            .clone()
+            .unwrap_or_else(|| "aws/s3".to_string());
+        if let Some(destination_key) = self.kms_keys.get(&source_key) {
+            metadata.ssekms_key_id = Some(destination_key.clone());
+            return None;
+        }
+
+        let reason = match &self.default_kms_key {
+            Some(default_kms_key) => {
+                metadata.ssekms_key_id = Some(default_kms_key.clone());
+                format!(
+                    "KMS key {} isn't mapped, encrypted with the default key {}",
+                    source_key, default_kms_key
+                )
+            }
+            None => {
+                metadata.server_side_encryption = Some(SSE_S3.to_string());
+                metadata.ssekms_key_id = None;
+                format!(
+                    "KMS key {} isn't mapped and there is no default key, encrypted with {}",
+                    source_key, SSE_S3
+                )
+            }
+        };
+
+        self.unfaithful
+            .lock()
+            .unwrap()
+            .push(format!("{}: {}", key, reason));
+        Some(reason)
+    }
+
+    pub fn unfaithful_objects(&self) -> Vec<String> {
+        self.unfaithful.lock().unwrap().clone()
+    }
+}
+
+/// Checks the encryption set on all the destination objects with --server-side-encryption: a KMS
+/// key id is only meaningful with aws:kms
+pub fn validate_destination_encryption(
+    server_side_encryption: Option<&str>,
+    sse_kms_key_id: Option<&str>,
+) -> Result<(), String> {
+    match (server_side_encryption, sse_kms_key_id) {
+        (Some(SSE_S3), None) | (Some(SSE_KMS), _) | (None, None) => Ok(()),
+        (Some(SSE_S3), Some(_)) => Err(format!(
+            "--sse-kms-key-id can only be used with --server-side-encryption {}",
+            SSE_KMS
+        )),
+        (None, Some(_)) => Err("--sse-kms-key-id requires --server-side-encryption".to_string()),
+        (Some(algorithm), _) => Err(format!(
+            "Unknown server side encryption {}. Valid values are: {}, {}",
+            algorithm, SSE_S3, SSE_KMS
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kms_key_id_requires_aws_kms() {
+        assert!(validate_destination_encryption(None, None).is_ok());
+        assert!(validate_destination_encryption(Some(SSE_S3), None).is_ok());
+        assert!(validate_destination_encryption(Some(SSE_KMS), None).is_ok());
+        assert!(validate_destination_encryption(Some(SSE_KMS), Some("key-id")).is_ok());
+
+        assert!(validate_destination_encryption(Some(SSE_S3), Some("key-id")).is_err());
+        assert!(validate_destination_encryption(None, Some("key-id")).is_err());
+    }
+
+    #[test]
+    fn unknown_algorithm_is_rejected() {
+        let error = validate_destination_encryption(Some("aws:kms:dsse"), None).unwrap_err();
+        assert!(
+            error.starts_with("Unknown server side encryption"),
+            "{}",
+            error
+        );
+    }
+}
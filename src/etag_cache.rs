@@ -0,0 +1,66 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::Context;
+
+/// ETags of the source objects synchronized by previous runs, stored in a file shared by all the
+/// buckets. Each line is `<bucket>\t<etag>\t<key>`, the key comes last as it may contain tabs.
+/// When a cached ETag is known, the source object is downloaded with a conditional GET so an
+/// unchanged object isn't downloaded again
+#[derive(Debug)]
+pub struct SourceETagCache {
+    bucket: String,
+    etags: HashMap<String, String>,
+    file: Mutex<File>,
+}
+
+impl SourceETagCache {
+    pub fn open(path: &Path, bucket: &str) -> anyhow::Result<SourceETagCache> {
+        let mut etags = HashMap::new();
+
+        if path.exists() {
+            let file = File::open(path)
+                .with_context(|| format!("Failed to open ETag cache {}", path.display()))?;
+
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                let mut parts = line.splitn(3, '\t');
+                if let (Some(entry_bucket), Some(etag), Some(key)) =
+                    (parts.next(), parts.next(), parts.next())
+                {
+                    // Later entries are more recent
+                    if entry_bucket == bucket {
+                        etags.insert(key.to_string(), etag.to_string());
+                    }
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open ETag cache {}", path.display()))?;
+
+        Ok(SourceETagCache {
+            bucket: bucket.to_string(),
+            etags,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.etags.get(key).map(|etag| etag.as_str())
+    }
+
+    pub fn record(&self, key: &str, etag: &str) -> anyhow::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}\t{}\t{}", self.bucket, etag, key)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use bytesize::ByteSize;
+use tokio::task::JoinHandle;
+use tracing::{event, Level};
+
+/// Counters shared by all the sync threads of the run, read by the heartbeat, the progress bar
+/// and the metrics endpoint
+#[derive(Debug, Default)]
+pub struct SyncProgress {
+    objects_done: AtomicUsize,
+    bytes_done: AtomicUsize,
+    active_workers: AtomicUsize,
+    objects_failed: AtomicUsize,
+    /// Objects being synchronized, including their retries
+    objects_in_flight: AtomicUsize,
+    /// Objects to synchronize, added as each page of each bucket is handed to the sync threads
+    objects_total: AtomicUsize,
+    bytes_total: AtomicUsize,
+    /// Uploaded parts of the multipart uploads in progress
+    parts_bytes: AtomicUsize,
+    /// Bytes sent to the destination, never decreasing: the parts of the failed multipart
+    /// uploads stay counted
+    bytes_transferred: AtomicUsize,
+}
+
+impl SyncProgress {
+    pub fn objects_to_sync(&self, objects: usize, bytes: usize) {
+        self.objects_total.fetch_add(objects, Ordering::Relaxed);
+        self.bytes_total.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Objects and bytes to synchronize known so far
+    pub fn totals(&self) -> (usize, usize) {
+        (
+            self.objects_total.load(Ordering::Relaxed),
+            self.bytes_total.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn objects_done(&self) -> usize {
+        self.objects_done.load(Ordering::Relaxed)
+    }
+
+    /// Bytes of the synchronized objects and of the uploaded parts of the objects in progress
+    pub fn bytes_uploaded(&self) -> usize {
+        self.bytes_done() + self.parts_bytes.load(Ordering::Relaxed)
+    }
+
+    /// An object or a part sent to the destination
+    pub fn bytes_sent(&self, size: usize) {
+        self.bytes_transferred.fetch_add(size, Ordering::Relaxed);
+    }
+
+    pub fn bytes_transferred(&self) -> usize {
+        self.bytes_transferred.load(Ordering::Relaxed)
+    }
+
+    pub fn object_started(&self) {
+        self.objects_in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Called once per object whatever the outcome, before `object_done` for the successful ones
+    pub fn object_finished(&self, success: bool) {
+        self.objects_in_flight.fetch_sub(1, Ordering::Relaxed);
+        if !success {
+            self.objects_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn objects_failed(&self) -> usize {
+        self.objects_failed.load(Ordering::Relaxed)
+    }
+
+    pub fn objects_in_flight(&self) -> usize {
+        self.objects_in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn object_done(&self, size: usize) {
+        self.objects_done.fetch_add(1, Ordering::Relaxed);
+        self.bytes_done.fetch_add(size, Ordering::Relaxed);
+    }
+
+    /// An object found unchanged on the source since its last synchronization, it is no longer
+    /// part of the objects to synchronize
+    pub fn object_not_modified(&self, size: usize) {
+        self.objects_total.fetch_sub(1, Ordering::Relaxed);
+        self.bytes_total.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    pub fn bytes_done(&self) -> usize {
+        self.bytes_done.load(Ordering::Relaxed)
+    }
+
+    pub fn worker_started(&self) {
+        self.active_workers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn worker_stopped(&self) {
+        self.active_workers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Parts uploaded for a multipart upload. They are counted until the upload completes or fails,
+/// then the whole object is counted by `SyncProgress::object_done` if it succeeded
+pub struct PartsProgress<'a> {
+    progress: &'a SyncProgress,
+    bytes: usize,
+}
+
+impl<'a> PartsProgress<'a> {
+    pub fn new(progress: &'a SyncProgress) -> PartsProgress<'a> {
+        PartsProgress { progress, bytes: 0 }
+    }
+
+    pub fn part_done(&mut self, size: usize) {
+        self.part_resumed(size);
+        self.progress.bytes_sent(size);
+    }
+
+    /// A part uploaded by a previous attempt, it isn't sent again
+    pub fn part_resumed(&mut self, size: usize) {
+        self.bytes += size;
+        self.progress.parts_bytes.fetch_add(size, Ordering::Relaxed);
+    }
+}
+
+impl Drop for PartsProgress<'_> {
+    fn drop(&mut self) {
+        self.progress
+            .parts_bytes
+            .fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// Logs a heartbeat every `interval`, even if nothing happened, so an external monitoring can
+/// detect a hung run when the heartbeats stop
+pub fn spawn_heartbeat(interval: Duration, progress: Arc<SyncProgress>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately
+        ticker.tick().await;
+
+        let mut last_tick = Instant::now();
+        // The progress is shared by the passes of --continuous
+        let mut last_bytes_done = progress.bytes_done();
+        loop {
+            ticker.tick().await;
+
+            let objects_done = progress.objects_done.load(Ordering::Relaxed);
+            let bytes_done = progress.bytes_done.load(Ordering::Relaxed);
+            let active_workers = progress.active_workers.load(Ordering::Relaxed);
+            let rate = (bytes_done - last_bytes_done) as f64 / last_tick.elapsed().as_secs_f64();
+
+            event!(
+                Level::INFO,
+                objects_done,
+                bytes_done,
+                active_workers,
+                bytes_per_second = rate as u64,
+                "Heartbeat | {} objects synchronized ({}), {} active threads, {}/s",
+                objects_done,
+                ByteSize(bytes_done as u64),
+                active_workers,
+                ByteSize(rate as u64)
+            );
+
+            last_tick = Instant::now();
+            last_bytes_done = bytes_done;
+        }
+    })
+}
@@ -0,0 +1,112 @@
+use regex::Regex;
+
+use crate::provider::ProviderObject;
+
+/// Restricts the synchronized objects by key, on both the source and the destination. Objects
+/// filtered out are neither copied nor deleted
+#[derive(Debug, Clone)]
+pub struct KeyFilter {
+    include: Option<Regex>,
+    exclude: Option<Regex>,
+}
+
+impl KeyFilter {
+    /// The patterns are compiled once, invalid ones are reported with the option they come from
+    pub fn new(include: Option<&str>, exclude: Option<&str>) -> Result<KeyFilter, String> {
+        let compile = |option: &str, pattern: &str| {
+            Regex::new(pattern)
+                .map_err(|error| format!("Invalid regex for {}: {}: {}", option, pattern, error))
+        };
+
+        Ok(KeyFilter {
+            include: include
+                .map(|pattern| compile("--include-regex", pattern))
+                .transpose()?,
+            exclude: exclude
+                .map(|pattern| compile("--exclude-regex", pattern))
+                .transpose()?,
+        })
+    }
+
+    /// The exclude pattern wins over the include pattern when both match
+    pub fn matches(&self, key: &str) -> bool {
+        if let Some(exclude) = &self.exclude {
+            if exclude.is_match(key) {
+                return false;
+            }
+        }
+
+        self.include
+            .as_ref()
+            .map(|include| include.is_match(key))
+            .unwrap_or(true)
+    }
+
+    pub fn filter(&self, objects: &[ProviderObject]) -> Vec<ProviderObject> {
+        objects
+            .iter()
+            .filter(|object| self.matches(&object.get_key()))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_only() {
+        let key_filter = KeyFilter::new(Some(r"\.jpg$"), None).unwrap();
+        assert!(key_filter.matches("photos/cat.jpg"));
+        assert!(!key_filter.matches("photos/cat.png"));
+    }
+
+    #[test]
+    fn exclude_only() {
+        let key_filter = KeyFilter::new(None, Some(r"\.tmp$")).unwrap();
+        assert!(key_filter.matches("data.csv"));
+        assert!(!key_filter.matches("data.csv.tmp"));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let key_filter = KeyFilter::new(Some(r"^logs/"), Some(r"\.tmp$")).unwrap();
+        assert!(key_filter.matches("logs/app.log"));
+        assert!(!key_filter.matches("logs/app.log.tmp"));
+        assert!(!key_filter.matches("data/app.log"));
+    }
+
+    #[test]
+    fn without_patterns() {
+        assert!(KeyFilter::new(None, None).unwrap().matches("any key"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        let error = KeyFilter::new(Some("(unclosed"), None).unwrap_err();
+        assert!(
+            error.starts_with("Invalid regex for --include-regex: (unclosed:"),
+            "{}",
+            error
+        );
+        let error = KeyFilter::new(None, Some("[a-")).unwrap_err();
+        assert!(
+            error.starts_with("Invalid regex for --exclude-regex: [a-:"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn filtered_objects() {
+        let key_filter = KeyFilter::new(None, Some(r"\.tmp$")).unwrap();
+        let objects = [
+            ProviderObject::new("kept".to_string(), 1),
+            ProviderObject::new("dropped.tmp".to_string(), 1),
+        ];
+        let filtered = key_filter.filter(&objects);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].get_key(), "kept");
+    }
+}
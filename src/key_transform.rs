@@ -0,0 +1,175 @@
+use tracing::{event, Level};
+
+use crate::provider::ProviderObject;
+
+/// What to do with the source keys which don't start with the prefix to strip
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnmatchedKeysPolicy {
+    /// Copy them with the destination prefix only
+    Keep,
+    Skip,
+}
+
+impl TryFrom<&str> for UnmatchedKeysPolicy {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "keep" => Ok(UnmatchedKeysPolicy::Keep),
+            "skip" => Ok(UnmatchedKeysPolicy::Skip),
+            _ => Err(format!("Failed to parse unmatched keys policy: {}", value)),
+        }
+    }
+}
+
+/// Rewrites the destination keys, e.g. to move `olddir/` to `newdir/`. The objects are still
+/// read from their source key, and the destination objects are compared with the rewritten key
+/// so a new run only copies what changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyTransform {
+    pub strip_prefix: Option<String>,
+    pub add_prefix: Option<String>,
+    pub unmatched_keys: UnmatchedKeysPolicy,
+}
+
+impl KeyTransform {
+    /// Returns None when the key should be skipped
+    pub fn destination_key(&self, key: &str) -> Option<String> {
+        let stripped = match &self.strip_prefix {
+            Some(prefix) => match key.strip_prefix(prefix.as_str()) {
+                Some(stripped) => stripped,
+                None if self.unmatched_keys == UnmatchedKeysPolicy::Skip => return None,
+                None => key,
+            },
+            None => key,
+        };
+
+        Some(format!(
+            "{}{}",
+            self.add_prefix.as_deref().unwrap_or_default(),
+            stripped
+        ))
+    }
+
+    /// Returns the objects to diff with the destination, with their rewritten destination key.
+    /// Applied after the unsafe keys policy, so a sanitized key is rewritten too
+    pub fn apply(&self, bucket: &str, objects: Vec<ProviderObject>) -> Vec<ProviderObject> {
+        objects
+            .into_iter()
+            .filter_map(|object| {
+                let destination_key = self.destination_key(&object.get_destination_key());
+                match destination_key {
+                    Some(destination_key) if destination_key.is_empty() => {
+                        event!(
+                            Level::WARN,
+                            "{} | Skipping object {:?}: its destination key would be empty",
+                            bucket,
+                            object.get_key()
+                        );
+                        None
+                    }
+                    Some(destination_key) => Some(object.with_destination_key(destination_key)),
+                    None => {
+                        event!(
+                            Level::DEBUG,
+                            "{} | Skipping object {:?}: it doesn't start with the prefix to strip",
+                            bucket,
+                            object.get_key()
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(
+        strip_prefix: Option<&str>,
+        add_prefix: Option<&str>,
+        unmatched_keys: UnmatchedKeysPolicy,
+    ) -> KeyTransform {
+        KeyTransform {
+            strip_prefix: strip_prefix.map(str::to_string),
+            add_prefix: add_prefix.map(str::to_string),
+            unmatched_keys,
+        }
+    }
+
+    #[test]
+    fn strip_only() {
+        let transform = transform(Some("olddir/"), None, UnmatchedKeysPolicy::Keep);
+
+        assert_eq!(
+            transform.destination_key("olddir/a/b"),
+            Some("a/b".to_string())
+        );
+        assert_eq!(
+            transform.destination_key("other/a"),
+            Some("other/a".to_string())
+        );
+    }
+
+    #[test]
+    fn prepend_only() {
+        let transform = transform(None, Some("archive/"), UnmatchedKeysPolicy::Skip);
+
+        assert_eq!(
+            transform.destination_key("olddir/a"),
+            Some("archive/olddir/a".to_string())
+        );
+    }
+
+    #[test]
+    fn strip_and_prepend() {
+        let transform = transform(Some("olddir/"), Some("newdir/"), UnmatchedKeysPolicy::Keep);
+
+        assert_eq!(
+            transform.destination_key("olddir/a"),
+            Some("newdir/a".to_string())
+        );
+        assert_eq!(
+            transform.destination_key("other/a"),
+            Some("newdir/other/a".to_string())
+        );
+    }
+
+    #[test]
+    fn unmatched_keys_are_skipped() {
+        let transform = transform(Some("olddir/"), Some("newdir/"), UnmatchedKeysPolicy::Skip);
+
+        assert_eq!(
+            transform.destination_key("olddir/a"),
+            Some("newdir/a".to_string())
+        );
+        assert_eq!(transform.destination_key("other/a"), None);
+        assert_eq!(transform.destination_key("olddir"), None);
+    }
+
+    #[test]
+    fn objects_are_read_from_their_source_key() {
+        let transform = transform(Some("olddir/"), None, UnmatchedKeysPolicy::Keep);
+        let objects = ["olddir/a", "olddir/", "other"]
+            .iter()
+            .map(|key| ProviderObject::new(key.to_string(), 10))
+            .collect();
+
+        let objects = transform.apply("bucket", objects);
+
+        let keys: Vec<(String, String)> = objects
+            .iter()
+            .map(|object| (object.get_key(), object.get_destination_key()))
+            .collect();
+        // The object whose destination key would be empty is skipped
+        assert_eq!(
+            keys,
+            [
+                ("olddir/a".to_string(), "a".to_string()),
+                ("other".to_string(), "other".to_string())
+            ]
+        );
+    }
+}
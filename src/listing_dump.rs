@@ -0,0 +1,64 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use tracing::{event, Level};
+
+use crate::{
+    migrate::{get_bucket_providers, BucketMigrationConfiguration},
+    provider::{list_all_objects, ProviderObject},
+};
+
+/// One `<key>\t<size>\t<etag>` line per object, sorted by key
+fn write_listing(path: &Path, mut objects: Vec<ProviderObject>) -> anyhow::Result<()> {
+    objects.sort_by_key(|object| object.get_key());
+
+    let mut file = BufWriter::new(File::create(path)?);
+    for object in objects {
+        writeln!(
+            file,
+            "{}\t{}\t{}",
+            object.get_key(),
+            object.get_size(),
+            object.get_etag().trim_matches('"')
+        )?;
+    }
+    file.flush()?;
+
+    Ok(())
+}
+
+/// Writes the listings of the source and destination buckets to
+/// `<directory>/<source bucket>.source.tsv` and `<directory>/<source bucket>.destination.tsv`
+pub async fn dump_listings(
+    conf: &BucketMigrationConfiguration,
+    directory: &Path,
+) -> anyhow::Result<()> {
+    let (source_provider, destination_provider) = get_bucket_providers(conf);
+
+    let source_path = directory.join(format!("{}.source.tsv", conf.source_bucket));
+    write_listing(&source_path, list_all_objects(&*source_provider).await?)?;
+
+    // The destination bucket doesn't exist yet on a first dry run
+    let destination_objects = if conf.dry_run {
+        list_all_objects(&*destination_provider)
+            .await
+            .unwrap_or_default()
+    } else {
+        list_all_objects(&*destination_provider).await?
+    };
+    let destination_path = directory.join(format!("{}.destination.tsv", conf.source_bucket));
+    write_listing(&destination_path, destination_objects)?;
+
+    event!(
+        Level::INFO,
+        "Bucket {} | Listings written to {} and {}",
+        conf.source_bucket,
+        source_path.display(),
+        destination_path.display()
+    );
+
+    Ok(())
+}
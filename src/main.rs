@@ -1,122 +1,1662 @@
+mod acl_rules;
+mod bandwidth;
+mod benchmark;
+mod bucket_digest;
+mod checksum_manifest;
+mod circuit_breaker;
+mod content_dedup;
+mod credentials;
+mod destination_bucket;
+mod encryption;
+mod etag_cache;
+mod heartbeat;
+mod key_filter;
+mod key_transform;
+mod listing_dump;
+mod md5_hasher;
+mod metadata_filter;
+mod metrics;
 mod migrate;
+mod modified_filter;
+mod object_timings;
+mod plan;
+mod progress_bar;
 mod provider;
 mod radosgw;
+mod report;
+mod resume;
 mod riakcs;
+mod run_window;
+mod state_file;
+#[cfg(feature = "sqlite-status")]
+mod status_db;
+mod storage_class;
+mod throughput;
+mod timing_trace;
+mod tls;
+mod unsafe_keys;
 
-use std::str::FromStr;
+use std::{
+    collections::HashMap, net::SocketAddr, path::PathBuf, str::FromStr, sync::Arc, time::Duration,
+};
 
 use bytesize::ByteSize;
+use chrono::{DateTime, Utc};
 use clap::{value_parser, ArgAction};
 use clap::{Arg, ArgMatches, Command};
 use migrate::BucketMigrationConfiguration;
-use rusoto_core::Region;
+#[cfg(feature = "otlp")]
+use opentelemetry_otlp::WithExportConfig;
+use ring::rand::{SecureRandom, SystemRandom};
+use rusoto_core::{Region, RusotoError};
+use rusoto_s3::ListBucketsError;
 use tracing::event;
 use tracing::instrument;
 use tracing::Level;
+#[cfg(feature = "otlp")]
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::filter::{self, FilterExt};
 use tracing_subscriber::fmt::format::FmtSpan;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::TestWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer};
 
-use crate::migrate::{BucketMigrationError, BucketMigrationStats};
-use crate::provider::ProviderConf;
+use crate::acl_rules::{AclRule, AclRules, DestinationAcl};
+use crate::bandwidth::BandwidthLimit;
+use crate::benchmark::BenchmarkConfiguration;
+use crate::checksum_manifest::ChecksumManifest;
+use crate::circuit_breaker::{CircuitBreaker, CircuitBreakerConfiguration};
+use crate::content_dedup::ContentDedup;
+use crate::credentials::ProfileCredentials;
+use crate::destination_bucket::DestinationBucketNames;
+use crate::encryption::{validate_destination_encryption, EncryptionMirror};
+use crate::etag_cache::SourceETagCache;
+use crate::heartbeat::{spawn_heartbeat, SyncProgress};
+use crate::key_filter::KeyFilter;
+use crate::key_transform::{KeyTransform, UnmatchedKeysPolicy};
+use crate::metadata_filter::MetadataFilter;
+use crate::metrics::MetricsServer;
+use crate::migrate::{
+    migration_stats, BucketMigrationError, BucketMigrationStats, CopyListFormat,
+    DestinationFullError, EmptySourcePolicy, MigrationFailedError, PreflightError,
+    SourceEmptyError, VerificationReport,
+};
+use crate::modified_filter::{parse_rfc3339, ModifiedFilter, UnreliableLastModifiedPolicy};
+use crate::object_timings::ObjectTimings;
+use crate::plan::{MigrationPlan, PlanWriter};
+use crate::progress_bar::{spawn_progress_bar, SuspendProgressBar};
+use crate::provider::{CompareStrategy, ETagFilter, ProviderConf};
+use crate::radosgw::faults::SimulatedFailures;
+use crate::radosgw::trace::enable_trace_requests;
+use crate::radosgw::MAX_FETCH_KEYS;
+use crate::radosgw::uploader::ObjectPriority;
+use crate::report::MigrationReport;
+use crate::resume::ResumeToken;
+use crate::run_window::RunWindow;
+use crate::state_file::StateFile;
+#[cfg(feature = "sqlite-status")]
+use crate::status_db::ObjectStatusDb;
+use crate::storage_class::{parse_storage_class, StorageClassRule};
 use crate::provider::{get_provider, Providers};
+use crate::throughput::ThroughputReport;
+use crate::timing_trace::TimingTrace;
+use crate::unsafe_keys::UnsafeKeysPolicy;
+
+/// Invalid options, credentials or destination buckets, nothing has been synchronized
+const CONFIGURATION_ERROR_EXIT_CODE: i32 = 1;
+/// Some buckets or objects failed to synchronize, or the bucket digests differ
+const PARTIAL_FAILURE_EXIT_CODE: i32 = 2;
+/// Exit code of runs with `--empty-source fail` that found an empty source bucket
+const SOURCE_EMPTY_EXIT_CODE: i32 = 3;
+/// The destination endpoint couldn't be reached before anything has been synchronized
+const DESTINATION_UNREACHABLE_EXIT_CODE: i32 = 4;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let _ = tracing_subscriber::fmt()
-        .with_env_filter(
-            std::env::var(EnvFilter::DEFAULT_ENV)
-                .map(|_| EnvFilter::from_default_env())
-                .unwrap_or_else(|_| EnvFilter::new("info")),
-        )
-        .with_span_events(FmtSpan::CLOSE | FmtSpan::NEW)
-        .with_test_writer()
-        .try_init();
+    let migrate_subcommand = Command::new("migrate")
+        .about("Migrate a bucket to a Cellar cluster. By default, it will dry run unless --execute is passed")
+        .arg(Arg::new("source-bucket").long("source-bucket").help("Source bucket from which files will be copied. If omitted, all buckets of the add-on will be synchronized"))
+        .arg(Arg::new("prefix").long("prefix").help("Only synchronize the objects whose key starts with this prefix, e.g. logs/2023/. Objects outside of it are left untouched on the destination"))
+        .arg(Arg::new("include-regex").long("include-regex").help("Only synchronize the objects whose key matches this regex, e.g. '\\.(jpg|png)$'. Other objects are left untouched on the destination").required(false))
+        .arg(Arg::new("exclude-regex").long("exclude-regex").help("Don't synchronize the objects whose key matches this regex, e.g. '\\.tmp$'. Wins over --include-regex. Excluded objects are left untouched on the destination").required(false))
+        .arg(Arg::new("strip-prefix").long("strip-prefix").help("Remove this prefix from the source keys on the destination, e.g. olddir/. Combine with --add-prefix to move objects to another directory").required(false).conflicts_with("delete-extraneous"))
+        .arg(Arg::new("add-prefix").long("add-prefix").help("Prepend this prefix to the destination keys, e.g. newdir/").required(false).conflicts_with("delete-extraneous"))
+        .arg(Arg::new("unmatched-keys").long("unmatched-keys").help("What to do with the source keys which don't start with --strip-prefix: keep (copy them with --add-prefix only) or skip").required(false).default_value("keep").requires("strip-prefix"))
+        .arg(Arg::new("source-access-key").long("source-access-key").help("Source bucket Cellar access key").required_unless_present("source-profile"))
+        .arg(Arg::new("source-secret-key").long("source-secret-key").help("Source bucket Cellar secret key").required_unless_present("source-profile"))
+        .arg(Arg::new("source-profile").long("source-profile").help("Read the source access and secret keys from this profile of the AWS credentials or config file instead")
+            .required(false).conflicts_with_all(["source-access-key", "source-secret-key"])
+        )
+        .arg(Arg::new("source-endpoint").long("source-endpoint").help("Source endpoint of the S3 Bucket"))
+        .arg(Arg::new("source-provider").long("source-provider").help("Provider for source bucket (AWS, Ceph, RiakCS, ..)").required(true))
+        .arg(Arg::new("source-region").long("source-region").help("Region of the source bucket (eu-west-1,..)"))
+        .arg(Arg::new("destination-bucket").long("destination-bucket").help("Destination bucket to which the files will be copied. If omitted, the bucket will be created if it doesn't exist"))
+        .arg(Arg::new("destination-bucket-prefix").long("destination-bucket-prefix").help("Prefix to apply to the destination bucket name"))
+        .arg(
+            Arg::new("destination-bucket-template").long("destination-bucket-template")
+            .help("Template of the destination bucket names, with the {bucket}, {prefix} and {date} (YYYYMMDD, when the run started) placeholders, e.g. {prefix}{bucket}-migrated. Defaults to {prefix}{bucket}")
+            .required(false)
+        )
+        .arg(Arg::new("destination-access-key").long("destination-access-key").help("Destination bucket Cellar access key").required_unless_present("destination-profile"))
+        .arg(Arg::new("destination-secret-key").long("destination-secret-key").help("Destination bucket Cellar secret key").required_unless_present("destination-profile"))
+        .arg(Arg::new("destination-profile").long("destination-profile").help("Read the destination access and secret keys from this profile of the AWS credentials or config file instead")
+            .required(false).conflicts_with_all(["destination-access-key", "destination-secret-key"])
+        )
+        .arg(Arg::new("destination-endpoint").long("destination-endpoint").help("Destination endpoint of the Cellar cluster. Defaults to Paris Cellar cluster")
+            .required(false).default_value("cellar-c2.services.clever-cloud.com")
+        )
+        .arg(
+            Arg::new("destination-region").long("destination-region")
+            .help("Region name the destination requests are signed with, for endpoints checking the region of the signature")
+            .required(false).default_value("default")
+        )
+        .arg(
+            Arg::new("auto-create-destination-bucket").long("auto-create-destination-bucket")
+            .help("Create the destination bucket again when it is missing at the start of its migration, e.g. deleted during a --continuous run, instead of failing it")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("threads").long("threads").short('t').help("Number of threads used to synchronize this bucket")
+            .required(false).value_parser(value_parser!(usize))
+        )
+        .arg(
+            Arg::new("verify-threads").long("verify-threads")
+            .help("Number of HEAD requests sent at the same time to verify the uploaded objects. Defaults to the number of threads of the bucket")
+            .required(false).value_parser(value_parser!(usize))
+        )
+        .arg(
+            Arg::new("delete-threads").long("delete-threads")
+            .help("Number of delete requests sent at the same time to remove the destination objects missing from the source. Defaults to the number of threads of the bucket")
+            .required(false).value_parser(value_parser!(usize))
+        )
+        .arg(
+            Arg::new("max-bucket-objects").long("max-bucket-objects")
+            .help("Don't migrate buckets having more objects than this limit. The whole bucket is listed first to count its objects")
+            .required(false).value_parser(value_parser!(usize))
+        )
+        .arg(
+            Arg::new("empty-source").long("empty-source")
+            .help("What to do with source buckets without any object: ignore (log it) or fail (report an error and exit with code 3 at the end of the run)")
+            .required(false).default_value("ignore").value_parser(["ignore", "fail"])
+        )
+        .arg(
+            Arg::new("trace-requests").long("trace-requests")
+            .help("Debugging mode: log the method, URL, region and headers (without the signature) of the Cellar and AWS S3 requests instead of sending them. Every request gets an empty response, nothing is migrated")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("ca-bundle").long("ca-bundle")
+            .help("PEM file with the certificates of the CAs to trust on top of the system ones, for the source and destination endpoints, e.g. for an on-prem gateway using an internal CA")
+            .required(false).value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("danger-accept-invalid-certs").long("danger-accept-invalid-certs")
+            .help("Don't verify the TLS certificates of the source and destination endpoints. Anyone on the network path can then read and modify the objects, only use it for testing")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("source-requester-pays").long("source-requester-pays")
+            .help("The source buckets are requester pays buckets: accept to be charged for the listing and download requests")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("max-bucket-bytes").long("max-bucket-bytes")
+            .help("Don't migrate buckets bigger than this size in bytes. The whole bucket is listed first to compute its size")
+            .required(false).value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("min-object-size").long("min-object-size")
+            .help("Don't synchronize the source objects smaller than this size in bytes. Their destination copies aren't deleted")
+            .required(false).value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("max-object-size").long("max-object-size")
+            .help("Don't synchronize the source objects bigger than this size in bytes, e.g. to copy them with another tool. Their destination copies aren't deleted")
+            .required(false).value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("modified-since").long("modified-since")
+            .help("Only synchronize the source objects modified at or after this RFC 3339 date, e.g. 2024-01-31T00:00:00Z. Their destination copies aren't deleted")
+            .required(false)
+        )
+        .arg(
+            Arg::new("modified-before").long("modified-before")
+            .help("Only synchronize the source objects modified before this RFC 3339 date, e.g. 2024-01-31T00:00:00Z. Their destination copies aren't deleted")
+            .required(false)
+        )
+        .arg(
+            Arg::new("unreliable-last-modified").long("unreliable-last-modified")
+            .help("With --modified-since or --modified-before, whether the source objects whose last modified date is the Unix epoch or in the future are synchronized")
+            .required(false).default_value("include").value_parser(["include", "exclude"])
+        )
+        .arg(
+            Arg::new("force").long("force")
+            .help("Migrate buckets even if they exceed --max-bucket-objects or --max-bucket-bytes")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("continuous").long("continuous")
+            .help("After the migration, synchronize again every given seconds to copy new and changed objects, until the --cutover-file is created. A last synchronization is then done before exiting")
+            .required(false).value_parser(value_parser!(u64)).requires("execute").requires("cutover-file")
+        )
+        .arg(
+            Arg::new("cutover-file").long("cutover-file")
+            .help("With --continuous, create this file once writes to the source are stopped to run the last synchronization")
+            .required(false).value_parser(value_parser!(PathBuf)).requires("continuous")
+        )
+        .arg(
+            Arg::new("print-copy-list").long("print-copy-list")
+            .help("Print the objects that need to be synchronized to stdout and exit without synchronizing. keys: NUL delimited keys, to be piped to xargs -0. tsv: <size>\t<key> lines. Logs are written to stderr")
+            .required(false).value_parser(["keys", "tsv"]).conflicts_with("execute")
+        )
+        .arg(
+            Arg::new("write-plan").long("write-plan")
+            .help("Write the objects to copy and delete to this file, to be reviewed then applied with --apply-plan")
+            .required(false).value_parser(value_parser!(PathBuf)).conflicts_with("execute")
+        )
+        .arg(
+            Arg::new("apply-plan").long("apply-plan")
+            .help("Copy and delete exactly the objects of a plan written by --write-plan, without listing the buckets")
+            .required(false).value_parser(value_parser!(PathBuf)).requires("execute")
+            .conflicts_with_all(["resume-token", "verify-and-repair"])
+        )
+        .arg(
+            Arg::new("bucket-threads").long("bucket-threads")
+            .help("Override the number of threads for a bucket, as <bucket>=<threads>. Can be repeated. Can't be greater than --threads")
+            .required(false).action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("multipart-chunk-size-mb").long("multipart-chunk-size-mb")
+            .help("Size of each chunk of multipart upload in Megabytes. Files bigger than this size are automatically uploaded using multipart upload, unless --multipart-threshold-mb is set")
+            .required(false).value_parser(value_parser!(usize)).default_value("100")
+        )
+        .arg(
+            Arg::new("multipart-threshold-mb").long("multipart-threshold-mb")
+            .help("Objects of at least this size in Megabytes are uploaded using multipart upload, smaller ones with a single request. At least 5. Defaults to --multipart-chunk-size-mb")
+            .required(false).value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("multipart-part-size-mb").long("multipart-part-size-mb")
+            .help("Size of each part of multipart uploads in Megabytes, between 5 and 5120. Defaults to --multipart-chunk-size-mb")
+            .required(false).value_parser(value_parser!(usize))
+        )
+        .arg(
+            Arg::new("execute").long("execute").short('e')
+            .help("Execute the synchronization. THIS COMMAND WILL MAKE PRODUCTION CHANGES TO THE DESTINATION BUCKET.")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("max-keys").long("max-keys").short('m')
+            .help("Define the maximum number of object keys to list when listing the bucket. Lowering this might help listing huge buckets")
+            .required(false).value_parser(value_parser!(usize)).default_value("1000")
+        )
+        .arg(
+            Arg::new("destination-max-keys").long("destination-max-keys")
+            .help("Number of object keys of each destination listing request, at most 1000. Lower it if the destination times out listing huge buckets")
+            .required(false).value_parser(value_parser!(usize)).default_value("1000")
+        )
+        .arg(
+            Arg::new("max-concurrent-multipart").long("max-concurrent-multipart")
+            .help("Maximum number of multipart uploads in progress at the same time across all threads and buckets. Objects smaller than the multipart threshold are not affected")
+            .required(false).value_parser(value_parser!(usize))
+        )
+        .arg(
+            Arg::new("resume-multipart-uploads").long("resume-multipart-uploads")
+            .help("Reuse the parts of the multipart uploads left in progress on the destination by an interrupted run, found with ListMultipartUploads and ListParts, instead of uploading the objects from the start")
+            .required(false).action(ArgAction::SetTrue).conflicts_with("verify-checksum")
+        )
+        .arg(
+            Arg::new("compare-mode").long("compare-mode")
+            .help("How objects are compared to know if they need to be synchronized: etag, content-md5, size, size-and-last-modified. content-md5 needs a HEAD request on both sides for each object and falls back to etag when the Content-MD5 is missing")
+            .required(false).default_value("etag")
+        )
+        .arg(
+            Arg::new("trust-size-for-multipart").long("trust-size-for-multipart")
+            .help("Only compare the size of the objects uploaded using multipart on one of the sides, for destinations computing multipart ETags differently")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("recopy-storage-class-changes").long("recopy-storage-class-changes")
+            .help("Copy again objects whose storage class on the destination bucket isn't the one the migration writes (e.g. after a lifecycle transition). By default, storage class differences are ignored")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("storage-class").long("storage-class")
+            .help("Storage class of the destination objects, e.g. STANDARD_IA. The destination default (STANDARD) if omitted")
+            .required(false)
+        )
+        .arg(
+            Arg::new("storage-class-rule").long("storage-class-rule")
+            .help("Storage class of the objects whose source key matches a pattern, as <key pattern>=<storage class>, e.g. archive/*=GLACIER. * matches any characters. Can be repeated, the first matching rule wins over --storage-class")
+            .required(false).action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("acl-rule").long("acl-rule")
+            .help("Set the destination ACL from the source object tags instead of the source ACL, as <tag key>=<tag value>:<private|public-read>. Can be repeated, the first matching rule wins")
+            .required(false).action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("acl-rule-default").long("acl-rule-default")
+            .help("ACL (private or public-read) of the objects whose tags don't match any --acl-rule. The source ACL is kept if omitted")
+            .required(false).requires("acl-rule")
+        )
+        .arg(
+            Arg::new("preserve-acl").long("preserve-acl")
+            .help("Keep the source ACL when it is private, public-read, public-read-write or authenticated-read instead of only keeping public-read. Other grants are logged and not copied")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("preserve-tags").long("preserve-tags")
+            .help("Copy the tags of the source objects to the destination objects. Riak CS doesn't support object tagging, its objects have none")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("mirror-encryption").long("mirror-encryption")
+            .help("Encrypt each destination object like its source object (none, AES256 or aws:kms) instead of leaving them unencrypted. The objects which can't be mirrored faithfully are reported at the end")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("kms-key-map").long("kms-key-map")
+            .help("Destination KMS key of the objects encrypted with a source KMS key, as <source key id>=<destination key id>. Can be repeated")
+            .required(false).action(ArgAction::Append).requires("mirror-encryption")
+        )
+        .arg(
+            Arg::new("default-kms-key").long("default-kms-key")
+            .help("Destination KMS key of the objects whose source KMS key isn't mapped with --kms-key-map. They are encrypted with AES256 if omitted")
+            .required(false).requires("mirror-encryption")
+        )
+        .arg(
+            Arg::new("server-side-encryption").long("server-side-encryption")
+            .help("Encrypt all the destination objects with AES256 or aws:kms. Required by some destinations")
+            .required(false).value_parser(["AES256", "aws:kms"]).conflicts_with("mirror-encryption")
+        )
+        .arg(
+            Arg::new("sse-kms-key-id").long("sse-kms-key-id")
+            .help("KMS key of the destination objects encrypted with --server-side-encryption aws:kms. The default KMS key of the destination is used if omitted")
+            .required(false).requires("server-side-encryption")
+        )
+        .arg(
+            Arg::new("checksum-manifest").long("checksum-manifest")
+            .help("File with the known MD5 of the source objects, one <bucket>\\t<md5>\\t<key> line per object. They are sent as Content-MD5 so the destination verifies the uploaded objects. Objects missing from the file keep the Content-MD5 of the source")
+            .required(false).value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("verify-checksum").long("verify-checksum")
+            .help("Compute the MD5 of each object and part while uploading it and check it against the ETag returned by the destination, failing the corrupted uploads. The checksums are written in the --report-json report")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("content-dedup").long("content-dedup")
+            .help("Copy server side the objects whose content (ETag and size) has already been uploaded during the run instead of transferring them again. Objects uploaded using multipart aren't deduplicated")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("state-file").long("state-file")
+            .help("File in which the synchronized objects are recorded as they complete. On the next runs, these objects are skipped without being compared to the destination, so an interrupted migration continues where it stopped")
+            .required(false).value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("source-etag-cache").long("source-etag-cache")
+            .help("File in which the ETags of the synchronized source objects are kept. On the next runs, objects that need to be copied again are downloaded with a conditional GET and skipped if the source didn't change")
+            .required(false).value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("resume-token").long("resume-token")
+            .help("Continue a previous partial run of a single bucket from where it stopped, using the token it printed. The token is rejected if the configuration changed")
+            .required(false).requires("source-bucket")
+        )
+        .arg(
+            Arg::new("etag-filter").long("etag-filter")
+            .help("Only synchronize objects whose source ETag is: multipart (has a -N suffix), simple or all")
+            .required(false).default_value("all")
+        )
+        .arg(
+            Arg::new("unsafe-keys").long("unsafe-keys")
+            .help("What to do with objects whose key ends with a whitespace or contains control characters: copy, skip (and log them) or sanitize (copy them under a key without trailing whitespaces and with control characters replaced by _)")
+            .required(false).default_value("copy")
+        )
+        .arg(
+            Arg::new("verify-uploads").long("verify-uploads")
+            .help("HEAD each uploaded object once the uploads are done and report the ones whose size or ETag don't match the source listing")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("verify-and-repair").long("verify-and-repair")
+            .help("Also HEAD the objects that are identical in both listings and copy again the ones whose size, Content-MD5 or ETag don't match")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("skip-existing-fast").long("skip-existing-fast")
+            .help("Skip the source objects whose key exists on the destination, without comparing their size or ETag. For append-only destinations: objects modified on the source are never copied again")
+            .required(false).action(ArgAction::SetTrue)
+            .conflicts_with_all(["compare-mode", "verify-and-repair", "recopy-storage-class-changes"])
+        )
+        .arg(
+            Arg::new("timing-trace").long("timing-trace")
+            .help("Write the timings of each phase of each object (queue, download, upload, complete) to this file, in the Chrome trace event format (chrome://tracing, https://ui.perfetto.dev)")
+            .required(false).value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("slowest-objects").long("slowest-objects")
+            .help("Log the duration, number of parts and throughput of the N slowest uploaded objects of each bucket at the end of the migration")
+            .required(false).value_parser(value_parser!(usize)).requires("execute")
+        )
+        .arg(
+            Arg::new("max-retries").long("max-retries")
+            .help("Number of times an object failing with a transient destination error (5xx, timeout, connection reset) is synchronized again before being reported as failed")
+            .required(false).default_value("3").value_parser(value_parser!(usize))
+        )
+        .arg(
+            Arg::new("retry-base-delay-ms").long("retry-base-delay-ms")
+            .help("Delay in milliseconds before the first retry of an object, doubled on each following retry with some random jitter")
+            .required(false).default_value("500").value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("connect-timeout-secs").long("connect-timeout-secs")
+            .help("Maximum time in seconds to connect to the destination. No timeout if omitted")
+            .required(false).value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("request-timeout-secs").long("request-timeout-secs")
+            .help("Maximum time in seconds of a request to the destination until its response headers are received, including the upload of the request body: it must be longer than the upload of a part. Timed out uploads are retried. No timeout if omitted")
+            .required(false).value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("circuit-breaker-threshold").long("circuit-breaker-threshold")
+            .help("Pause the synchronization of new objects when the ratio (0 to 1) of destination requests failing with a 5xx error over --circuit-breaker-window exceeds this threshold. After --circuit-breaker-cool-down, objects are synchronized one by one until the destination answers again")
+            .required(false).value_parser(value_parser!(f64))
+        )
+        .arg(
+            Arg::new("circuit-breaker-window").long("circuit-breaker-window")
+            .help("Window in seconds over which the destination 5xx ratio is computed")
+            .required(false).default_value("60").value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("circuit-breaker-cool-down").long("circuit-breaker-cool-down")
+            .help("Seconds during which no new object is synchronized once the circuit breaker opened")
+            .required(false).default_value("30").value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("bucket-concurrency").long("bucket-concurrency")
+            .help("Number of buckets migrated at the same time. The buckets share the --threads, the number of objects synchronized at the same time stays the same")
+            .required(false).default_value("1").value_parser(value_parser!(usize))
+        )
+        .arg(
+            Arg::new("run-window").long("run-window")
+            .help("Only start synchronizing new objects during this daily window, e.g. 22:00-06:00, in the local time of the host. Objects in flight when the window closes are finished. Combine with --state-file to skip already synchronized objects if the run is restarted")
+            .required(false)
+        )
+        .arg(
+            Arg::new("max-bandwidth-bytes-per-sec").long("max-bandwidth-bytes-per-sec")
+            .help("Maximum upload rate to the destination in bytes per second, shared by all the threads and buckets. Server side copies aren't limited")
+            .required(false).value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("bucket-max-bandwidth-bytes-per-sec").long("bucket-max-bandwidth-bytes-per-sec")
+            .help("Maximum upload rate to the destination of a bucket, as <bucket>=<bytes per second>. Can be repeated. Can't be greater than --max-bandwidth-bytes-per-sec")
+            .required(false).action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("heartbeat").long("heartbeat")
+            .help("Log a heartbeat with the synchronization progress every given seconds, so a monitoring can detect a hung run")
+            .required(false).value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("metrics-addr").long("metrics-addr")
+            .help("Serve Prometheus metrics of the run (synchronized and failed objects, transferred bytes, uploads in flight, throughput) on http://<addr>/metrics, e.g. 0.0.0.0:9100")
+            .required(false).value_parser(value_parser!(SocketAddr))
+        )
+        .arg(
+            Arg::new("progress").long("progress")
+            .help("Show a progress bar with the synchronized objects and bytes, the current throughput and an ETA. Disabled when stdout isn't a terminal")
+            .required(false).action(ArgAction::SetTrue).conflicts_with("print-copy-list")
+        )
+        .arg(
+            Arg::new("throughput-report").long("throughput-report")
+            .help("Write the throughput of the run sampled every --throughput-interval seconds, with its peak and average, to this JSON file")
+            .required(false).value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("report-json").long("report-json")
+            .help("Write a JSON report of the run to this file: the stats and errors of each bucket and the outcome (uploaded, skipped or failed) of each object")
+            .required(false).value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("throughput-interval").long("throughput-interval")
+            .help("Seconds between two throughput samples of --throughput-report")
+            .required(false).default_value("10").value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("run-id").long("run-id")
+            .help("Identifier of this migration run, used to correlate logs, traces and output files. A random UUID is generated if omitted")
+            .required(false)
+        )
+        .arg(
+            Arg::new("small-object-threshold-kb").long("small-object-threshold-kb")
+            .help("Objects smaller than this size in Kilobytes are synchronized by a dedicated pool of threads (see --small-object-threads). Helps buckets with a lot of tiny objects")
+            .required(false).value_parser(value_parser!(usize))
+        )
+        .arg(
+            Arg::new("small-object-threads").long("small-object-threads")
+            .help("Number of threads used to synchronize small objects. Defaults to 4 times --threads")
+            .required(false).value_parser(value_parser!(usize)).requires("small-object-threshold-kb")
+        )
+        .arg(
+            Arg::new("dump-listings").long("dump-listings")
+            .help("Write the source and destination listings of each bucket, sorted by key, to <bucket>.source.tsv and <bucket>.destination.tsv in this directory (one key, size and ETag per line) before synchronizing it")
+            .required(false).value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("bucket-digest").long("bucket-digest")
+            .help("Instead of synchronizing, compare a digest of the (key, size, ETag) of all the objects of each source bucket with the one of its destination bucket and list the differing keys. No object is downloaded")
+            .required(false).action(ArgAction::SetTrue).conflicts_with("execute")
+        )
+        .arg(
+            Arg::new("fail-fast").long("fail-fast")
+            .help("Stop the whole migration on the first object that fails to synchronize. In-flight multipart uploads are aborted and only the first error is reported")
+            .required(false).action(ArgAction::SetTrue).requires("execute")
+        )
+        .arg(
+            Arg::new("metadata-allowlist").long("metadata-allowlist")
+            .help("Comma separated list of the only metadata fields copied to the destination objects, among: cache-control, content-disposition, content-encoding, content-language, content-type, expires, website-redirect-location")
+            .required(false).conflicts_with("metadata-denylist")
+        )
+        .arg(
+            Arg::new("metadata-denylist").long("metadata-denylist")
+            .help("Comma separated list of metadata fields not copied to the destination objects, among: cache-control, content-disposition, content-encoding, content-language, content-type, expires, website-redirect-location")
+            .required(false)
+        )
+        .arg(
+            Arg::new("infer-content-type").long("infer-content-type")
+            .help("Guess the Content-Type of the source objects without one from the extension of their key, e.g. text/html for .html. Objects without a known extension are left without one")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("record-source-bucket").long("record-source-bucket")
+            .help("Record the name of the source bucket as x-amz-meta-source-bucket on each destination object, useful when buckets are renamed using --destination-bucket, --destination-bucket-prefix or --destination-bucket-template")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("priority-by").long("priority-by")
+            .help("Order in which objects are synchronized: listing, size-asc (smallest first), size-desc (biggest first) or key")
+            .required(false).default_value("listing")
+            .value_parser(["listing", "size-asc", "size-desc", "key"])
+        )
+        .arg(
+            Arg::new("truncate-bytes").long("truncate-bytes")
+            .help("Testing only: copy only the first given bytes of each object to validate the pipeline. Requires --i-know-objects-will-be-truncated")
+            .hide(true).required(false).value_parser(value_parser!(u64))
+            .requires("i-know-objects-will-be-truncated").requires("destination-bucket")
+        )
+        .arg(
+            Arg::new("i-know-objects-will-be-truncated").long("i-know-objects-will-be-truncated")
+            .hide(true).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("delete-extraneous").long("delete-extraneous").visible_alias("delete").short('d')
+            .help("Delete the objects of the destination bucket that don't exist in the source bucket, making the destination a mirror of the source. In dry run mode, they are only logged")
+            .required(false).action(ArgAction::SetTrue)
+        );
+
+    // Fault injection is only meant to test the tool in a staging environment, it must never be
+    // available in a release build
+    #[cfg(feature = "fault-injection")]
+    let migrate_subcommand = migrate_subcommand.arg(
+        Arg::new("simulate-failures").long("simulate-failures")
+        .help("Inject synthetic failures: part-failure-rate=<0..1>,key=<object key>,delay-ms=<ms>")
+        .hide(true)
+        .value_parser(value_parser!(SimulatedFailures))
+    );
+
+    // SQLite is only built with the sqlite-status feature, to keep the default build lean
+    #[cfg(feature = "sqlite-status")]
+    let migrate_subcommand = migrate_subcommand
+        .arg(
+            Arg::new("status-db").long("status-db")
+            .help("SQLite database in which the status (pending, done or failed), attempts and last error of each object to synchronize are recorded")
+            .required(false).value_parser(value_parser!(PathBuf)).requires("execute")
+        )
+        .arg(
+            Arg::new("retry-from-status-db").long("retry-from-status-db")
+            .help("Only synchronize the objects left pending or failed in the --status-db database by the previous runs")
+            .required(false).action(ArgAction::SetTrue).requires("status-db")
+        );
+
+    // The OTLP exporter needs protoc to be built, so it is only built with the otlp feature
+    #[cfg(feature = "otlp")]
+    let migrate_subcommand = migrate_subcommand.arg(
+        Arg::new("otlp-endpoint").long("otlp-endpoint")
+        .help("OpenTelemetry collector endpoint (gRPC) to which traces will be exported, e.g. http://localhost:4317")
+        .required(false)
+    );
+
+    let benchmark_subcommand = Command::new("benchmark")
+        .about("Upload synthetic objects to a scratch bucket with several threads and chunk sizes, then recommend the fastest options")
+        .arg(Arg::new("destination-access-key").long("destination-access-key").help("Destination Cellar access key").required(true))
+        .arg(Arg::new("destination-secret-key").long("destination-secret-key").help("Destination Cellar secret key").required(true))
+        .arg(Arg::new("destination-endpoint").long("destination-endpoint").help("Destination endpoint").default_value("cellar-c2.services.clever-cloud.com"))
+        .arg(Arg::new("scratch-bucket").long("scratch-bucket").help("Existing bucket in which the synthetic objects are uploaded. They are deleted at the end of each run").required(true))
+        .arg(
+            Arg::new("object-size-mb").long("object-size-mb")
+            .help("Size of the synthetic objects")
+            .default_value("64").value_parser(value_parser!(usize))
+        )
+        .arg(
+            Arg::new("total-size-mb").long("total-size-mb")
+            .help("Data uploaded for each threads / chunk size combination")
+            .default_value("1024").value_parser(value_parser!(usize))
+        );
+
+    let create_buckets_subcommand = Command::new("create-buckets")
+        .about("Only create the destination buckets, without synchronizing any object. By default, it will dry run unless --execute is passed")
+        .arg(Arg::new("source-bucket").long("source-bucket").help("Source bucket whose destination bucket will be created. If omitted, the destination buckets of all buckets of the add-on will be created"))
+        .arg(Arg::new("source-access-key").long("source-access-key").help("Source bucket Cellar access key").required_unless_present("source-profile"))
+        .arg(Arg::new("source-secret-key").long("source-secret-key").help("Source bucket Cellar secret key").required_unless_present("source-profile"))
+        .arg(Arg::new("source-profile").long("source-profile").help("Read the source access and secret keys from this profile of the AWS credentials or config file instead")
+            .required(false).conflicts_with_all(["source-access-key", "source-secret-key"])
+        )
+        .arg(Arg::new("source-endpoint").long("source-endpoint").help("Source endpoint of the S3 Bucket"))
+        .arg(Arg::new("source-provider").long("source-provider").help("Provider for source bucket (AWS, Ceph, RiakCS, ..)").required(true))
+        .arg(Arg::new("source-region").long("source-region").help("Region of the source bucket (eu-west-1,..)"))
+        .arg(Arg::new("destination-bucket").long("destination-bucket").help("Destination bucket to create").requires("source-bucket"))
+        .arg(Arg::new("destination-bucket-prefix").long("destination-bucket-prefix").help("Prefix to apply to the destination bucket name"))
+        .arg(
+            Arg::new("destination-bucket-template").long("destination-bucket-template")
+            .help("Template of the destination bucket names, with the {bucket}, {prefix} and {date} (YYYYMMDD, when the run started) placeholders, e.g. {prefix}{bucket}-migrated. Defaults to {prefix}{bucket}")
+            .required(false)
+        )
+        .arg(Arg::new("destination-access-key").long("destination-access-key").help("Destination bucket Cellar access key").required_unless_present("destination-profile"))
+        .arg(Arg::new("destination-secret-key").long("destination-secret-key").help("Destination bucket Cellar secret key").required_unless_present("destination-profile"))
+        .arg(Arg::new("destination-profile").long("destination-profile").help("Read the destination access and secret keys from this profile of the AWS credentials or config file instead")
+            .required(false).conflicts_with_all(["destination-access-key", "destination-secret-key"])
+        )
+        .arg(Arg::new("destination-endpoint").long("destination-endpoint").help("Destination endpoint of the Cellar cluster. Defaults to Paris Cellar cluster")
+            .required(false).default_value("cellar-c2.services.clever-cloud.com")
+        )
+        .arg(
+            Arg::new("destination-region").long("destination-region")
+            .help("Region name the destination requests are signed with, for endpoints checking the region of the signature")
+            .required(false).default_value("default")
+        )
+        .arg(
+            Arg::new("ca-bundle").long("ca-bundle")
+            .help("PEM file with the certificates of the CAs to trust on top of the system ones, for the source and destination endpoints, e.g. for an on-prem gateway using an internal CA")
+            .required(false).value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("danger-accept-invalid-certs").long("danger-accept-invalid-certs")
+            .help("Don't verify the TLS certificates of the source and destination endpoints. Anyone on the network path can then read and modify the objects, only use it for testing")
+            .required(false).action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("execute").long("execute").short('e')
+            .help("Create the missing destination buckets. THIS COMMAND WILL MAKE PRODUCTION CHANGES TO THE DESTINATION.")
+            .action(ArgAction::SetTrue)
+        );
 
     let clap = clap::command!()
         .arg_required_else_help(true)
-        .subcommand(
-            Command::new("migrate")
-            .about("Migrate a bucket to a Cellar cluster. By default, it will dry run unless --execute is passed")
-            .arg(Arg::new("source-bucket").long("source-bucket").help("Source bucket from which files will be copied. If omitted, all buckets of the add-on will be synchronized"))
-            .arg(Arg::new("source-access-key").long("source-access-key").help("Source bucket Cellar access key").required(true))
-            .arg(Arg::new("source-secret-key").long("source-secret-key").help("Source bucket Cellar secret key").required(true))
-            .arg(Arg::new("source-endpoint").long("source-endpoint").help("Source endpoint of the S3 Bucket"))
-            .arg(Arg::new("source-provider").long("source-provider").help("Provider for source bucket (AWS, Ceph, RiakCS, ..)").required(true))
-            .arg(Arg::new("source-region").long("source-region").help("Region of the source bucket (eu-west-1,..)"))
-            .arg(Arg::new("destination-bucket").long("destination-bucket").help("Destination bucket to which the files will be copied. If omitted, the bucket will be created if it doesn't exist"))
-            .arg(Arg::new("destination-bucket-prefix").long("destination-bucket-prefix").help("Prefix to apply to the destination bucket name"))
-            .arg(Arg::new("destination-access-key").long("destination-access-key").help("Destination bucket Cellar access key").required(true))
-            .arg(Arg::new("destination-secret-key").long("destination-secret-key").help("Destination bucket Cellar secret key").required(true))
-            .arg(Arg::new("destination-endpoint").long("destination-endpoint").help("Destination endpoint of the Cellar cluster. Defaults to Paris Cellar cluster")
-                .required(false).default_value("cellar-c2.services.clever-cloud.com")
-            )
-            .arg(
-                Arg::new("threads").long("threads").short('t').help("Number of threads used to synchronize this bucket")
-                .required(false).value_parser(value_parser!(usize))
-            )
-            .arg(
-                Arg::new("multipart-chunk-size-mb").long("multipart-chunk-size-mb")
-                .help("Size of each chunk of multipart upload in Megabytes. Files bigger than this size are automatically uploaded using multipart upload")
-                .required(false).value_parser(value_parser!(usize)).default_value("100")
-            )
-            .arg(
-                Arg::new("execute").long("execute").short('e')
-                .help("Execute the synchronization. THIS COMMAND WILL MAKE PRODUCTION CHANGES TO THE DESTINATION BUCKET.")
-                .action(ArgAction::SetTrue)
-            )
-            .arg(
-                Arg::new("max-keys").long("max-keys").short('m')
-                .help("Define the maximum number of object keys to list when listing the bucket. Lowering this might help listing huge buckets")
-                .required(false).value_parser(value_parser!(usize)).default_value("1000")
-            )
-            /* .arg(
-                Arg::new("delete").long("delete").short('d')
-                .help("Delete extraneous files from destination bucket")
-                .action(ArgAction::SetTrue)
-            )*/
+        .arg(
+            Arg::new("log-format").long("log-format")
+            .help("Format of the logs. With json, each event is a JSON object with its fields and the fields of its spans, e.g. bucket, key and size")
+            .global(true).default_value("text").value_parser(["text", "json"])
         )
+        .subcommand(migrate_subcommand)
+        .subcommand(benchmark_subcommand)
+        .subcommand(create_buckets_subcommand)
         .get_matches();
 
-    match clap.subcommand() {
-        Some(("migrate", migrate_matches)) => migrate_command(migrate_matches).await,
+    let otlp_endpoint = clap
+        .subcommand()
+        .and_then(|(_, matches)| matches.try_get_one::<String>("otlp-endpoint").ok().flatten());
+    // Keep stdout for the copy list only
+    let logs_to_stderr = clap
+        .subcommand()
+        .map(|(_, matches)| matches.try_contains_id("print-copy-list").unwrap_or(false))
+        .unwrap_or(false);
+    // Enabled before the logs so they can be written around the progress bar
+    let progress_bar = clap
+        .subcommand()
+        .and_then(|(_, matches)| matches.try_get_one::<bool>("progress").ok().flatten());
+    if progress_bar == Some(&true) {
+        progress_bar::enable();
+    }
+    let json_logs = clap.get_one::<String>("log-format").map(String::as_str) == Some("json");
+    init_tracing(otlp_endpoint, logs_to_stderr, json_logs)?;
+    // Date of the {date} placeholder of the destination bucket names, the same for all the passes
+    // of --continuous even if they run on another day
+    let started_at = Utc::now();
+
+    let result = match clap.subcommand() {
+        Some(("migrate", migrate_matches)) => {
+            // Shared by the passes of --continuous so the metrics counters never go back
+            let progress = Arc::new(SyncProgress::default());
+            let metrics_server = migrate_matches
+                .get_one::<SocketAddr>("metrics-addr")
+                .map(|addr| MetricsServer::start(*addr, progress.clone()))
+                .transpose()?;
+
+            let result = match migrate_matches.get_one::<u64>("continuous") {
+                Some(interval) => {
+                    continuous_migrate_command(
+                        migrate_matches,
+                        Duration::from_secs(*interval),
+                        &progress,
+                        started_at,
+                    )
+                    .await
+                }
+                None => migrate_command(migrate_matches, &progress, started_at).await,
+            };
+
+            if let Some(metrics_server) = metrics_server {
+                metrics_server.shutdown().await;
+            }
+            result
+        }
+        Some(("benchmark", benchmark_matches)) => benchmark_command(benchmark_matches).await,
+        Some(("create-buckets", create_buckets_matches)) => {
+            create_buckets_command(create_buckets_matches, started_at).await
+        }
         e => unreachable!("Failed to parse subcommand: {:#?}", e),
+    };
+
+    #[cfg(feature = "otlp")]
+    if otlp_endpoint.is_some() {
+        // Flush the spans still waiting in the batch exporter. This call blocks until the exporter
+        // is done so keep it out of the async runtime threads
+        tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider).await?;
+    }
+
+    if let Err(error) = &result {
+        if let Some(exit_code) = exit_code(error) {
+            if exit_code == DESTINATION_UNREACHABLE_EXIT_CODE {
+                event!(Level::ERROR, "Destination is unreachable: {:#}", error);
+            } else {
+                event!(Level::ERROR, "{}", error);
+            }
+            std::process::exit(exit_code);
+        }
+    }
+
+    result
+}
+
+/// Exit code of the errors which aren't configuration errors
+fn exit_code(error: &anyhow::Error) -> Option<i32> {
+    if error.is::<MigrationFailedError>() {
+        Some(PARTIAL_FAILURE_EXIT_CODE)
+    } else if is_destination_unreachable(error) {
+        Some(DESTINATION_UNREACHABLE_EXIT_CODE)
+    } else {
+        None
+    }
+}
+
+/// The bucket listing is the first request sent to the destination
+fn is_destination_unreachable(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<RusotoError<ListBucketsError>>(),
+        Some(RusotoError::HttpDispatch(_))
+    )
+}
+
+/// Each event is a JSON object with its fields and the fields of its spans. The per bucket and
+/// per object spans are at the debug level: they are kept for the events to carry their fields,
+/// without logging their creation and end
+fn json_layer<S>(writer: BoxMakeWriter, env_filter: EnvFilter) -> impl Layer<S>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_subscriber::fmt::layer()
+        .json()
+        .with_current_span(false)
+        .with_span_list(true)
+        .with_writer(writer)
+        .with_filter(env_filter.or(filter::filter_fn(|metadata| {
+            metadata.is_span() && metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+        })))
+}
+
+fn init_tracing(
+    otlp_endpoint: Option<&String>,
+    logs_to_stderr: bool,
+    json_logs: bool,
+) -> anyhow::Result<()> {
+    let env_filter = std::env::var(EnvFilter::DEFAULT_ENV)
+        .map(|_| EnvFilter::from_default_env())
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let writer = if logs_to_stderr {
+        BoxMakeWriter::new(SuspendProgressBar::new(std::io::stderr))
+    } else {
+        BoxMakeWriter::new(SuspendProgressBar::new(TestWriter::default()))
+    };
+
+    let (fmt_layer, json_layer) = if json_logs {
+        (None, Some(json_layer(writer, env_filter)))
+    } else {
+        let fmt_layer = tracing_subscriber::fmt::layer()
+            .with_span_events(FmtSpan::CLOSE | FmtSpan::NEW)
+            .with_writer(writer)
+            .with_filter(env_filter);
+        (Some(fmt_layer), None)
+    };
+
+    // Per object spans are at the debug level, we want them exported to the collector
+    // without having them printed in the console
+    #[cfg(feature = "otlp")]
+    let otlp_layer = match otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint.clone()),
+                )
+                .with_trace_config(opentelemetry::sdk::trace::config().with_resource(
+                    opentelemetry::sdk::Resource::new(vec![opentelemetry::KeyValue::new(
+                        "service.name",
+                        env!("CARGO_PKG_NAME"),
+                    )]),
+                ))
+                .install_batch(opentelemetry::runtime::Tokio)?;
+
+            Some(
+                tracing_opentelemetry::layer()
+                    .with_tracer(tracer)
+                    .with_filter(LevelFilter::DEBUG),
+            )
+        }
+        None => None,
+    };
+    #[cfg(not(feature = "otlp"))]
+    let otlp_layer: Option<tracing_subscriber::layer::Identity> = None;
+
+    let _ = tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(json_layer)
+        .with(otlp_layer)
+        .try_init();
+
+    if let Some(endpoint) = otlp_endpoint {
+        event!(Level::INFO, "Exporting traces to OTLP endpoint {}", endpoint);
+    }
+
+    Ok(())
+}
+
+async fn benchmark_command(params: &ArgMatches) -> anyhow::Result<()> {
+    let run_id = generate_run_id()?;
+    let object_size = params
+        .get_one::<usize>("object-size-mb")
+        .expect("Object size should be a usize")
+        * 1024
+        * 1024;
+    let total_size = params
+        .get_one::<usize>("total-size-mb")
+        .expect("Total size should be a usize")
+        * 1024
+        * 1024;
+
+    benchmark::run_benchmark(BenchmarkConfiguration {
+        run_id,
+        destination_endpoint: params
+            .get_one::<String>("destination-endpoint")
+            .unwrap()
+            .to_string(),
+        destination_access_key: params
+            .get_one::<String>("destination-access-key")
+            .unwrap()
+            .to_string(),
+        destination_secret_key: params
+            .get_one::<String>("destination-secret-key")
+            .unwrap()
+            .to_string(),
+        scratch_bucket: params
+            .get_one::<String>("scratch-bucket")
+            .unwrap()
+            .to_string(),
+        object_size,
+        total_size,
+    })
+    .await
+}
+
+/// Provisions the destination buckets ahead of a migration run later
+async fn create_buckets_command(
+    params: &ArgMatches,
+    started_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let dry_run = params.get_one::<bool>("execute") == Some(&false);
+    configure_tls(params);
+
+    let source_bucket = params.get_one::<String>("source-bucket");
+    let source_endpoint = params
+        .get_one::<String>("source-endpoint")
+        .map(|s| s.to_owned());
+    let source_region = params
+        .get_one::<String>("source-region")
+        .map(|s| s.to_owned());
+    let source_provider = params
+        .get_one::<String>("source-provider")
+        .ok_or("Missing source provider".to_string())
+        .and_then(|s| Providers::try_from(s.as_str()))
+        .unwrap();
+
+    let destination_bucket = params
+        .get_one::<String>("destination-bucket")
+        .map(|s| s.to_string());
+    let destination_bucket_prefix = params
+        .get_one::<String>("destination-bucket-prefix")
+        .map(|b| format!("{}-", b))
+        .unwrap_or_default();
+    let destination_buckets = DestinationBucketNames::new(
+        destination_bucket,
+        destination_bucket_prefix,
+        params
+            .get_one::<String>("destination-bucket-template")
+            .cloned(),
+        started_at,
+    )
+    .unwrap_or_else(|error| {
+        event!(Level::ERROR, "{}", error);
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    });
+    let destination_endpoint = params
+        .get_one::<String>("destination-endpoint")
+        .unwrap()
+        .to_string();
+    let destination_region = params
+        .get_one::<String>("destination-region")
+        .unwrap()
+        .to_string();
+
+    if source_endpoint.is_none() && source_region.is_none() {
+        event!(
+            Level::ERROR,
+            "You have to define either --source-endpoint or --source-region"
+        );
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
     }
+
+    let buckets = match source_bucket {
+        Some(bucket) => vec![bucket.clone()],
+        None => {
+            let (source_access_key, source_secret_key) = keys_or_profile(
+                params,
+                "source-access-key",
+                "source-secret-key",
+                "source-profile",
+            );
+            get_provider(
+                &source_provider,
+                ProviderConf::new(
+                    source_endpoint.clone(),
+                    source_region,
+                    source_access_key,
+                    source_secret_key,
+                    None,
+                ),
+            )
+            .get_buckets()
+            .await?
+        }
+    };
+
+    migrate::check_source_is_not_destination(
+        source_endpoint.as_deref(),
+        &destination_endpoint,
+        &destination_buckets,
+        &buckets,
+    )?;
+
+    let (destination_access_key, destination_secret_key) = keys_or_profile(
+        params,
+        "destination-access-key",
+        "destination-secret-key",
+        "destination-profile",
+    );
+    migrate::create_destination_buckets(
+        destination_endpoint,
+        destination_region,
+        destination_access_key,
+        destination_secret_key,
+        &destination_buckets,
+        &buckets,
+        dry_run,
+    )
+    .await?;
+
+    event!(
+        Level::INFO,
+        "{} destination buckets checked{}",
+        buckets.len(),
+        if dry_run { " (dry run)" } else { "" }
+    );
+
+    Ok(())
 }
 
-#[instrument(skip_all, level = "debug")]
-async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
+/// Keeps the destination nearly up to date during a cutover window: synchronizes again at each
+/// interval until the cutover file is created, then runs a last synchronization
+async fn continuous_migrate_command(
+    params: &ArgMatches,
+    interval: Duration,
+    progress: &Arc<SyncProgress>,
+    started_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let cutover_file = params
+        .get_one::<PathBuf>("cutover-file")
+        .unwrap_or_else(|| {
+            event!(Level::ERROR, "--continuous requires --cutover-file");
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        });
+    let mut pass = 1;
+
+    loop {
+        // Objects written before the cutover file was created are all copied by this pass
+        let last_pass = cutover_file.exists();
+
+        event!(
+            Level::INFO,
+            "Continuous | Starting synchronization pass {}",
+            pass
+        );
+        let result = migrate_command(params, progress, started_at).await;
+        if let Err(error) = &result {
+            event!(
+                Level::ERROR,
+                "Continuous | Synchronization pass {} failed: {:?}",
+                pass,
+                error
+            );
+        }
+
+        // The exit code is the one of the last pass
+        if last_pass {
+            event!(
+                Level::INFO,
+                "Continuous | Cutover file {} found, last synchronization pass done",
+                cutover_file.display()
+            );
+            return result;
+        }
+
+        event!(
+            Level::INFO,
+            "Continuous | Next synchronization pass in {:?}. Create {} to run the last one",
+            interval,
+            cutover_file.display()
+        );
+        tokio::time::sleep(interval).await;
+        pass += 1;
+    }
+}
+
+#[instrument(skip_all, level = "info", fields(run_id))]
+async fn migrate_command(
+    params: &ArgMatches,
+    progress: &Arc<SyncProgress>,
+    started_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let run_id = match params.get_one::<String>("run-id") {
+        Some(run_id) => run_id.clone(),
+        None => generate_run_id()?,
+    };
+    tracing::Span::current().record("run_id", run_id.as_str());
+    event!(Level::INFO, "Starting migration run {}", run_id);
+
     let dry_run = params.get_one::<bool>("execute") == Some(&false);
+    configure_tls(params);
+    let print_copy_list = params
+        .get_one::<String>("print-copy-list")
+        .map(|format| CopyListFormat::try_from(format.as_str()).unwrap());
 
     if dry_run {
         event!(Level::WARN, "Running in dry run mode. No changes will be made. If you want to synchronize for real, use --execute");
     }
 
+    let write_plan_path = params.get_one::<PathBuf>("write-plan");
+    let plan_writer = write_plan_path
+        .map(|path| PlanWriter::create(path).map(Arc::new))
+        .transpose()?;
+    let migration_plan = params
+        .get_one::<PathBuf>("apply-plan")
+        .map(|path| MigrationPlan::load(path))
+        .transpose()?;
+
     let sync_threads: usize = *params
         .get_one::<usize>("threads")
         .unwrap_or(&num_cpus::get());
+    let bucket_threads = params
+        .get_many::<String>("bucket-threads")
+        .unwrap_or_default()
+        .map(|value| parse_bucket_threads(value, sync_threads))
+        .collect::<Result<HashMap<String, usize>, String>>()
+        .unwrap_or_else(|error| {
+            event!(Level::ERROR, "{}", error);
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        });
+    let verify_threads = params.get_one::<usize>("verify-threads").copied();
+    let delete_threads = params.get_one::<usize>("delete-threads").copied();
+    for (name, threads) in [
+        ("--verify-threads", verify_threads),
+        ("--delete-threads", delete_threads),
+    ] {
+        if threads == Some(0) {
+            event!(Level::ERROR, "{} should be greater than 0", name);
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        }
+    }
     let multipart_upload_chunk_size: usize = params
         .get_one::<usize>("multipart-chunk-size-mb")
         .expect("Multipart chunk size should be a usize")
         * 1024
         * 1024;
+    let multipart_part_size: usize = params
+        .get_one::<usize>("multipart-part-size-mb")
+        .map(|part_size| part_size * 1024 * 1024)
+        .unwrap_or(multipart_upload_chunk_size);
+
+    if let Err(error) = migrate::check_multipart_part_size(multipart_part_size) {
+        event!(Level::ERROR, "{}", error);
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+
+    let multipart_threshold: u64 = params
+        .get_one::<u64>("multipart-threshold-mb")
+        .map(|threshold| threshold * 1024 * 1024)
+        .unwrap_or(multipart_upload_chunk_size as u64);
+
+    if let Err(error) = migrate::check_multipart_threshold(multipart_threshold) {
+        event!(Level::ERROR, "{}", error);
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+
     let max_keys: usize = *params
         .get_one("max-keys")
         .expect("max-keys should be a usize");
+    let destination_max_keys = match *params
+        .get_one::<usize>("destination-max-keys")
+        .expect("destination-max-keys should be a usize")
+    {
+        0 => {
+            event!(Level::ERROR, "--destination-max-keys should be greater than 0");
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        }
+        max_keys if max_keys > MAX_FETCH_KEYS => {
+            event!(
+                Level::WARN,
+                "--destination-max-keys {} is above the S3 maximum, {} keys will be listed per request",
+                max_keys,
+                MAX_FETCH_KEYS
+            );
+            MAX_FETCH_KEYS
+        }
+        max_keys => max_keys,
+    };
+    let max_concurrent_multipart: Option<usize> =
+        params.get_one::<usize>("max-concurrent-multipart").copied();
+
+    if max_concurrent_multipart == Some(0) {
+        event!(
+            Level::ERROR,
+            "--max-concurrent-multipart should be greater than 0"
+        );
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+    let resume_multipart_uploads =
+        params.get_one::<bool>("resume-multipart-uploads") == Some(&true);
+
+    let small_object_threshold: Option<usize> = params
+        .get_one::<usize>("small-object-threshold-kb")
+        .map(|threshold| threshold * 1024);
+    let small_object_threads: usize = match small_object_threshold {
+        Some(_) => *params
+            .get_one::<usize>("small-object-threads")
+            .unwrap_or(&(sync_threads * 4)),
+        None => 0,
+    };
+
+    if matches!(small_object_threshold, Some(threshold) if threshold as u64 > multipart_threshold) {
+        event!(
+            Level::ERROR,
+            "--small-object-threshold-kb should be lower than the multipart threshold"
+        );
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+
+    let priority = params
+        .get_one::<String>("priority-by")
+        .ok_or("Missing priority".to_string())
+        .and_then(|s| ObjectPriority::try_from(s.as_str()))
+        .unwrap();
+
+    let compare = params
+        .get_one::<String>("compare-mode")
+        .ok_or("Missing compare mode".to_string())
+        .and_then(|s| CompareStrategy::try_from(s.as_str()))
+        .unwrap();
+
+    let etag_filter = params
+        .get_one::<String>("etag-filter")
+        .ok_or("Missing ETag filter".to_string())
+        .and_then(|s| ETagFilter::try_from(s.as_str()))
+        .unwrap();
+
+    let empty_source = params
+        .get_one::<String>("empty-source")
+        .ok_or("Missing empty source policy".to_string())
+        .and_then(|s| EmptySourcePolicy::try_from(s.as_str()))
+        .unwrap();
+
+    let source_requester_pays = params.get_one::<bool>("source-requester-pays") == Some(&true);
+
+    let unsafe_keys = params
+        .get_one::<String>("unsafe-keys")
+        .ok_or("Missing unsafe keys policy".to_string())
+        .and_then(|s| UnsafeKeysPolicy::try_from(s.as_str()))
+        .unwrap();
+
+    let trust_size_for_multipart =
+        params.get_one::<bool>("trust-size-for-multipart") == Some(&true);
+
+    let recopy_storage_class_changes =
+        params.get_one::<bool>("recopy-storage-class-changes") == Some(&true);
+    let skip_existing_fast = params.get_one::<bool>("skip-existing-fast") == Some(&true);
+
+    let storage_class = params
+        .get_one::<String>("storage-class")
+        .map(|storage_class| parse_storage_class(storage_class.as_str()))
+        .transpose()
+        .unwrap_or_else(|error| {
+            event!(Level::ERROR, "{}", error);
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        });
+    let storage_class_rules = params
+        .get_many::<String>("storage-class-rule")
+        .into_iter()
+        .flatten()
+        .map(|rule| StorageClassRule::try_from(rule.as_str()))
+        .collect::<Result<Vec<StorageClassRule>, String>>()
+        .unwrap_or_else(|error| {
+            event!(Level::ERROR, "{}", error);
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        });
+
+    #[cfg(feature = "fault-injection")]
+    let simulated_failures = params.get_one::<SimulatedFailures>("simulate-failures").cloned();
+    #[cfg(not(feature = "fault-injection"))]
+    let simulated_failures: Option<SimulatedFailures> = None;
+
+    if let Some(simulated_failures) = &simulated_failures {
+        event!(
+            Level::WARN,
+            "SIMULATED FAILURES ARE ENABLED. THIS BUILD MUST NOT BE USED FOR A REAL MIGRATION: {:?}",
+            simulated_failures
+        );
+    }
+
+    let acl_rules = params
+        .get_many::<String>("acl-rule")
+        .map(|rules| {
+            let rules = rules
+                .map(|rule| AclRule::try_from(rule.as_str()))
+                .collect::<Result<Vec<AclRule>, String>>()?;
+            let default = params
+                .get_one::<String>("acl-rule-default")
+                .map(|acl| DestinationAcl::try_from(acl.as_str()))
+                .transpose()?;
+
+            Ok::<AclRules, String>(AclRules { rules, default })
+        })
+        .transpose()
+        .unwrap_or_else(|error| {
+            event!(Level::ERROR, "{}", error);
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        });
+
+    let preserve_acl = params.get_one::<bool>("preserve-acl") == Some(&true);
+    let auto_create_destination_bucket =
+        params.get_one::<bool>("auto-create-destination-bucket") == Some(&true);
+    let preserve_tags = params.get_one::<bool>("preserve-tags") == Some(&true);
+    let infer_content_type = params.get_one::<bool>("infer-content-type") == Some(&true);
+
+    let verify = params.get_one::<bool>("verify-uploads") == Some(&true);
+
+    let verification_report = if params.get_one::<bool>("verify-and-repair") == Some(&true) {
+        Some(Arc::new(VerificationReport::default()))
+    } else {
+        None
+    };
+
+    let timing_trace = params
+        .get_one::<PathBuf>("timing-trace")
+        .map(|path| TimingTrace::create(path).map(Arc::new))
+        .transpose()?;
+
+    let slowest_objects = params.get_one::<usize>("slowest-objects").copied();
+    if slowest_objects == Some(0) {
+        event!(Level::ERROR, "--slowest-objects should be greater than 0");
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+
+    let max_retries = *params.get_one::<usize>("max-retries").unwrap();
+    let retry_base_delay =
+        Duration::from_millis(*params.get_one::<u64>("retry-base-delay-ms").unwrap());
+
+    let timeout_secs = |option: &str| {
+        params.get_one::<u64>(option).map(|secs| {
+            if *secs == 0 {
+                event!(Level::ERROR, "--{} should be greater than 0", option);
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+            }
+            Duration::from_secs(*secs)
+        })
+    };
+    let connect_timeout = timeout_secs("connect-timeout-secs");
+    let request_timeout = timeout_secs("request-timeout-secs");
+
+    let circuit_breaker = params
+        .get_one::<f64>("circuit-breaker-threshold")
+        .map(|threshold| {
+            if !(0.0..1.0).contains(threshold) {
+                event!(
+                    Level::ERROR,
+                    "--circuit-breaker-threshold should be between 0 and 1"
+                );
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+            }
+
+            Arc::new(CircuitBreaker::new(CircuitBreakerConfiguration {
+                threshold: *threshold,
+                window: Duration::from_secs(
+                    *params.get_one::<u64>("circuit-breaker-window").unwrap(),
+                ),
+                cool_down: Duration::from_secs(
+                    *params.get_one::<u64>("circuit-breaker-cool-down").unwrap(),
+                ),
+            }))
+        });
+
+    let bucket_concurrency = *params.get_one::<usize>("bucket-concurrency").unwrap();
+    if bucket_concurrency == 0 {
+        event!(Level::ERROR, "--bucket-concurrency should be greater than 0");
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+
+    let run_window = params
+        .get_one::<String>("run-window")
+        .map(|window| RunWindow::try_from(window.as_str()).map(Arc::new))
+        .transpose()
+        .unwrap_or_else(|error| {
+            event!(Level::ERROR, "{}", error);
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        });
+
+    let max_bandwidth = params
+        .get_one::<u64>("max-bandwidth-bytes-per-sec")
+        .copied();
+    if max_bandwidth == Some(0) {
+        event!(
+            Level::ERROR,
+            "--max-bandwidth-bytes-per-sec should be greater than 0"
+        );
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+    let bandwidth_limit = max_bandwidth.map(|limit| Arc::new(BandwidthLimit::new(limit)));
+    let bucket_bandwidth = params
+        .get_many::<String>("bucket-max-bandwidth-bytes-per-sec")
+        .unwrap_or_default()
+        .map(|value| parse_bucket_bandwidth(value, max_bandwidth))
+        .collect::<Result<HashMap<String, u64>, String>>()
+        .unwrap_or_else(|error| {
+            event!(Level::ERROR, "{}", error);
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        });
+
+    let heartbeat_interval = params.get_one::<u64>("heartbeat").copied();
+
+    if heartbeat_interval == Some(0) {
+        event!(Level::ERROR, "--heartbeat should be greater than 0");
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+
+    let throughput_report_path = params.get_one::<PathBuf>("throughput-report");
+    let report_json_path = params.get_one::<PathBuf>("report-json");
+    let json_report = report_json_path.map(|_| Arc::new(MigrationReport::default()));
+    let throughput_interval = *params.get_one::<u64>("throughput-interval").unwrap();
+
+    if throughput_report_path.is_some() && throughput_interval == 0 {
+        event!(Level::ERROR, "--throughput-interval should be greater than 0");
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+
+    let source_etag_cache = params.get_one::<PathBuf>("source-etag-cache");
+    let checksum_manifest = params.get_one::<PathBuf>("checksum-manifest");
+    let verify_checksum = params.get_one::<bool>("verify-checksum") == Some(&true);
+    let state_file = params.get_one::<PathBuf>("state-file");
+    let content_dedup = (params.get_one::<bool>("content-dedup") == Some(&true))
+        .then(|| Arc::new(ContentDedup::default()));
+    #[cfg(feature = "sqlite-status")]
+    let status_db = params.get_one::<PathBuf>("status-db");
+    #[cfg(feature = "sqlite-status")]
+    let retry_from_status_db = params.get_one::<bool>("retry-from-status-db") == Some(&true);
+
+    let resume_token = params
+        .get_one::<String>("resume-token")
+        .map(|token| ResumeToken::try_from(token.as_str()))
+        .transpose()
+        .unwrap_or_else(|error| {
+            event!(Level::ERROR, "{}", error);
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        });
+
+    let force = params.get_one::<bool>("force") == Some(&true);
+    let (max_bucket_objects, max_bucket_bytes) = if force {
+        (None, None)
+    } else {
+        (
+            params.get_one::<usize>("max-bucket-objects").copied(),
+            params.get_one::<u64>("max-bucket-bytes").copied(),
+        )
+    };
+
+    let min_object_size = params.get_one::<u64>("min-object-size").copied();
+    let max_object_size = params.get_one::<u64>("max-object-size").copied();
+    if let (Some(min), Some(max)) = (min_object_size, max_object_size) {
+        if min > max {
+            event!(
+                Level::ERROR,
+                "--min-object-size should not be greater than --max-object-size"
+            );
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        }
+    }
+
+    let dump_listings = params.get_one::<PathBuf>("dump-listings");
+    if let Some(directory) = dump_listings {
+        std::fs::create_dir_all(directory)?;
+    }
+
+    let bucket_digest = params.get_one::<bool>("bucket-digest") == Some(&true);
+    let mut bucket_digest_mismatch = false;
+
+    let fail_fast = params.get_one::<bool>("fail-fast") == Some(&true);
+
+    let modified_date = |option: &str| {
+        params.get_one::<String>(option).map(|value| {
+            parse_rfc3339(value).unwrap_or_else(|error| {
+                event!(Level::ERROR, "--{}: {}", option, error);
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+            })
+        })
+    };
+    let modified_since = modified_date("modified-since");
+    let modified_before = modified_date("modified-before");
+    let modified_filter = if modified_since.is_some() || modified_before.is_some() {
+        let unreliable = params
+            .get_one::<String>("unreliable-last-modified")
+            .ok_or("Missing unreliable last modified policy".to_string())
+            .and_then(|s| UnreliableLastModifiedPolicy::try_from(s.as_str()))
+            .unwrap();
+        Some(
+            ModifiedFilter::new(modified_since, modified_before, unreliable).unwrap_or_else(
+                |error| {
+                    event!(Level::ERROR, "{}", error);
+                    std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+                },
+            ),
+        )
+    } else {
+        None
+    };
+
+    let metadata_filter = match (
+        params.get_one::<String>("metadata-allowlist"),
+        params.get_one::<String>("metadata-denylist"),
+    ) {
+        (Some(fields), _) => Some(MetadataFilter::parse_fields(fields).map(MetadataFilter::Allow)),
+        (None, Some(fields)) => {
+            Some(MetadataFilter::parse_fields(fields).map(MetadataFilter::Deny))
+        }
+        (None, None) => None,
+    }
+    .transpose()
+    .unwrap_or_else(|error| {
+        event!(Level::ERROR, "{}", error);
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    });
+
+    if let Some(metadata_filter) = &metadata_filter {
+        event!(
+            Level::INFO,
+            "Only these metadata fields will be copied: {}",
+            metadata_filter.effective_fields().join(", ")
+        );
+    }
+
+    let record_source_bucket = params.get_one::<bool>("record-source-bucket") == Some(&true);
+
+    let encryption_mirror = if params.get_one::<bool>("mirror-encryption") == Some(&true) {
+        let kms_keys = params
+            .get_many::<String>("kms-key-map")
+            .map(|mappings| {
+                mappings
+                    .map(|mapping| EncryptionMirror::parse_kms_key_mapping(mapping))
+                    .collect::<Result<HashMap<String, String>, String>>()
+            })
+            .transpose()
+            .unwrap_or_else(|error| {
+                event!(Level::ERROR, "{}", error);
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+            })
+            .unwrap_or_default();
+        let default_kms_key = params.get_one::<String>("default-kms-key").cloned();
+
+        Some(Arc::new(EncryptionMirror::new(kms_keys, default_kms_key)))
+    } else {
+        None
+    };
+
+    let server_side_encryption = params.get_one::<String>("server-side-encryption").cloned();
+    let sse_kms_key_id = params.get_one::<String>("sse-kms-key-id").cloned();
+
+    if let Err(error) = validate_destination_encryption(
+        server_side_encryption.as_deref(),
+        sse_kms_key_id.as_deref(),
+    ) {
+        event!(Level::ERROR, "{}", error);
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+
+    if encryption_mirror.is_some() {
+        event!(Level::INFO, "Destination encryption: mirrored from the source objects");
+    } else {
+        match (&server_side_encryption, &sse_kms_key_id) {
+            (Some(algorithm), Some(key_id)) => event!(
+                Level::INFO,
+                "Destination encryption: {} with KMS key {}",
+                algorithm,
+                key_id
+            ),
+            (Some(algorithm), None) => {
+                event!(Level::INFO, "Destination encryption: {}", algorithm)
+            }
+            (None, _) => event!(Level::INFO, "Destination encryption: none"),
+        }
+    }
+
+    let truncate_bytes = params.get_one::<u64>("truncate-bytes").copied();
+
+    if let Some(truncate_bytes) = truncate_bytes {
+        if truncate_bytes == 0 {
+            event!(Level::ERROR, "--truncate-bytes should be greater than 0");
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        }
+
+        event!(
+            Level::WARN,
+            "OBJECTS WILL BE TRUNCATED TO {} BYTES. THIS RUN MUST NOT BE USED FOR A REAL MIGRATION",
+            truncate_bytes
+        );
+    }
+
+    let delete_destination_files = params.get_one::<bool>("delete-extraneous") == Some(&true);
+
+    let source_bucket: Option<String> = params
+        .get_one("source-bucket")
+        .map(|s: &String| s.to_owned());
+    let include_prefix = params.get_one::<String>("prefix").cloned();
+
+    let include_regex = params.get_one::<String>("include-regex");
+    let exclude_regex = params.get_one::<String>("exclude-regex");
+    let key_filter = if include_regex.is_some() || exclude_regex.is_some() {
+        match KeyFilter::new(
+            include_regex.map(String::as_str),
+            exclude_regex.map(String::as_str),
+        ) {
+            Ok(key_filter) => Some(Arc::new(key_filter)),
+            Err(error) => {
+                event!(Level::ERROR, "{}", error);
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+            }
+        }
+    } else {
+        None
+    };
 
-    //let delete_destination_files = params.get_one::<bool>("delete") == Some(&true);
-    let delete_destination_files = false;
+    let strip_prefix = params.get_one::<String>("strip-prefix").cloned();
+    let add_prefix = params.get_one::<String>("add-prefix").cloned();
+    let key_transform = if strip_prefix.is_some() || add_prefix.is_some() {
+        let unmatched_keys = params
+            .get_one::<String>("unmatched-keys")
+            .ok_or("Missing unmatched keys policy".to_string())
+            .and_then(|s| UnmatchedKeysPolicy::try_from(s.as_str()))
+            .unwrap_or_else(|error| {
+                event!(Level::ERROR, "{}", error);
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+            });
 
-    let source_bucket: Option<String> = params
-        .get_one("source-bucket")
-        .map(|s: &String| s.to_owned());
-    let source_access_key: String = params
-        .get_one::<String>("source-access-key")
-        .unwrap()
-        .to_string();
-    let source_secret_key: String = params
-        .get_one::<String>("source-secret-key")
-        .unwrap()
-        .to_string();
+        Some(KeyTransform {
+            strip_prefix,
+            add_prefix,
+            unmatched_keys,
+        })
+    } else {
+        None
+    };
+
+    let (source_access_key, source_secret_key) = keys_or_profile(
+        params,
+        "source-access-key",
+        "source-secret-key",
+        "source-profile",
+    );
     let source_endpoint = params
         .get_one::<String>("source-endpoint")
         .map(|s| s.to_owned());
@@ -130,6 +1670,20 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
         .and_then(|s| Providers::try_from(s.as_str()))
         .unwrap();
 
+    if params.get_one::<bool>("trace-requests") == Some(&true) {
+        enable_trace_requests();
+        event!(
+            Level::WARN,
+            "Requests are traced and not sent, nothing will be migrated"
+        );
+        if matches!(source_provider, Providers::RiakCS) {
+            event!(
+                Level::WARN,
+                "RiakCS requests aren't traced, the source buckets will still be listed"
+            );
+        }
+    }
+
     let destination_bucket = params
         .get_one::<String>("destination-bucket")
         .map(|s| s.as_str().to_string());
@@ -137,22 +1691,36 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
         .get_one::<String>("destination-bucket-prefix")
         .map(|b| format!("{}-", b))
         .unwrap_or_default();
-    let destination_access_key = params
-        .get_one::<String>("destination-access-key")
-        .unwrap()
-        .to_string();
-    let destination_secret_key = params
-        .get_one::<String>("destination-secret-key")
-        .unwrap()
-        .to_string();
+    let destination_buckets = DestinationBucketNames::new(
+        destination_bucket.clone(),
+        destination_bucket_prefix,
+        params
+            .get_one::<String>("destination-bucket-template")
+            .cloned(),
+        started_at,
+    )
+    .unwrap_or_else(|error| {
+        event!(Level::ERROR, "{}", error);
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    });
+    let (destination_access_key, destination_secret_key) = keys_or_profile(
+        params,
+        "destination-access-key",
+        "destination-secret-key",
+        "destination-profile",
+    );
     let destination_endpoint = params
         .get_one::<String>("destination-endpoint")
         .unwrap()
         .to_string();
+    let destination_region = params
+        .get_one::<String>("destination-region")
+        .unwrap()
+        .to_string();
 
     if source_bucket.is_none() && destination_bucket.is_some() {
         event!(Level::ERROR, "You can't give a destination bucket without a source bucket. Please specify the --source-bucket option");
-        std::process::exit(1);
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
     }
 
     match (&source_endpoint, &source_region) {
@@ -161,7 +1729,7 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
                 Level::ERROR,
                 "You have to define either --source-endpoint or --source-region"
             );
-            std::process::exit(1);
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
         }
         (None, Some(region)) => {
             if Region::from_str(region).is_err() {
@@ -169,7 +1737,7 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
                     Level::ERROR,
                     "Failed to parse given region to --source-region"
                 );
-                std::process::exit(1);
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
             }
         }
         (Some(_), None) => {
@@ -178,7 +1746,7 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
                     Level::ERROR,
                     "For source-provider aws-s3, you need to specify --source-region as well"
                 );
-                std::process::exit(1);
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
             }
         }
         _ => {}
@@ -194,7 +1762,20 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
         None,
     );
 
-    let buckets_to_migrate = if let Some(bucket) = source_bucket.as_ref() {
+    let buckets_to_migrate = if let Some(plan) = &migration_plan {
+        let buckets = plan
+            .buckets()
+            .iter()
+            .filter(|bucket| source_bucket.is_none() || source_bucket.as_ref() == Some(*bucket))
+            .cloned()
+            .collect::<Vec<String>>();
+        event!(
+            Level::INFO,
+            "The {} buckets of the plan will be migrated",
+            buckets.len()
+        );
+        buckets
+    } else if let Some(bucket) = source_bucket.as_ref() {
         event!(Level::INFO, "Only bucket {} will be migrated", bucket);
         vec![bucket.clone()]
     } else {
@@ -208,14 +1789,24 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
             .await?
     };
 
+    if let Err(error) = migrate::check_source_is_not_destination(
+        source_endpoint.as_deref(),
+        &destination_endpoint,
+        &destination_buckets,
+        &buckets_to_migrate,
+    ) {
+        event!(Level::ERROR, "{}. Aborting now.", error);
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+
     // First make sure the destination buckets exist / can be created
     // If not, exit now
     if let Err(error) = migrate::create_destination_buckets(
         destination_endpoint.clone(),
+        destination_region.clone(),
         destination_access_key.clone(),
         destination_secret_key.clone(),
-        destination_bucket.clone(),
-        destination_bucket_prefix.clone(),
+        &destination_buckets,
         &buckets_to_migrate,
         dry_run,
     )
@@ -226,37 +1817,49 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
             "Error while creating destination buckets. Error = {:?}. Aborting now.",
             error
         );
-        std::process::exit(1);
+        if is_destination_unreachable(&error) {
+            std::process::exit(DESTINATION_UNREACHABLE_EXIT_CODE);
+        }
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+
+    if !dry_run {
+        if let Err(error) = migrate::check_destination_write_permissions(
+            destination_endpoint.clone(),
+            destination_region.clone(),
+            destination_access_key.clone(),
+            destination_secret_key.clone(),
+            &destination_buckets,
+            &buckets_to_migrate,
+            &run_id,
+        )
+        .await
+        {
+            event!(Level::ERROR, "{}. Aborting now.", error);
+            std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+        }
     }
 
-    let mut migration_results = Vec::with_capacity(buckets_to_migrate.len());
+    let mut bucket_migrations = Vec::with_capacity(buckets_to_migrate.len());
+
+    let heartbeat = heartbeat_interval
+        .map(|interval| spawn_heartbeat(Duration::from_secs(interval), progress.clone()));
+    let throughput_report = throughput_report_path.map(|_| {
+        Arc::new(ThroughputReport::new(
+            Duration::from_secs(throughput_interval),
+            progress.bytes_done(),
+        ))
+    });
+    let throughput_sampler = throughput_report
+        .as_ref()
+        .map(|report| report.spawn_sampler(progress.clone()));
+    let progress_bar = spawn_progress_bar(progress.clone());
 
     for bucket in &buckets_to_migrate {
-        if dry_run {
-            event!(
-                Level::INFO,
-                "DRY-RUN | Bucket {} | Starting listing of files that need to be synchronized",
-                bucket
-            );
-        } else {
-            event!(
-                Level::INFO,
-                "Bucket {} | Starting migration of bucket",
-                bucket
-            );
+        if source_bucket.is_some() && buckets_to_migrate.len() > 1 {
+            panic!("We can't have a source bucket specified but with multiple buckets to migrate");
         }
-
-        let destination_bucket = if source_bucket.is_some() {
-            if buckets_to_migrate.len() == 1 {
-                destination_bucket.as_ref().unwrap_or(bucket)
-            } else {
-                panic!(
-                    "We can't have a source bucket specified but with multiple buckets to migrate"
-                );
-            }
-        } else {
-            bucket
-        };
+        let destination_bucket = destination_buckets.name(bucket);
 
         event!(
             Level::DEBUG,
@@ -265,23 +1868,207 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
             destination_bucket
         );
 
-        let bucket_migration = BucketMigrationConfiguration {
+        let bucket_source_etag_cache = source_etag_cache
+            .map(|path| SourceETagCache::open(path, bucket).map(Arc::new))
+            .transpose()?;
+        let bucket_checksum_manifest = checksum_manifest
+            .map(|path| ChecksumManifest::open(path, bucket).map(Arc::new))
+            .transpose()?;
+        let bucket_state_file = state_file
+            .map(|path| StateFile::open(path, bucket).map(Arc::new))
+            .transpose()?;
+        if let Some(state_file) = &bucket_state_file {
+            event!(
+                Level::INFO,
+                "Bucket {} | {} objects already synchronized according to the state file",
+                bucket,
+                state_file.completed_count()
+            );
+        }
+        #[cfg(feature = "sqlite-status")]
+        let bucket_status_db = status_db
+            .map(|path| ObjectStatusDb::open(path, bucket, retry_from_status_db).map(Arc::new))
+            .transpose()?;
+
+        let bucket_sync_threads = bucket_threads.get(bucket).copied().unwrap_or(sync_threads);
+        // The bucket limit comes on top of the global one, which is shared with the other buckets
+        let bucket_bandwidth_limits = bandwidth_limit
+            .iter()
+            .cloned()
+            .chain(
+                bucket_bandwidth
+                    .get(bucket)
+                    .map(|limit| Arc::new(BandwidthLimit::new(*limit))),
+            )
+            .collect();
+        let mut bucket_migration = BucketMigrationConfiguration {
+            run_id: run_id.clone(),
             source_bucket: bucket.clone(),
             source_access_key: source_access_key.clone(),
             source_secret_key: source_secret_key.clone(),
             source_endpoint: source_endpoint.clone(),
             source_region: source_region.clone(),
             source_provider: source_provider.clone(),
-            destination_bucket: format!("{}{}", destination_bucket_prefix, destination_bucket),
+            destination_bucket,
             destination_access_key: destination_access_key.clone(),
             destination_secret_key: destination_secret_key.clone(),
             destination_endpoint: destination_endpoint.clone(),
+            destination_region: destination_region.clone(),
+            auto_create_destination_bucket,
             delete_destination_files,
+            include_prefix: include_prefix.clone(),
+            key_filter: key_filter.clone(),
+            key_transform: key_transform.clone(),
             max_keys,
-            chunk_size: multipart_upload_chunk_size,
-            sync_threads,
+            min_object_size,
+            max_object_size,
+            destination_max_keys,
+            multipart_threshold,
+            multipart_part_size,
+            sync_threads: bucket_sync_threads,
+            verify_threads: verify_threads.unwrap_or(bucket_sync_threads),
+            delete_threads: delete_threads.unwrap_or(bucket_sync_threads),
+            max_concurrent_multipart,
+            multipart_budget: None,
+            resume_multipart_uploads,
+            multipart_uploads: Default::default(),
+            small_object_threshold,
+            small_object_threads,
+            priority,
+            source_etag_cache: bucket_source_etag_cache,
+            checksum_manifest: bucket_checksum_manifest,
+            state_file: bucket_state_file,
+            object_report: json_report.as_ref().map(|report| report.bucket(bucket)),
+            content_dedup: content_dedup.clone(),
+            #[cfg(feature = "sqlite-status")]
+            status_db: bucket_status_db,
+            acl_rules: acl_rules.clone(),
+            preserve_acl,
+            preserve_tags,
+            infer_content_type,
+            object_acls_disabled: false,
+            progress: progress.clone(),
+            verification_report: verification_report.clone(),
+            verify,
+            compare: compare.clone(),
+            etag_filter: etag_filter.clone(),
+            unsafe_keys: unsafe_keys.clone(),
+            trust_size_for_multipart,
+            recopy_storage_class_changes,
+            skip_existing_fast,
+            storage_class: storage_class.clone(),
+            storage_class_rules: storage_class_rules.clone(),
+            simulated_failures: simulated_failures.clone(),
+            truncate_bytes,
+            record_source_bucket,
+            metadata_filter: metadata_filter.clone(),
+            modified_filter: modified_filter.clone(),
+            encryption_mirror: encryption_mirror.clone(),
+            server_side_encryption: server_side_encryption.clone(),
+            sse_kms_key_id: sse_kms_key_id.clone(),
+            timing_trace: timing_trace.clone(),
+            object_timings: slowest_objects.map(|capacity| Arc::new(ObjectTimings::new(capacity))),
+            fail_fast,
+            circuit_breaker: circuit_breaker.clone(),
+            run_window: run_window.clone(),
+            bandwidth_limits: bucket_bandwidth_limits,
+            verify_checksum,
+            thread_budget: None,
+            max_retries,
+            retry_base_delay,
+            connect_timeout,
+            request_timeout,
+            max_bucket_objects,
+            max_bucket_bytes,
+            empty_source,
+            source_requester_pays,
+            print_copy_list: print_copy_list.clone(),
+            plan_writer: plan_writer.clone(),
+            planned: None,
+            start_after: resume_token
+                .as_ref()
+                .map(|token| token.start_after.clone()),
             dry_run,
         };
+        bucket_migration.object_acls_disabled =
+            migrate::check_destination_object_acls(&bucket_migration).await;
+
+        if let Some(plan) = &migration_plan {
+            let planned = plan
+                .bucket(bucket)
+                .expect("Bucket should be part of the plan");
+            if planned.destination_bucket != bucket_migration.destination_bucket {
+                event!(
+                    Level::ERROR,
+                    "Bucket {} | The plan copies it to destination bucket {}, not {}. Use the destination options used to write the plan",
+                    bucket,
+                    planned.destination_bucket,
+                    bucket_migration.destination_bucket
+                );
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+            }
+            bucket_migration.planned = Some(planned);
+        }
+
+        if let Some(resume_token) = &resume_token {
+            if let Err(error) = resume_token.validate(&bucket_migration) {
+                event!(
+                    Level::ERROR,
+                    "Bucket {} | {}. Run without --resume-token to start over",
+                    bucket,
+                    error
+                );
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+            }
+
+            event!(
+                Level::INFO,
+                "Bucket {} | Resuming synchronization after object {}",
+                bucket,
+                resume_token.start_after
+            );
+        }
+
+        if let Some(directory) = dump_listings {
+            if let Err(error) = listing_dump::dump_listings(&bucket_migration, directory).await {
+                event!(
+                    Level::ERROR,
+                    "Bucket {} | Failed to dump the listings: {:?}",
+                    bucket,
+                    error
+                );
+            }
+        }
+
+        if bucket_digest {
+            match bucket_digest::compare_bucket_digests(&bucket_migration).await {
+                Ok(differing_keys) if differing_keys.is_empty() => {
+                    event!(Level::INFO, "Bucket {} | Buckets match", bucket);
+                }
+                Ok(differing_keys) => {
+                    bucket_digest_mismatch = true;
+                    event!(
+                        Level::ERROR,
+                        "Bucket {} | Buckets don't match, {} keys differ",
+                        bucket,
+                        differing_keys.len()
+                    );
+                    for key in differing_keys {
+                        event!(Level::ERROR, "Bucket {} | Differing key: {}", bucket, key);
+                    }
+                }
+                Err(error) => {
+                    bucket_digest_mismatch = true;
+                    event!(
+                        Level::ERROR,
+                        "Bucket {} | Failed to compute the bucket digests: {:?}",
+                        bucket,
+                        error
+                    );
+                }
+            }
+            continue;
+        }
 
         event!(
             Level::TRACE,
@@ -290,35 +2077,115 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
             bucket_migration
         );
 
-        let migration_result = migrate::migrate_bucket(bucket_migration).await;
+        bucket_migrations.push(bucket_migration);
+    }
+
+    let migration_report = migrate::migrate_buckets(bucket_migrations, bucket_concurrency).await;
+
+    if let (Some(plan_writer), Some(path)) = (&plan_writer, write_plan_path) {
+        match plan_writer.flush() {
+            Ok(()) => event!(
+                Level::INFO,
+                "Plan written to {}, review it then apply it with --execute --apply-plan {}",
+                path.display(),
+                path.display()
+            ),
+            Err(error) => event!(Level::ERROR, "Failed to write the plan: {:?}", error),
+        }
+    }
+
+    if let Some(heartbeat) = heartbeat {
+        heartbeat.abort();
+    }
+
+    if let Some(progress_bar) = progress_bar {
+        progress_bar.abort();
+        progress_bar::finish();
+    }
+
+    if let Some(throughput_sampler) = throughput_sampler {
+        throughput_sampler.abort();
+    }
+
+    if let (Some(report), Some(path)) = (&throughput_report, throughput_report_path) {
+        match report.write(path, progress) {
+            Ok(()) => event!(
+                Level::INFO,
+                "Throughput report written to {}",
+                path.display()
+            ),
+            Err(error) => event!(
+                Level::ERROR,
+                "Failed to write the throughput report: {:?}",
+                error
+            ),
+        }
+    }
+
+    if let Some(run_window) = &run_window {
+        let paused = run_window.paused_time();
+        event!(
+            Level::INFO,
+            "Run window | Synchronization active for {:?}, paused for {:?}",
+            sync_start.elapsed().saturating_sub(paused),
+            paused
+        );
+    }
+
+    if let Some(circuit_breaker) = &circuit_breaker {
+        event!(
+            Level::INFO,
+            "Circuit breaker opened {} times",
+            circuit_breaker.activations()
+        );
+    }
 
+    if let Some(content_dedup) = &content_dedup {
         event!(
-            Level::TRACE,
-            "Bucket {} | Migration result: {:#?}",
-            bucket,
-            migration_result
+            Level::INFO,
+            "Content dedup | {} objects copied server side, {} not transferred",
+            content_dedup.copied_objects(),
+            ByteSize(content_dedup.saved_bytes())
         );
+    }
 
-        if !dry_run {
+    if let Some(encryption_mirror) = &encryption_mirror {
+        let unfaithful_objects = encryption_mirror.unfaithful_objects();
+        if !unfaithful_objects.is_empty() {
             event!(
-                Level::INFO,
-                "Bucket {} | Bucket has been synchronized",
-                bucket
+                Level::WARN,
+                "Encryption of {} objects couldn't be mirrored faithfully:\n{}",
+                unfaithful_objects.len(),
+                unfaithful_objects.join("\n")
             );
         }
+    }
+
+    if bucket_digest {
+        if bucket_digest_mismatch {
+            std::process::exit(PARTIAL_FAILURE_EXIT_CODE);
+        }
+        return Ok(());
+    }
 
-        migration_results.push(migration_result);
+    if let Some(report) = &verification_report {
+        let verified = report.verified.load(std::sync::atomic::Ordering::Relaxed);
+        let repaired = report.repaired.load(std::sync::atomic::Ordering::Relaxed);
+        event!(
+            Level::INFO,
+            "Verified {} objects: {} already correct, {} {}",
+            verified,
+            verified - repaired,
+            repaired,
+            if dry_run { "to repair" } else { "repaired" }
+        );
     }
 
     if dry_run {
-        let all_stats = migration_results
+        let all_stats = migration_report
+            .buckets
             .iter()
-            .filter_map(|result| match result {
-                Ok(stats) => Some(stats),
-                Err(error) => error
-                    .downcast_ref::<BucketMigrationError>()
-                    .map(|err| &err.stats),
-            })
+            .filter_map(|(_, result)| migration_stats(result))
             .collect::<Vec<&BucketMigrationStats>>();
 
         let total_sync_bytes = all_stats
@@ -352,20 +2219,38 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
                 ByteSize(total_delete_bytes as u64)
             );
         }
+
+        // Stdout only gets the copy list when it is printed
+        if print_copy_list.is_none() {
+            print_dry_run_summary(&all_stats, delete_destination_files);
+        }
     }
 
     let elapsed = sync_start.elapsed();
 
-    for (index, migration_result) in migration_results.iter().enumerate() {
-        let bucket = buckets_to_migrate
-            .get(index)
-            .expect("Bucket should be at index");
-
+    for (bucket, migration_result) in &migration_report.buckets {
         if let Err(error) = migration_result {
             if let Some(err) = error.downcast_ref::<BucketMigrationError>() {
                 for f in &err.errors {
                     event!(Level::ERROR, "Bucket {} | {}", bucket, f);
                 }
+                if !err.verification_errors.is_empty() {
+                    event!(
+                        Level::ERROR,
+                        "Bucket {} | {} uploaded objects don't match their source",
+                        bucket,
+                        err.verification_errors.len()
+                    );
+                }
+                for f in &err.verification_errors {
+                    event!(Level::ERROR, "Bucket {} | {}", bucket, f);
+                }
+            } else if let Some(err) = error.downcast_ref::<DestinationFullError>() {
+                event!(Level::ERROR, "Bucket {} | {}", bucket, err);
+            } else if let Some(err) = error.downcast_ref::<SourceEmptyError>() {
+                event!(Level::ERROR, "Bucket {} | {}", bucket, err);
+            } else if let Some(err) = error.downcast_ref::<PreflightError>() {
+                event!(Level::ERROR, "Bucket {} | {}", bucket, err);
             } else {
                 event!(
                     Level::ERROR,
@@ -374,29 +2259,76 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
                     error
                 );
             }
+
+            if let Some(resume_token) =
+                migration_stats(migration_result).and_then(|stats| stats.resume_token.as_ref())
+            {
+                event!(
+                    Level::INFO,
+                    "Bucket {} | To continue where this run stopped, run again with --source-bucket {} --resume-token {}",
+                    bucket,
+                    bucket,
+                    resume_token
+                );
+            }
         }
     }
 
-    let synchronization_size = migration_results.iter().fold(0, |acc, migration_result| {
-        let stats = match migration_result {
-            Ok(stats) => Some(stats),
-            Err(error) => error
-                .downcast_ref::<BucketMigrationError>()
-                .map(|error| &error.stats),
+    for (bucket, migration_result) in &migration_report.buckets {
+        if let Some(stats) = migration_stats(migration_result) {
+            if stats.total_files_size_excluded > 0 {
+                event!(
+                    Level::WARN,
+                    "Bucket {} | {} objects out of --min-object-size and --max-object-size not synchronized",
+                    bucket,
+                    stats.total_files_size_excluded
+                );
+            }
+        }
+
+        let per_object_timings = match migration_stats(migration_result) {
+            Some(stats) if !stats.per_object_timings.is_empty() => &stats.per_object_timings,
+            _ => continue,
         };
 
-        if let Some(bucket_stats) = stats {
-            acc + bucket_stats.synchronization_size
-        } else {
-            acc
+        event!(Level::INFO, "Bucket {} | Slowest objects:", bucket);
+        for timing in per_object_timings {
+            event!(
+                Level::INFO,
+                "Bucket {} | {} - {} in {:?}, {} parts ({}/s)",
+                bucket,
+                timing.key,
+                ByteSize(timing.size),
+                timing.duration,
+                timing.number_of_parts,
+                ByteSize(timing.throughput)
+            );
         }
-    });
+    }
+
+    let failed_buckets = migration_report.failed_buckets();
+    if !failed_buckets.is_empty() {
+        event!(
+            Level::ERROR,
+            "{} buckets failed: {}",
+            failed_buckets.len(),
+            failed_buckets.join(", ")
+        );
+    }
+
+    for bucket in &migration_report.skipped_buckets {
+        event!(Level::WARN, "Bucket {} | Not synchronized", bucket);
+    }
+
+    let synchronization_size = migration_report.synchronization_size();
 
     if dry_run {
         event!(Level::INFO, "Dry run files diff took {:?}", elapsed,);
     } else {
         event!(
             Level::INFO,
+            duration_ms = elapsed.as_millis() as u64,
+            size = synchronization_size,
             "Sync took {:?} for {} ({}/s)",
             elapsed,
             ByteSize(synchronization_size as u64),
@@ -404,5 +2336,326 @@ async fn migrate_command(params: &ArgMatches) -> anyhow::Result<()> {
         );
     }
 
+    if let (Some(report), Some(path)) = (&json_report, report_json_path) {
+        match report.write(path, &migration_report, elapsed, dry_run) {
+            Ok(()) => event!(Level::INFO, "JSON report written to {}", path.display()),
+            Err(error) => event!(Level::ERROR, "Failed to write the JSON report: {:?}", error),
+        }
+    }
+
+    if migration_report
+        .buckets
+        .iter()
+        .any(|(_, result)| matches!(result, Err(error) if error.is::<SourceEmptyError>()))
+    {
+        std::process::exit(SOURCE_EMPTY_EXIT_CODE);
+    }
+
+    if let Some(failure) = migration_report.failure() {
+        return Err(anyhow::Error::new(failure));
+    }
+
     Ok(())
 }
+
+/// Table of what a run with --execute would do, one line per bucket followed by the totals
+fn print_dry_run_summary(all_stats: &[&BucketMigrationStats], delete_destination_files: bool) {
+    let total = BucketMigrationStats {
+        bucket: "TOTAL".to_string(),
+        synchronization_time: Duration::ZERO,
+        synchronization_size: all_stats
+            .iter()
+            .map(|stats| stats.synchronization_size)
+            .sum(),
+        per_object_timings: Vec::new(),
+        delete_size: all_stats.iter().map(|stats| stats.delete_size).sum(),
+        total_files_sync: all_stats.iter().map(|stats| stats.total_files_sync).sum(),
+        total_files_delete: all_stats.iter().map(|stats| stats.total_files_delete).sum(),
+        total_files_in_sync: all_stats
+            .iter()
+            .map(|stats| stats.total_files_in_sync)
+            .sum(),
+        total_files_size_excluded: all_stats
+            .iter()
+            .map(|stats| stats.total_files_size_excluded)
+            .sum(),
+        resume_token: None,
+    };
+    let width = all_stats
+        .iter()
+        .map(|stats| stats.bucket.len())
+        .chain(std::iter::once(total.bucket.len()))
+        .max()
+        .unwrap_or_default();
+
+    let mut header = format!(
+        "{:<width$}  {:>10}  {:>12}  {:>10}",
+        "BUCKET", "TO UPLOAD", "UPLOAD SIZE", "IN SYNC"
+    );
+    if delete_destination_files {
+        header.push_str(&format!("  {:>10}  {:>12}", "TO DELETE", "DELETE SIZE"));
+    }
+    println!("{}", header);
+
+    for stats in all_stats.iter().copied().chain(std::iter::once(&total)) {
+        let mut line = format!(
+            "{:<width$}  {:>10}  {:>12}  {:>10}",
+            stats.bucket,
+            stats.total_files_sync,
+            ByteSize(stats.synchronization_size as u64).to_string(),
+            stats.total_files_in_sync
+        );
+        if delete_destination_files {
+            line.push_str(&format!(
+                "  {:>10}  {:>12}",
+                stats.total_files_delete,
+                ByteSize(stats.delete_size as u64).to_string()
+            ));
+        }
+        println!("{}", line);
+    }
+}
+
+/// Parses a `<bucket>=<threads>` override, bounded by the global number of threads
+fn parse_bucket_threads(value: &str, max_threads: usize) -> Result<(String, usize), String> {
+    let (bucket, threads) = value
+        .rsplit_once('=')
+        .ok_or(format!("Invalid --bucket-threads {}. Expected <bucket>=<threads>", value))?;
+    let threads = threads
+        .parse::<usize>()
+        .map_err(|error| format!("Invalid --bucket-threads {}: {}", value, error))?;
+
+    if threads == 0 || threads > max_threads {
+        return Err(format!(
+            "--bucket-threads for bucket {} should be between 1 and --threads ({})",
+            bucket, max_threads
+        ));
+    }
+
+    Ok((bucket.to_string(), threads))
+}
+
+/// Parses a `<bucket>=<bytes per second>` override, bounded by the global bandwidth limit
+fn parse_bucket_bandwidth(
+    value: &str,
+    max_bandwidth: Option<u64>,
+) -> Result<(String, u64), String> {
+    let (bucket, bandwidth) = value.rsplit_once('=').ok_or(format!(
+        "Invalid --bucket-max-bandwidth-bytes-per-sec {}. Expected <bucket>=<bytes per second>",
+        value
+    ))?;
+    let bandwidth = bandwidth.parse::<u64>().map_err(|error| {
+        format!(
+            "Invalid --bucket-max-bandwidth-bytes-per-sec {}: {}",
+            value, error
+        )
+    })?;
+
+    if bandwidth == 0 {
+        return Err(format!(
+            "--bucket-max-bandwidth-bytes-per-sec for bucket {} should be greater than 0",
+            bucket
+        ));
+    }
+    match max_bandwidth {
+        Some(max_bandwidth) if bandwidth > max_bandwidth => Err(format!(
+            "--bucket-max-bandwidth-bytes-per-sec for bucket {} can't be greater than --max-bandwidth-bytes-per-sec ({})",
+            bucket, max_bandwidth
+        )),
+        _ => Ok((bucket.to_string(), bandwidth)),
+    }
+}
+
+/// Access and secret keys given on the command line, or read from the profile option
+fn keys_or_profile(
+    params: &ArgMatches,
+    access_key: &str,
+    secret_key: &str,
+    profile: &str,
+) -> (String, String) {
+    match params.get_one::<String>(profile) {
+        Some(name) => {
+            let credentials = ProfileCredentials::load(name).unwrap_or_else(|error| {
+                event!(Level::ERROR, "--{}: {}", profile, error);
+                std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+            });
+            (credentials.access_key, credentials.secret_key)
+        }
+        None => (
+            params.get_one::<String>(access_key).unwrap().to_string(),
+            params.get_one::<String>(secret_key).unwrap().to_string(),
+        ),
+    }
+}
+
+/// TLS settings of --ca-bundle and --danger-accept-invalid-certs, shared by all the clients
+fn configure_tls(params: &ArgMatches) {
+    let ca_bundle = params.get_one::<PathBuf>("ca-bundle");
+    let accept_invalid_certs =
+        params.get_one::<bool>("danger-accept-invalid-certs") == Some(&true);
+    if ca_bundle.is_none() && !accept_invalid_certs {
+        return;
+    }
+
+    if accept_invalid_certs {
+        event!(
+            Level::WARN,
+            "TLS certificates aren't verified, the connections to the endpoints aren't secure"
+        );
+    }
+
+    if let Err(error) = tls::configure(ca_bundle.map(PathBuf::as_path), accept_invalid_certs) {
+        event!(Level::ERROR, "{:#}", error);
+        std::process::exit(CONFIGURATION_ERROR_EXIT_CODE);
+    }
+}
+
+/// Random (v4) UUID identifying a migration run
+fn generate_run_id() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("Failed to generate a random run id"))?;
+
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_threads_override() {
+        assert_eq!(
+            parse_bucket_threads("photos=4", 8),
+            Ok(("photos".to_string(), 4))
+        );
+        assert_eq!(
+            parse_bucket_threads("photos=8", 8),
+            Ok(("photos".to_string(), 8))
+        );
+    }
+
+    #[test]
+    fn bucket_threads_out_of_bounds() {
+        assert!(parse_bucket_threads("photos=0", 8).is_err());
+        let error = parse_bucket_threads("photos=9", 8).unwrap_err();
+        assert!(error.contains("--threads (8)"), "{}", error);
+    }
+
+    #[test]
+    fn invalid_bucket_threads() {
+        assert!(parse_bucket_threads("photos", 8).is_err());
+        assert!(parse_bucket_threads("photos=four", 8).is_err());
+    }
+
+    #[test]
+    fn bucket_bandwidth_override() {
+        assert_eq!(
+            parse_bucket_bandwidth("photos=1000", Some(5000)),
+            Ok(("photos".to_string(), 1000))
+        );
+        assert_eq!(
+            parse_bucket_bandwidth("photos=1000", None),
+            Ok(("photos".to_string(), 1000))
+        );
+    }
+
+    #[test]
+    fn bucket_bandwidth_out_of_bounds() {
+        assert!(parse_bucket_bandwidth("photos=0", None).is_err());
+        let error = parse_bucket_bandwidth("photos=6000", Some(5000)).unwrap_err();
+        assert!(
+            error.contains("--max-bandwidth-bytes-per-sec (5000)"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn invalid_bucket_bandwidth() {
+        assert!(parse_bucket_bandwidth("photos", None).is_err());
+        assert!(parse_bucket_bandwidth("photos=-1", None).is_err());
+    }
+
+    #[test]
+    fn exit_code_of_each_outcome() {
+        let failure = anyhow::Error::new(MigrationFailedError {
+            failed_buckets: 1,
+            failed_objects: 2,
+            skipped_buckets: 0,
+        });
+        assert_eq!(exit_code(&failure), Some(PARTIAL_FAILURE_EXIT_CODE));
+
+        let unreachable = anyhow::Error::new(RusotoError::<ListBucketsError>::HttpDispatch(
+            rusoto_core::request::HttpDispatchError::new("Connection refused".to_string()),
+        ));
+        assert_eq!(
+            exit_code(&unreachable),
+            Some(DESTINATION_UNREACHABLE_EXIT_CODE)
+        );
+
+        // Exits with 1 like the configuration errors
+        assert_eq!(exit_code(&anyhow::anyhow!("Invalid option")), None);
+    }
+
+    /// Logs written by a layer, shared with the test
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_events_carry_the_fields_of_their_spans() {
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::registry().with(json_layer(
+            BoxMakeWriter::new(move || writer.clone()),
+            EnvFilter::new("info"),
+        ));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::span!(Level::DEBUG, "migrate_bucket", bucket = "photos");
+            let _entered = span.enter();
+            event!(Level::INFO, key = "a.jpg", size = 5, "Object uploaded");
+            event!(Level::DEBUG, "Not logged at the info level");
+        });
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = logs
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 1, "{}", logs);
+        assert_eq!(events[0]["level"], "INFO");
+        assert_eq!(events[0]["fields"]["message"], "Object uploaded");
+        assert_eq!(events[0]["fields"]["key"], "a.jpg");
+        assert_eq!(events[0]["fields"]["size"], 5);
+        assert_eq!(events[0]["spans"][0]["name"], "migrate_bucket");
+        assert_eq!(events[0]["spans"][0]["bucket"], "photos");
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::Stream;
+use md5::{Digest, Md5};
+use tokio::task::JoinHandle;
+
+/// MD5 of a body computed on the blocking thread pool while its chunks stream through. Hashing
+/// is bound by the CPU: hashing a 100 MB part on a runtime thread would stall every other task
+/// scheduled on it, like the requests of the small objects. The chunks are only reference
+/// counted, so handing them to the hashing thread doesn't copy them
+#[derive(Debug)]
+pub struct Md5Hasher {
+    chunks: std::sync::mpsc::Sender<Bytes>,
+    digest: JoinHandle<[u8; 16]>,
+}
+
+impl Md5Hasher {
+    pub fn new() -> Md5Hasher {
+        let (chunks, received_chunks) = std::sync::mpsc::channel::<Bytes>();
+        let digest = tokio::task::spawn_blocking(move || {
+            let mut hasher = Md5::new();
+            // Ends once the sender is dropped by finish
+            for chunk in received_chunks {
+                hasher.update(&chunk);
+            }
+            hasher.finalize().into()
+        });
+
+        Md5Hasher { chunks, digest }
+    }
+
+    pub fn update(&self, chunk: Bytes) {
+        // The hashing thread only stops once the sender is dropped
+        let _ = self.chunks.send(chunk);
+    }
+
+    /// Hashes the chunks of the stream as they are read, without buffering them. The digest is
+    /// only complete once the stream is dropped
+    pub fn hash<S>(&self, stream: S) -> HashingStream<S> {
+        HashingStream {
+            inner: Box::pin(stream),
+            chunks: self.chunks.clone(),
+        }
+    }
+
+    /// Waits for the chunks still queued to be hashed
+    pub async fn finish(self) -> anyhow::Result<[u8; 16]> {
+        drop(self.chunks);
+        Ok(self.digest.await?)
+    }
+}
+
+/// Body stream handing each of its chunks to an `Md5Hasher`
+pub struct HashingStream<S> {
+    inner: Pin<Box<S>>,
+    chunks: std::sync::mpsc::Sender<Bytes>,
+}
+
+impl<S> Stream for HashingStream<S>
+where
+    S: Stream<Item = Result<Bytes, std::io::Error>>,
+{
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(bytes))) = &poll {
+            let _ = self.chunks.send(bytes.clone());
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn digest_of_the_chunks() {
+        let hasher = Md5Hasher::new();
+        hasher.update(Bytes::from_static(b"hello "));
+        hasher.update(Bytes::from_static(b"world"));
+
+        assert_eq!(
+            hasher.finish().await.unwrap(),
+            <[u8; 16]>::from(Md5::digest(b"hello world"))
+        );
+    }
+
+    #[tokio::test]
+    async fn digest_of_a_hashed_stream() {
+        let hasher = Md5Hasher::new();
+        let body = hasher.hash(futures::stream::iter(vec![
+            Ok(Bytes::from_static(b"hello ")),
+            Ok(Bytes::from_static(b"world")),
+        ]));
+        let read: Vec<_> = body.collect().await;
+
+        assert_eq!(read.len(), 2);
+        assert_eq!(
+            hasher.finish().await.unwrap(),
+            <[u8; 16]>::from(Md5::digest(b"hello world"))
+        );
+    }
+
+    #[tokio::test]
+    async fn digest_of_an_empty_body() {
+        assert_eq!(
+            Md5Hasher::new().finish().await.unwrap(),
+            <[u8; 16]>::from(Md5::digest(b""))
+        );
+    }
+}
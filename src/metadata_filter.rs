@@ -0,0 +1,78 @@
+use crate::provider::ProviderObjectMetadata;
+
+/// Metadata fields of the source objects that can be filtered, by header name
+pub const FILTERABLE_METADATA: [&str; 7] = [
+    "cache-control",
+    "content-disposition",
+    "content-encoding",
+    "content-language",
+    "content-type",
+    "expires",
+    "website-redirect-location",
+];
+
+/// Restricts the metadata fields carried from the source objects to the destination objects
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataFilter {
+    /// Only these fields are copied
+    Allow(Vec<String>),
+    /// Every field but these is copied
+    Deny(Vec<String>),
+}
+
+impl MetadataFilter {
+    /// Parses a comma separated list of header names
+    pub fn parse_fields(value: &str) -> Result<Vec<String>, String> {
+        value
+            .split(',')
+            .map(|field| field.trim().to_lowercase())
+            .map(|field| {
+                if FILTERABLE_METADATA.contains(&field.as_str()) {
+                    Ok(field)
+                } else {
+                    Err(format!(
+                        "Unknown metadata field {}. Valid values are: {}",
+                        field,
+                        FILTERABLE_METADATA.join(", ")
+                    ))
+                }
+            })
+            .collect()
+    }
+
+    fn is_copied(&self, field: &str) -> bool {
+        match self {
+            MetadataFilter::Allow(fields) => fields.iter().any(|f| f == field),
+            MetadataFilter::Deny(fields) => !fields.iter().any(|f| f == field),
+        }
+    }
+
+    /// Fields copied to the destination objects
+    pub fn effective_fields(&self) -> Vec<&'static str> {
+        FILTERABLE_METADATA
+            .into_iter()
+            .filter(|field| self.is_copied(field))
+            .collect()
+    }
+
+    pub fn apply(&self, metadata: &mut ProviderObjectMetadata) {
+        let fields: [(&str, &mut Option<String>); 7] = [
+            ("cache-control", &mut metadata.cache_control),
+            ("content-disposition", &mut metadata.content_disposition),
+            ("content-encoding", &mut metadata.content_encoding),
+            ("content-language", &mut metadata.content_language),
+            ("content-type", &mut metadata.content_type),
+            ("expires", &mut metadata.expires),
+            (
+                "website-redirect-location",
+                &mut metadata.website_redirect_location,
+            ),
+        ];
+
+        for (field, value) in fields {
+            if !self.is_copied(field) {
+                *value = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    convert::Infallible,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::Context;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tracing::{event, Level};
+
+use crate::heartbeat::SyncProgress;
+
+/// Prometheus endpoint of --metrics-addr, serving the counters of the sync threads on /metrics.
+/// It serves all the passes of --continuous so the counters never go back
+pub struct MetricsServer {
+    addr: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    server: JoinHandle<()>,
+}
+
+/// The throughput is computed between two scrapes
+struct Scrapes {
+    progress: Arc<SyncProgress>,
+    last_scrape: Mutex<(Instant, usize)>,
+}
+
+impl Scrapes {
+    fn render(&self) -> String {
+        let bytes_transferred = self.progress.bytes_transferred();
+        let bytes_per_second = {
+            let mut last_scrape = self.last_scrape.lock().unwrap();
+            let (last_instant, last_bytes_transferred) = *last_scrape;
+            *last_scrape = (Instant::now(), bytes_transferred);
+            (bytes_transferred - last_bytes_transferred) as f64
+                / last_instant.elapsed().as_secs_f64()
+        };
+
+        let metrics = [
+            (
+                "cellar_migration_objects_migrated_total",
+                "counter",
+                "Objects synchronized",
+                self.progress.objects_done() as f64,
+            ),
+            (
+                "cellar_migration_objects_failed_total",
+                "counter",
+                "Objects which failed to synchronize after their retries",
+                self.progress.objects_failed() as f64,
+            ),
+            (
+                "cellar_migration_bytes_transferred_total",
+                "counter",
+                "Bytes uploaded to the destination, including the parts of the failed multipart uploads",
+                bytes_transferred as f64,
+            ),
+            (
+                "cellar_migration_uploads_in_flight",
+                "gauge",
+                "Objects being synchronized",
+                self.progress.objects_in_flight() as f64,
+            ),
+            (
+                "cellar_migration_throughput_bytes_per_second",
+                "gauge",
+                "Upload throughput since the previous scrape",
+                bytes_per_second,
+            ),
+        ];
+
+        let mut body = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(body, "# HELP {} {}", name, help);
+            let _ = writeln!(body, "# TYPE {} {}", name, kind);
+            let _ = writeln!(body, "{} {}", name, value);
+        }
+        body
+    }
+}
+
+impl MetricsServer {
+    pub fn start(addr: SocketAddr, progress: Arc<SyncProgress>) -> anyhow::Result<MetricsServer> {
+        let scrapes = Arc::new(Scrapes {
+            progress,
+            last_scrape: Mutex::new((Instant::now(), 0)),
+        });
+
+        let make_svc = make_service_fn(move |_conn| {
+            let scrapes = scrapes.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let scrapes = scrapes.clone();
+                    async move {
+                        let response = match (request.method(), request.uri().path()) {
+                            (&Method::GET, "/metrics") => Response::builder()
+                                .header("Content-Type", "text/plain; version=0.0.4")
+                                .body(Body::from(scrapes.render())),
+                            _ => Response::builder()
+                                .status(StatusCode::NOT_FOUND)
+                                .body(Body::empty()),
+                        };
+                        Ok::<_, Infallible>(response.expect("Metrics response should be valid"))
+                    }
+                }))
+            }
+        });
+
+        let (shutdown, shutdown_signal) = oneshot::channel::<()>();
+        let server = Server::try_bind(&addr)
+            .with_context(|| format!("Failed to listen on --metrics-addr {}", addr))?
+            .serve(make_svc);
+        // The port chosen by the system if it was 0
+        let addr = server.local_addr();
+        let server = server.with_graceful_shutdown(async {
+            let _ = shutdown_signal.await;
+        });
+
+        event!(Level::INFO, "Serving metrics on http://{}/metrics", addr);
+
+        Ok(MetricsServer {
+            addr,
+            shutdown,
+            server: tokio::spawn(async move {
+                if let Err(error) = server.await {
+                    event!(Level::ERROR, "Metrics server failed: {:?}", error);
+                }
+            }),
+        })
+    }
+
+    /// Waits for the scrapes in progress
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.server.await;
+        event!(Level::DEBUG, "Metrics server on {} stopped", self.addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heartbeat::PartsProgress;
+
+    async fn scrape(server: &MetricsServer) -> String {
+        let uri = format!("http://{}/metrics", server.addr).parse().unwrap();
+        let response = hyper::Client::new().get(uri).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn value(metrics: &str, name: &str) -> f64 {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{} ", name)))
+            .unwrap_or_else(|| panic!("{} should be exposed: {}", name, metrics))
+            .parse()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn metrics_scraped_during_a_migration() {
+        let progress = Arc::new(SyncProgress::default());
+        let server =
+            MetricsServer::start(SocketAddr::from(([127, 0, 0, 1], 0)), progress.clone()).unwrap();
+
+        progress.object_started();
+        progress.object_started();
+        progress.bytes_sent(10);
+        progress.object_finished(true);
+        progress.object_done(10);
+        let mut parts = PartsProgress::new(&progress);
+        parts.part_done(5);
+
+        let metrics = scrape(&server).await;
+        assert_eq!(
+            value(&metrics, "cellar_migration_objects_migrated_total"),
+            1.0
+        );
+        assert_eq!(
+            value(&metrics, "cellar_migration_objects_failed_total"),
+            0.0
+        );
+        assert_eq!(
+            value(&metrics, "cellar_migration_bytes_transferred_total"),
+            15.0
+        );
+        assert_eq!(value(&metrics, "cellar_migration_uploads_in_flight"), 1.0);
+        assert!(value(&metrics, "cellar_migration_throughput_bytes_per_second") > 0.0);
+        assert!(metrics.contains("# TYPE cellar_migration_bytes_transferred_total counter"));
+
+        // The multipart upload fails
+        drop(parts);
+        progress.object_finished(false);
+
+        let metrics = scrape(&server).await;
+        assert_eq!(
+            value(&metrics, "cellar_migration_objects_failed_total"),
+            1.0
+        );
+        assert_eq!(
+            value(&metrics, "cellar_migration_bytes_transferred_total"),
+            15.0
+        );
+        assert_eq!(value(&metrics, "cellar_migration_uploads_in_flight"), 0.0);
+        assert_eq!(progress.bytes_uploaded(), 10);
+
+        server.shutdown().await;
+    }
+}
@@ -1,20 +1,63 @@
-use std::{cmp::Ordering, error};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    error,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering},
+        Arc,
+    },
+};
 
 use bytesize::ByteSize;
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 
 use rusoto_core::RusotoError;
-use rusoto_s3::{CreateBucketError, ListObjectsV2Error};
+use rusoto_s3::{CreateBucketError, ListObjectsV2Error, MultipartUpload};
 use std::time::Duration;
-use tokio::task::JoinError;
+use tokio::{sync::Semaphore, task::JoinError};
 use tracing::{event, instrument, Level};
 
+#[cfg(feature = "sqlite-status")]
+use crate::status_db::ObjectStatusDb;
 use crate::{
-    provider::{get_provider, ProviderConf, ProviderObject, Providers},
+    acl_rules::AclRules,
+    bandwidth::BandwidthLimit,
+    checksum_manifest::ChecksumManifest,
+    circuit_breaker::CircuitBreaker,
+    content_dedup::ContentDedup,
+    destination_bucket::DestinationBucketNames,
+    encryption::EncryptionMirror,
+    etag_cache::SourceETagCache,
+    heartbeat::SyncProgress,
+    key_filter::KeyFilter,
+    key_transform::KeyTransform,
+    metadata_filter::MetadataFilter,
+    modified_filter::ModifiedFilter,
+    object_timings::{ObjectTiming, ObjectTimings},
+    plan::{PlanAction, PlanWriter, PlannedBucket},
+    provider::{
+        get_provider, CompareStrategy, ETagFilter, Provider, ProviderConf, ProviderObject,
+        ProviderObjectMetadata, Providers, DEFAULT_STORAGE_CLASS,
+    },
     radosgw::{
-        uploader::{ThreadMigrationResult, Uploader},
-        RadosGW,
+        clock_skew_message,
+        faults::SimulatedFailures,
+        timeout::HttpTimeouts,
+        uploader::{
+            DestinationFull, ObjectPriority, ThreadMigrationResult, Uploader,
+            UploaderConfiguration,
+        },
+        RadosGW, MAX_DELETE_KEYS,
     },
+    report::{BucketObjectsReport, ObjectStatus},
+    resume::ResumeToken,
+    riakcs::RiakCSError,
+    run_window::RunWindow,
+    state_file::StateFile,
+    storage_class::{StorageClassRule, StorageClassRules},
+    timing_trace::TimingTrace,
+    unsafe_keys::{apply_unsafe_keys_policy, UnsafeKeysPolicy},
 };
 
 #[derive(Debug)]
@@ -22,14 +65,24 @@ pub struct BucketMigrationStats {
     pub bucket: String,
     pub synchronization_time: Duration,
     pub synchronization_size: usize,
+    /// Slowest uploaded objects, only recorded with `--slowest-objects`
+    pub per_object_timings: Vec<ObjectTiming>,
     pub delete_size: usize,
     pub total_files_sync: usize,
     pub total_files_delete: usize,
+    /// Source objects already synchronized, only counted in dry run mode
+    pub total_files_in_sync: usize,
+    /// Source objects not synchronized because of `min_object_size` or `max_object_size`
+    pub total_files_size_excluded: usize,
+    /// Only set when the run stopped before all the objects have been synchronized
+    pub resume_token: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct BucketMigrationError {
     pub errors: Vec<String>,
+    /// Objects uploaded successfully whose destination copy doesn't match (`verify`)
+    pub verification_errors: Vec<String>,
     pub stats: BucketMigrationStats,
 }
 
@@ -45,8 +98,190 @@ impl std::fmt::Display for BucketMigrationError {
     }
 }
 
+/// The destination refused uploads because it is full. The migration of all the buckets is stopped.
+/// `stats` only counts the objects that have been copied before it happened.
+#[derive(Debug)]
+pub struct DestinationFullError {
+    pub reason: String,
+    pub stats: BucketMigrationStats,
+}
+
+impl error::Error for DestinationFullError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl std::fmt::Display for DestinationFullError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "DestinationFull: stopped after copying {} objects ({}). {}",
+            self.stats.total_files_sync,
+            ByteSize(self.stats.synchronization_size as u64),
+            self.reason
+        )
+    }
+}
+
+/// The source bucket has no object at all, as opposed to a bucket whose objects are all
+/// synchronized already. Only returned with `EmptySourcePolicy::Fail`
+#[derive(Debug)]
+pub struct SourceEmptyError {
+    pub bucket: String,
+}
+
+impl error::Error for SourceEmptyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl std::fmt::Display for SourceEmptyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SourceEmpty: source bucket {} has no object", self.bucket)
+    }
+}
+
+/// Why the source or destination bucket can't be listed, found by `preflight_check`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreflightFailure {
+    /// The credentials are rejected or aren't allowed to list the bucket
+    AccessDenied,
+    NoSuchBucket,
+    /// Connection error or timeout
+    Unreachable,
+    Other,
+}
+
+#[derive(Debug)]
+pub struct PreflightError {
+    /// source or destination
+    pub side: &'static str,
+    pub bucket: String,
+    pub failure: PreflightFailure,
+    pub reason: String,
+}
+
+impl error::Error for PreflightError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl std::fmt::Display for PreflightError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let failure = match self.failure {
+            PreflightFailure::AccessDenied => "denies access with the given credentials",
+            PreflightFailure::NoSuchBucket => "doesn't exist",
+            PreflightFailure::Unreachable => "can't be reached",
+            PreflightFailure::Other => "can't be listed",
+        };
+        write!(
+            f,
+            "Preflight: {} bucket {} {}: {}",
+            self.side, self.bucket, failure, self.reason
+        )
+    }
+}
+
+/// The migration ran to the end but some buckets or objects weren't synchronized
+#[derive(Debug)]
+pub struct MigrationFailedError {
+    pub failed_buckets: usize,
+    pub failed_objects: usize,
+    pub skipped_buckets: usize,
+}
+
+impl error::Error for MigrationFailedError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl std::fmt::Display for MigrationFailedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "MigrationFailed: {} buckets failed with {} failed objects, {} buckets not synchronized",
+            self.failed_buckets, self.failed_objects, self.skipped_buckets
+        )
+    }
+}
+
+/// What to do with source buckets without any object
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmptySourcePolicy {
+    /// Log it and consider the bucket synchronized
+    Ignore,
+    Fail,
+}
+
+impl TryFrom<&str> for EmptySourcePolicy {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "ignore" => Ok(EmptySourcePolicy::Ignore),
+            "fail" => Ok(EmptySourcePolicy::Fail),
+            _ => Err(format!("Failed to parse empty source policy: {}", value)),
+        }
+    }
+}
+
+/// Format of the objects to synchronize printed to stdout by `--print-copy-list`
+#[derive(Debug, Clone)]
+pub enum CopyListFormat {
+    /// NUL delimited keys, for `xargs -0`
+    Keys,
+    /// `<size>\t<key>` lines
+    Tsv,
+}
+
+impl TryFrom<&str> for CopyListFormat {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "keys" => Ok(CopyListFormat::Keys),
+            "tsv" => Ok(CopyListFormat::Tsv),
+            _ => Err(format!("Failed to parse copy list format: {}", value)),
+        }
+    }
+}
+
+/// Limits of the size of a multipart upload part. The last part may be smaller than the minimum
+const MIN_MULTIPART_PART_SIZE: usize = 5 * 1024 * 1024;
+const MAX_MULTIPART_PART_SIZE: usize = 5 * 1024 * 1024 * 1024;
+
+pub fn check_multipart_part_size(part_size: usize) -> Result<(), String> {
+    if (MIN_MULTIPART_PART_SIZE..=MAX_MULTIPART_PART_SIZE).contains(&part_size) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Multipart part size {} should be between {} and {}",
+            ByteSize(part_size as u64),
+            ByteSize(MIN_MULTIPART_PART_SIZE as u64),
+            ByteSize(MAX_MULTIPART_PART_SIZE as u64)
+        ))
+    }
+}
+
+/// Smaller objects are put in a single request, a lower threshold would only create multipart
+/// uploads of a single part
+pub fn check_multipart_threshold(threshold: u64) -> Result<(), String> {
+    if threshold >= MIN_MULTIPART_PART_SIZE as u64 {
+        Ok(())
+    } else {
+        Err(format!(
+            "Multipart threshold {} should be at least {}",
+            ByteSize(threshold),
+            ByteSize(MIN_MULTIPART_PART_SIZE as u64)
+        ))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BucketMigrationConfiguration {
+    pub run_id: String,
     pub source_bucket: String,
     pub source_access_key: String,
     pub source_secret_key: String,
@@ -57,48 +292,577 @@ pub struct BucketMigrationConfiguration {
     pub destination_access_key: String,
     pub destination_secret_key: String,
     pub destination_endpoint: String,
+    /// Region name the destination requests are signed with
+    pub destination_region: String,
+    /// Create the destination bucket when it is missing at the start of the migration
+    pub auto_create_destination_bucket: bool,
     pub delete_destination_files: bool,
+    /// Only the objects whose key starts with it are listed on both sides, and synchronized
+    pub include_prefix: Option<String>,
+    /// Applied to the keys of both listings, before the diff
+    pub key_filter: Option<Arc<KeyFilter>>,
+    pub key_transform: Option<KeyTransform>,
     pub max_keys: usize,
-    pub chunk_size: usize,
+    /// Smaller source objects are not synchronized, nor deleted from the destination
+    pub min_object_size: Option<u64>,
+    /// Bigger source objects are not synchronized, nor deleted from the destination
+    pub max_object_size: Option<u64>,
+    /// Keys of each destination listing page, at most `MAX_FETCH_KEYS`
+    pub destination_max_keys: usize,
+    /// Objects of at least this size are uploaded using multipart, checked with
+    /// `check_multipart_threshold`
+    pub multipart_threshold: u64,
+    /// Checked with `check_multipart_part_size`
+    pub multipart_part_size: usize,
     pub sync_threads: usize,
+    /// HEAD requests sent at the same time to verify the uploaded objects
+    pub verify_threads: usize,
+    /// Delete requests sent at the same time, each deleting up to `MAX_DELETE_KEYS` objects
+    pub delete_threads: usize,
+    /// Multipart uploads in progress at the same time in all the buckets
+    pub max_concurrent_multipart: Option<usize>,
+    /// Set by `migrate_buckets` from `max_concurrent_multipart`
+    pub multipart_budget: Option<Arc<Semaphore>>,
+    /// Reuse the multipart uploads left in progress on the destination by a previous run
+    pub resume_multipart_uploads: bool,
+    /// Set by `migrate_bucket` with `resume_multipart_uploads`, by destination key. They are
+    /// listed once for all the pages of the bucket
+    pub multipart_uploads: Arc<HashMap<String, Vec<MultipartUpload>>>,
+    pub small_object_threshold: Option<usize>,
+    pub small_object_threads: usize,
+    pub priority: ObjectPriority,
+    pub source_etag_cache: Option<Arc<SourceETagCache>>,
+    pub checksum_manifest: Option<Arc<ChecksumManifest>>,
+    /// Objects synchronized by previous runs, skipped without being compared
+    pub state_file: Option<Arc<StateFile>>,
+    /// Outcome of each object, for `--report-json`
+    pub object_report: Option<Arc<BucketObjectsReport>>,
+    pub content_dedup: Option<Arc<ContentDedup>>,
+    #[cfg(feature = "sqlite-status")]
+    pub status_db: Option<Arc<ObjectStatusDb>>,
+    pub acl_rules: Option<AclRules>,
+    /// Keep the private, public-read, public-read-write or authenticated-read ACL of the source
+    pub preserve_acl: bool,
+    /// Copy the tag set of the source objects, Riak CS objects have none
+    pub preserve_tags: bool,
+    /// Set by the ownership controls preflight of the destination bucket
+    pub object_acls_disabled: bool,
+    pub infer_content_type: bool,
+    pub progress: Arc<SyncProgress>,
+    /// Set with `--verify-and-repair`
+    pub verification_report: Option<Arc<VerificationReport>>,
+    /// HEAD the uploaded objects once the uploads of a page are done
+    pub verify: bool,
+    pub compare: CompareStrategy,
+    pub etag_filter: ETagFilter,
+    pub unsafe_keys: UnsafeKeysPolicy,
+    /// Multipart objects are only compared by size, whatever the compare strategy
+    pub trust_size_for_multipart: bool,
+    pub recopy_storage_class_changes: bool,
+    /// Source objects whose key exists on the destination are skipped without being compared
+    pub skip_existing_fast: bool,
+    /// Storage class of the destination objects no rule matches
+    pub storage_class: Option<String>,
+    pub storage_class_rules: Vec<StorageClassRule>,
+    pub simulated_failures: Option<SimulatedFailures>,
+    /// Testing only, copy the first bytes of each object
+    pub truncate_bytes: Option<u64>,
+    pub record_source_bucket: bool,
+    pub metadata_filter: Option<MetadataFilter>,
+    /// Only the source objects modified in this range are synchronized
+    pub modified_filter: Option<ModifiedFilter>,
+    pub encryption_mirror: Option<Arc<EncryptionMirror>>,
+    /// Encryption of all the destination objects when they don't mirror the source encryption
+    pub server_side_encryption: Option<String>,
+    pub sse_kms_key_id: Option<String>,
+    pub timing_trace: Option<Arc<TimingTrace>>,
+    /// Slowest objects of the bucket, for `--slowest-objects`
+    pub object_timings: Option<Arc<ObjectTimings>>,
+    pub fail_fast: bool,
+    pub circuit_breaker: Option<Arc<CircuitBreaker>>,
+    pub run_window: Option<Arc<RunWindow>>,
+    /// The global limit, shared by all the buckets, and the limit of the bucket
+    pub bandwidth_limits: Vec<Arc<BandwidthLimit>>,
+    pub verify_checksum: bool,
+    /// Shared by the buckets migrated concurrently, one permit per object being synchronized
+    pub thread_budget: Option<Arc<Semaphore>>,
+    pub max_retries: usize,
+    pub retry_base_delay: Duration,
+    /// Timeouts of the requests to the destination, a timed out upload is retried
+    pub connect_timeout: Option<Duration>,
+    pub request_timeout: Option<Duration>,
+    /// Buckets having more objects or bytes than these limits aren't migrated
+    pub max_bucket_objects: Option<usize>,
+    pub max_bucket_bytes: Option<u64>,
+    pub empty_source: EmptySourcePolicy,
+    /// Send the requester pays header on the source requests
+    pub source_requester_pays: bool,
+    /// Only set in dry run mode
+    pub print_copy_list: Option<CopyListFormat>,
+    /// Only set in dry run mode, records the objects to copy and delete (`--write-plan`)
+    pub plan_writer: Option<Arc<PlanWriter>>,
+    /// Copies and deletes of the bucket read from a plan, executed instead of listing the buckets
+    pub planned: Option<Arc<PlannedBucket>>,
+    pub start_after: Option<String>,
     pub dry_run: bool,
 }
 
+impl BucketMigrationConfiguration {
+    pub fn storage_classes(&self) -> StorageClassRules {
+        StorageClassRules {
+            rules: self.storage_class_rules.clone(),
+            default: self.storage_class.clone(),
+        }
+    }
+
+    /// Objects exactly at a bound are synchronized
+    pub fn object_size_excluded(&self, size: u64) -> Option<ObjectStatus> {
+        if self.min_object_size.is_some_and(|min| size < min) {
+            Some(ObjectStatus::TooSmall)
+        } else if self.max_object_size.is_some_and(|max| size > max) {
+            Some(ObjectStatus::TooLarge)
+        } else {
+            None
+        }
+    }
+
+    pub fn per_object_timings(&self) -> Vec<ObjectTiming> {
+        self.object_timings
+            .as_ref()
+            .map(|object_timings| object_timings.slowest())
+            .unwrap_or_default()
+    }
+
+    pub fn destination_timeouts(&self) -> HttpTimeouts {
+        HttpTimeouts {
+            connect: self.connect_timeout,
+            request: self.request_timeout,
+        }
+    }
+}
+
+/// Outcome of the comparison of a source object with its destination counterpart
+#[derive(Debug, PartialEq)]
+enum ObjectDiff {
+    Synchronized,
+    Copy,
+    /// Same size, the Content-MD5 stored on both sides has to be compared
+    CompareContentMd5,
+    /// Same size but different ETags, the destination may compute ETags differently
+    RecheckETagScheme,
+}
+
+/// Objects uploaded using multipart on one of the sides are only compared by size with
+/// `trust_size_for_multipart`, whatever the compare strategy
+fn diff_object(
+    compare: &CompareStrategy,
+    trust_size_for_multipart: bool,
+    object: &ProviderObject,
+    found: &ProviderObject,
+) -> ObjectDiff {
+    let same_size = object.get_size() == found.get_size();
+    if trust_size_for_multipart && (object.is_multipart() || found.is_multipart()) {
+        return match same_size {
+            true => ObjectDiff::Synchronized,
+            false => ObjectDiff::Copy,
+        };
+    }
+
+    match compare {
+        CompareStrategy::ContentMd5 if same_size => ObjectDiff::CompareContentMd5,
+        _ if compare.is_synchronized(object, found) => ObjectDiff::Synchronized,
+        CompareStrategy::ETag if same_size => ObjectDiff::RecheckETagScheme,
+        _ => ObjectDiff::Copy,
+    }
+}
+
 pub enum BucketObjectsMigrationResult {
-    DryRun(Vec<ProviderObject>, Vec<ProviderObject>),
-    Executed(Vec<Result<ThreadMigrationResult, JoinError>>),
+    /// Objects to copy, objects to delete and number of source objects already synchronized
+    DryRun(Vec<ProviderObject>, Vec<ProviderObject>, usize),
+    /// Results of the sync threads and the verification errors of the uploaded objects
+    Executed(Vec<Result<ThreadMigrationResult, JoinError>>, Vec<String>),
+}
+
+fn storage_class_changed(
+    conf: &BucketMigrationConfiguration,
+    object: &ProviderObject,
+    found: &ProviderObject,
+) -> bool {
+    if !conf.recopy_storage_class_changes {
+        return false;
+    }
+
+    let storage_classes = conf.storage_classes();
+    let expected = storage_classes
+        .storage_class(&object.get_key())
+        .unwrap_or(DEFAULT_STORAGE_CLASS);
+    if found.get_storage_class().unwrap_or(DEFAULT_STORAGE_CLASS) != expected {
+        event!(
+            Level::DEBUG,
+            "Object {} has storage class {:?} on destination bucket, it will be copied again to restore {}",
+            object.get_key(),
+            found.get_storage_class(),
+            expected
+        );
+        true
+    } else {
+        false
+    }
+}
+
+/// Compare objects having the same key and size using their Content-MD5.
+/// If one of the sides doesn't expose it, fall back to the ETag comparison.
+async fn content_md5_diff(
+    conf: &BucketMigrationConfiguration,
+    source_provider: &dyn Provider,
+    destination_provider: &dyn Provider,
+    objects: Vec<(&ProviderObject, &ProviderObject)>,
+) -> Vec<ProviderObject> {
+    futures::stream::iter(objects)
+        .map(|(object, found)| async move {
+            let source_md5 = source_provider
+                .get_object_metadata(object)
+                .await
+                .map(|metadata| metadata.content_md5);
+            let destination_md5 = destination_provider
+                .get_object_metadata(found)
+                .await
+                .map(|metadata| metadata.content_md5);
+
+            let differ = match (source_md5, destination_md5) {
+                (Ok(Some(source_md5)), Ok(Some(destination_md5))) => {
+                    source_md5 != destination_md5
+                }
+                _ => {
+                    event!(
+                        Level::DEBUG,
+                        "Object {} doesn't have a Content-MD5 on both sides. Falling back to ETag comparison",
+                        object.get_key()
+                    );
+                    !object.matches_destination(found)
+                }
+            };
+
+            if differ || storage_class_changed(conf, object, found) {
+                Some(object.clone())
+            } else {
+                None
+            }
+        })
+        .buffer_unordered(conf.sync_threads)
+        .filter_map(futures::future::ready)
+        .collect()
+        .await
+}
+
+/// HEAD the objects uploaded by the sync threads and compare their destination copy with the
+/// source listing. Returns the verification error of each object that doesn't match
+async fn verify_uploads(
+    conf: &BucketMigrationConfiguration,
+    destination_provider: &dyn Provider,
+    objects: Vec<&ProviderObject>,
+) -> Vec<String> {
+    event!(
+        Level::INFO,
+        "{} | Verifying {} uploaded objects",
+        conf.source_bucket,
+        objects.len()
+    );
+
+    futures::stream::iter(objects)
+        .map(|object| async move {
+            let expected_size = match conf.truncate_bytes {
+                Some(truncate_bytes) => std::cmp::min(object.get_size(), truncate_bytes),
+                None => object.get_size(),
+            };
+            let destination = ProviderObject::new(object.get_destination_key(), expected_size);
+            let source_etag = object.get_etag().trim_matches('"');
+
+            let mismatch = match destination_provider.get_object_metadata(&destination).await {
+                Ok(metadata) if metadata.content_length as u64 != expected_size => Some(format!(
+                    "size differs ({} expected, {} on destination)",
+                    expected_size, metadata.content_length
+                )),
+                // Multipart and SSE-KMS ETags aren't the MD5 of the content, truncated objects
+                // don't have the source content
+                Ok(metadata) => match metadata.etag {
+                    Some(etag)
+                        if !object.is_multipart()
+                            && expected_size == object.get_size()
+                            && metadata.server_side_encryption.as_deref() != Some("aws:kms")
+                            && etag.trim_matches('"') != source_etag =>
+                    {
+                        Some(format!(
+                            "ETag differs ({} on source, {} on destination)",
+                            source_etag, etag
+                        ))
+                    }
+                    _ => None,
+                },
+                Err(error) => Some(format!("destination HEAD failed: {:?}", error)),
+            };
+
+            mismatch.map(|reason| {
+                let error = format!(
+                    "{} | Verification of object {} failed: {}",
+                    conf.source_bucket,
+                    object.get_key(),
+                    reason
+                );
+                event!(Level::WARN, "{}", error);
+                error
+            })
+        })
+        .buffer_unordered(conf.verify_threads)
+        .filter_map(futures::future::ready)
+        .collect()
+        .await
+}
+
+/// Objects having the same size but different ETags on both sides are copied again, unless the
+/// destination object was copied from a source object having the same ETag: the destination
+/// computes ETags differently and copying the object again would never make them match
+async fn etag_scheme_diff(
+    conf: &BucketMigrationConfiguration,
+    destination_provider: &dyn Provider,
+    objects: Vec<(&ProviderObject, &ProviderObject)>,
+) -> Vec<ProviderObject> {
+    let mismatches = AtomicUsize::new(0);
+    let mismatches_ref = &mismatches;
+
+    let objects_to_migrate = futures::stream::iter(objects)
+        .map(|(object, found)| async move {
+            let source_etag = destination_provider
+                .get_object_metadata(found)
+                .await
+                .ok()
+                .and_then(|metadata| metadata.source_etag);
+
+            if source_etag.as_deref() == Some(object.get_etag().trim_matches('"')) {
+                event!(
+                    Level::DEBUG,
+                    "{} | Object {} has ETag {} on destination bucket but was copied from the same source object (ETag {})",
+                    conf.source_bucket,
+                    object.get_key(),
+                    found.get_etag(),
+                    object.get_etag()
+                );
+                mismatches_ref.fetch_add(1, AtomicOrdering::Relaxed);
+                None
+            } else {
+                Some(object.clone())
+            }
+        })
+        .buffer_unordered(conf.sync_threads)
+        .filter_map(futures::future::ready)
+        .collect()
+        .await;
+
+    let mismatches = mismatches.into_inner();
+    if mismatches > 0 {
+        event!(
+            Level::WARN,
+            "{} | {} objects have a different ETag on the destination although they were copied from an unchanged source object. The destination computes ETags differently, they won't be copied again. Use --trust-size-for-multipart to skip this check for multipart objects",
+            conf.source_bucket,
+            mismatches
+        );
+    }
+
+    objects_to_migrate
+}
+
+/// Counts of the `--verify-and-repair` pass, shared by all the buckets
+#[derive(Debug, Default)]
+pub struct VerificationReport {
+    pub verified: AtomicUsize,
+    pub repaired: AtomicUsize,
+}
+
+fn metadata_mismatch(
+    source: &ProviderObjectMetadata,
+    destination: &ProviderObjectMetadata,
+) -> Option<String> {
+    if source.content_length != destination.content_length {
+        return Some(format!(
+            "size differs ({} on source, {} on destination)",
+            source.content_length, destination.content_length
+        ));
+    }
+
+    if let (Some(source_md5), Some(destination_md5)) =
+        (&source.content_md5, &destination.content_md5)
+    {
+        if source_md5 != destination_md5 {
+            return Some("Content-MD5 differs".to_string());
+        }
+    }
+
+    if source.website_redirect_location != destination.website_redirect_location {
+        return Some("website redirect location differs".to_string());
+    }
+
+    // Multipart ETags depend on the chunk size, they can't be compared
+    match (&source.etag, &destination.etag) {
+        (Some(source_etag), Some(destination_etag))
+            if !source_etag.contains('-')
+                && !destination_etag.contains('-')
+                && source_etag.trim_matches('"') != destination_etag.trim_matches('"') =>
+        {
+            Some("ETag differs".to_string())
+        }
+        _ => None,
+    }
+}
+
+/// HEAD objects the listings consider identical on both sides and return the ones whose
+/// metadata don't match, so they are copied again
+async fn verify_objects(
+    conf: &BucketMigrationConfiguration,
+    source_provider: &dyn Provider,
+    destination_provider: &dyn Provider,
+    objects: Vec<(&ProviderObject, &ProviderObject)>,
+) -> Vec<ProviderObject> {
+    let verified = objects.len();
+    let to_repair: Vec<ProviderObject> = futures::stream::iter(objects)
+        .map(|(object, found)| async move {
+            let source_metadata = source_provider.get_object_metadata(object).await;
+            let destination_metadata = destination_provider.get_object_metadata(found).await;
+
+            let mismatch = match (source_metadata, destination_metadata) {
+                (Ok(mut source), Ok(destination)) => {
+                    // Filtered fields are expected to be missing on the destination
+                    if let Some(metadata_filter) = &conf.metadata_filter {
+                        metadata_filter.apply(&mut source);
+                    }
+                    metadata_mismatch(&source, &destination)
+                }
+                (Err(error), _) => {
+                    // The source object may have been deleted since the listing, leave it
+                    event!(
+                        Level::WARN,
+                        "Failed to verify object {}: {:?}",
+                        object.get_key(),
+                        error
+                    );
+                    None
+                }
+                (_, Err(error)) => Some(format!("destination HEAD failed: {:?}", error)),
+            };
+
+            mismatch.map(|reason| {
+                event!(
+                    Level::INFO,
+                    "Object {} doesn't match its destination copy, it will be repaired: {}",
+                    object.get_key(),
+                    reason
+                );
+                object.clone()
+            })
+        })
+        .buffer_unordered(conf.verify_threads)
+        .filter_map(futures::future::ready)
+        .collect()
+        .await;
+
+    if let Some(report) = &conf.verification_report {
+        report.verified.fetch_add(verified, AtomicOrdering::Relaxed);
+        report
+            .repaired
+            .fetch_add(to_repair.len(), AtomicOrdering::Relaxed);
+    }
+
+    to_repair
 }
 
+/// Also returns the number of source objects excluded by their size
 #[instrument(skip_all, level = "debug")]
 async fn migrate_objects(
     conf: BucketMigrationConfiguration,
     src_objects: &[ProviderObject],
     dst_objects: &[ProviderObject],
-) -> BucketObjectsMigrationResult {
+) -> (BucketObjectsMigrationResult, usize) {
     let source_provider_conf = ProviderConf::new(
-        conf.source_endpoint,
-        conf.source_region,
-        conf.source_access_key,
-        conf.source_secret_key,
+        conf.source_endpoint.clone(),
+        conf.source_region.clone(),
+        conf.source_access_key.clone(),
+        conf.source_secret_key.clone(),
         Some(conf.source_bucket.clone()),
-    );
+    )
+    .with_requester_pays(conf.source_requester_pays);
     let source_provider = get_provider(&conf.source_provider, source_provider_conf);
 
     let radosgw_client = RadosGW::new(
-        Some(conf.destination_endpoint),
-        None,
-        conf.destination_access_key,
-        conf.destination_secret_key,
-        Some(conf.destination_bucket),
-    );
-    let objects_to_migrate: Vec<ProviderObject> = src_objects
+        Some(conf.destination_endpoint.clone()),
+        Some(conf.destination_region.clone()),
+        conf.destination_access_key.clone(),
+        conf.destination_secret_key.clone(),
+        Some(conf.destination_bucket.clone()),
+    )
+    .with_timeouts(conf.destination_timeouts());
+    let src_objects =
+        apply_unsafe_keys_policy(&conf.unsafe_keys, &conf.source_bucket, src_objects);
+    let src_objects = match &conf.key_transform {
+        Some(key_transform) => key_transform.apply(&conf.source_bucket, src_objects),
+        None => src_objects,
+    };
+    // Indexed once, looking up each object in the slices would make the diff quadratic
+    let dst_index: HashMap<String, &ProviderObject> = dst_objects
+        .iter()
+        .map(|object| (object.get_key(), object))
+        .collect();
+    let mut objects_to_compare = Vec::new();
+    let mut objects_to_recheck = Vec::new();
+    let mut objects_to_verify = Vec::new();
+    let mut size_excluded = Vec::new();
+    let mut not_modified_in_range = Vec::new();
+    let mut objects_to_migrate: Vec<ProviderObject> = src_objects
         .iter()
         .filter_map(|object| {
-            if let Some(found) = dst_objects.iter().find(|d| d.get_key() == object.get_key()) {
-                if object != found {
-                    Some(object.clone())
-                } else {
-                    None
+            // Still part of the source listing, so its destination object isn't deleted either
+            if let Some(status) = conf.object_size_excluded(object.get_size()) {
+                size_excluded.push((object, status));
+                return None;
+            }
+
+            if let Some(modified_filter) = &conf.modified_filter {
+                if !modified_filter.matches(object) {
+                    not_modified_in_range.push(object);
+                    return None;
+                }
+            }
+
+            // Still part of the source listing, so its destination object isn't deleted
+            if let Some(state_file) = &conf.state_file {
+                if state_file.is_completed(&object.get_key()) {
+                    return None;
+                }
+            }
+
+            if conf.skip_existing_fast {
+                return (!dst_index.contains_key(&object.get_destination_key()))
+                    .then(|| object.clone());
+            }
+
+            if let Some(&found) = dst_index.get(&object.get_destination_key()) {
+                match diff_object(&conf.compare, conf.trust_size_for_multipart, object, found) {
+                    ObjectDiff::CompareContentMd5 => {
+                        // Needs to fetch the metadata of both objects, done concurrently below
+                        objects_to_compare.push((object, found));
+                        None
+                    }
+                    _ if storage_class_changed(&conf, object, found) => Some(object.clone()),
+                    ObjectDiff::Copy => Some(object.clone()),
+                    ObjectDiff::RecheckETagScheme => {
+                        // May have been copied by a previous run, checked concurrently below
+                        objects_to_recheck.push((object, found));
+                        None
+                    }
+                    ObjectDiff::Synchronized => {
+                        if conf.verification_report.is_some() {
+                            objects_to_verify.push((object, found));
+                        }
+                        None
+                    }
                 }
             } else {
                 Some(object.clone())
@@ -106,19 +870,112 @@ async fn migrate_objects(
         })
         .collect();
 
+    if !objects_to_compare.is_empty() {
+        objects_to_migrate.extend(
+            content_md5_diff(
+                &conf,
+                &*source_provider,
+                &radosgw_client,
+                objects_to_compare,
+            )
+            .await,
+        );
+    }
+
+    if !objects_to_recheck.is_empty() {
+        objects_to_migrate
+            .extend(etag_scheme_diff(&conf, &radosgw_client, objects_to_recheck).await);
+    }
+
+    if !objects_to_verify.is_empty() {
+        objects_to_migrate.extend(
+            verify_objects(
+                &conf,
+                &*source_provider,
+                &radosgw_client,
+                objects_to_verify,
+            )
+            .await,
+        );
+    }
+
+    if conf.etag_filter != ETagFilter::All {
+        let multipart = objects_to_migrate
+            .iter()
+            .filter(|object| object.is_multipart())
+            .count();
+        event!(
+            Level::INFO,
+            "{} | {} multipart and {} simple objects differ, only {:?} objects will be synchronized",
+            conf.source_bucket,
+            multipart,
+            objects_to_migrate.len() - multipart,
+            conf.etag_filter
+        );
+
+        objects_to_migrate.retain(|object| conf.etag_filter.matches(object));
+    }
+
+    #[cfg(feature = "sqlite-status")]
+    if let Some(status_db) = conf.status_db.as_ref().filter(|db| db.retry_only()) {
+        objects_to_migrate.retain(|object| {
+            status_db
+                .needs_retry(&object.get_key())
+                .unwrap_or_else(|error| {
+                    event!(
+                        Level::WARN,
+                        "{} | Failed to read the status of object {}, it will be synchronized: {:?}",
+                        conf.source_bucket,
+                        object.get_key(),
+                        error
+                    );
+                    true
+                })
+        });
+    }
+
+    if let Some(object_report) = &conf.object_report {
+        let mut to_migrate: HashSet<String> = objects_to_migrate
+            .iter()
+            .map(|object| object.get_key())
+            .collect();
+        for (object, status) in &size_excluded {
+            object_report.record(object.get_key(), object.get_size(), *status, None, None);
+            to_migrate.insert(object.get_key());
+        }
+        for object in &not_modified_in_range {
+            object_report.record(
+                object.get_key(),
+                object.get_size(),
+                ObjectStatus::NotModifiedInRange,
+                None,
+                None,
+            );
+            to_migrate.insert(object.get_key());
+        }
+        for object in src_objects
+            .iter()
+            .filter(|object| !to_migrate.contains(&object.get_key()))
+        {
+            object_report.record(
+                object.get_key(),
+                object.get_size(),
+                ObjectStatus::Skipped,
+                None,
+                None,
+            );
+        }
+    }
+
     let objects_to_delete: Vec<ProviderObject> = if conf.delete_destination_files {
+        let src_keys: HashSet<String> = src_objects
+            .iter()
+            .map(|object| object.get_destination_key())
+            .collect();
         dst_objects
             .iter()
-            .filter_map(|object| {
-                if !src_objects
-                    .iter()
-                    .any(|src| src.get_key() == object.get_key())
-                {
-                    Some(object.clone())
-                } else {
-                    None
-                }
-            })
+            .filter(|object| !src_keys.contains(&object.get_key()))
+            .cloned()
             .collect()
     } else {
         Vec::new()
@@ -126,54 +983,606 @@ async fn migrate_objects(
 
     let objects_to_sync = objects_to_migrate.len() + objects_to_delete.len();
 
-    if !conf.dry_run {
+    let result = if !conf.dry_run {
         if objects_to_sync > 0 {
-            let mut uploader = Uploader::new(
+            let (results, verification_errors) = upload_objects(
+                &conf,
                 source_provider,
                 radosgw_client,
                 objects_to_migrate,
                 objects_to_delete,
-                conf.sync_threads,
-                conf.chunk_size,
-            );
-            let results = uploader.sync().await;
-            BucketObjectsMigrationResult::Executed(results)
+            )
+            .await;
+            BucketObjectsMigrationResult::Executed(results, verification_errors)
         } else {
-            BucketObjectsMigrationResult::Executed(Vec::new())
+            BucketObjectsMigrationResult::Executed(Vec::new(), Vec::new())
         }
     } else {
-        BucketObjectsMigrationResult::DryRun(objects_to_migrate, objects_to_delete)
+        let in_sync = src_objects.len()
+            - objects_to_migrate.len()
+            - size_excluded.len()
+            - not_modified_in_range.len();
+        BucketObjectsMigrationResult::DryRun(objects_to_migrate, objects_to_delete, in_sync)
+    };
+
+    (result, size_excluded.len())
+}
+
+/// Synchronizes the objects with the sync threads, then verifies the uploaded objects when
+/// `verify` is set
+async fn upload_objects(
+    conf: &BucketMigrationConfiguration,
+    source_provider: Box<dyn Provider>,
+    radosgw_client: RadosGW,
+    objects_to_migrate: Vec<ProviderObject>,
+    objects_to_delete: Vec<ProviderObject>,
+) -> (Vec<Result<ThreadMigrationResult, JoinError>>, Vec<String>) {
+    #[cfg(feature = "sqlite-status")]
+    if let Some(status_db) = &conf.status_db {
+        if let Err(error) = status_db.mark_pending(&objects_to_migrate) {
+            event!(
+                Level::WARN,
+                "{} | Failed to record the objects to synchronize in the status database: {:?}",
+                conf.source_bucket,
+                error
+            );
+        }
     }
+
+    let multipart_uploads = objects_to_migrate
+        .iter()
+        .filter_map(|object| conf.multipart_uploads.get(&object.get_destination_key()))
+        .flatten()
+        .cloned()
+        .collect();
+    let mut uploader = Uploader::new(
+        source_provider,
+        radosgw_client.clone(),
+        objects_to_migrate,
+        objects_to_delete,
+        UploaderConfiguration {
+            threads: conf.sync_threads,
+            multipart_threshold: conf.multipart_threshold,
+            multipart_part_size: conf.multipart_part_size,
+            multipart_budget: conf.multipart_budget.clone(),
+            multipart_uploads,
+            small_object_threshold: conf.small_object_threshold,
+            small_object_threads: conf.small_object_threads,
+            delete_threads: conf.delete_threads,
+            priority: conf.priority,
+            source_etag_cache: conf.source_etag_cache.clone(),
+            checksum_manifest: conf.checksum_manifest.clone(),
+            state_file: conf.state_file.clone(),
+            object_report: conf.object_report.clone(),
+            content_dedup: conf.content_dedup.clone(),
+            acl_rules: conf.acl_rules.clone(),
+            preserve_acl: conf.preserve_acl,
+            preserve_tags: conf.preserve_tags,
+            storage_classes: conf.storage_classes(),
+            infer_content_type: conf.infer_content_type,
+            object_acls_disabled: conf.object_acls_disabled,
+            progress: conf.progress.clone(),
+            truncate_bytes: conf.truncate_bytes,
+            source_bucket: conf
+                .record_source_bucket
+                .then(|| conf.source_bucket.clone()),
+            metadata_filter: conf.metadata_filter.clone(),
+            key_filter: conf.key_filter.clone(),
+            encryption_mirror: conf.encryption_mirror.clone(),
+            server_side_encryption: conf.server_side_encryption.clone(),
+            sse_kms_key_id: conf.sse_kms_key_id.clone(),
+            timing_trace: conf.timing_trace.clone(),
+            object_timings: conf.object_timings.clone(),
+            fail_fast: conf.fail_fast,
+            circuit_breaker: conf.circuit_breaker.clone(),
+            run_window: conf.run_window.clone(),
+            bandwidth_limits: conf.bandwidth_limits.clone(),
+            verify_checksum: conf.verify_checksum,
+            thread_budget: conf.thread_budget.clone(),
+            max_retries: conf.max_retries,
+            retry_base_delay: conf.retry_base_delay,
+            simulated_failures: conf.simulated_failures.clone(),
+        },
+    );
+    #[cfg(feature = "sqlite-status")]
+    uploader.set_status_db(conf.status_db.clone());
+    let results = uploader.sync().await;
+    let verification_errors = if conf.verify {
+        let synchronized_objects = results
+            .iter()
+            .flatten()
+            .flat_map(|result| result.synchronized_objects.iter())
+            .collect();
+        verify_uploads(conf, &radosgw_client, synchronized_objects).await
+    } else {
+        Vec::new()
+    };
+    (results, verification_errors)
 }
 
-#[instrument(skip_all, level = "debug")]
-pub async fn migrate_bucket(
+/// Executes the copies and deletes of a plan written by a dry run, without listing the buckets.
+/// The objects are read from the source when the plan is applied
+async fn apply_plan(
     conf: BucketMigrationConfiguration,
+    planned: &PlannedBucket,
 ) -> anyhow::Result<BucketMigrationStats> {
     let sync_start = std::time::Instant::now();
+    event!(
+        Level::INFO,
+        "{} | Applying plan: {} objects to copy, {} objects to delete",
+        conf.source_bucket,
+        planned.copies.len(),
+        planned.deletes.len()
+    );
 
-    let async_conf = conf.clone();
-    let source_provider_conf = ProviderConf::new(
-        conf.source_endpoint,
-        conf.source_region,
+    let (source_provider, _) = get_bucket_providers(&conf);
+    let radosgw_client = RadosGW::new(
+        Some(conf.destination_endpoint.clone()),
+        Some(conf.destination_region.clone()),
+        conf.destination_access_key.clone(),
+        conf.destination_secret_key.clone(),
+        Some(conf.destination_bucket.clone()),
+    )
+    .with_timeouts(conf.destination_timeouts());
+    let (results, verification_errors) = upload_objects(
+        &conf,
+        source_provider,
+        radosgw_client,
+        planned.copies.clone(),
+        planned.deletes.clone(),
+    )
+    .await;
+
+    let mut errors = Vec::new();
+    let mut destination_full = None;
+    let mut stats = BucketMigrationStats {
+        bucket: conf.source_bucket.clone(),
+        synchronization_time: Duration::ZERO,
+        synchronization_size: 0,
+        per_object_timings: conf.per_object_timings(),
+        delete_size: 0,
+        total_files_sync: 0,
+        total_files_delete: 0,
+        total_files_in_sync: 0,
+        total_files_size_excluded: 0,
+        resume_token: None,
+    };
+
+    for result in results {
+        let result = result?;
+        for sync_result in result.sync_results {
+            match sync_result {
+                Ok(size) => {
+                    stats.synchronization_size += size;
+                    stats.total_files_sync += 1;
+                }
+                Err(error) => {
+                    if let Some(full) = error.downcast_ref::<DestinationFull>() {
+                        destination_full = Some(full.to_string());
+                    }
+                    errors.push(format!(
+                        "{} | Error synchronizing file: {:?}",
+                        conf.source_bucket, error
+                    ));
+                }
+            }
+        }
+        for delete_result in result.delete_results {
+            match delete_result {
+                Ok(size) => {
+                    stats.delete_size += size;
+                    stats.total_files_delete += 1;
+                }
+                Err(error) => errors.push(format!(
+                    "{} | Error deleting file on destination bucket: {:?}",
+                    conf.source_bucket, error
+                )),
+            }
+        }
+    }
+    stats.synchronization_time = sync_start.elapsed();
+
+    if let Some(reason) = destination_full {
+        Err(anyhow::Error::new(DestinationFullError { reason, stats }))
+    } else if !errors.is_empty() || !verification_errors.is_empty() {
+        Err(anyhow::Error::new(BucketMigrationError {
+            errors,
+            verification_errors,
+            stats,
+        }))
+    } else {
+        Ok(stats)
+    }
+}
+
+/// Source and destination providers of the bucket, for the operations done outside of the
+/// migration itself
+pub fn get_bucket_providers(
+    conf: &BucketMigrationConfiguration,
+) -> (Box<dyn Provider>, Box<dyn Provider>) {
+    let source_provider = get_provider(
+        &conf.source_provider,
+        ProviderConf::new(
+            conf.source_endpoint.clone(),
+            conf.source_region.clone(),
+            conf.source_access_key.clone(),
+            conf.source_secret_key.clone(),
+            Some(conf.source_bucket.clone()),
+        )
+        .with_requester_pays(conf.source_requester_pays)
+        .with_prefix(conf.include_prefix.clone()),
+    );
+    let destination_provider = get_provider(
+        &Providers::Cellar,
+        ProviderConf::new(
+            Some(conf.destination_endpoint.clone()),
+            Some(conf.destination_region.clone()),
+            conf.destination_access_key.clone(),
+            conf.destination_secret_key.clone(),
+            Some(conf.destination_bucket.clone()),
+        )
+        .with_prefix(conf.include_prefix.clone())
+        .with_timeouts(conf.destination_timeouts())
+        .with_page_size(conf.destination_max_keys),
+    );
+
+    (source_provider, destination_provider)
+}
+
+/// Lists the whole source bucket and refuses to migrate it if it is larger than expected
+async fn check_bucket_limits(
+    conf: &BucketMigrationConfiguration,
+    source_provider: &dyn Provider,
+) -> anyhow::Result<()> {
+    let mut total_objects: usize = 0;
+    let mut total_bytes: u64 = 0;
+
+    let mut listing = source_provider.list_objects(None, conf.start_after.clone());
+    while let Some(objects) = listing.next().await {
+        let objects = objects?;
+        total_objects += objects.len();
+        total_bytes += objects.iter().map(|object| object.get_size()).sum::<u64>();
+    }
+
+    event!(
+        Level::INFO,
+        "{} | Bucket has {} objects for a total size of {}",
+        conf.source_bucket,
+        total_objects,
+        ByteSize(total_bytes)
+    );
+
+    let too_many_objects = matches!(conf.max_bucket_objects, Some(max) if total_objects > max);
+    let too_many_bytes = matches!(conf.max_bucket_bytes, Some(max) if total_bytes > max);
+    if too_many_objects || too_many_bytes {
+        anyhow::bail!(
+            "{} | Bucket has {} objects ({}), more than the limits (--max-bucket-objects={:?}, --max-bucket-bytes={:?}). It won't be migrated, use --force to migrate it anyway",
+            conf.source_bucket,
+            total_objects,
+            ByteSize(total_bytes),
+            conf.max_bucket_objects,
+            conf.max_bucket_bytes
+        );
+    }
+
+    Ok(())
+}
+
+type ObjectListing<'a> = Pin<Box<dyn Stream<Item = anyhow::Result<Vec<ProviderObject>>> + 'a>>;
+
+/// Source and destination listings of a bucket, the first source page already fetched
+struct BucketListings<'a> {
+    source: ObjectListing<'a>,
+    destination: ObjectListing<'a>,
+    source_empty: bool,
+}
+
+/// Fetches the first source page before listing the destination, so an empty source is
+/// reported on its own and the destination isn't listed for nothing. A source listing error
+/// stops the bucket before any destination listing or upload
+async fn list_bucket<'a>(
+    source_provider: &'a dyn Provider,
+    dest_provider: &'a dyn Provider,
+    source_start_after: Option<String>,
+    dest_start_after: Option<String>,
+) -> anyhow::Result<BucketListings<'a>> {
+    let mut source_listing = source_provider.list_objects(None, source_start_after);
+    let first_source_page = source_listing.next().await.transpose()?;
+    let source_empty = first_source_page.iter().all(Vec::is_empty);
+
+    Ok(BucketListings {
+        source: Box::pin(futures::stream::iter(first_source_page.map(Ok)).chain(source_listing)),
+        destination: dest_provider.list_objects(None, dest_start_after),
+        source_empty,
+    })
+}
+
+/// Logs why the source bucket couldn't be listed
+fn source_listing_error(conf: &BucketMigrationConfiguration, err: anyhow::Error) -> anyhow::Error {
+    if let Some(message) = err
+        .downcast_ref::<RusotoError<ListObjectsV2Error>>()
+        .and_then(clock_skew_message)
+    {
+        event!(Level::ERROR, "{}", message);
+        return anyhow::anyhow!(message);
+    }
+    event!(Level::ERROR, "Failed to fetch source objects: {:?}", err);
+    if !conf.source_requester_pays && is_access_denied(&err) {
+        event!(
+            Level::ERROR,
+            "{} | Access to the source bucket is denied. If it is a requester pays bucket, use --source-requester-pays",
+            conf.source_bucket
+        );
+    }
+    err
+}
+
+/// Whether a source listing error is a 403, which is what requester pays buckets answer to
+/// requests not accepting the charges
+fn is_access_denied(error: &anyhow::Error) -> bool {
+    if let Some(RusotoError::Unknown(response)) =
+        error.downcast_ref::<RusotoError<ListObjectsV2Error>>()
+    {
+        return response.status.as_u16() == 403;
+    }
+
+    error
+        .downcast_ref::<RiakCSError>()
+        .map(|error| error.code() == 403)
+        .unwrap_or(false)
+}
+
+fn preflight_failure(error: &anyhow::Error) -> PreflightFailure {
+    if let Some(error) = error.downcast_ref::<RusotoError<ListObjectsV2Error>>() {
+        return match error {
+            RusotoError::Service(ListObjectsV2Error::NoSuchBucket(_)) => {
+                PreflightFailure::NoSuchBucket
+            }
+            RusotoError::HttpDispatch(_) => PreflightFailure::Unreachable,
+            RusotoError::Credentials(_) => PreflightFailure::AccessDenied,
+            RusotoError::Unknown(response) => match response.status.as_u16() {
+                401 | 403 => PreflightFailure::AccessDenied,
+                404 => PreflightFailure::NoSuchBucket,
+                _ => PreflightFailure::Other,
+            },
+            _ => PreflightFailure::Other,
+        };
+    }
+
+    if let Some(error) = error.downcast_ref::<RiakCSError>() {
+        return match error.code() {
+            401 | 403 => PreflightFailure::AccessDenied,
+            404 => PreflightFailure::NoSuchBucket,
+            _ => PreflightFailure::Other,
+        };
+    }
+
+    // The RiakCS client sends its requests with hyper
+    if error.chain().any(|cause| cause.is::<hyper::Error>()) {
+        PreflightFailure::Unreachable
+    } else {
+        PreflightFailure::Other
+    }
+}
+
+/// Lists a single object of the source and destination buckets, so a bucket which can't be
+/// migrated fails right away instead of after a long listing. The destination bucket may not
+/// exist yet in dry run mode, it is created with `auto_create_destination_bucket`. Writing to
+/// the destination is checked once for all the buckets by `check_destination_write_permissions`
+pub async fn preflight_check(conf: &BucketMigrationConfiguration) -> anyhow::Result<()> {
+    let (source_provider, destination_provider) = get_bucket_providers(conf);
+    let sides = [
+        ("source", &conf.source_bucket, source_provider),
+        (
+            "destination",
+            &conf.destination_bucket,
+            destination_provider,
+        ),
+    ];
+
+    for (side, bucket, provider) in sides {
+        if let Some(Err(error)) = provider.list_objects(Some(1), None).next().await {
+            let failure = preflight_failure(&error);
+            if side == "destination" && failure == PreflightFailure::NoSuchBucket {
+                if conf.dry_run {
+                    continue;
+                }
+
+                if conf.auto_create_destination_bucket {
+                    create_missing_destination_bucket(conf).await?;
+                    continue;
+                }
+            }
+
+            return Err(anyhow::Error::new(PreflightError {
+                side,
+                bucket: bucket.clone(),
+                failure,
+                reason: format!("{:#}", error),
+            }));
+        }
+    }
+
+    event!(
+        Level::DEBUG,
+        "{} | Source and destination buckets can be listed",
+        conf.source_bucket
+    );
+
+    Ok(())
+}
+
+/// The destination bucket may have been deleted since `create_destination_buckets` ran, e.g.
+/// between two passes of a continuous migration. The new bucket is empty, every source object
+/// is synchronized
+async fn create_missing_destination_bucket(
+    conf: &BucketMigrationConfiguration,
+) -> anyhow::Result<()> {
+    event!(
+        Level::WARN,
+        "{} | Destination bucket {} is missing, creating it",
+        conf.source_bucket,
+        conf.destination_bucket
+    );
+
+    let client = RadosGW::new(
+        Some(conf.destination_endpoint.clone()),
+        Some(conf.destination_region.clone()),
+        conf.destination_access_key.clone(),
+        conf.destination_secret_key.clone(),
+        None,
+    )
+    .with_timeouts(conf.destination_timeouts());
+
+    match client.create_bucket(conf.destination_bucket.clone()).await {
+        Ok(_) | Err(RusotoError::Service(CreateBucketError::BucketAlreadyOwnedByYou(_))) => {
+            event!(
+                Level::INFO,
+                "Bucket {} | Bucket created",
+                conf.destination_bucket
+            );
+            Ok(())
+        }
+        Err(error) => {
+            bucket_already_created(&conf.destination_bucket);
+            Err(anyhow::Error::from(error))
+        }
+    }
+}
+
+#[instrument(skip_all, level = "debug", fields(run_id = %conf.run_id, bucket = %conf.source_bucket, destination_bucket = %conf.destination_bucket))]
+/// Multipart uploads in progress on the destination bucket, by key. None are resumed if they
+/// can't be listed
+async fn list_multipart_uploads(
+    conf: &BucketMigrationConfiguration,
+) -> HashMap<String, Vec<MultipartUpload>> {
+    let radosgw_client = RadosGW::new(
+        Some(conf.destination_endpoint.clone()),
+        Some(conf.destination_region.clone()),
+        conf.destination_access_key.clone(),
+        conf.destination_secret_key.clone(),
+        Some(conf.destination_bucket.clone()),
+    )
+    .with_prefix(match &conf.key_transform {
+        Some(_) => None,
+        None => conf.include_prefix.clone(),
+    })
+    .with_timeouts(conf.destination_timeouts());
+
+    let uploads = match radosgw_client.list_multipart_uploads().await {
+        Ok(uploads) => uploads,
+        Err(error) => {
+            event!(
+                Level::WARN,
+                "{} | Failed to list the multipart uploads in progress on the destination, none will be resumed: {:?}",
+                conf.source_bucket,
+                error
+            );
+            return HashMap::new();
+        }
+    };
+
+    let mut uploads_by_key: HashMap<String, Vec<MultipartUpload>> = HashMap::new();
+    for upload in uploads {
+        if let Some(key) = upload.key.clone() {
+            uploads_by_key.entry(key).or_default().push(upload);
+        }
+    }
+    uploads_by_key
+}
+
+pub async fn migrate_bucket(
+    mut conf: BucketMigrationConfiguration,
+) -> anyhow::Result<BucketMigrationStats> {
+    let sync_start = std::time::Instant::now();
+
+    preflight_check(&conf).await?;
+
+    if conf.resume_multipart_uploads && !conf.dry_run {
+        conf.multipart_uploads = Arc::new(list_multipart_uploads(&conf).await);
+    }
+
+    if let Some(planned) = conf.planned.clone() {
+        return apply_plan(conf, &planned).await;
+    }
+
+    let async_conf = conf.clone();
+    let source_provider_conf = ProviderConf::new(
+        conf.source_endpoint,
+        conf.source_region,
         conf.source_access_key,
         conf.source_secret_key,
         Some(conf.source_bucket.clone()),
-    );
+    )
+    .with_requester_pays(conf.source_requester_pays)
+    .with_prefix(conf.include_prefix.clone());
 
+    // The destination diff is scoped to the same keys as the source. Rewritten keys don't
+    // follow the order of the source keys, the whole destination is listed first instead
     let dest_provider_conf = ProviderConf::new(
         Some(conf.destination_endpoint),
-        None,
+        Some(conf.destination_region),
         conf.destination_access_key,
         conf.destination_secret_key,
         Some(conf.destination_bucket.clone()),
-    );
+    )
+    .with_prefix(match &conf.key_transform {
+        Some(_) => None,
+        None => conf.include_prefix.clone(),
+    })
+    .with_timeouts(async_conf.destination_timeouts())
+    .with_page_size(conf.destination_max_keys);
 
     let source_provider = get_provider(&conf.source_provider, source_provider_conf);
     let dest_provider = get_provider(&Providers::Cellar, dest_provider_conf);
 
-    let mut source_objects_stream = source_provider.list_objects(None, None);
-    let mut dest_listing = dest_provider.list_objects(None, None);
+    // When resuming, both listings start after the cursor so the diff stays consistent
+    if conf.max_bucket_objects.is_some() || conf.max_bucket_bytes.is_some() {
+        check_bucket_limits(&async_conf, &*source_provider).await?;
+    }
+
+    let dest_start_after = match &conf.key_transform {
+        Some(_) => None,
+        None => conf.start_after.clone(),
+    };
+    let BucketListings {
+        source: mut source_objects_stream,
+        destination: mut dest_listing,
+        source_empty,
+    } = list_bucket(
+        &*source_provider,
+        &*dest_provider,
+        conf.start_after.clone(),
+        dest_start_after,
+    )
+    .await
+    .map_err(|err| source_listing_error(&async_conf, err))?;
+    if source_empty && conf.start_after.is_none() && !conf.delete_destination_files {
+        event!(
+            Level::WARN,
+            "{} | Source bucket is empty, nothing to synchronize",
+            conf.source_bucket
+        );
+
+        if conf.empty_source == EmptySourcePolicy::Fail {
+            return Err(anyhow::Error::new(SourceEmptyError {
+                bucket: conf.source_bucket.clone(),
+            }));
+        }
+
+        return Ok(BucketMigrationStats {
+            bucket: conf.source_bucket.clone(),
+            synchronization_time: sync_start.elapsed(),
+            synchronization_size: 0,
+            per_object_timings: async_conf.per_object_timings(),
+            delete_size: 0,
+            total_files_sync: 0,
+            total_files_delete: 0,
+            total_files_in_sync: 0,
+            total_files_size_excluded: 0,
+            resume_token: None,
+        });
+    }
 
     // Instead of listing all the files from each side and diff, fetch from both sides some files.
     // From each fetch, check that the last source file is lesser than our last destination file
@@ -183,17 +1592,36 @@ pub async fn migrate_bucket(
     async {
         let mut sync_errors: Vec<anyhow::Error> = Vec::new();
         let mut delete_errors: Vec<anyhow::Error> = Vec::new();
+        let mut verification_errors: Vec<String> = Vec::new();
         let mut total_synced_size: usize = 0;
         let mut total_deleted_size: usize = 0;
         let mut total_files_sync: usize = 0;
         let mut total_files_delete: usize = 0;
+        let mut total_files_in_sync: usize = 0;
+        let mut total_files_size_excluded: usize = 0;
         let mut no_more_dst_objects = false;
         let mut dst_objects: Vec<ProviderObject> = Vec::new();
+        let mut destination_full: Option<String> = None;
+        // Last source key before which every object has been synchronized
+        let mut resume_after: Option<String> = conf.start_after.clone();
+        let mut resume_cursor_stuck = false;
+        let mut interrupted = false;
+        // The next source page is listed while the current one is synchronized, so the uploads
+        // don't wait for the listing between two pages
+        let mut prefetched_source_page = None;
+
+        loop {
+            let src_next = match prefetched_source_page.take() {
+                Some(page) => page,
+                None => source_objects_stream.next().await,
+            };
+            let src_next = match src_next {
+                Some(src_next) => src_next,
+                None => break,
+            };
 
-        while let Some(src_next) = source_objects_stream.next().await {
             if let Err(err) = src_next {
-                event!(Level::ERROR, "Failed to fetch source objects: {:?}", err);
-                anyhow::bail!(err);
+                return Err(source_listing_error(&async_conf, err));
             }
 
             let src_objects = src_next.ok().unwrap();
@@ -214,6 +1642,8 @@ pub async fn migrate_bucket(
             event!(Level::TRACE, "Migrate: source objects: {:#?}", src_objects);
             event!(Level::TRACE, "Migrate: dst_objects: {:#?}", dst_objects);
 
+            // Empty pages, e.g. of an empty source bucket, have nothing to compare. The
+            // destination objects are deleted once the source is listed
             if let Some(last_src) = src_objects.last() {
                 let mut fetch_dst_objects = false;
                 'inner: loop {
@@ -231,6 +1661,10 @@ pub async fn migrate_bucket(
                             order
                         );
                         match order {
+                            // Rewritten keys can be anywhere in the destination listing
+                            _ if async_conf.key_transform.is_some() => {
+                                fetch_dst_objects = true;
+                            }
                             // We have fetched more destination objects than source objects
                             // So let's sync
                             Ordering::Equal | Ordering::Less => {
@@ -252,14 +1686,35 @@ pub async fn migrate_bucket(
                                 dst_objects.extend(objects)
                             },
                             Some(Err(error)) => {
+                                if let Some(message) = error
+                                    .downcast_ref::<RusotoError<ListObjectsV2Error>>()
+                                    .and_then(clock_skew_message)
+                                {
+                                    event!(Level::ERROR, "{}", message);
+                                    anyhow::bail!(message);
+                                }
                                 match error.downcast_ref::<RusotoError<ListObjectsV2Error>>() {
-                                    Some(RusotoError::Service(ListObjectsV2Error::NoSuchBucket(bucket))) => {
+                                    Some(RusotoError::Service(ListObjectsV2Error::NoSuchBucket(_))) => {
                                         if conf.dry_run {
                                             // This may be normal since the bucket may not exist yet
                                             // treat it as empty
                                             no_more_dst_objects = true;
+                                        } else if conf.auto_create_destination_bucket {
+                                            // Deleted since the preflight check, e.g. between two
+                                            // passes of a continuous migration
+                                            create_missing_destination_bucket(&async_conf).await?;
+                                            no_more_dst_objects = true;
                                         } else {
-                                            unreachable!("We started migrating objects but dest bucket {} does not exist", bucket);
+                                            event!(
+                                                Level::ERROR,
+                                                "{} | Destination bucket {} was deleted during the migration",
+                                                conf.source_bucket,
+                                                conf.destination_bucket
+                                            );
+                                            anyhow::bail!(
+                                                "Destination bucket {} does not exist",
+                                                conf.destination_bucket
+                                            );
                                         }
                                     },
                                     _ => {
@@ -283,25 +1738,81 @@ pub async fn migrate_bucket(
                 event!(Level::DEBUG, "Source objects: {}", src_objects.len());
                 event!(Level::DEBUG, "Destination objects: {}", dst_objects.len());
 
-                let migration_result =
-                    migrate_objects(async_conf.clone(), &src_objects, &dst_objects).await;
+                // The destination objects after the last source object may be in the next
+                // source pages, they must not be deleted yet
+                let page_dst_end = match &async_conf.key_transform {
+                    Some(_) => dst_objects.len(),
+                    None => dst_objects
+                        .partition_point(|object| object.get_key() <= last_src.get_key()),
+                };
+                let page_dst_objects = &dst_objects[..page_dst_end];
+
+                let page_sync_errors = sync_errors.len();
+                let migration = async {
+                    match &async_conf.key_filter {
+                        // The filters match source keys, not the rewritten destination keys
+                        Some(key_filter) if async_conf.key_transform.is_some() => {
+                            migrate_objects(
+                                async_conf.clone(),
+                                &key_filter.filter(&src_objects),
+                                page_dst_objects,
+                            )
+                            .await
+                        }
+                        Some(key_filter) => {
+                            migrate_objects(
+                                async_conf.clone(),
+                                &key_filter.filter(&src_objects),
+                                &key_filter.filter(page_dst_objects),
+                            )
+                            .await
+                        }
+                        None => {
+                            migrate_objects(async_conf.clone(), &src_objects, page_dst_objects)
+                                .await
+                        }
+                    }
+                };
+                let ((migration_result, size_excluded), next_source_page) =
+                    futures::join!(migration, source_objects_stream.next());
+                total_files_size_excluded += size_excluded;
+                prefetched_source_page = Some(next_source_page);
 
                 match migration_result {
-                    BucketObjectsMigrationResult::DryRun(to_migrate, to_delete) => {
+                    BucketObjectsMigrationResult::DryRun(to_migrate, to_delete, in_sync) => {
                         total_files_sync += to_migrate.len();
+                        total_files_in_sync += in_sync;
 
                         to_migrate.iter().for_each(|object| {
                             total_synced_size += object.get_size() as usize;
                             event!(
                                 Level::INFO,
+                                key = %object.get_key(),
+                                size = object.get_size(),
                                 "Object to sync : {}/{} - {}",
                                 async_conf.source_bucket,
                                 object.get_key(),
                                 ByteSize(object.get_size())
                             );
 
+                            match async_conf.print_copy_list {
+                                Some(CopyListFormat::Keys) => print!("{}\0", object.get_key()),
+                                Some(CopyListFormat::Tsv) => {
+                                    println!("{}\t{}", object.get_size(), object.get_key())
+                                }
+                                None => {}
+                            }
                         });
 
+                        if let Some(plan_writer) = &async_conf.plan_writer {
+                            plan_writer.record(
+                                &async_conf.source_bucket,
+                                &async_conf.destination_bucket,
+                                PlanAction::Copy,
+                                &to_migrate,
+                            )?;
+                        }
+
                         event!(Level::INFO,
                             "Current sync status: {} objects to sync for a total size of {}",
                             total_files_sync,
@@ -311,6 +1822,7 @@ pub async fn migrate_bucket(
                         if async_conf.delete_destination_files {
                             total_files_delete += to_delete.len();
                             to_delete.iter().for_each(|object| {
+                                total_deleted_size += object.get_size() as usize;
                                 event!(
                                     Level::INFO,
                                     "To delete on destination bucket: {}/{} - {}",
@@ -320,6 +1832,15 @@ pub async fn migrate_bucket(
                                 )
                             });
 
+                            if let Some(plan_writer) = &async_conf.plan_writer {
+                                plan_writer.record(
+                                    &async_conf.source_bucket,
+                                    &async_conf.destination_bucket,
+                                    PlanAction::Delete,
+                                    &to_delete,
+                                )?;
+                            }
+
                             event!(Level::INFO,
                                 "Current delete status: {} objects to delete for a total size of {}",
                                 total_files_delete,
@@ -327,7 +1848,9 @@ pub async fn migrate_bucket(
                             );
                         }
                     }
-                    BucketObjectsMigrationResult::Executed(mut results) => {
+                    BucketObjectsMigrationResult::Executed(mut results, page_verification_errors) => {
+                        verification_errors.extend(page_verification_errors);
+
                         while let Some(result) = results.pop() {
                             let mut result = result.unwrap();
                             total_files_sync += result.sync_results.len();
@@ -341,6 +1864,9 @@ pub async fn migrate_bucket(
                                     Ok(size) => total_synced_size += size,
                                     Err(err) => {
                                         event!(Level::WARN, "Failed to sync a file: {:?}", err);
+                                        if let Some(full) = err.downcast_ref::<DestinationFull>() {
+                                            destination_full = Some(full.to_string());
+                                        }
                                         sync_errors.push(anyhow::anyhow!(err));
                                     }
                                 };
@@ -373,18 +1899,147 @@ pub async fn migrate_bucket(
                     }
                 };
 
+                // Once a page has failures, the following pages can't move the cursor
+                // otherwise the failed objects would be skipped by the next run
+                if sync_errors.len() > page_sync_errors {
+                    resume_cursor_stuck = true;
+                } else if !resume_cursor_stuck {
+                    resume_after = Some(last_src.get_key());
+                }
+
+                // All the following uploads would fail the same way, stop here
+                if let Some(reason) = destination_full {
+                    let stats = BucketMigrationStats {
+                        bucket: conf.source_bucket.clone(),
+                        synchronization_time: sync_start.elapsed(),
+                        synchronization_size: total_synced_size,
+                        per_object_timings: async_conf.per_object_timings(),
+                        delete_size: total_deleted_size,
+                        total_files_sync: total_files_sync - sync_errors.len(),
+                        total_files_delete,
+                        total_files_in_sync,
+                        total_files_size_excluded,
+                        resume_token: resume_after.map(|start_after| {
+                            ResumeToken::new(&async_conf, start_after).encode()
+                        }),
+                    };
+
+                    return Err(anyhow::Error::new(DestinationFullError { reason, stats }));
+                }
+
+                if async_conf.fail_fast && (!sync_errors.is_empty() || !delete_errors.is_empty()) {
+                    event!(
+                        Level::ERROR,
+                        "{} | Stopping at the first error (--fail-fast)",
+                        conf.source_bucket
+                    );
+                    interrupted = true;
+                    break;
+                }
+
                 // Cleanup old dst objets already migrated
-                dst_objects.retain(|object| {
-                    !matches!(object.get_key().cmp(&last_src.get_key()), Ordering::Equal | Ordering::Less)
-                });
-            } else {
-                // We don't have any more src objects to sync
-                unreachable!("We don't have any more src objects to sync");
+                if async_conf.key_transform.is_none() {
+                    dst_objects.retain(|object| {
+                        !matches!(object.get_key().cmp(&last_src.get_key()), Ordering::Equal | Ordering::Less)
+                    });
+                }
+            }
+        }
+
+        // The destination objects after the last source object don't exist in the source
+        if async_conf.delete_destination_files && !interrupted {
+            while !no_more_dst_objects {
+                match dest_listing.next().await {
+                    Some(Ok(objects)) => dst_objects.extend(objects),
+                    Some(Err(error)) => {
+                        event!(Level::ERROR, "Failed to fetch dest objects: {:?}", error);
+                        anyhow::bail!(error);
+                    }
+                    None => no_more_dst_objects = true,
+                }
+            }
+
+            let extraneous = match &async_conf.key_filter {
+                Some(key_filter) => key_filter.filter(&dst_objects),
+                None => dst_objects,
+            };
+
+            if !extraneous.is_empty() {
+                total_files_delete += extraneous.len();
+
+                if conf.dry_run {
+                    for object in &extraneous {
+                        total_deleted_size += object.get_size() as usize;
+                        event!(
+                            Level::INFO,
+                            "To delete on destination bucket: {}/{} - {}",
+                            async_conf.destination_bucket,
+                            object.get_key(),
+                            ByteSize(object.get_size())
+                        );
+                    }
+
+                    if let Some(plan_writer) = &async_conf.plan_writer {
+                        plan_writer.record(
+                            &async_conf.source_bucket,
+                            &async_conf.destination_bucket,
+                            PlanAction::Delete,
+                            &extraneous,
+                        )?;
+                    }
+                } else {
+                    event!(
+                        Level::INFO,
+                        "{} | Deleting {} objects missing from the source bucket",
+                        conf.source_bucket,
+                        extraneous.len()
+                    );
+
+                    let radosgw_client = RadosGW::new(
+                        Some(async_conf.destination_endpoint.clone()),
+                        Some(async_conf.destination_region.clone()),
+                        async_conf.destination_access_key.clone(),
+                        async_conf.destination_secret_key.clone(),
+                        Some(async_conf.destination_bucket.clone()),
+                    )
+                    .with_timeouts(async_conf.destination_timeouts());
+                    let keys: Vec<String> =
+                        extraneous.iter().map(|object| object.get_key()).collect();
+                    let mut failed = futures::stream::iter(keys.chunks(MAX_DELETE_KEYS))
+                        .map(|batch| radosgw_client.delete_objects(batch.to_vec()))
+                        .buffer_unordered(async_conf.delete_threads)
+                        .fold(HashMap::new(), |mut failed, batch_failed| async move {
+                            failed.extend(batch_failed);
+                            failed
+                        })
+                        .await;
+
+                    for object in extraneous {
+                        match failed.remove(&object.get_key()) {
+                            Some(reason) => {
+                                let err = anyhow::anyhow!(
+                                    "Failed to delete object {}: {}",
+                                    object.get_key(),
+                                    reason
+                                );
+                                event!(Level::WARN, "Failed to delete a file: {:?}", err);
+                                delete_errors.push(err);
+                            }
+                            None => total_deleted_size += object.get_size() as usize,
+                        }
+                    }
+                }
+
+                event!(Level::INFO,
+                    "Current delete status: {} objects to delete for a total size of {}",
+                    total_files_delete,
+                    ByteSize(total_deleted_size as u64)
+                );
             }
         }
 
         if !conf.dry_run {
-            if total_files_sync > 0 {
+            if total_files_sync > 0 || total_files_delete > 0 {
                 let sync_errors = sync_errors
                     .iter()
                     .map(|error| {
@@ -407,18 +2062,25 @@ pub async fn migrate_bucket(
 
                 let results_errors = vec![&sync_errors[..], &delete_errors[..]].concat();
 
-                if !results_errors.is_empty() {
+                if !results_errors.is_empty() || !verification_errors.is_empty() {
                     let stats = BucketMigrationStats {
                         bucket: conf.source_bucket.clone(),
                         synchronization_time: sync_start.elapsed(),
                         synchronization_size: total_synced_size,
+                        per_object_timings: async_conf.per_object_timings(),
                         delete_size: total_deleted_size,
                         total_files_sync,
                         total_files_delete,
+                        total_files_in_sync,
+                        total_files_size_excluded,
+                        resume_token: resume_after.map(|start_after| {
+                            ResumeToken::new(&async_conf, start_after).encode()
+                        }),
                     };
 
                     Err(anyhow::Error::new(BucketMigrationError {
                         errors: results_errors,
+                        verification_errors,
                         stats,
                     }))
                 } else {
@@ -426,9 +2088,13 @@ pub async fn migrate_bucket(
                         bucket: conf.source_bucket.clone(),
                         synchronization_time: sync_start.elapsed(),
                         synchronization_size: total_synced_size,
+                        per_object_timings: async_conf.per_object_timings(),
                         delete_size: total_deleted_size,
                         total_files_sync,
                         total_files_delete,
+                        total_files_in_sync,
+                        total_files_size_excluded,
+                        resume_token: None,
                     })
                 }
             } else {
@@ -441,9 +2107,13 @@ pub async fn migrate_bucket(
                     bucket: conf.source_bucket.clone(),
                     synchronization_time: sync_start.elapsed(),
                     synchronization_size: 0,
+                    per_object_timings: async_conf.per_object_timings(),
                     delete_size: total_deleted_size,
                     total_files_sync,
                     total_files_delete,
+                    total_files_in_sync,
+                    total_files_size_excluded,
+                    resume_token: None,
                 })
             }
         } else {
@@ -451,28 +2121,364 @@ pub async fn migrate_bucket(
                 bucket: conf.source_bucket.clone(),
                 synchronization_time: sync_start.elapsed(),
                 synchronization_size: total_synced_size,
+                per_object_timings: async_conf.per_object_timings(),
                 delete_size: total_deleted_size,
                 total_files_sync,
                 total_files_delete,
+                total_files_in_sync,
+                total_files_size_excluded,
+                resume_token: None,
             })
         }
     }
     .await
 }
 
+/// Outcome of the migration of each bucket run by `migrate_buckets`
+#[derive(Debug, Default)]
+pub struct MultiBucketMigrationReport {
+    /// Source bucket and its migration result, in the order the migrations completed
+    pub buckets: Vec<(String, anyhow::Result<BucketMigrationStats>)>,
+    /// Buckets not migrated because the destination is full or a bucket failed with fail fast
+    pub skipped_buckets: Vec<String>,
+}
+
+impl MultiBucketMigrationReport {
+    pub fn failed_buckets(&self) -> Vec<&str> {
+        self.buckets
+            .iter()
+            .filter(|(_, result)| result.is_err())
+            .map(|(bucket, _)| bucket.as_str())
+            .collect()
+    }
+
+    /// Objects which failed to synchronize or to be deleted, in the buckets which failed
+    pub fn failed_objects(&self) -> usize {
+        self.buckets
+            .iter()
+            .filter_map(|(_, result)| result.as_ref().err())
+            .filter_map(|error| error.downcast_ref::<BucketMigrationError>())
+            .map(|error| error.errors.len() + error.verification_errors.len())
+            .sum()
+    }
+
+    /// The run didn't synchronize everything when buckets failed or were skipped
+    pub fn failure(&self) -> Option<MigrationFailedError> {
+        let failed_buckets = self.failed_buckets().len();
+        if failed_buckets == 0 && self.skipped_buckets.is_empty() {
+            return None;
+        }
+
+        Some(MigrationFailedError {
+            failed_buckets,
+            failed_objects: self.failed_objects(),
+            skipped_buckets: self.skipped_buckets.len(),
+        })
+    }
+
+    /// Including the objects synchronized by the buckets which failed
+    pub fn synchronization_size(&self) -> usize {
+        self.buckets
+            .iter()
+            .filter_map(|(_, result)| migration_stats(result))
+            .map(|stats| stats.synchronization_size)
+            .sum()
+    }
+}
+
+/// Stats of a bucket migration, also available when some of its objects failed or the
+/// destination became full
+pub fn migration_stats(
+    migration_result: &anyhow::Result<BucketMigrationStats>,
+) -> Option<&BucketMigrationStats> {
+    match migration_result {
+        Ok(stats) => Some(stats),
+        Err(error) => error
+            .downcast_ref::<BucketMigrationError>()
+            .map(|error| &error.stats)
+            .or_else(|| {
+                error
+                    .downcast_ref::<DestinationFullError>()
+                    .map(|error| &error.stats)
+            }),
+    }
+}
+
+/// Migrates up to `bucket_concurrency` buckets at the same time. The concurrent buckets share
+/// the largest `sync_threads` of the configurations, so the number of objects synchronized at
+/// the same time doesn't grow with the number of buckets, and the same goes for the multipart
+/// uploads in progress. No new bucket is started once the destination is full or, with fail
+/// fast, once a bucket failed
+pub async fn migrate_buckets(
+    configs: Vec<BucketMigrationConfiguration>,
+    bucket_concurrency: usize,
+) -> MultiBucketMigrationReport {
+    let thread_budget = (bucket_concurrency > 1).then(|| {
+        let sync_threads = configs.iter().map(|conf| conf.sync_threads).max();
+        Arc::new(Semaphore::new(sync_threads.unwrap_or(1)))
+    });
+    let multipart_budget = configs
+        .iter()
+        .filter_map(|conf| conf.max_concurrent_multipart)
+        .min()
+        .map(|permits| Arc::new(Semaphore::new(permits)));
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let results: Vec<(String, Option<anyhow::Result<BucketMigrationStats>>)> =
+        futures::stream::iter(configs)
+            .map(|mut conf| {
+                let stopped = stopped.clone();
+                conf.thread_budget = thread_budget.clone();
+                conf.multipart_budget = multipart_budget.clone();
+                async move {
+                    let bucket = conf.source_bucket.clone();
+                    if stopped.load(AtomicOrdering::SeqCst) {
+                        return (bucket, None);
+                    }
+
+                    if conf.dry_run {
+                        event!(
+                            Level::INFO,
+                            "DRY-RUN | Bucket {} | Starting listing of files that need to be synchronized",
+                            bucket
+                        );
+                    } else {
+                        event!(
+                            Level::INFO,
+                            "Bucket {} | Starting migration of bucket",
+                            bucket
+                        );
+                    }
+
+                    let dry_run = conf.dry_run;
+                    let fail_fast = conf.fail_fast;
+                    let migration_result = migrate_bucket(conf).await;
+
+                    event!(
+                        Level::TRACE,
+                        "Bucket {} | Migration result: {:#?}",
+                        bucket,
+                        migration_result
+                    );
+
+                    if !dry_run {
+                        event!(
+                            Level::INFO,
+                            "Bucket {} | Bucket has been synchronized",
+                            bucket
+                        );
+                    }
+
+                    if matches!(&migration_result, Err(error) if error.is::<DestinationFullError>())
+                    {
+                        event!(
+                            Level::ERROR,
+                            "Bucket {} | Destination is full, the remaining buckets won't be synchronized",
+                            bucket
+                        );
+                        stopped.store(true, AtomicOrdering::SeqCst);
+                    } else if fail_fast && migration_result.is_err() {
+                        event!(
+                            Level::ERROR,
+                            "Bucket {} | Synchronization failed and --fail-fast is set, the remaining buckets won't be synchronized",
+                            bucket
+                        );
+                        stopped.store(true, AtomicOrdering::SeqCst);
+                    }
+
+                    (bucket, Some(migration_result))
+                }
+            })
+            .buffer_unordered(bucket_concurrency.max(1))
+            .collect()
+            .await;
+
+    let mut report = MultiBucketMigrationReport::default();
+    for (bucket, result) in results {
+        match result {
+            Some(result) => report.buckets.push((bucket, result)),
+            None => report.skipped_buckets.push(bucket),
+        }
+    }
+
+    report
+}
+
+/// Makes sure two source buckets are never synchronized into the same destination bucket.
+/// Bucket names are DNS names on the destination so they are compared case insensitively.
+fn check_destination_bucket_collisions(
+    destination_buckets: &DestinationBucketNames,
+    buckets: &[String],
+) -> anyhow::Result<()> {
+    let mut destinations: HashMap<String, Vec<&String>> = HashMap::new();
+    for bucket in buckets {
+        let destination = destination_buckets.name(bucket);
+        destinations
+            .entry(destination.to_lowercase())
+            .or_default()
+            .push(bucket);
+    }
+
+    let mut collisions = destinations
+        .into_iter()
+        .filter(|(_, sources)| sources.len() > 1)
+        .map(|(destination, sources)| {
+            format!(
+                "{} <- {}",
+                destination,
+                sources
+                    .iter()
+                    .map(|source| source.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            )
+        })
+        .collect::<Vec<String>>();
+
+    if !collisions.is_empty() {
+        collisions.sort();
+        anyhow::bail!(
+            "Several source buckets would be synchronized into the same destination bucket: {}",
+            collisions.join("; ")
+        );
+    }
+
+    Ok(())
+}
+
+fn normalize_endpoint(endpoint: &str) -> String {
+    let endpoint = endpoint.trim().to_lowercase();
+    let endpoint = endpoint
+        .strip_prefix("https://")
+        .or_else(|| endpoint.strip_prefix("http://"))
+        .unwrap_or(&endpoint);
+
+    endpoint.trim_end_matches('/').to_string()
+}
+
+/// Makes sure no bucket is synchronized into itself: objects would be read and overwritten at
+/// the same time and, with --delete, removed. Runs before any operation on the destination
+pub fn check_source_is_not_destination(
+    source_endpoint: Option<&str>,
+    destination_endpoint: &str,
+    destination_buckets: &DestinationBucketNames,
+    buckets: &[String],
+) -> anyhow::Result<()> {
+    // Without an endpoint, the source is AWS S3
+    let source_endpoint = match source_endpoint {
+        Some(source_endpoint) => normalize_endpoint(source_endpoint),
+        None => return Ok(()),
+    };
+    if source_endpoint != normalize_endpoint(destination_endpoint) {
+        return Ok(());
+    }
+
+    let same_buckets = buckets
+        .iter()
+        .filter(|bucket| {
+            destination_buckets
+                .name(bucket)
+                .eq_ignore_ascii_case(bucket)
+        })
+        .map(|bucket| bucket.as_str())
+        .collect::<Vec<&str>>();
+
+    if !same_buckets.is_empty() {
+        anyhow::bail!(
+            "Source and destination are the same bucket on {}: {}. Check --destination-endpoint, --destination-bucket, --destination-bucket-prefix and --destination-bucket-template",
+            source_endpoint,
+            same_buckets.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Preflight run before any listing: credentials allowed to list may still be read-only
+#[instrument(skip(destination_access_key, destination_secret_key), level = "debug")]
+pub async fn check_destination_write_permissions(
+    destination_endpoint: String,
+    destination_region: String,
+    destination_access_key: String,
+    destination_secret_key: String,
+    destination_buckets: &DestinationBucketNames,
+    buckets: &[String],
+    run_id: &str,
+) -> anyhow::Result<()> {
+    for bucket in buckets {
+        let destination_bucket = destination_buckets.name(bucket);
+        let client = RadosGW::new(
+            Some(destination_endpoint.clone()),
+            Some(destination_region.clone()),
+            destination_access_key.clone(),
+            destination_secret_key.clone(),
+            Some(destination_bucket.clone()),
+        );
+
+        client
+            .check_write_permission(format!(".cellar-migration-canary-{}", run_id))
+            .await
+            .map_err(|error| {
+                anyhow::anyhow!(
+                    "Destination bucket {} isn't writable with the given credentials: {}",
+                    destination_bucket,
+                    error
+                )
+            })?;
+
+        event!(
+            Level::DEBUG,
+            "Destination bucket {} is writable",
+            destination_bucket
+        );
+    }
+
+    Ok(())
+}
+
+/// Preflight of each bucket: with BucketOwnerEnforced ownership controls, the destination bucket
+/// rejects the uploads setting an object ACL
+pub async fn check_destination_object_acls(conf: &BucketMigrationConfiguration) -> bool {
+    let client = RadosGW::new(
+        Some(conf.destination_endpoint.clone()),
+        Some(conf.destination_region.clone()),
+        conf.destination_access_key.clone(),
+        conf.destination_secret_key.clone(),
+        Some(conf.destination_bucket.clone()),
+    )
+    .with_timeouts(conf.destination_timeouts());
+
+    let object_acls_disabled = client.object_acls_disabled().await;
+    if object_acls_disabled {
+        event!(
+            Level::WARN,
+            "Bucket {} | Object ACLs are disabled on destination bucket {} (BucketOwnerEnforced), public objects will be uploaded without ACL. Grant them public read access with a bucket policy instead",
+            conf.source_bucket,
+            conf.destination_bucket
+        );
+    }
+
+    object_acls_disabled
+}
+
 #[instrument(skip(destination_access_key, destination_secret_key), level = "debug")]
 pub async fn create_destination_buckets(
     destination_endpoint: String,
+    destination_region: String,
     destination_access_key: String,
     destination_secret_key: String,
-    destination_bucket: Option<String>,
-    destination_bucket_prefix: String,
+    destination_buckets: &DestinationBucketNames,
     buckets: &[String],
     dry_run: bool,
 ) -> anyhow::Result<()> {
+    destination_buckets
+        .check_names(buckets)
+        .map_err(anyhow::Error::msg)?;
+    check_destination_bucket_collisions(destination_buckets, buckets)?;
+
     let client = RadosGW::new(
         Some(destination_endpoint.clone()),
-        None,
+        Some(destination_region.clone()),
         destination_access_key.clone(),
         destination_secret_key.clone(),
         None,
@@ -483,8 +2489,7 @@ pub async fn create_destination_buckets(
         buckets
             .iter()
             .filter(|riakcs_bucket| {
-                let riakcs_bucket_name =
-                    format!("{}{}", destination_bucket_prefix, **riakcs_bucket);
+                let riakcs_bucket_name = destination_buckets.name(riakcs_bucket);
 
                 !radosgw_buckets.iter().any(|radosgw_bucket| -> bool {
                     let radosgw_bucket_name = radosgw_bucket
@@ -499,24 +2504,20 @@ pub async fn create_destination_buckets(
     };
 
     for bucket in missing_buckets {
-        let destination_bucket = if let Some(destination_bucket) = &destination_bucket {
-            format!("{}{}", destination_bucket_prefix, destination_bucket)
-        } else {
-            format!("{}{}", destination_bucket_prefix, bucket)
-        };
+        let destination_bucket = destination_buckets.name(bucket);
 
         if dry_run {
             // To know if the bucket already exists on another add-on, we can try to list its files. If it's not created, we will receive a NoSuchBucket error
             // If it is, we will receive another error
             let client_dry_run = get_provider(
                 &Providers::Cellar,
-                ProviderConf {
-                    endpoint: Some(destination_endpoint.clone()),
-                    region: None,
-                    access_key: destination_access_key.clone(),
-                    secret_key: destination_secret_key.clone(),
-                    bucket: Some(destination_bucket.clone()),
-                },
+                ProviderConf::new(
+                    Some(destination_endpoint.clone()),
+                    Some(destination_region.clone()),
+                    destination_access_key.clone(),
+                    destination_secret_key.clone(),
+                    Some(destination_bucket.clone()),
+                ),
             );
 
             match client_dry_run.list_objects(Some(1), None).next().await {
@@ -565,3 +2566,920 @@ fn bucket_already_created(bucket: &str) {
     event!(Level::ERROR, "Bucket {} | Bucket can't be created because it probably has been created in another Cellar add-on, maybe by another user.", bucket);
     event!(Level::ERROR, "Please refer to https://github.com/CleverCloud/cellar-migration/#my-bucket-already-exists-on-the-destination-cluster to find a workaround");
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::{
+        acl_rules::ObjectGrant,
+        benchmark::SyntheticProvider,
+        key_transform::UnmatchedKeysPolicy,
+        provider::ProviderResponse,
+        radosgw::mock_destination::{
+            accept_uploads, error_response, etag_response, list_objects_response,
+            paginated_list_objects_response, Concurrency, MockDestination, ReceivedRequest,
+        },
+    };
+    use hyper::{Body, Method, Response};
+
+    fn object(key: &str, size: u64, etag: &str) -> ProviderObject {
+        ProviderObject::planned(key.to_string(), size, etag.to_string(), Utc::now(), None)
+    }
+
+    /// Migration of bucket `source` to bucket `destination`, comparing the ETags
+    fn configuration() -> BucketMigrationConfiguration {
+        BucketMigrationConfiguration {
+            run_id: "run".to_string(),
+            source_bucket: "source".to_string(),
+            source_access_key: "access_key".to_string(),
+            source_secret_key: "secret_key".to_string(),
+            source_endpoint: Some("127.0.0.1:1".to_string()),
+            source_region: None,
+            source_provider: Providers::RiakCS,
+            destination_bucket: "destination".to_string(),
+            destination_access_key: "access_key".to_string(),
+            destination_secret_key: "secret_key".to_string(),
+            destination_endpoint: "http://127.0.0.1:1".to_string(),
+            destination_region: "us-east-1".to_string(),
+            auto_create_destination_bucket: false,
+            delete_destination_files: false,
+            include_prefix: None,
+            key_filter: None,
+            key_transform: None,
+            max_keys: 1000,
+            min_object_size: None,
+            max_object_size: None,
+            destination_max_keys: 1000,
+            multipart_threshold: MIN_MULTIPART_PART_SIZE as u64,
+            multipart_part_size: MIN_MULTIPART_PART_SIZE,
+            sync_threads: 1,
+            verify_threads: 1,
+            delete_threads: 1,
+            max_concurrent_multipart: None,
+            multipart_budget: None,
+            resume_multipart_uploads: false,
+            multipart_uploads: Arc::new(HashMap::new()),
+            small_object_threshold: None,
+            small_object_threads: 0,
+            priority: ObjectPriority::Listing,
+            source_etag_cache: None,
+            checksum_manifest: None,
+            state_file: None,
+            object_report: None,
+            content_dedup: None,
+            #[cfg(feature = "sqlite-status")]
+            status_db: None,
+            acl_rules: None,
+            preserve_acl: false,
+            preserve_tags: false,
+            object_acls_disabled: false,
+            infer_content_type: false,
+            progress: Arc::new(SyncProgress::default()),
+            verification_report: None,
+            verify: false,
+            compare: CompareStrategy::ETag,
+            etag_filter: ETagFilter::All,
+            unsafe_keys: UnsafeKeysPolicy::Copy,
+            trust_size_for_multipart: false,
+            recopy_storage_class_changes: false,
+            skip_existing_fast: false,
+            storage_class: None,
+            storage_class_rules: Vec::new(),
+            simulated_failures: None,
+            truncate_bytes: None,
+            record_source_bucket: false,
+            metadata_filter: None,
+            modified_filter: None,
+            encryption_mirror: None,
+            server_side_encryption: None,
+            sse_kms_key_id: None,
+            timing_trace: None,
+            object_timings: None,
+            fail_fast: false,
+            circuit_breaker: None,
+            run_window: None,
+            bandwidth_limits: Vec::new(),
+            verify_checksum: false,
+            thread_budget: None,
+            max_retries: 0,
+            retry_base_delay: std::time::Duration::ZERO,
+            connect_timeout: None,
+            request_timeout: None,
+            max_bucket_objects: None,
+            max_bucket_bytes: None,
+            empty_source: EmptySourcePolicy::Ignore,
+            source_requester_pays: false,
+            print_copy_list: None,
+            plan_writer: None,
+            planned: None,
+            start_after: None,
+            dry_run: false,
+        }
+    }
+
+    /// Lists `pages`, counting the pages fetched, and answers the HEAD requests of `objects`
+    #[derive(Debug, Clone, Default)]
+    struct ListedProvider {
+        pages: Vec<Result<Vec<ProviderObject>, String>>,
+        pages_listed: Arc<AtomicUsize>,
+        objects: Vec<ProviderObject>,
+    }
+
+    #[async_trait::async_trait]
+    impl Provider for ListedProvider {
+        async fn get_buckets(&self) -> anyhow::Result<Vec<String>> {
+            unimplemented!()
+        }
+
+        fn list_objects(&self, _: Option<usize>, _: Option<String>) -> ObjectListing<'_> {
+            Box::pin(futures::stream::iter(self.pages.clone()).map(move |page| {
+                self.pages_listed.fetch_add(1, AtomicOrdering::SeqCst);
+                page.map_err(anyhow::Error::msg)
+            }))
+        }
+
+        async fn get_object_metadata(
+            &self,
+            object: &ProviderObject,
+        ) -> anyhow::Result<ProviderObjectMetadata> {
+            let found = self
+                .objects
+                .iter()
+                .find(|found| found.get_key() == object.get_key())
+                .ok_or_else(|| anyhow::anyhow!("NoSuchKey"))?;
+            let mut metadata = SyntheticProvider {}.get_object_metadata(found).await?;
+            metadata.etag = Some(found.get_etag().to_string());
+            Ok(metadata)
+        }
+
+        async fn get_object(
+            &self,
+            _: &ProviderObject,
+        ) -> anyhow::Result<Box<dyn ProviderResponse>> {
+            unimplemented!()
+        }
+
+        async fn get_object_range(
+            &self,
+            _: &ProviderObject,
+            _: u64,
+            _: u64,
+        ) -> anyhow::Result<Box<dyn ProviderResponse>> {
+            unimplemented!()
+        }
+
+        async fn get_object_if_none_match(
+            &self,
+            _: &ProviderObject,
+            _: &str,
+        ) -> anyhow::Result<Option<Box<dyn ProviderResponse>>> {
+            unimplemented!()
+        }
+
+        async fn get_object_tags(
+            &self,
+            _: &ProviderObject,
+        ) -> anyhow::Result<HashMap<String, String>> {
+            unimplemented!()
+        }
+
+        async fn get_object_acl(&self, _: &ProviderObject) -> anyhow::Result<Vec<ObjectGrant>> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn source_listing_error_stops_the_bucket_before_the_destination_is_listed() {
+        let source = ListedProvider {
+            pages: vec![
+                Err("AccessDenied".to_string()),
+                Ok(vec![object("b", 10, "bb")]),
+            ],
+            ..Default::default()
+        };
+        let destination = ListedProvider {
+            pages: vec![Ok(vec![object("a", 10, "aa")])],
+            ..Default::default()
+        };
+
+        let listings = list_bucket(&source, &destination, None, None).await;
+        assert_eq!(
+            listings.err().map(|error| error.to_string()),
+            Some("AccessDenied".to_string())
+        );
+        assert_eq!(source.pages_listed.load(AtomicOrdering::SeqCst), 1);
+        assert_eq!(destination.pages_listed.load(AtomicOrdering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn destination_is_listed_after_the_first_source_page() {
+        let source = ListedProvider {
+            pages: vec![
+                Ok(vec![object("a", 10, "aa")]),
+                Ok(vec![object("b", 10, "bb")]),
+            ],
+            ..Default::default()
+        };
+        let destination = ListedProvider {
+            pages: vec![Ok(vec![object("a", 10, "aa")])],
+            ..Default::default()
+        };
+
+        let mut listings = list_bucket(&source, &destination, None, None)
+            .await
+            .unwrap();
+        assert!(!listings.source_empty);
+        assert_eq!(destination.pages_listed.load(AtomicOrdering::SeqCst), 0);
+
+        let keys = |page: Option<anyhow::Result<Vec<ProviderObject>>>| {
+            page.unwrap()
+                .unwrap()
+                .iter()
+                .map(|object| object.get_key())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(keys(listings.source.next().await), vec!["a"]);
+        assert_eq!(keys(listings.source.next().await), vec!["b"]);
+        assert_eq!(keys(listings.destination.next().await), vec!["a"]);
+        assert_eq!(source.pages_listed.load(AtomicOrdering::SeqCst), 2);
+    }
+
+    /// Bucket on a local port holding `objects`
+    fn bucket(objects: Vec<ProviderObject>) -> MockDestination {
+        MockDestination::start(move |request| list_objects_response(request, &objects))
+    }
+
+    /// Dry run of the migration of the `source` bucket to the `destination` bucket
+    fn dry_run(
+        source: &MockDestination,
+        destination: &MockDestination,
+    ) -> BucketMigrationConfiguration {
+        BucketMigrationConfiguration {
+            source_provider: Providers::Cellar,
+            source_endpoint: Some(source.endpoint()),
+            destination_endpoint: destination.endpoint(),
+            dry_run: true,
+            ..configuration()
+        }
+    }
+
+    #[tokio::test]
+    async fn prefix_scopes_the_listings_of_both_buckets() {
+        let source = bucket(vec![
+            object("logs/2022/a", 10, "aa"),
+            object("logs/2023/a", 10, "aa"),
+            object("logs/2023/b", 10, "bb"),
+            object("other", 10, "cc"),
+        ]);
+        let destination = bucket(vec![
+            object("logs/2022/z", 10, "zz"),
+            object("logs/2023/a", 10, "aa"),
+        ]);
+
+        let stats = migrate_bucket(BucketMigrationConfiguration {
+            include_prefix: Some("logs/2023/".to_string()),
+            delete_destination_files: true,
+            ..dry_run(&source, &destination)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(stats.total_files_sync, 1);
+        assert_eq!(stats.total_files_in_sync, 1);
+        assert_eq!(stats.total_files_delete, 0);
+        let requests = [source.requests(), destination.requests()].concat();
+        for request in requests {
+            assert_eq!(request.query("prefix").as_deref(), Some("logs/2023/"));
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_run_only_lists_the_buckets() {
+        let source = bucket(vec![object("a", 10, "aa"), object("b", 10, "bb")]);
+        let destination = MockDestination::start(|_| error_response(404, "NoSuchBucket"));
+
+        let stats = migrate_bucket(BucketMigrationConfiguration {
+            auto_create_destination_bucket: true,
+            delete_destination_files: true,
+            ..dry_run(&source, &destination)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(stats.total_files_sync, 2);
+        assert_eq!(stats.synchronization_size, 20);
+        let requests = [source.requests(), destination.requests()].concat();
+        assert!(!destination.requests().is_empty());
+        for request in requests {
+            assert_eq!(request.method, Method::GET, "{}", request.uri);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn next_source_page_is_listed_while_the_current_page_is_synchronized() {
+        let objects = vec![
+            object("a", 5, "aa"),
+            object("b", 5, "bb"),
+            object("c", 5, "cc"),
+        ];
+        // Both buckets on the same port, so the requests are recorded in a single order
+        let buckets = MockDestination::start(move |request| {
+            let source = request.uri.starts_with("/source");
+            match (source, request.query("list-type")) {
+                (true, Some(_)) => paginated_list_objects_response(request, &objects, 1),
+                (true, None) => Response::new(Body::from(vec![0; 5])),
+                (false, Some(_)) => list_objects_response(request, &[]),
+                (false, None) => {
+                    // Leaves the time to list the next page during the upload
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    accept_uploads(request)
+                }
+            }
+        });
+
+        let stats = migrate_bucket(BucketMigrationConfiguration {
+            source_provider: Providers::Cellar,
+            source_endpoint: Some(buckets.endpoint()),
+            destination_endpoint: buckets.endpoint(),
+            ..configuration()
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(stats.total_files_sync, 3);
+        let requests = buckets.requests();
+        let position = |predicate: &dyn Fn(&ReceivedRequest) -> bool| {
+            requests.iter().position(predicate).unwrap()
+        };
+        let first_upload = position(&|request| request.method == Method::PUT);
+        let second_page = position(&|request| request.query("start-after").as_deref() == Some("a"));
+        let last_page = position(&|request| request.query("start-after").as_deref() == Some("b"));
+        assert!(second_page < first_upload, "{:?}", requests);
+        assert!(first_upload < last_page, "{:?}", requests);
+    }
+
+    #[tokio::test]
+    async fn destination_requests_are_signed_with_the_destination_region() {
+        let source = bucket(vec![object("a", 10, "aa")]);
+        let destination = bucket(Vec::new());
+
+        migrate_bucket(BucketMigrationConfiguration {
+            destination_region: "zone-a".to_string(),
+            ..dry_run(&source, &destination)
+        })
+        .await
+        .unwrap();
+
+        let requests = destination.requests();
+        assert!(!requests.is_empty());
+        for request in requests {
+            let authorization = request.header("Authorization").unwrap_or_default();
+            assert!(
+                authorization.contains("/zone-a/s3/aws4_request"),
+                "{}",
+                request.uri
+            );
+        }
+    }
+
+    /// Source and destination buckets on the same port, the destination answering the uploads
+    /// with `upload_status`
+    fn buckets(objects: Vec<ProviderObject>, upload_status: u16) -> MockDestination {
+        MockDestination::start(move |request| {
+            let source = request.uri.starts_with("/source");
+            match (source, request.query("list-type")) {
+                (true, Some(_)) => list_objects_response(request, &objects),
+                (true, None) => Response::new(Body::from(vec![0; 10])),
+                (false, Some(_)) => list_objects_response(request, &[]),
+                (false, None) if upload_status == 200 => accept_uploads(request),
+                (false, None) => error_response(upload_status, "AccessDenied"),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn run_with_failed_objects_is_a_failure() {
+        let buckets = buckets(vec![object("a", 10, "aa"), object("b", 10, "bb")], 403);
+        let conf = BucketMigrationConfiguration {
+            source_provider: Providers::Cellar,
+            source_endpoint: Some(buckets.endpoint()),
+            destination_endpoint: buckets.endpoint(),
+            ..configuration()
+        };
+
+        let report = migrate_buckets(vec![conf], 1).await;
+
+        let failure = report.failure().expect("The run should fail");
+        assert_eq!(failure.failed_buckets, 1);
+        assert_eq!(failure.failed_objects, 2);
+        assert_eq!(failure.skipped_buckets, 0);
+    }
+
+    #[tokio::test]
+    async fn run_synchronizing_everything_is_a_success() {
+        let buckets = buckets(vec![object("a", 10, "aa")], 200);
+        let conf = BucketMigrationConfiguration {
+            source_provider: Providers::Cellar,
+            source_endpoint: Some(buckets.endpoint()),
+            destination_endpoint: buckets.endpoint(),
+            ..configuration()
+        };
+
+        let report = migrate_buckets(vec![conf], 1).await;
+
+        assert!(report.failure().is_none(), "{:?}", report.buckets);
+    }
+
+    /// Side and failure of the preflight check of the `source` and `destination` buckets
+    async fn preflight(
+        conf: BucketMigrationConfiguration,
+    ) -> Option<(&'static str, PreflightFailure)> {
+        let error = preflight_check(&conf).await.err()?;
+        let error = error
+            .downcast_ref::<PreflightError>()
+            .expect("The preflight check should fail with a PreflightError");
+        Some((error.side, error.failure))
+    }
+
+    fn execution(
+        source: &MockDestination,
+        destination: &MockDestination,
+    ) -> BucketMigrationConfiguration {
+        BucketMigrationConfiguration {
+            dry_run: false,
+            ..dry_run(source, destination)
+        }
+    }
+
+    #[tokio::test]
+    async fn preflight_of_listable_buckets() {
+        let source = bucket(vec![object("a", 10, "aa")]);
+        let destination = bucket(Vec::new());
+
+        assert_eq!(preflight(execution(&source, &destination)).await, None);
+        for request in [source.requests(), destination.requests()].concat() {
+            assert_eq!(request.query("max-keys").as_deref(), Some("1"));
+        }
+    }
+
+    #[tokio::test]
+    async fn preflight_of_a_source_denying_access() {
+        let source = MockDestination::start(|_| error_response(403, "AccessDenied"));
+        let destination = bucket(Vec::new());
+
+        assert_eq!(
+            preflight(execution(&source, &destination)).await,
+            Some(("source", PreflightFailure::AccessDenied))
+        );
+        assert!(destination.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn preflight_of_a_missing_destination_bucket() {
+        let source = bucket(Vec::new());
+        let destination = MockDestination::start(|_| error_response(404, "NoSuchBucket"));
+
+        assert_eq!(
+            preflight(execution(&source, &destination)).await,
+            Some(("destination", PreflightFailure::NoSuchBucket))
+        );
+        // A dry run doesn't need the destination bucket
+        assert_eq!(preflight(dry_run(&source, &destination)).await, None);
+    }
+
+    /// Source holding `objects` and destination bucket created by its first PUT request
+    fn missing_destination_bucket(objects: Vec<ProviderObject>) -> MockDestination {
+        let created = AtomicBool::new(false);
+        MockDestination::start(move |request| {
+            let source = request.uri.starts_with("/source");
+            match (source, request.query("list-type")) {
+                (true, Some(_)) => list_objects_response(request, &objects),
+                (true, None) => Response::new(Body::from(vec![0; 10])),
+                (false, Some(_)) if created.load(AtomicOrdering::SeqCst) => {
+                    list_objects_response(request, &[])
+                }
+                (false, Some(_)) => error_response(404, "NoSuchBucket"),
+                (false, None) if request.uri == "/destination" => {
+                    created.store(true, AtomicOrdering::SeqCst);
+                    Response::new(Body::empty())
+                }
+                (false, None) => accept_uploads(request),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn missing_destination_bucket_is_created_then_migrated() {
+        let buckets = missing_destination_bucket(vec![object("a", 10, "aa")]);
+        let conf = BucketMigrationConfiguration {
+            auto_create_destination_bucket: true,
+            ..execution(&buckets, &buckets)
+        };
+
+        let report = migrate_buckets(vec![conf], 1).await;
+
+        assert!(report.failure().is_none(), "{:?}", report.buckets);
+        let writes: Vec<String> = buckets
+            .requests()
+            .into_iter()
+            .filter(|request| request.method == Method::PUT)
+            .map(|request| request.uri)
+            .collect();
+        assert_eq!(writes, ["/destination", "/destination/a"]);
+    }
+
+    #[tokio::test]
+    async fn missing_destination_bucket_is_not_created_in_dry_run() {
+        let buckets = missing_destination_bucket(vec![object("a", 10, "aa")]);
+        let conf = BucketMigrationConfiguration {
+            auto_create_destination_bucket: true,
+            ..dry_run(&buckets, &buckets)
+        };
+
+        let report = migrate_buckets(vec![conf], 1).await;
+
+        assert!(report.failure().is_none(), "{:?}", report.buckets);
+        assert!(buckets
+            .requests()
+            .iter()
+            .all(|request| request.method != Method::PUT));
+    }
+
+    #[tokio::test]
+    async fn preflight_of_an_unreachable_destination() {
+        let source = bucket(Vec::new());
+        let conf = BucketMigrationConfiguration {
+            // Nothing listens on port 1
+            destination_endpoint: "http://127.0.0.1:1".to_string(),
+            ..execution(&source, &source)
+        };
+
+        assert_eq!(
+            preflight(conf).await,
+            Some(("destination", PreflightFailure::Unreachable))
+        );
+    }
+
+    fn keys(objects: &[ProviderObject]) -> Vec<String> {
+        objects.iter().map(|object| object.get_key()).collect()
+    }
+
+    #[tokio::test]
+    async fn only_the_objects_missing_from_the_source_are_deleted() {
+        let conf = BucketMigrationConfiguration {
+            delete_destination_files: true,
+            dry_run: true,
+            ..configuration()
+        };
+        let source = [object("a", 10, "aa"), object("b", 10, "bb")];
+        let destination = [
+            object("a", 10, "aa"),
+            object("b", 5, "cc"),
+            object("c", 10, "cc"),
+            object("d", 10, "dd"),
+        ];
+
+        let (result, _) = migrate_objects(conf, &source, &destination).await;
+
+        let BucketObjectsMigrationResult::DryRun(to_copy, to_delete, _) = result else {
+            panic!("A dry run shouldn't synchronize the objects");
+        };
+        assert_eq!(keys(&to_copy), ["b"]);
+        assert_eq!(keys(&to_delete), ["c", "d"]);
+    }
+
+    /// A page of `count` source objects: every third one is missing from the destination and
+    /// every fifth one has changed. The destination also holds objects deleted from the source
+    fn large_page(count: usize) -> (Vec<ProviderObject>, Vec<ProviderObject>) {
+        let source: Vec<ProviderObject> = (0..count)
+            .map(|i| object(&format!("key-{:06}", i), 10, "aa"))
+            .collect();
+        let destination = (0..count)
+            .filter(|i| i % 3 != 0)
+            .map(|i| match i % 5 {
+                0 => object(&format!("key-{:06}", i), 20, "bb"),
+                _ => object(&format!("key-{:06}", i), 10, "aa"),
+            })
+            .chain((0..count / 10).map(|i| object(&format!("deleted-{:06}", i), 10, "aa")))
+            .collect();
+        (source, destination)
+    }
+
+    /// Objects to copy and to delete found by looking up each object in the other slice, like
+    /// the page diff did before the destination objects were indexed
+    fn linear_diff(
+        source: &[ProviderObject],
+        destination: &[ProviderObject],
+    ) -> (Vec<String>, Vec<String>) {
+        let to_copy = source
+            .iter()
+            .filter(|object| {
+                !destination.iter().any(|found| {
+                    found.get_key() == object.get_key() && found.get_size() == object.get_size()
+                })
+            })
+            .map(|object| object.get_key())
+            .collect();
+        let to_delete = destination
+            .iter()
+            .filter(|object| !source.iter().any(|src| src.get_key() == object.get_key()))
+            .map(|object| object.get_key())
+            .collect();
+        (to_copy, to_delete)
+    }
+
+    #[tokio::test]
+    async fn indexed_diff_finds_the_objects_of_the_linear_diff() {
+        let conf = BucketMigrationConfiguration {
+            delete_destination_files: true,
+            dry_run: true,
+            ..configuration()
+        };
+        let (source, destination) = large_page(3000);
+
+        let (result, _) = migrate_objects(conf, &source, &destination).await;
+
+        let BucketObjectsMigrationResult::DryRun(to_copy, to_delete, in_sync) = result else {
+            panic!("A dry run shouldn't synchronize the objects");
+        };
+        let (expected_to_copy, expected_to_delete) = linear_diff(&source, &destination);
+        assert_eq!(keys(&to_copy), expected_to_copy);
+        assert_eq!(keys(&to_delete), expected_to_delete);
+        assert_eq!(in_sync, source.len() - expected_to_copy.len());
+    }
+
+    /// Compares the durations of both diffs of a large page, run with
+    /// `cargo test --release indexed_diff_benchmark -- --ignored --nocapture`
+    #[tokio::test]
+    #[ignore]
+    async fn indexed_diff_benchmark() {
+        let conf = BucketMigrationConfiguration {
+            delete_destination_files: true,
+            dry_run: true,
+            ..configuration()
+        };
+        let (source, destination) = large_page(20_000);
+
+        let start = std::time::Instant::now();
+        let _ = linear_diff(&source, &destination);
+        let linear = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let _ = migrate_objects(conf, &source, &destination).await;
+        let indexed = start.elapsed();
+
+        println!(
+            "Diff of {} objects: linear {:?}, indexed {:?}",
+            source.len(),
+            linear,
+            indexed
+        );
+        assert!(indexed < linear);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn uploads_are_verified_by_the_verify_threads() {
+        let concurrency = Arc::new(Concurrency::default());
+        let answered = concurrency.clone();
+        let destination = MockDestination::start(move |_| {
+            let mut response = etag_response("aa");
+            response
+                .headers_mut()
+                .insert("Content-Length", "0".parse().unwrap());
+            answered.respond(std::time::Duration::from_millis(50), response)
+        });
+        let conf = BucketMigrationConfiguration {
+            sync_threads: 1,
+            verify_threads: 3,
+            ..configuration()
+        };
+        let objects: Vec<ProviderObject> = (0..12)
+            .map(|i| object(&format!("key-{}", i), 0, "aa"))
+            .collect();
+
+        verify_uploads(&conf, &destination.client(), objects.iter().collect()).await;
+
+        assert_eq!(destination.requests().len(), objects.len());
+        assert_eq!(concurrency.max(), 3);
+    }
+
+    #[tokio::test]
+    async fn rewritten_keys_are_compared_with_the_destination() {
+        let conf = BucketMigrationConfiguration {
+            key_transform: Some(KeyTransform {
+                strip_prefix: Some("olddir/".to_string()),
+                add_prefix: Some("newdir/".to_string()),
+                unmatched_keys: UnmatchedKeysPolicy::Skip,
+            }),
+            dry_run: true,
+            ..configuration()
+        };
+        let source = [
+            object("olddir/a", 10, "aa"),
+            object("olddir/b", 10, "bb"),
+            object("other", 10, "cc"),
+        ];
+        let destination = [object("newdir/a", 10, "aa"), object("olddir/b", 10, "bb")];
+
+        let (result, _) = migrate_objects(conf, &source, &destination).await;
+
+        let BucketObjectsMigrationResult::DryRun(to_copy, _, in_sync) = result else {
+            panic!("A dry run shouldn't synchronize the objects");
+        };
+        assert_eq!(keys(&to_copy), ["olddir/b"]);
+        assert_eq!(to_copy[0].get_destination_key(), "newdir/b");
+        assert_eq!(in_sync, 1);
+    }
+
+    #[test]
+    fn objects_at_the_size_bounds_are_synchronized() {
+        let conf = BucketMigrationConfiguration {
+            min_object_size: Some(10),
+            max_object_size: Some(20),
+            ..configuration()
+        };
+
+        assert_eq!(conf.object_size_excluded(9), Some(ObjectStatus::TooSmall));
+        assert_eq!(conf.object_size_excluded(10), None);
+        assert_eq!(conf.object_size_excluded(20), None);
+        assert_eq!(conf.object_size_excluded(21), Some(ObjectStatus::TooLarge));
+        assert_eq!(configuration().object_size_excluded(0), None);
+    }
+
+    #[tokio::test]
+    async fn objects_out_of_the_size_bounds_are_neither_synchronized_nor_deleted() {
+        let conf = BucketMigrationConfiguration {
+            min_object_size: Some(10),
+            max_object_size: Some(20),
+            delete_destination_files: true,
+            dry_run: true,
+            ..configuration()
+        };
+        let source = [
+            object("a", 9, "aa"),
+            object("b", 10, "bb"),
+            object("c", 20, "cc"),
+            object("d", 21, "dd"),
+            object("e", 15, "ee"),
+        ];
+        let destination = [object("a", 5, "zz"), object("e", 15, "ee")];
+
+        let (result, size_excluded) = migrate_objects(conf, &source, &destination).await;
+
+        let BucketObjectsMigrationResult::DryRun(to_copy, to_delete, in_sync) = result else {
+            panic!("A dry run shouldn't synchronize the objects");
+        };
+        assert_eq!(keys(&to_copy), ["b", "c"]);
+        assert!(to_delete.is_empty());
+        assert_eq!(size_excluded, 2);
+        // The excluded objects aren't counted as in sync
+        assert_eq!(in_sync, 1);
+    }
+
+    #[tokio::test]
+    async fn uploads_not_matching_the_source_fail_the_verification() {
+        let destination = ListedProvider {
+            objects: vec![object("a", 10, "aa"), object("b", 7, "bb")],
+            ..Default::default()
+        };
+        let (a, b, c) = (
+            object("a", 10, "aa"),
+            object("b", 10, "bb"),
+            object("c", 10, "cc"),
+        );
+
+        let mut errors = verify_uploads(&configuration(), &destination, vec![&a, &b, &c]).await;
+        errors.sort();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0],
+            "source | Verification of object b failed: size differs (10 expected, 7 on destination)"
+        );
+        assert!(
+            errors[1].starts_with(
+                "source | Verification of object c failed: destination HEAD failed: NoSuchKey"
+            ),
+            "{}",
+            errors[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn upload_with_another_etag_fails_the_verification() {
+        let destination = ListedProvider {
+            objects: vec![object("a", 10, "bb")],
+            ..Default::default()
+        };
+        let source = object("a", 10, "aa");
+
+        let errors = verify_uploads(&configuration(), &destination, vec![&source]).await;
+
+        assert_eq!(
+            errors,
+            ["source | Verification of object a failed: ETag differs (aa on source, bb on destination)"]
+        );
+    }
+
+    #[test]
+    fn diff_of_identical_objects() {
+        let source = object("a", 10, "aa");
+        for compare in [
+            CompareStrategy::ETag,
+            CompareStrategy::Size,
+            CompareStrategy::SizeAndLastModified,
+        ] {
+            assert_eq!(
+                diff_object(&compare, false, &source, &source),
+                ObjectDiff::Synchronized
+            );
+        }
+        assert_eq!(
+            diff_object(&CompareStrategy::ContentMd5, false, &source, &source),
+            ObjectDiff::CompareContentMd5
+        );
+    }
+
+    #[test]
+    fn diff_of_objects_of_different_sizes() {
+        let (source, destination) = (object("a", 10, "aa"), object("a", 11, "aa"));
+        for compare in [CompareStrategy::ETag, CompareStrategy::ContentMd5] {
+            for trust_size in [false, true] {
+                assert_eq!(
+                    diff_object(&compare, trust_size, &source, &destination),
+                    ObjectDiff::Copy
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn diff_of_objects_with_different_etags() {
+        let (source, destination) = (object("a", 10, "aa"), object("a", 10, "bb"));
+        assert_eq!(
+            diff_object(&CompareStrategy::ETag, false, &source, &destination),
+            ObjectDiff::RecheckETagScheme
+        );
+        assert_eq!(
+            diff_object(&CompareStrategy::Size, false, &source, &destination),
+            ObjectDiff::Synchronized
+        );
+    }
+
+    #[test]
+    fn diff_trusting_the_size_of_multipart_objects() {
+        // The destination copy is older than the source, the ETag comparison copies it again
+        let source = object("a", 10, "aa-2");
+        let destination = ProviderObject::planned(
+            "a".to_string(),
+            10,
+            "bb-3".to_string(),
+            *source.get_last_modified() - Duration::hours(1),
+            None,
+        );
+        assert_eq!(
+            diff_object(&CompareStrategy::ETag, false, &source, &destination),
+            ObjectDiff::RecheckETagScheme
+        );
+        for compare in [CompareStrategy::ETag, CompareStrategy::ContentMd5] {
+            assert_eq!(
+                diff_object(&compare, true, &source, &destination),
+                ObjectDiff::Synchronized
+            );
+        }
+        assert_eq!(
+            diff_object(
+                &CompareStrategy::ETag,
+                true,
+                &object("a", 10, "aa"),
+                &object("a", 10, "bb-3")
+            ),
+            ObjectDiff::Synchronized
+        );
+    }
+
+    #[test]
+    fn diff_without_trusting_the_size_of_simple_objects() {
+        let (source, destination) = (object("a", 10, "aa"), object("a", 10, "bb"));
+        assert_eq!(
+            diff_object(&CompareStrategy::ETag, true, &source, &destination),
+            ObjectDiff::RecheckETagScheme
+        );
+    }
+
+    #[test]
+    fn part_size_bounds() {
+        assert!(check_multipart_part_size(MIN_MULTIPART_PART_SIZE).is_ok());
+        assert!(check_multipart_part_size(MIN_MULTIPART_PART_SIZE - 1).is_err());
+        assert!(check_multipart_part_size(MAX_MULTIPART_PART_SIZE).is_ok());
+        assert!(check_multipart_part_size(MAX_MULTIPART_PART_SIZE + 1).is_err());
+    }
+
+    #[test]
+    fn multipart_threshold_is_at_least_the_minimum_part_size() {
+        assert!(check_multipart_threshold(MIN_MULTIPART_PART_SIZE as u64).is_ok());
+        assert!(check_multipart_threshold(MIN_MULTIPART_PART_SIZE as u64 - 1).is_err());
+    }
+}
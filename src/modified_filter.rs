@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+
+use crate::provider::ProviderObject;
+
+/// What to do with the objects whose last modified date can't be trusted: the Unix epoch or a
+/// date in the future, which some gateways return for objects written by old versions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnreliableLastModifiedPolicy {
+    Include,
+    Exclude,
+}
+
+impl TryFrom<&str> for UnreliableLastModifiedPolicy {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "include" => Ok(UnreliableLastModifiedPolicy::Include),
+            "exclude" => Ok(UnreliableLastModifiedPolicy::Exclude),
+            _ => Err(format!(
+                "Failed to parse unreliable last modified policy: {}",
+                value
+            )),
+        }
+    }
+}
+
+/// Restricts the synchronized source objects to the ones modified in a time range, e.g. since
+/// the previous run. Objects out of the range are not copied, their destination copies aren't
+/// deleted either
+#[derive(Debug, Clone)]
+pub struct ModifiedFilter {
+    /// Objects modified at this date are synchronized
+    since: Option<DateTime<Utc>>,
+    /// Objects modified at this date are not synchronized
+    before: Option<DateTime<Utc>>,
+    unreliable: UnreliableLastModifiedPolicy,
+}
+
+impl ModifiedFilter {
+    pub fn new(
+        since: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+        unreliable: UnreliableLastModifiedPolicy,
+    ) -> Result<ModifiedFilter, String> {
+        if let (Some(since), Some(before)) = (since, before) {
+            if since >= before {
+                return Err(format!(
+                    "--modified-since {} should be before --modified-before {}",
+                    since.to_rfc3339(),
+                    before.to_rfc3339()
+                ));
+            }
+        }
+
+        Ok(ModifiedFilter {
+            since,
+            before,
+            unreliable,
+        })
+    }
+
+    pub fn matches(&self, object: &ProviderObject) -> bool {
+        let last_modified = object.get_last_modified();
+        if last_modified.timestamp() <= 0 || *last_modified > Utc::now() {
+            return self.unreliable == UnreliableLastModifiedPolicy::Include;
+        }
+
+        !matches!(self.since, Some(since) if *last_modified < since)
+            && !matches!(self.before, Some(before) if *last_modified >= before)
+    }
+}
+
+/// RFC 3339 date of the command line, e.g. 2024-01-31T00:00:00Z
+pub fn parse_rfc3339(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|error| format!("Invalid RFC 3339 date {}: {}", value, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    fn modified_at(last_modified: DateTime<Utc>) -> ProviderObject {
+        ProviderObject::planned(
+            "key".to_string(),
+            1,
+            "etag".to_string(),
+            last_modified,
+            None,
+        )
+    }
+
+    fn date(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap()
+    }
+
+    fn filter(
+        since: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+        unreliable: UnreliableLastModifiedPolicy,
+    ) -> ModifiedFilter {
+        ModifiedFilter::new(since, before, unreliable).expect("The range should be valid")
+    }
+
+    #[test]
+    fn objects_modified_since() {
+        let filter = filter(Some(date(10)), None, UnreliableLastModifiedPolicy::Include);
+
+        assert!(!filter.matches(&modified_at(date(9))));
+        assert!(!filter.matches(&modified_at(date(10) - Duration::seconds(1))));
+        assert!(filter.matches(&modified_at(date(10))));
+        assert!(filter.matches(&modified_at(date(11))));
+    }
+
+    #[test]
+    fn objects_modified_before() {
+        let filter = filter(None, Some(date(10)), UnreliableLastModifiedPolicy::Include);
+
+        assert!(filter.matches(&modified_at(date(9))));
+        assert!(filter.matches(&modified_at(date(10) - Duration::seconds(1))));
+        assert!(!filter.matches(&modified_at(date(10))));
+        assert!(!filter.matches(&modified_at(date(11))));
+    }
+
+    #[test]
+    fn objects_modified_in_a_range() {
+        let filter = filter(
+            Some(date(10)),
+            Some(date(20)),
+            UnreliableLastModifiedPolicy::Include,
+        );
+
+        assert!(!filter.matches(&modified_at(date(9))));
+        assert!(filter.matches(&modified_at(date(10))));
+        assert!(filter.matches(&modified_at(date(15))));
+        assert!(!filter.matches(&modified_at(date(20))));
+    }
+
+    #[test]
+    fn range_should_not_be_empty() {
+        for (since, before) in [(date(10), date(10)), (date(20), date(10))] {
+            assert!(ModifiedFilter::new(
+                Some(since),
+                Some(before),
+                UnreliableLastModifiedPolicy::Include
+            )
+            .unwrap_err()
+            .starts_with("--modified-since"));
+        }
+    }
+
+    #[test]
+    fn unreliable_last_modified_follows_the_policy() {
+        let unreliable = [
+            modified_at(Utc.timestamp_opt(0, 0).unwrap()),
+            modified_at(Utc::now() + Duration::days(1)),
+        ];
+
+        let included = filter(
+            Some(date(10)),
+            Some(date(20)),
+            UnreliableLastModifiedPolicy::Include,
+        );
+        let excluded = filter(
+            Some(date(10)),
+            Some(date(20)),
+            UnreliableLastModifiedPolicy::Exclude,
+        );
+        for object in &unreliable {
+            assert!(included.matches(object));
+            assert!(!excluded.matches(object));
+        }
+        // A reliable date in the range is kept by both
+        assert!(excluded.matches(&modified_at(date(15))));
+    }
+
+    #[test]
+    fn dates_are_parsed_as_rfc3339() {
+        assert_eq!(parse_rfc3339("2024-01-10T00:00:00Z"), Ok(date(10)));
+        assert_eq!(parse_rfc3339("2024-01-10T02:00:00+02:00"), Ok(date(10)));
+        assert!(parse_rfc3339("2024-01-10")
+            .unwrap_err()
+            .starts_with("Invalid RFC 3339 date 2024-01-10"));
+    }
+}
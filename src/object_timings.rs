@@ -0,0 +1,105 @@
+use std::{sync::Mutex, time::Duration};
+
+#[derive(Debug, Clone)]
+pub struct ObjectTiming {
+    pub key: String,
+    pub size: u64,
+    /// From the first request to the end of the upload, retries included
+    pub duration: Duration,
+    /// 1 for the objects not uploaded in parts
+    pub number_of_parts: usize,
+    /// Bytes per second
+    pub throughput: u64,
+}
+
+/// Timings of the slowest objects uploaded to a bucket. Only the `capacity` slowest ones are
+/// kept so the memory used doesn't grow with the number of objects
+#[derive(Debug)]
+pub struct ObjectTimings {
+    capacity: usize,
+    /// Sorted from the slowest object
+    timings: Mutex<Vec<ObjectTiming>>,
+}
+
+impl ObjectTimings {
+    pub fn new(capacity: usize) -> ObjectTimings {
+        ObjectTimings {
+            capacity,
+            timings: Mutex::new(Vec::with_capacity(capacity)),
+        }
+    }
+
+    pub fn record(&self, key: String, size: u64, duration: Duration, number_of_parts: usize) {
+        let mut timings = self.timings.lock().unwrap();
+        let position = timings.partition_point(|timing| timing.duration >= duration);
+        if position >= self.capacity {
+            return;
+        }
+
+        let throughput = if duration.is_zero() {
+            size
+        } else {
+            (size as f64 / duration.as_secs_f64()) as u64
+        };
+        timings.insert(
+            position,
+            ObjectTiming {
+                key,
+                size,
+                duration,
+                number_of_parts,
+                throughput,
+            },
+        );
+        timings.truncate(self.capacity);
+    }
+
+    /// Sorted from the slowest object
+    pub fn slowest(&self) -> Vec<ObjectTiming> {
+        self.timings.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timings: &ObjectTimings, key: &str, milliseconds: u64) {
+        timings.record(
+            key.to_string(),
+            1000,
+            Duration::from_millis(milliseconds),
+            1,
+        );
+    }
+
+    fn keys(timings: &ObjectTimings) -> Vec<String> {
+        timings
+            .slowest()
+            .into_iter()
+            .map(|timing| timing.key)
+            .collect()
+    }
+
+    #[test]
+    fn only_the_slowest_objects_are_kept() {
+        let timings = ObjectTimings::new(3);
+        for (key, milliseconds) in [("a", 20), ("b", 50), ("c", 10), ("d", 40), ("e", 30)] {
+            record(&timings, key, milliseconds);
+        }
+
+        assert_eq!(keys(&timings), ["b", "d", "e"]);
+    }
+
+    #[test]
+    fn throughput_of_the_object() {
+        let timings = ObjectTimings::new(2);
+        timings.record("a".to_string(), 1000, Duration::from_millis(500), 2);
+        timings.record("b".to_string(), 1000, Duration::ZERO, 1);
+
+        let slowest = timings.slowest();
+        assert_eq!(slowest[0].throughput, 2000);
+        assert_eq!(slowest[0].number_of_parts, 2);
+        assert_eq!(slowest[1].throughput, 1000);
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::provider::ProviderObject;
+
+/// Bumped on incompatible changes of the plan entries, older plans are then rejected
+const PLAN_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlanAction {
+    /// Copy the source object to the destination. Objects whose metadata differ are copied too
+    Copy,
+    /// Delete the destination object, missing from the source
+    Delete,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PlanEntry {
+    version: u32,
+    bucket: String,
+    destination_bucket: String,
+    action: PlanAction,
+    /// Source key for copies, destination key for deletes
+    key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    destination_key: Option<String>,
+    size: u64,
+    etag: String,
+    /// RFC 3339
+    last_modified: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    storage_class: Option<String>,
+}
+
+/// Written by a dry run with `--write-plan`. Each line is a JSON object describing one copy or
+/// delete, with the listing entry of the object so the plan can be applied without listing
+/// the buckets again
+#[derive(Debug)]
+pub struct PlanWriter {
+    file: Mutex<BufWriter<File>>,
+}
+
+impl PlanWriter {
+    pub fn create(path: &Path) -> anyhow::Result<PlanWriter> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create plan file {}", path.display()))?;
+
+        Ok(PlanWriter {
+            file: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn record(
+        &self,
+        bucket: &str,
+        destination_bucket: &str,
+        action: PlanAction,
+        objects: &[ProviderObject],
+    ) -> anyhow::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        for object in objects {
+            let destination_key = object.get_destination_key();
+            let entry = PlanEntry {
+                version: PLAN_VERSION,
+                bucket: bucket.to_string(),
+                destination_bucket: destination_bucket.to_string(),
+                action,
+                destination_key: (destination_key != object.get_key()).then_some(destination_key),
+                key: object.get_key(),
+                size: object.get_size(),
+                etag: object.get_etag().to_string(),
+                last_modified: object.get_last_modified().to_rfc3339(),
+                storage_class: object.get_storage_class().map(|class| class.to_string()),
+            };
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.file.lock().unwrap().flush()?;
+        Ok(())
+    }
+}
+
+/// Copies and deletes of a bucket read from a plan file
+#[derive(Debug, Default)]
+pub struct PlannedBucket {
+    pub destination_bucket: String,
+    pub copies: Vec<ProviderObject>,
+    pub deletes: Vec<ProviderObject>,
+}
+
+/// Plan file read by `--apply-plan`
+#[derive(Debug)]
+pub struct MigrationPlan {
+    /// In the order of the plan file
+    buckets: Vec<String>,
+    planned: HashMap<String, Arc<PlannedBucket>>,
+}
+
+impl MigrationPlan {
+    pub fn load(path: &Path) -> anyhow::Result<MigrationPlan> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open plan file {}", path.display()))?;
+
+        let mut buckets = Vec::new();
+        let mut planned: HashMap<String, PlannedBucket> = HashMap::new();
+
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+
+            let entry: PlanEntry = serde_json::from_str(&line).with_context(|| {
+                format!("Invalid line {} of plan file {}", index + 1, path.display())
+            })?;
+            if entry.version != PLAN_VERSION {
+                anyhow::bail!(
+                    "Line {} of plan file {} has version {}, only version {} is supported",
+                    index + 1,
+                    path.display(),
+                    entry.version,
+                    PLAN_VERSION
+                );
+            }
+
+            let last_modified = DateTime::parse_from_rfc3339(&entry.last_modified)
+                .with_context(|| {
+                    format!("Invalid line {} of plan file {}", index + 1, path.display())
+                })?
+                .with_timezone(&Utc);
+            let mut object = ProviderObject::planned(
+                entry.key,
+                entry.size,
+                entry.etag,
+                last_modified,
+                entry.storage_class,
+            );
+            if let Some(destination_key) = entry.destination_key {
+                object = object.with_destination_key(destination_key);
+            }
+
+            if !planned.contains_key(&entry.bucket) {
+                buckets.push(entry.bucket.clone());
+            }
+            let bucket = planned
+                .entry(entry.bucket.clone())
+                .or_insert_with(|| PlannedBucket {
+                    destination_bucket: entry.destination_bucket.clone(),
+                    ..Default::default()
+                });
+            if bucket.destination_bucket != entry.destination_bucket {
+                anyhow::bail!(
+                    "Line {} of plan file {} copies bucket {} to {}, previous lines to {}",
+                    index + 1,
+                    path.display(),
+                    entry.bucket,
+                    entry.destination_bucket,
+                    bucket.destination_bucket
+                );
+            }
+
+            match entry.action {
+                PlanAction::Copy => bucket.copies.push(object),
+                PlanAction::Delete => bucket.deletes.push(object),
+            }
+        }
+
+        Ok(MigrationPlan {
+            buckets,
+            planned: planned
+                .into_iter()
+                .map(|(bucket, planned)| (bucket, Arc::new(planned)))
+                .collect(),
+        })
+    }
+
+    pub fn buckets(&self) -> &[String] {
+        &self.buckets
+    }
+
+    pub fn bucket(&self, bucket: &str) -> Option<Arc<PlannedBucket>> {
+        self.planned.get(bucket).cloned()
+    }
+}
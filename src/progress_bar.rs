@@ -0,0 +1,103 @@
+use std::{
+    io::{IsTerminal, Write},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use tokio::task::JoinHandle;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::heartbeat::SyncProgress;
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Set once before the logs are initialized, so the log writer can hide the bar while a line is
+/// printed
+static PROGRESS_BAR: OnceLock<ProgressBar> = OnceLock::new();
+
+/// Enables the progress bar of --progress. It is only drawn on a terminal, the logs of a
+/// redirected output aren't cluttered with it
+pub fn enable() {
+    if !std::io::stdout().is_terminal() {
+        return;
+    }
+
+    let bar = ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::stdout());
+    bar.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}] {wide_bar} {bytes}/{total_bytes} | {msg} | {bytes_per_sec} | ETA {eta}",
+        )
+        .expect("Progress bar template should be valid"),
+    );
+    let _ = PROGRESS_BAR.set(bar);
+}
+
+/// Refreshes the progress bar from the counters of the sync threads until the task is aborted
+pub fn spawn_progress_bar(progress: Arc<SyncProgress>) -> Option<JoinHandle<()>> {
+    let bar = PROGRESS_BAR.get()?.clone();
+
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(REFRESH_INTERVAL);
+        loop {
+            ticker.tick().await;
+
+            let (objects_total, bytes_total) = progress.totals();
+            bar.set_length(bytes_total as u64);
+            bar.set_position(progress.bytes_uploaded() as u64);
+            bar.set_message(format!(
+                "{}/{} objects",
+                progress.objects_done(),
+                objects_total
+            ));
+        }
+    }))
+}
+
+pub fn finish() {
+    if let Some(bar) = PROGRESS_BAR.get() {
+        bar.finish();
+    }
+}
+
+/// Log writer hiding the progress bar while a log line is written, otherwise the line would be
+/// printed in the middle of the bar
+pub struct SuspendProgressBar<M> {
+    inner: M,
+}
+
+impl<M> SuspendProgressBar<M> {
+    pub fn new(inner: M) -> SuspendProgressBar<M> {
+        SuspendProgressBar { inner }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for SuspendProgressBar<M> {
+    type Writer = SuspendedWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        SuspendedWriter {
+            inner: self.inner.make_writer(),
+        }
+    }
+}
+
+pub struct SuspendedWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> Write for SuspendedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match PROGRESS_BAR.get() {
+            Some(bar) => bar.suspend(|| self.inner.write(buf)),
+            None => self.inner.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match PROGRESS_BAR.get() {
+            Some(bar) => bar.suspend(|| self.inner.flush()),
+            None => self.inner.flush(),
+        }
+    }
+}
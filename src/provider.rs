@@ -1,5 +1,5 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     fmt::Debug,
     pin::Pin,
     str::FromStr,
@@ -15,19 +15,29 @@ use futures::{Stream, StreamExt};
 use tracing::{event, instrument, Level};
 
 use crate::{
-    radosgw::RadosGW,
+    acl_rules::{DestinationAcl, ObjectGrant},
+    radosgw::{timeout::HttpTimeouts, RadosGW, MAX_FETCH_KEYS},
     riakcs::{
         dto::{ObjectContents, ObjectMetadataResponse},
         RiakCS,
     },
 };
 
+/// Storage class applied by the destination when none is given on upload
+pub const DEFAULT_STORAGE_CLASS: &str = "STANDARD";
+
 pub struct ProviderConf {
     pub endpoint: Option<String>,
     pub region: Option<String>,
     pub access_key: String,
     pub secret_key: String,
     pub bucket: Option<String>,
+    pub requester_pays: bool,
+    pub prefix: Option<String>,
+    /// Only applied to the S3 providers
+    pub timeouts: HttpTimeouts,
+    /// Keys of each listing page of the S3 providers
+    pub page_size: usize,
 }
 
 impl ProviderConf {
@@ -44,8 +54,32 @@ impl ProviderConf {
             access_key,
             secret_key,
             bucket,
+            requester_pays: false,
+            prefix: None,
+            timeouts: HttpTimeouts::default(),
+            page_size: MAX_FETCH_KEYS,
+        }
+    }
+
+    /// Restricts the listings to the keys starting with `prefix`
+    pub fn with_prefix(self, prefix: Option<String>) -> ProviderConf {
+        ProviderConf { prefix, ..self }
+    }
+
+    pub fn with_requester_pays(self, requester_pays: bool) -> ProviderConf {
+        ProviderConf {
+            requester_pays,
+            ..self
         }
     }
+
+    pub fn with_timeouts(self, timeouts: HttpTimeouts) -> ProviderConf {
+        ProviderConf { timeouts, ..self }
+    }
+
+    pub fn with_page_size(self, page_size: usize) -> ProviderConf {
+        ProviderConf { page_size, ..self }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -54,13 +88,84 @@ pub struct ProviderObject {
     last_modified: DateTime<Utc>,
     etag: String,
     size: u64,
+    storage_class: Option<String>,
+    /// Set when the object is copied under another key on the destination
+    destination_key: Option<String>,
 }
 
 impl ProviderObject {
+    /// Object that doesn't come from a listing
+    pub fn new(key: String, size: u64) -> ProviderObject {
+        ProviderObject {
+            key,
+            last_modified: Utc::now(),
+            etag: String::new(),
+            size,
+            storage_class: None,
+            destination_key: None,
+        }
+    }
+
+    /// Object read back from a migration plan, as it was listed when the plan was written
+    pub fn planned(
+        key: String,
+        size: u64,
+        etag: String,
+        last_modified: DateTime<Utc>,
+        storage_class: Option<String>,
+    ) -> ProviderObject {
+        ProviderObject {
+            key,
+            last_modified,
+            etag,
+            size,
+            storage_class,
+            destination_key: None,
+        }
+    }
+
     pub fn get_key(&self) -> String {
         self.key.clone()
     }
 
+    pub fn get_destination_key(&self) -> String {
+        self.destination_key.clone().unwrap_or_else(|| self.get_key())
+    }
+
+    pub fn with_destination_key(&self, destination_key: String) -> ProviderObject {
+        ProviderObject {
+            destination_key: Some(destination_key),
+            ..self.clone()
+        }
+    }
+
+    /// Whether `destination` is an up to date copy of this source object. A renamed source object
+    /// is compared under its destination key, its source key never matches
+    pub fn matches_destination(&self, destination: &ProviderObject) -> bool {
+        destination.key == self.get_destination_key() && self.same_content(destination)
+    }
+
+    #[instrument(skip_all, level = "trace")]
+    fn same_content(&self, other: &ProviderObject) -> bool {
+        event!(Level::TRACE, "Self: {:#?}\nOther: {:#?}", self, other);
+
+        if other.size == self.get_size() {
+            if other.etag == self.etag {
+                true
+            } else if self.get_etag().contains('-') {
+                event!(Level::WARN, "Object {} has been uploaded using multipart upload. Falling back to last modification date to compare objects.", self.get_key());
+                self.last_modified < other.last_modified
+            } else if other.etag.contains('-') {
+                event!(Level::WARN, "Object {} has been uploaded without multipart on source bucket but with multipart on destination bucket. Falling back to last modification date to compare objects.", self.get_key());
+                self.last_modified < other.last_modified
+            } else {
+                false
+            }
+        } else {
+            false
+        }
+    }
+
     pub fn get_last_modified(&self) -> &DateTime<Utc> {
         &self.last_modified
     }
@@ -69,9 +174,25 @@ impl ProviderObject {
         &self.etag
     }
 
+    /// Same object, with its size reduced to `size`
+    pub fn truncated(&self, size: u64) -> ProviderObject {
+        ProviderObject {
+            size: std::cmp::min(self.size, size),
+            ..self.clone()
+        }
+    }
+
+    pub fn is_multipart(&self) -> bool {
+        self.etag.contains('-')
+    }
+
     pub fn get_size(&self) -> u64 {
         self.size
     }
+
+    pub fn get_storage_class(&self) -> Option<&str> {
+        self.storage_class.as_deref()
+    }
 }
 
 impl From<&ObjectContents> for ProviderObject {
@@ -81,6 +202,8 @@ impl From<&ObjectContents> for ProviderObject {
             etag: value.get_etag(),
             last_modified: value.get_last_modified(),
             size: value.get_size(),
+            storage_class: None,
+            destination_key: None,
         }
     }
 }
@@ -96,36 +219,115 @@ impl From<&rusoto_s3::Object> for ProviderObject {
                 .expect("Object last_modified shouldn't be null"),
             etag: value.e_tag.clone().expect("Object ETag shouldn't be null"),
             size: value.size.expect("Object size shouldn't be null") as u64,
+            storage_class: value.storage_class.clone(),
+            destination_key: None,
         }
     }
 }
 
+/// The storage class isn't part of the comparison: a lifecycle rule transitioning the destination
+/// object to another class doesn't change its content, so it shouldn't be copied again.
 impl PartialEq<ProviderObject> for ProviderObject {
-    #[instrument(skip_all, level = "trace")]
     fn eq(&self, other: &ProviderObject) -> bool {
-        event!(Level::TRACE, "Self: {:#?}\nOther: {:#?}", self, other);
+        other.key == self.key && self.same_content(other)
+    }
+}
 
-        if other.key == self.key && other.size == self.get_size() {
-            if other.etag == self.etag {
-                true
-            } else if self.get_etag().contains('-') {
-                event!(Level::WARN, "Object {} has been uploaded using multipart upload. Falling back to last modification date to compare objects.", self.get_key());
-                self.last_modified < other.last_modified
-            } else if other.etag.contains('-') {
-                event!(Level::WARN, "Object {} has been uploaded without multipart on source bucket but with multipart on destination bucket. Falling back to last modification date to compare objects.", self.get_key());
-                self.last_modified < other.last_modified
-            } else {
-                false
+/// How a source object is compared to its destination counterpart to know if it needs to be copied
+#[derive(Debug, Clone)]
+pub enum CompareStrategy {
+    /// Size and ETag. Falls back to the last modification date for multipart uploaded objects
+    ETag,
+    /// Size and Content-MD5 stored on both sides. Needs a HEAD request per object having the same size on both sides.
+    /// Falls back to the ETag comparison when one of the sides doesn't expose a Content-MD5.
+    ContentMd5,
+    /// Size only. Cheapest, but misses objects modified without changing their size
+    Size,
+    /// Size, and the destination object must not be older than the source object
+    SizeAndLastModified,
+}
+
+impl CompareStrategy {
+    /// Whether the destination object is up to date with the source object. Objects of the same
+    /// size compared by Content-MD5 need their metadata, they're handled by the caller
+    pub fn is_synchronized(&self, source: &ProviderObject, destination: &ProviderObject) -> bool {
+        match self {
+            CompareStrategy::ETag | CompareStrategy::ContentMd5 => {
+                source.matches_destination(destination)
             }
-        } else {
-            false
+            CompareStrategy::Size => source.get_size() == destination.get_size(),
+            CompareStrategy::SizeAndLastModified => {
+                source.get_size() == destination.get_size()
+                    && source.get_last_modified() <= destination.get_last_modified()
+            }
+        }
+    }
+}
+
+impl TryFrom<&str> for CompareStrategy {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "etag" => Ok(CompareStrategy::ETag),
+            "content-md5" => Ok(CompareStrategy::ContentMd5),
+            "size" => Ok(CompareStrategy::Size),
+            "size-and-last-modified" => Ok(CompareStrategy::SizeAndLastModified),
+            _ => Err(format!("Failed to parse compare mode: {}", value)),
+        }
+    }
+}
+
+/// Restricts the objects to synchronize by the format of their source ETag
+#[derive(Debug, Clone, PartialEq)]
+pub enum ETagFilter {
+    All,
+    /// ETag has a `-<parts count>` suffix, the object was uploaded using multipart
+    Multipart,
+    Simple,
+}
+
+impl ETagFilter {
+    pub fn matches(&self, object: &ProviderObject) -> bool {
+        match self {
+            ETagFilter::All => true,
+            ETagFilter::Multipart => object.is_multipart(),
+            ETagFilter::Simple => !object.is_multipart(),
+        }
+    }
+}
+
+impl TryFrom<&str> for ETagFilter {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "all" => Ok(ETagFilter::All),
+            "multipart" => Ok(ETagFilter::Multipart),
+            "simple" => Ok(ETagFilter::Simple),
+            _ => Err(format!("Failed to parse ETag filter: {}", value)),
         }
     }
 }
 
+/// S3 doesn't return the Content-MD5 of an object on HEAD requests. We store it as user metadata
+/// on the destination object so it can be compared on the next runs.
+pub const CONTENT_MD5_METADATA: &str = "content-md5";
+
+/// User metadata marking destination objects that only contain the first bytes of their source
+pub const TRUNCATED_FROM_METADATA: &str = "truncated-from";
+
+/// User metadata recording the source bucket of destination objects (--record-source-bucket)
+pub const SOURCE_BUCKET_METADATA: &str = "source-bucket";
+
+/// User metadata recording the ETag of the source object a destination object was copied from.
+/// Some destinations compute ETags differently, the objects copied from an unchanged source
+/// object are recognized with it instead of being copied again on each run
+pub const SOURCE_ETAG_METADATA: &str = "source-etag";
+
 #[derive(Debug)]
 pub struct ProviderObjectMetadata {
     pub acl_public: bool,
+    /// Canned ACL of the destination object, replaces `acl_public` when set
+    pub acl: Option<DestinationAcl>,
     pub last_modified: Option<DateTime<FixedOffset>>,
     pub etag: Option<String>,
     pub content_type: Option<String>,
@@ -136,6 +338,20 @@ pub struct ProviderObjectMetadata {
     pub content_language: Option<String>,
     pub content_md5: Option<String>,
     pub expires: Option<String>,
+    pub website_redirect_location: Option<String>,
+    /// Only carried to the destination with --mirror-encryption
+    pub server_side_encryption: Option<String>,
+    pub ssekms_key_id: Option<String>,
+    /// Original size of the source object when only its first bytes are copied (--truncate-bytes)
+    pub truncated_from: Option<u64>,
+    /// Name of the source bucket, for buckets renamed on the destination
+    pub source_bucket: Option<String>,
+    /// Only set on objects copied by this tool
+    pub source_etag: Option<String>,
+    /// Tag set of the destination object, only copied from the source with --preserve-tags
+    pub tags: HashMap<String, String>,
+    /// Storage class of the destination object, the destination default when not set
+    pub storage_class: Option<String>,
 }
 
 impl From<ObjectMetadataResponse> for ProviderObjectMetadata {
@@ -143,6 +359,7 @@ impl From<ObjectMetadataResponse> for ProviderObjectMetadata {
         let m = value.metadata;
         ProviderObjectMetadata {
             acl_public: value.acl_public,
+            acl: None,
             last_modified: m.last_modified,
             etag: m.etag.clone(),
             content_type: m.content_type.clone(),
@@ -153,14 +370,25 @@ impl From<ObjectMetadataResponse> for ProviderObjectMetadata {
             content_language: m.content_language.clone(),
             content_md5: m.content_md5.clone(),
             expires: m.expires,
+            website_redirect_location: m.website_redirect_location,
+            // RiakCS doesn't encrypt objects
+            server_side_encryption: None,
+            ssekms_key_id: None,
+            truncated_from: None,
+            source_bucket: None,
+            source_etag: None,
+            tags: HashMap::new(),
+            storage_class: None,
         }
     }
 }
 
 impl From<rusoto_s3::HeadObjectOutput> for ProviderObjectMetadata {
     fn from(value: rusoto_s3::HeadObjectOutput) -> Self {
+        let mut user_metadata = value.metadata.unwrap_or_default();
         ProviderObjectMetadata {
             acl_public: false,
+            acl: None,
             last_modified: value.last_modified.map(|d| {
                 DateTime::parse_from_rfc2822(&d).unwrap_or_else(|_| {
                     panic!("Object should have a valid last modified date: {}", d)
@@ -175,8 +403,16 @@ impl From<rusoto_s3::HeadObjectOutput> for ProviderObjectMetadata {
             content_disposition: value.content_disposition,
             content_encoding: value.content_encoding,
             content_language: value.content_language,
-            content_md5: None,
+            content_md5: user_metadata.remove(CONTENT_MD5_METADATA),
             expires: value.expires,
+            website_redirect_location: value.website_redirect_location,
+            server_side_encryption: value.server_side_encryption,
+            ssekms_key_id: value.ssekms_key_id,
+            truncated_from: None,
+            source_bucket: None,
+            source_etag: user_metadata.remove(SOURCE_ETAG_METADATA),
+            tags: HashMap::new(),
+            storage_class: None,
         }
     }
 }
@@ -207,6 +443,9 @@ impl Stream for ProviderResponseStreamChunkWrapper {
 
 pub trait ProviderResponse: Debug + Send + Sync {
     fn status(&self) -> u16;
+    /// True when the response body is streamed without a Content-Length (e.g. chunked transfer encoding).
+    /// In that case, the object size from the listing can't be trusted to build the destination request.
+    fn is_chunked(&self) -> bool;
     fn body(&mut self) -> Pin<Box<dyn Stream<Item = Result<bytes::Bytes, std::io::Error>> + Send>>;
     fn body_chunked(
         &mut self,
@@ -221,7 +460,8 @@ impl dyn ProviderResponse {
 
     pub async fn consume_body(&mut self) -> Option<Result<bytes::Bytes, std::io::Error>> {
         let mut ret = BytesMut::new();
-        while let Some(res) = self.body().next().await {
+        let mut body = self.body();
+        while let Some(res) = body.next().await {
             match res {
                 Ok(part) => ret.extend(part),
                 Err(err) => return Some(Err(err)),
@@ -252,10 +492,48 @@ pub trait Provider: Debug + DynClone + Send + Sync {
         &self,
         object: &ProviderObject,
     ) -> anyhow::Result<Box<dyn ProviderResponse>>;
+    /// Only fetches `length` bytes of the object from the byte `start`
+    async fn get_object_range(
+        &self,
+        object: &ProviderObject,
+        start: u64,
+        length: u64,
+    ) -> anyhow::Result<Box<dyn ProviderResponse>>;
+    /// Conditional GET, returns `None` if the source answered that the object still has this ETag
+    async fn get_object_if_none_match(
+        &self,
+        object: &ProviderObject,
+        etag: &str,
+    ) -> anyhow::Result<Option<Box<dyn ProviderResponse>>>;
+    async fn get_object_tags(
+        &self,
+        object: &ProviderObject,
+    ) -> anyhow::Result<HashMap<String, String>>;
+    async fn get_object_acl(&self, object: &ProviderObject) -> anyhow::Result<Vec<ObjectGrant>>;
 }
 
 dyn_clone::clone_trait_object!(Provider);
 
+/// Lists the whole bucket in memory
+pub async fn list_all_objects(provider: &dyn Provider) -> anyhow::Result<Vec<ProviderObject>> {
+    let mut objects = Vec::new();
+    let mut listing = provider.list_objects(None, None);
+    while let Some(page) = listing.next().await {
+        objects.extend(page?);
+    }
+
+    Ok(objects)
+}
+
+/// `If-None-Match` expects a quoted ETag but some listings return them without quotes
+pub fn quote_etag(etag: &str) -> String {
+    if etag.starts_with('"') {
+        etag.to_string()
+    } else {
+        format!("\"{}\"", etag)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Providers {
     RiakCS,
@@ -277,27 +555,44 @@ impl TryFrom<&str> for Providers {
 
 pub fn get_provider(provider: &Providers, conf: ProviderConf) -> Box<dyn Provider> {
     match provider {
-        Providers::RiakCS => Box::new(RiakCS::new(
-            conf.endpoint
-                .expect("RiakCS requires an endpoint and not a region"),
-            conf.access_key,
-            conf.secret_key,
-            conf.bucket,
-        )),
-        Providers::Cellar => Box::new(RadosGW::new(
-            conf.endpoint,
-            None,
-            conf.access_key,
-            conf.secret_key,
-            conf.bucket,
-        )),
-        Providers::AwsS3 => Box::new(RadosGW::new(
-            None,
-            conf.region,
-            conf.access_key,
-            conf.secret_key,
-            conf.bucket,
-        )),
+        Providers::RiakCS => Box::new(
+            RiakCS::new(
+                conf.endpoint
+                    .expect("RiakCS requires an endpoint and not a region"),
+                conf.access_key,
+                conf.secret_key,
+                conf.bucket,
+            )
+            .with_requester_pays(conf.requester_pays)
+            .with_prefix(conf.prefix),
+        ),
+        Providers::Cellar => Box::new(
+            RadosGW::new(
+                conf.endpoint,
+                // Name of the region the requests are signed with
+                conf.region,
+                conf.access_key,
+                conf.secret_key,
+                conf.bucket,
+            )
+            .with_requester_pays(conf.requester_pays)
+            .with_prefix(conf.prefix)
+            .with_timeouts(conf.timeouts)
+            .with_page_size(conf.page_size),
+        ),
+        Providers::AwsS3 => Box::new(
+            RadosGW::new(
+                None,
+                conf.region,
+                conf.access_key,
+                conf.secret_key,
+                conf.bucket,
+            )
+            .with_requester_pays(conf.requester_pays)
+            .with_prefix(conf.prefix)
+            .with_timeouts(conf.timeouts)
+            .with_page_size(conf.page_size),
+        ),
     }
 }
 
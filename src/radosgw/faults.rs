@@ -0,0 +1,117 @@
+use std::{str::FromStr, time::Duration};
+
+use anyhow::anyhow;
+use ring::rand::{SecureRandom, SystemRandom};
+use tracing::{event, Level};
+
+use crate::provider::ProviderObject;
+
+/// Synthetic failures injected by the Uploader to exercise the retry / resume handling
+/// against a healthy gateway.
+///
+/// The only way to build it is the hidden --simulate-failures option, which only exists when the tool
+/// is compiled with the `fault-injection` feature. Release builds can't enable it.
+///
+/// Format: `part-failure-rate=0.1,key=path/to/object,delay-ms=500`. `key` can be repeated.
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedFailures {
+    part_failure_rate: f64,
+    failing_keys: Vec<String>,
+    delay: Option<Duration>,
+}
+
+impl SimulatedFailures {
+    /// Called before an object is downloaded from the source
+    pub async fn before_object(&self, object: &ProviderObject) -> anyhow::Result<()> {
+        if let Some(delay) = self.delay {
+            event!(
+                Level::WARN,
+                "SIMULATED FAILURE | Delaying object {} by {:?}",
+                object.get_key(),
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+
+        if self.failing_keys.contains(&object.get_key()) {
+            event!(
+                Level::WARN,
+                "SIMULATED FAILURE | Failing object {}",
+                object.get_key()
+            );
+            return Err(anyhow!(
+                "Simulated failure for object {}",
+                object.get_key()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Called before each part of a multipart upload is sent to the destination
+    pub fn before_part(&self, object: &ProviderObject, part_number: usize) -> anyhow::Result<()> {
+        if self.part_failure_rate > 0.0 && random_ratio()? < self.part_failure_rate {
+            event!(
+                Level::WARN,
+                "SIMULATED FAILURE | Failing part {} of object {}",
+                part_number,
+                object.get_key()
+            );
+            return Err(anyhow!(
+                "Simulated failure for part {} of object {}",
+                part_number,
+                object.get_key()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for SimulatedFailures {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut simulated_failures = SimulatedFailures::default();
+
+        for option in value.split(',').filter(|option| !option.is_empty()) {
+            let (name, value) = option
+                .split_once('=')
+                .ok_or_else(|| format!("Failed to parse simulated failure: {}", option))?;
+
+            match name {
+                "part-failure-rate" => {
+                    let rate = value
+                        .parse::<f64>()
+                        .map_err(|_| format!("Failed to parse part-failure-rate: {}", value))?;
+                    if !(0.0..=1.0).contains(&rate) {
+                        return Err(format!(
+                            "part-failure-rate should be between 0 and 1, got {}",
+                            rate
+                        ));
+                    }
+                    simulated_failures.part_failure_rate = rate;
+                }
+                "key" => simulated_failures.failing_keys.push(value.to_string()),
+                "delay-ms" => {
+                    let delay = value
+                        .parse::<u64>()
+                        .map_err(|_| format!("Failed to parse delay-ms: {}", value))?;
+                    simulated_failures.delay = Some(Duration::from_millis(delay));
+                }
+                _ => return Err(format!("Unknown simulated failure: {}", name)),
+            }
+        }
+
+        Ok(simulated_failures)
+    }
+}
+
+fn random_ratio() -> anyhow::Result<f64> {
+    let mut bytes = [0u8; 4];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow!("Failed to generate a random number"))?;
+
+    Ok(u32::from_be_bytes(bytes) as f64 / u32::MAX as f64)
+}
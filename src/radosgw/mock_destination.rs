@@ -0,0 +1,252 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use base64::Engine;
+use bytes::Bytes;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, HeaderMap, Method, Request, Response, Server,
+};
+use md5::{Digest, Md5};
+
+use super::RadosGW;
+use crate::provider::ProviderObject;
+
+/// Request received by a `MockDestination`
+#[derive(Debug, Clone)]
+pub struct ReceivedRequest {
+    pub method: Method,
+    /// Path and query
+    pub uri: String,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl ReceivedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// Decoded value of a query parameter
+    pub fn query(&self, name: &str) -> Option<String> {
+        self.uri
+            .split_once('?')?
+            .1
+            .split('&')
+            .filter_map(|parameter| parameter.split_once('='))
+            .find(|(parameter, _)| *parameter == name)
+            .and_then(|(_, value)| urlencoding::decode(value).ok())
+            .map(|value| value.into_owned())
+    }
+}
+
+/// S3 destination on a local port answering each request with `respond`. The requests are
+/// recorded in their arrival order
+pub struct MockDestination {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockDestination {
+    pub fn start<F>(respond: F) -> MockDestination
+    where
+        F: Fn(&ReceivedRequest) -> Response<Body> + Send + Sync + 'static,
+    {
+        let respond = Arc::new(respond);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded_requests = requests.clone();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted_connections = connections.clone();
+
+        let make_svc = make_service_fn(move |_conn| {
+            accepted_connections.fetch_add(1, Ordering::SeqCst);
+            let respond = respond.clone();
+            let requests = recorded_requests.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let respond = respond.clone();
+                    let requests = requests.clone();
+                    async move {
+                        let (parts, body) = request.into_parts();
+                        let request = ReceivedRequest {
+                            method: parts.method,
+                            uri: parts.uri.to_string(),
+                            headers: parts.headers,
+                            body: hyper::body::to_bytes(body)
+                                .await
+                                .expect("Request body should be readable"),
+                        };
+                        let response = respond(&request);
+                        requests.lock().unwrap().push(request);
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        MockDestination {
+            addr,
+            requests,
+            connections,
+        }
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Client of the `bucket` bucket of the destination
+    pub fn client(&self) -> RadosGW {
+        RadosGW::new(
+            Some(self.endpoint()),
+            None,
+            "access_key".to_string(),
+            "secret_key".to_string(),
+            Some("bucket".to_string()),
+        )
+    }
+
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// TCP connections accepted, a client reusing its connections opens a single one for
+    /// sequential requests
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+/// Counts the requests a `MockDestination` answers at the same time. The answers block their
+/// worker thread, the tests need a multi-threaded runtime with more workers than requests
+#[derive(Debug, Default)]
+pub struct Concurrency {
+    in_flight: AtomicUsize,
+    max: AtomicUsize,
+}
+
+impl Concurrency {
+    /// Holds `response` for `delay`, so the concurrent requests overlap
+    pub fn respond(&self, delay: Duration, response: Response<Body>) -> Response<Body> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max.fetch_max(in_flight, Ordering::SeqCst);
+        std::thread::sleep(delay);
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        response
+    }
+
+    /// Highest number of requests answered at the same time
+    pub fn max(&self) -> usize {
+        self.max.load(Ordering::SeqCst)
+    }
+}
+
+/// S3 error response, e.g. `BadDigest`
+pub fn error_response(status: u16, code: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/xml")
+        .body(Body::from(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Error><Code>{}</Code></Error>",
+            code
+        )))
+        .expect("Error response should be valid")
+}
+
+/// Successful response with the given ETag
+pub fn etag_response(etag: &str) -> Response<Body> {
+    Response::builder()
+        .header("ETag", format!("\"{}\"", etag))
+        .body(Body::empty())
+        .expect("ETag response should be valid")
+}
+
+/// Answers like a destination accepting every upload, the ETag of a body being its MD5. A body
+/// not matching its Content-MD5 is rejected with `BadDigest`
+pub fn accept_uploads(request: &ReceivedRequest) -> Response<Body> {
+    let digest = Md5::digest(&request.body);
+    let content_md5 = base64::engine::general_purpose::STANDARD.encode(digest);
+    if request
+        .header("Content-MD5")
+        .is_some_and(|expected| expected != content_md5)
+    {
+        return error_response(400, "BadDigest");
+    }
+
+    match request.method {
+        Method::POST if request.uri.contains("?uploads") => Response::new(Body::from(
+            "<InitiateMultipartUploadResult><Bucket>bucket</Bucket><Key>key</Key>\
+             <UploadId>upload</UploadId></InitiateMultipartUploadResult>",
+        )),
+        Method::POST => Response::new(Body::from(
+            "<CompleteMultipartUploadResult><ETag>\"etag\"</ETag></CompleteMultipartUploadResult>",
+        )),
+        Method::PUT => etag_response(&format!("{:x}", digest)),
+        _ => Response::new(Body::empty()),
+    }
+}
+
+/// Answers the ListObjectsV2 requests like a bucket holding `objects`, honoring their prefix,
+/// start-after and max-keys parameters
+pub fn list_objects_response(
+    request: &ReceivedRequest,
+    objects: &[ProviderObject],
+) -> Response<Body> {
+    paginated_list_objects_response(request, objects, 1000)
+}
+
+/// Like `list_objects_response`, with at most `page_size` keys per page whatever the max-keys
+pub fn paginated_list_objects_response(
+    request: &ReceivedRequest,
+    objects: &[ProviderObject],
+    page_size: usize,
+) -> Response<Body> {
+    let prefix = request.query("prefix").unwrap_or_default();
+    let start_after = request.query("start-after").unwrap_or_default();
+    let max_keys = request
+        .query("max-keys")
+        .and_then(|max_keys| max_keys.parse().ok())
+        .unwrap_or(1000)
+        .min(page_size);
+
+    let mut listed: Vec<&ProviderObject> = objects
+        .iter()
+        .filter(|object| object.get_key().starts_with(&prefix) && object.get_key() > start_after)
+        .collect();
+    listed.sort_by_key(|object| object.get_key());
+    let truncated = listed.len() > max_keys;
+    listed.truncate(max_keys);
+
+    let contents: String = listed
+        .iter()
+        .map(|object| {
+            format!(
+                "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag>\
+                 <Size>{}</Size></Contents>",
+                object.get_key(),
+                object.get_last_modified().to_rfc3339(),
+                object.get_etag(),
+                object.get_size()
+            )
+        })
+        .collect();
+    Response::new(Body::from(format!(
+        "<ListBucketResult><Name>bucket</Name><KeyCount>{}</KeyCount>\
+         <IsTruncated>{}</IsTruncated>{}</ListBucketResult>",
+        listed.len(),
+        truncated,
+        contents
+    )))
+}
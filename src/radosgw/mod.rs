@@ -1,10 +1,17 @@
 pub mod awscredentials;
+pub mod faults;
+#[cfg(test)]
+pub mod mock_destination;
+pub mod retry;
+pub mod timeout;
+pub mod trace;
 pub mod uploader;
 
 use std::{
+    collections::HashMap,
     pin::Pin,
     str::FromStr,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, OnceLock},
 };
 
 use anyhow::anyhow;
@@ -15,19 +22,31 @@ use rusoto_core::{ByteStream, RusotoError};
 use rusoto_s3::{
     AbortMultipartUploadError, AbortMultipartUploadOutput, AbortMultipartUploadRequest, Bucket,
     CompleteMultipartUploadError, CompleteMultipartUploadOutput, CompleteMultipartUploadRequest,
-    CompletedMultipartUpload, CompletedPart, CreateBucketError, CreateBucketRequest,
-    CreateMultipartUploadError, CreateMultipartUploadOutput, CreateMultipartUploadRequest,
-    DeleteObjectError, DeleteObjectRequest, GetObjectError, GetObjectOutput, GetObjectRequest,
-    HeadObjectOutput, HeadObjectRequest, ListObjectsV2Request, PutObjectError, PutObjectOutput,
-    PutObjectRequest, S3Client, UploadPartError, UploadPartOutput, UploadPartRequest, S3,
+    CompletedMultipartUpload, CompletedPart, CopyObjectError, CopyObjectOutput, CopyObjectRequest,
+    CreateBucketError, CreateBucketRequest, CreateMultipartUploadError,
+    CreateMultipartUploadOutput, CreateMultipartUploadRequest, Delete, DeleteObjectError,
+    DeleteObjectRequest, DeleteObjectsRequest, GetBucketOwnershipControlsRequest,
+    GetObjectAclRequest, GetObjectError, GetObjectOutput, GetObjectRequest,
+    GetObjectTaggingRequest, HeadObjectOutput, HeadObjectRequest, ListMultipartUploadsRequest,
+    ListObjectsV2Request, ListPartsRequest, MultipartUpload, ObjectIdentifier, Part,
+    PutObjectError, PutObjectOutput, PutObjectRequest, S3Client, UploadPartError, UploadPartOutput,
+    UploadPartRequest, S3,
 };
 use tracing::{event, instrument, Level};
 
+use crate::acl_rules::{Grantee, ObjectGrant};
 use crate::provider::{
-    Provider, ProviderObject, ProviderObjectMetadata, ProviderResponse, ProviderResponseStreamChunk,
+    quote_etag, Provider, ProviderObject, ProviderObjectMetadata, ProviderResponse,
+    ProviderResponseStreamChunk, CONTENT_MD5_METADATA, SOURCE_BUCKET_METADATA,
+    SOURCE_ETAG_METADATA, TRUNCATED_FROM_METADATA,
 };
+use crate::radosgw::timeout::{HttpTimeouts, TimeoutDispatcher};
+use crate::tls;
 
-const MAX_FETCH_KEYS: usize = 1000;
+/// Maximum number of keys of a ListObjectsV2 page
+pub const MAX_FETCH_KEYS: usize = 1000;
+/// Maximum number of keys of a DeleteObjects request
+pub const MAX_DELETE_KEYS: usize = 1000;
 const REQUESTS_MAX_RETRIES: usize = 5;
 
 #[derive(Debug, Clone)]
@@ -37,6 +56,23 @@ pub struct RadosGW {
     access_key: String,
     secret_key: String,
     bucket: Option<String>,
+    requester_pays: bool,
+    /// Only the keys starting with it are listed
+    prefix: Option<String>,
+    timeouts: HttpTimeouts,
+    /// Keys requested by each ListObjectsV2 request
+    page_size: usize,
+    /// Built on the first request and shared by the clones, so all the sync threads reuse the
+    /// same connection pool
+    client: Arc<OnceLock<CachedClient>>,
+}
+
+struct CachedClient(S3Client);
+
+impl std::fmt::Debug for CachedClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("S3Client")
+    }
 }
 
 impl RadosGW {
@@ -53,16 +89,60 @@ impl RadosGW {
             access_key,
             secret_key,
             bucket,
+            requester_pays: false,
+            prefix: None,
+            timeouts: HttpTimeouts::default(),
+            page_size: MAX_FETCH_KEYS,
+            client: Arc::new(OnceLock::new()),
         }
     }
 
-    #[instrument(skip(self), level = "trace")]
+    /// Requester pays buckets reject the reads that don't accept to be charged for them
+    pub fn with_requester_pays(self, requester_pays: bool) -> RadosGW {
+        RadosGW {
+            requester_pays,
+            ..self
+        }
+    }
+
+    pub fn with_prefix(self, prefix: Option<String>) -> RadosGW {
+        RadosGW { prefix, ..self }
+    }
+
+    /// Fewer keys per listing page make more requests, at most `MAX_FETCH_KEYS`
+    pub fn with_page_size(self, page_size: usize) -> RadosGW {
+        RadosGW {
+            page_size: page_size.min(MAX_FETCH_KEYS),
+            ..self
+        }
+    }
+
+    /// The client is built again with the timeouts
+    pub fn with_timeouts(self, timeouts: HttpTimeouts) -> RadosGW {
+        RadosGW {
+            timeouts,
+            client: Arc::new(OnceLock::new()),
+            ..self
+        }
+    }
+
+    fn request_payer(&self) -> Option<String> {
+        self.requester_pays.then(|| "requester".to_string())
+    }
+
     fn get_client(&self) -> S3Client {
+        self.client
+            .get_or_init(|| CachedClient(self.build_client()))
+            .0
+            .clone()
+    }
+
+    #[instrument(skip(self), level = "trace")]
+    fn build_client(&self) -> S3Client {
         let radosgw_credential_provider = awscredentials::AWSCredentialsProvider::new(
             self.access_key.clone(),
             self.secret_key.clone(),
         );
-        let http_client = rusoto_core::HttpClient::new().unwrap();
         let region = match (&self.endpoint, &self.region) {
             // Can happen for other S3 like services
             (Some(endpoint), Some(region)) => rusoto_core::Region::Custom {
@@ -81,6 +161,23 @@ impl RadosGW {
 
         event!(Level::DEBUG, "Using client with region: {:?}", region);
 
+        if trace::trace_requests_enabled() {
+            return S3Client::new_with(
+                trace::TraceDispatcher,
+                radosgw_credential_provider,
+                region,
+            );
+        }
+
+        if self.timeouts.is_set() || tls::is_configured() {
+            return S3Client::new_with(
+                TimeoutDispatcher::new(self.timeouts),
+                radosgw_credential_provider,
+                region,
+            );
+        }
+
+        let http_client = rusoto_core::HttpClient::new().unwrap();
         S3Client::new_with(http_client, radosgw_credential_provider, region)
     }
 
@@ -92,6 +189,13 @@ impl RadosGW {
         size: i64,
         body: ByteStream,
     ) -> Result<PutObjectOutput, RusotoError<PutObjectError>> {
+        // An empty streamed body has no size hint and would be sent chunked, some gateways
+        // reject it
+        let body = if size == 0 {
+            ByteStream::from(Vec::new())
+        } else {
+            body
+        };
         let put_object_request = PutObjectRequest {
             body: Some(body),
             key,
@@ -100,11 +204,7 @@ impl RadosGW {
                 .clone()
                 .expect("put_object should have a bucket"),
             content_length: Some(size),
-            acl: if object_metadata.acl_public {
-                Some("public-read".to_string())
-            } else {
-                None
-            },
+            acl: canned_acl(object_metadata),
             cache_control: object_metadata.cache_control.clone(),
             content_disposition: object_metadata.content_disposition.clone(),
             content_encoding: object_metadata.content_encoding.clone(),
@@ -112,6 +212,12 @@ impl RadosGW {
             content_md5: object_metadata.content_md5.clone(),
             content_type: object_metadata.content_type.clone(),
             expires: object_metadata.expires.clone(),
+            website_redirect_location: object_metadata.website_redirect_location.clone(),
+            server_side_encryption: object_metadata.server_side_encryption.clone(),
+            ssekms_key_id: object_metadata.ssekms_key_id.clone(),
+            metadata: user_metadata(object_metadata),
+            tagging: tagging(object_metadata),
+            storage_class: object_metadata.storage_class.clone(),
             ..Default::default()
         };
 
@@ -119,6 +225,53 @@ impl RadosGW {
         client.put_object(put_object_request).await
     }
 
+    /// `CopySource` of an object of this bucket
+    pub fn copy_source(&self, key: &str) -> String {
+        format!(
+            "{}/{}",
+            self.bucket.as_ref().expect("copy_source should have a bucket"),
+            urlencoding::encode(key)
+        )
+    }
+
+    /// Server side copy of `copy_source` to `key`, with the metadata of the copied object replaced
+    #[instrument(skip(self), level = "debug")]
+    pub async fn copy_object(
+        &self,
+        copy_source: String,
+        key: String,
+        object_metadata: &ProviderObjectMetadata,
+    ) -> Result<CopyObjectOutput, RusotoError<CopyObjectError>> {
+        let copy_object_request = CopyObjectRequest {
+            bucket: self
+                .bucket
+                .clone()
+                .expect("copy_object should have a bucket"),
+            key,
+            copy_source,
+            metadata_directive: Some("REPLACE".to_string()),
+            // Otherwise the copy keeps the tags of the copied object
+            tagging_directive: Some("REPLACE".to_string()),
+            tagging: tagging(object_metadata),
+            storage_class: object_metadata.storage_class.clone(),
+            acl: canned_acl(object_metadata),
+            cache_control: object_metadata.cache_control.clone(),
+            content_disposition: object_metadata.content_disposition.clone(),
+            content_encoding: object_metadata.content_encoding.clone(),
+            content_language: object_metadata.content_language.clone(),
+            content_type: object_metadata.content_type.clone(),
+            expires: object_metadata.expires.clone(),
+            website_redirect_location: object_metadata.website_redirect_location.clone(),
+            server_side_encryption: object_metadata.server_side_encryption.clone(),
+            ssekms_key_id: object_metadata.ssekms_key_id.clone(),
+            metadata: user_metadata(object_metadata),
+            ..Default::default()
+        };
+
+        let client = self.get_client();
+        client.copy_object(copy_object_request).await
+    }
+
     #[instrument(skip(self), level = "debug")]
     pub async fn create_multipart_upload(
         &self,
@@ -131,11 +284,7 @@ impl RadosGW {
                 .bucket
                 .clone()
                 .expect("create_multipart_upload should have a bucket"),
-            acl: if object_metadata.acl_public {
-                Some("public-read".to_string())
-            } else {
-                None
-            },
+            acl: canned_acl(object_metadata),
             // We don't have the content_md5 in this list but I don't think we really care
             cache_control: object_metadata.cache_control.clone(),
             content_disposition: object_metadata.content_disposition.clone(),
@@ -143,6 +292,12 @@ impl RadosGW {
             content_language: object_metadata.content_language.clone(),
             content_type: object_metadata.content_type.clone(),
             expires: object_metadata.expires.clone(),
+            website_redirect_location: object_metadata.website_redirect_location.clone(),
+            server_side_encryption: object_metadata.server_side_encryption.clone(),
+            ssekms_key_id: object_metadata.ssekms_key_id.clone(),
+            metadata: user_metadata(object_metadata),
+            tagging: tagging(object_metadata),
+            storage_class: object_metadata.storage_class.clone(),
             ..Default::default()
         };
 
@@ -160,6 +315,7 @@ impl RadosGW {
         body: ByteStream,
         upload_id: String,
         part_number: i64,
+        content_md5: Option<String>,
     ) -> Result<UploadPartOutput, RusotoError<UploadPartError>> {
         let part_upload_request = UploadPartRequest {
             key,
@@ -171,6 +327,7 @@ impl RadosGW {
             upload_id,
             part_number,
             content_length: Some(size),
+            content_md5,
             ..Default::default()
         };
 
@@ -236,6 +393,68 @@ impl RadosGW {
             .await
     }
 
+    /// Multipart uploads in progress in the bucket, under the prefix if any
+    #[instrument(skip(self), level = "debug")]
+    pub async fn list_multipart_uploads(&self) -> anyhow::Result<Vec<MultipartUpload>> {
+        let client = self.get_client();
+        let mut uploads = Vec::new();
+        let mut key_marker = None;
+        let mut upload_id_marker = None;
+
+        loop {
+            let list_multipart_uploads_request = ListMultipartUploadsRequest {
+                bucket: self
+                    .bucket
+                    .clone()
+                    .expect("list_multipart_uploads should have a bucket"),
+                prefix: self.prefix.clone(),
+                key_marker,
+                upload_id_marker,
+                ..Default::default()
+            };
+
+            let output = client
+                .list_multipart_uploads(list_multipart_uploads_request)
+                .await?;
+            uploads.extend(output.uploads.unwrap_or_default());
+
+            if output.is_truncated != Some(true) {
+                return Ok(uploads);
+            }
+            key_marker = output.next_key_marker;
+            upload_id_marker = output.next_upload_id_marker;
+        }
+    }
+
+    /// Parts already uploaded to a multipart upload
+    #[instrument(skip(self), level = "debug")]
+    pub async fn list_parts(&self, key: String, upload_id: String) -> anyhow::Result<Vec<Part>> {
+        let client = self.get_client();
+        let mut parts = Vec::new();
+        let mut part_number_marker = None;
+
+        loop {
+            let list_parts_request = ListPartsRequest {
+                bucket: self
+                    .bucket
+                    .clone()
+                    .expect("list_parts should have a bucket"),
+                key: key.clone(),
+                upload_id: upload_id.clone(),
+                part_number_marker,
+                ..Default::default()
+            };
+
+            let output = client.list_parts(list_parts_request).await?;
+            parts.extend(output.parts.unwrap_or_default());
+
+            if output.is_truncated != Some(true) {
+                return Ok(parts);
+            }
+            part_number_marker = output.next_part_number_marker;
+        }
+    }
+
     #[instrument(skip(self), level = "trace")]
     async fn list_objects(
         &self,
@@ -246,11 +465,6 @@ impl RadosGW {
         let mut retries = 0;
 
         loop {
-            if retries > REQUESTS_MAX_RETRIES {
-                event!(Level::ERROR, "We've hit max retries when listing objects. Check warning logs for more details");
-                return Err(anyhow::anyhow!("MaxRetriesHit when listing objects"));
-            }
-
             let list_objects_request = ListObjectsV2Request {
                 bucket: self
                     .bucket
@@ -258,6 +472,8 @@ impl RadosGW {
                     .expect("list_objects should have a bucket"),
                 start_after: start_after.clone(),
                 max_keys: max_results,
+                prefix: self.prefix.clone(),
+                request_payer: self.request_payer(),
                 ..Default::default()
             };
 
@@ -283,6 +499,12 @@ impl RadosGW {
                     RusotoError::HttpDispatch(_) => {
                         event!(Level::WARN, "Got error when listing objects: {:?}", error);
                         retries += 1;
+                        if retries > REQUESTS_MAX_RETRIES {
+                            event!(Level::ERROR, "We've hit max retries when listing objects. Check warning logs for more details");
+                            // The dispatch error is kept for `preflight_failure`
+                            return Err(anyhow::Error::from(error)
+                                .context("MaxRetriesHit when listing objects"));
+                        }
                         continue;
                     }
                     _ => return Err(anyhow::Error::from(error)),
@@ -318,6 +540,130 @@ impl RadosGW {
             .map(|_| object)
     }
 
+    /// Deletes the keys using DeleteObjects requests of at most MAX_DELETE_KEYS keys. Returns the
+    /// keys that couldn't be deleted with the reason, the keys of a failed request all get its
+    /// error
+    #[instrument(skip_all, level = "debug", fields(keys = keys.len()))]
+    pub async fn delete_objects(&self, keys: Vec<String>) -> HashMap<String, String> {
+        let client = self.get_client();
+        let bucket = self
+            .bucket
+            .clone()
+            .expect("delete_objects should have a bucket");
+        let mut failed = HashMap::new();
+
+        for batch in keys.chunks(MAX_DELETE_KEYS) {
+            let delete_objects_request = DeleteObjectsRequest {
+                bucket: bucket.clone(),
+                delete: Delete {
+                    objects: batch
+                        .iter()
+                        .map(|key| ObjectIdentifier {
+                            key: key.clone(),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    // Only the errors are returned
+                    quiet: Some(true),
+                },
+                ..Default::default()
+            };
+
+            match client.delete_objects(delete_objects_request).await {
+                Ok(output) => {
+                    for error in output.errors.unwrap_or_default() {
+                        if let Some(key) = error.key {
+                            failed.insert(
+                                key,
+                                format!(
+                                    "{}: {}",
+                                    error.code.unwrap_or_default(),
+                                    error.message.unwrap_or_default()
+                                ),
+                            );
+                        }
+                    }
+                }
+                Err(error) => {
+                    let reason = format!("DeleteObjects request failed: {:?}", error);
+                    failed.extend(batch.iter().map(|key| (key.clone(), reason.clone())));
+                }
+            }
+        }
+
+        failed
+    }
+
+    /// Writes then deletes a tiny object to make sure the credentials aren't read-only
+    #[instrument(skip(self), level = "debug")]
+    pub async fn check_write_permission(&self, canary_key: String) -> anyhow::Result<()> {
+        let client = self.get_client();
+        let bucket = self
+            .bucket
+            .clone()
+            .expect("check_write_permission should have a bucket");
+        let body = b"cellar-migration canary".to_vec();
+
+        let put_object_request = PutObjectRequest {
+            bucket: bucket.clone(),
+            key: canary_key.clone(),
+            content_length: Some(body.len() as i64),
+            body: Some(ByteStream::from(body)),
+            ..Default::default()
+        };
+        client
+            .put_object(put_object_request)
+            .await
+            .map_err(|error| anyhow!("Failed to write object {}: {:?}", canary_key, error))?;
+
+        let delete_object_request = DeleteObjectRequest {
+            bucket,
+            key: canary_key.clone(),
+            ..Default::default()
+        };
+        client
+            .delete_object(delete_object_request)
+            .await
+            .map_err(|error| anyhow!("Failed to delete object {}: {:?}", canary_key, error))?;
+
+        Ok(())
+    }
+
+    /// Buckets enforcing the bucket owner ownership (BucketOwnerEnforced) reject the requests
+    /// setting an object ACL. Gateways without ownership controls always accept them
+    #[instrument(skip(self), level = "debug")]
+    pub async fn object_acls_disabled(&self) -> bool {
+        let bucket = self
+            .bucket
+            .clone()
+            .expect("object_acls_disabled should have a bucket");
+        let request = GetBucketOwnershipControlsRequest {
+            bucket: bucket.clone(),
+            ..Default::default()
+        };
+
+        match self.get_client().get_bucket_ownership_controls(request).await {
+            Ok(output) => output
+                .ownership_controls
+                .map(|controls| {
+                    controls
+                        .rules
+                        .iter()
+                        .any(|rule| rule.object_ownership == "BucketOwnerEnforced")
+                })
+                .unwrap_or(false),
+            Err(error) => {
+                event!(
+                    Level::DEBUG,
+                    "No ownership controls for bucket {}: {:?}",
+                    bucket,
+                    error
+                );
+                false
+            }
+        }
+    }
+
     #[instrument(skip(self), level = "debug")]
     pub async fn list_buckets(&self) -> anyhow::Result<Vec<Bucket>> {
         let client = self.get_client();
@@ -359,6 +705,7 @@ impl RadosGW {
                 .clone()
                 .expect("get_object_metadata should have a bucket"),
             key: object.get_key(),
+            request_payer: self.request_payer(),
             ..Default::default()
         };
 
@@ -384,6 +731,37 @@ impl RadosGW {
                 .clone()
                 .expect("get_object should have a bucket"),
             key: object.get_key(),
+            request_payer: self.request_payer(),
+            ..Default::default()
+        };
+
+        client
+            .get_object(get_object_request)
+            .await
+            .map_err(|error| anyhow!("Error fetching object {}: {:?}", object.get_key(), error))
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_object_range(
+        &self,
+        object: &ProviderObject,
+        start: u64,
+        length: u64,
+    ) -> anyhow::Result<GetObjectOutput> {
+        let client = self.get_client();
+
+        let get_object_request = GetObjectRequest {
+            bucket: self
+                .bucket
+                .clone()
+                .expect("get_object should have a bucket"),
+            key: object.get_key(),
+            range: Some(format!(
+                "bytes={}-{}",
+                start,
+                (start + length).saturating_sub(1)
+            )),
+            request_payer: self.request_payer(),
             ..Default::default()
         };
 
@@ -392,6 +770,216 @@ impl RadosGW {
             .await
             .map_err(|error| anyhow!("Error fetching object {}: {:?}", object.get_key(), error))
     }
+
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_object_if_none_match(
+        &self,
+        object: &ProviderObject,
+        etag: &str,
+    ) -> anyhow::Result<Option<GetObjectOutput>> {
+        let client = self.get_client();
+
+        let get_object_request = GetObjectRequest {
+            bucket: self
+                .bucket
+                .clone()
+                .expect("get_object should have a bucket"),
+            key: object.get_key(),
+            if_none_match: Some(quote_etag(etag)),
+            request_payer: self.request_payer(),
+            ..Default::default()
+        };
+
+        match client.get_object(get_object_request).await {
+            Ok(response) => Ok(Some(response)),
+            // rusoto treats 304 Not Modified as an error
+            Err(RusotoError::Unknown(response)) if response.status.as_u16() == 304 => Ok(None),
+            Err(error) => Err(anyhow!(
+                "Error fetching object {}: {:?}",
+                object.get_key(),
+                error
+            )),
+        }
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_object_tags(
+        &self,
+        object: &ProviderObject,
+    ) -> anyhow::Result<HashMap<String, String>> {
+        let client = self.get_client();
+
+        let get_object_tagging_request = GetObjectTaggingRequest {
+            bucket: self
+                .bucket
+                .clone()
+                .expect("get_object_tags should have a bucket"),
+            key: object.get_key(),
+            request_payer: self.request_payer(),
+            ..Default::default()
+        };
+
+        client
+            .get_object_tagging(get_object_tagging_request)
+            .await
+            .map(|output| {
+                output
+                    .tag_set
+                    .into_iter()
+                    .map(|tag| (tag.key, tag.value))
+                    .collect()
+            })
+            .map_err(|error| {
+                anyhow!(
+                    "Error fetching tags of object {}: {:?}",
+                    object.get_key(),
+                    error
+                )
+            })
+    }
+
+    pub async fn get_object_acl(
+        &self,
+        object: &ProviderObject,
+    ) -> anyhow::Result<Vec<ObjectGrant>> {
+        let client = self.get_client();
+
+        let get_object_acl_request = GetObjectAclRequest {
+            bucket: self
+                .bucket
+                .clone()
+                .expect("get_object_acl should have a bucket"),
+            key: object.get_key(),
+            request_payer: self.request_payer(),
+            ..Default::default()
+        };
+
+        let output = client
+            .get_object_acl(get_object_acl_request)
+            .await
+            .map_err(|error| {
+                anyhow!(
+                    "Error fetching ACL of object {}: {:?}",
+                    object.get_key(),
+                    error
+                )
+            })?;
+        let owner_id = output.owner.and_then(|owner| owner.id);
+
+        Ok(output
+            .grants
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|grant| {
+                let grantee = grant.grantee?;
+                let grantee = match (grantee.uri, grantee.id, grantee.email_address) {
+                    (Some(uri), _, _) => Grantee::from_uri(&uri),
+                    (None, Some(id), _) if Some(&id) == owner_id.as_ref() => Grantee::Owner,
+                    (None, Some(id), _) => Grantee::Other(id),
+                    (None, None, Some(email)) => Grantee::Other(email),
+                    (None, None, None) => return None,
+                };
+                Some(ObjectGrant {
+                    grantee,
+                    permission: grant.permission?,
+                })
+            })
+            .collect())
+    }
+}
+
+/// The ACL of the metadata when set, public-read for public objects otherwise
+fn canned_acl(object_metadata: &ProviderObjectMetadata) -> Option<String> {
+    match object_metadata.acl {
+        Some(acl) => Some(acl.canned().to_string()),
+        None if object_metadata.acl_public => Some("public-read".to_string()),
+        None => None,
+    }
+}
+
+/// RadosGW answers `QuotaExceeded` once the user or bucket quota is reached. Other gateways
+/// answer `507 Insufficient Storage` when they run out of space.
+pub fn is_destination_full<E>(error: &RusotoError<E>) -> bool {
+    match error {
+        RusotoError::Unknown(response) => {
+            let body = String::from_utf8_lossy(&response.body);
+            response.status.as_u16() == 507
+                || body.contains("<Code>QuotaExceeded</Code>")
+                || body.contains("<Code>InsufficientStorage</Code>")
+        }
+        _ => false,
+    }
+}
+
+/// SigV4 signatures are only valid for a few minutes around the server time. When the host clock
+/// is skewed, every request fails with `RequestTimeTooSkewed`, returns a message telling how to fix it
+pub fn clock_skew_message<E>(error: &RusotoError<E>) -> Option<String> {
+    match error {
+        RusotoError::Unknown(response) => {
+            let body = String::from_utf8_lossy(&response.body);
+            if !body.contains("<Code>RequestTimeTooSkewed</Code>") {
+                return None;
+            }
+
+            let server_time = body
+                .split_once("<ServerTime>")
+                .and_then(|(_, rest)| rest.split_once("</ServerTime>"))
+                .map(|(server_time, _)| server_time.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+
+            Some(format!(
+                "The clock of this host is skewed, requests are rejected with RequestTimeTooSkewed. Server time: {}, local time: {}. Please synchronize the clock of this host (e.g. using NTP)",
+                server_time,
+                chrono::Utc::now().to_rfc3339()
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn user_metadata(object_metadata: &ProviderObjectMetadata) -> Option<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+    if let Some(md5) = &object_metadata.content_md5 {
+        metadata.insert(CONTENT_MD5_METADATA.to_string(), md5.clone());
+    }
+    if let Some(size) = object_metadata.truncated_from {
+        metadata.insert(TRUNCATED_FROM_METADATA.to_string(), size.to_string());
+    }
+    if let Some(source_bucket) = &object_metadata.source_bucket {
+        metadata.insert(SOURCE_BUCKET_METADATA.to_string(), source_bucket.clone());
+    }
+    if let Some(source_etag) = &object_metadata.source_etag {
+        metadata.insert(SOURCE_ETAG_METADATA.to_string(), source_etag.clone());
+    }
+
+    if metadata.is_empty() {
+        None
+    } else {
+        Some(metadata)
+    }
+}
+
+/// URL-encoded query string of the tag set, as expected by the `x-amz-tagging` header. Objects
+/// without tags don't send it
+fn tagging(object_metadata: &ProviderObjectMetadata) -> Option<String> {
+    if object_metadata.tags.is_empty() {
+        return None;
+    }
+
+    let mut tags: Vec<(&String, &String)> = object_metadata.tags.iter().collect();
+    tags.sort();
+    Some(
+        tags.into_iter()
+            .map(|(key, value)| {
+                format!(
+                    "{}={}",
+                    urlencoding::encode(key),
+                    urlencoding::encode(value)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("&"),
+    )
 }
 
 struct RadosGWResponseInner {
@@ -419,10 +1007,14 @@ impl Stream for RadosGWResponseInner {
 pub struct RadosGWResponse {
     response: Option<Arc<Mutex<GetObjectOutput>>>,
     error: Option<anyhow::Error>,
+    chunked: bool,
 }
 
 impl RadosGWResponse {
     pub fn new(response: Result<GetObjectOutput, anyhow::Error>) -> RadosGWResponse {
+        // rusoto doesn't expose the Transfer-Encoding header, a missing Content-Length means
+        // the body is streamed
+        let chunked = matches!(&response, Ok(res) if res.content_length.is_none());
         let (res, error) = match response {
             Ok(res) => (Some(Arc::new(Mutex::new(res))), None),
             Err(err) => (None, Some(err)),
@@ -430,6 +1022,7 @@ impl RadosGWResponse {
         RadosGWResponse {
             response: res,
             error,
+            chunked,
         }
     }
 }
@@ -446,6 +1039,10 @@ impl ProviderResponse for RadosGWResponse {
         }
     }
 
+    fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+
     fn body(
         &mut self,
     ) -> std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, std::io::Error>> + Send>>
@@ -510,13 +1107,13 @@ impl Provider for RadosGW {
             move |(start_after, total_keys)| async move {
                 let max_results = max_keys
                     .map(|max| {
-                        if total_keys + MAX_FETCH_KEYS > max {
+                        if total_keys + self.page_size > max {
                             max - total_keys
                         } else {
-                            MAX_FETCH_KEYS
+                            self.page_size
                         }
                     })
-                    .unwrap_or(MAX_FETCH_KEYS);
+                    .unwrap_or(self.page_size);
                 event!(
                     Level::DEBUG,
                     "Listing objects (bucket={:?}): start_after={:?}, max_results={:?}, total_keys={}",
@@ -571,4 +1168,232 @@ impl Provider for RadosGW {
         let x: Box<dyn ProviderResponse> = Box::new(RadosGWResponse::new(object));
         Ok(x)
     }
+    async fn get_object_range(
+        &self,
+        object: &ProviderObject,
+        start: u64,
+        length: u64,
+    ) -> anyhow::Result<Box<dyn ProviderResponse>> {
+        let object = self.get_object_range(object, start, length).await;
+
+        let x: Box<dyn ProviderResponse> = Box::new(RadosGWResponse::new(object));
+        Ok(x)
+    }
+    async fn get_object_if_none_match(
+        &self,
+        object: &ProviderObject,
+        etag: &str,
+    ) -> anyhow::Result<Option<Box<dyn ProviderResponse>>> {
+        let object = match self.get_object_if_none_match(object, etag).await {
+            Ok(None) => return Ok(None),
+            Ok(Some(object)) => Ok(object),
+            Err(error) => Err(error),
+        };
+
+        let x: Box<dyn ProviderResponse> = Box::new(RadosGWResponse::new(object));
+        Ok(Some(x))
+    }
+    async fn get_object_tags(
+        &self,
+        object: &ProviderObject,
+    ) -> anyhow::Result<HashMap<String, String>> {
+        self.get_object_tags(object).await
+    }
+
+    async fn get_object_acl(&self, object: &ProviderObject) -> anyhow::Result<Vec<ObjectGrant>> {
+        self.get_object_acl(object).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{acl_rules::DestinationAcl, benchmark::SyntheticProvider};
+    use hyper::{Body, Method, Response};
+    use mock_destination::{accept_uploads, list_objects_response, MockDestination};
+
+    async fn object_metadata(size: u64) -> ProviderObjectMetadata {
+        SyntheticProvider {}
+            .get_object_metadata(&ProviderObject::new("key".to_string(), size))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn client_is_built_once_and_shared_by_the_clones() {
+        let destination = MockDestination::start(accept_uploads);
+        let client = destination.client();
+        let metadata = object_metadata(5).await;
+
+        for client in [&client, &client.clone(), &client] {
+            client
+                .put_object(
+                    "key".to_string(),
+                    &metadata,
+                    5,
+                    ByteStream::from(vec![0; 5]),
+                )
+                .await
+                .unwrap();
+        }
+
+        // A client built for each request would open a connection for each
+        assert_eq!(destination.requests().len(), 3);
+        assert_eq!(destination.connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn tags_are_put_url_encoded() {
+        let destination = MockDestination::start(accept_uploads);
+        let client = destination.client();
+        let tagged = ProviderObjectMetadata {
+            tags: HashMap::from([
+                ("team".to_string(), "data".to_string()),
+                ("cost center".to_string(), "r&d/42".to_string()),
+            ]),
+            ..object_metadata(0).await
+        };
+
+        for metadata in [&tagged, &object_metadata(0).await] {
+            client
+                .put_object("key".to_string(), metadata, 0, ByteStream::from(Vec::new()))
+                .await
+                .unwrap();
+        }
+
+        let requests = destination.requests();
+        assert_eq!(
+            requests[0].header("x-amz-tagging"),
+            Some("cost%20center=r%26d%2F42&team=data")
+        );
+        assert_eq!(requests[1].header("x-amz-tagging"), None);
+    }
+
+    #[tokio::test]
+    async fn object_is_put_with_its_canned_acl() {
+        let destination = MockDestination::start(accept_uploads);
+        let client = destination.client();
+        let acls = [
+            (None, false, None),
+            (None, true, Some("public-read")),
+            (Some(DestinationAcl::Private), true, Some("private")),
+            (Some(DestinationAcl::PublicRead), false, Some("public-read")),
+            (
+                Some(DestinationAcl::PublicReadWrite),
+                false,
+                Some("public-read-write"),
+            ),
+            (
+                Some(DestinationAcl::AuthenticatedRead),
+                false,
+                Some("authenticated-read"),
+            ),
+        ];
+
+        for (acl, acl_public, _) in acls {
+            let metadata = ProviderObjectMetadata {
+                acl,
+                acl_public,
+                ..object_metadata(0).await
+            };
+            client
+                .put_object(
+                    "key".to_string(),
+                    &metadata,
+                    0,
+                    ByteStream::from(Vec::new()),
+                )
+                .await
+                .unwrap();
+        }
+
+        let sent = destination
+            .requests()
+            .iter()
+            .map(|request| request.header("x-amz-acl").map(str::to_string))
+            .collect::<Vec<Option<String>>>();
+        let expected = acls
+            .iter()
+            .map(|(_, _, canned)| canned.map(str::to_string))
+            .collect::<Vec<Option<String>>>();
+        assert_eq!(sent, expected);
+    }
+
+    #[tokio::test]
+    async fn keys_are_deleted_in_batches() {
+        let destination = MockDestination::start(|request| {
+            let body = String::from_utf8_lossy(&request.body);
+            Response::new(Body::from(if body.contains("<Key>key-3</Key>") {
+                "<DeleteResult><Error><Key>key-3</Key><Code>AccessDenied</Code>\
+                 <Message>Access Denied</Message></Error></DeleteResult>"
+            } else {
+                "<DeleteResult></DeleteResult>"
+            }))
+        });
+        let keys = (0..MAX_DELETE_KEYS + 1)
+            .map(|index| format!("key-{}", index))
+            .collect();
+
+        let failed = destination.client().delete_objects(keys).await;
+
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed["key-3"], "AccessDenied: Access Denied");
+        let requests = destination.requests();
+        assert!(requests
+            .iter()
+            .all(|request| request.method == Method::POST));
+        let batches = requests
+            .iter()
+            .map(|request| {
+                String::from_utf8_lossy(&request.body)
+                    .matches("<Key>")
+                    .count()
+            })
+            .collect::<Vec<usize>>();
+        assert_eq!(batches, [MAX_DELETE_KEYS, 1]);
+    }
+
+    /// Keys of each page and max-keys of each request listing `objects` in pages of `page_size`
+    async fn listing_pages(
+        objects: Vec<ProviderObject>,
+        page_size: usize,
+    ) -> (Vec<usize>, Vec<Option<String>>) {
+        let destination =
+            MockDestination::start(move |request| list_objects_response(request, &objects));
+        let client = destination.client().with_page_size(page_size);
+
+        let pages: Vec<usize> = Provider::list_objects(&client, None, None)
+            .map(|page| page.unwrap().len())
+            .collect()
+            .await;
+        let max_keys = destination
+            .requests()
+            .iter()
+            .map(|request| request.query("max-keys"))
+            .collect();
+        (pages, max_keys)
+    }
+
+    #[tokio::test]
+    async fn listing_pages_have_the_configured_size() {
+        let objects = (0..5)
+            .map(|index| ProviderObject::new(format!("key-{}", index), 10))
+            .collect();
+
+        let (pages, max_keys) = listing_pages(objects, 2).await;
+
+        assert_eq!(pages, [2, 2, 1]);
+        assert!(max_keys
+            .iter()
+            .all(|max_keys| max_keys.as_deref() == Some("2")));
+    }
+
+    #[tokio::test]
+    async fn listing_page_size_is_capped() {
+        let objects = vec![ProviderObject::new("key".to_string(), 10)];
+
+        let (_, max_keys) = listing_pages(objects, MAX_FETCH_KEYS + 1).await;
+
+        assert_eq!(max_keys[0], Some(MAX_FETCH_KEYS.to_string()));
+    }
 }
@@ -0,0 +1,78 @@
+use std::{future::Future, time::Duration};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use rusoto_core::RusotoError;
+use rusoto_s3::{CompleteMultipartUploadError, PutObjectError, UploadPartError};
+use tracing::{event, Level};
+
+/// Exponential backoff between the attempts of a request failing with a transient error
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: usize,
+    /// Delay before the first retry, doubled on each following retry
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Runs `request` until it succeeds, fails with an error `is_retryable` rejects or
+    /// `max_retries` retries failed
+    pub async fn retry<T, F, Fut>(
+        &self,
+        description: &str,
+        is_retryable: fn(&anyhow::Error) -> bool,
+        mut request: F,
+    ) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let mut retries = 0;
+        loop {
+            match request().await {
+                Err(error) if retries < self.max_retries && is_retryable(&error) => {
+                    let delay = self.delay(retries);
+                    retries += 1;
+                    event!(
+                        Level::WARN,
+                        "{} failed, retrying in {:?} ({}/{}): {:?}",
+                        description,
+                        delay,
+                        retries,
+                        self.max_retries,
+                        error
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Between 50% and 100% of the exponential delay, so the threads failing at the same time
+    /// don't retry at the same time
+    fn delay(&self, retries: usize) -> Duration {
+        let delay = self.base_delay * 2u32.saturating_pow(retries as u32);
+        let mut bytes = [0u8; 4];
+        match SystemRandom::new().fill(&mut bytes) {
+            Ok(()) => delay.mul_f64(0.5 + u32::from_be_bytes(bytes) as f64 / u32::MAX as f64 / 2.0),
+            Err(_) => delay,
+        }
+    }
+}
+
+/// Connection errors, timeouts and 5xx responses. 4xx responses like AccessDenied won't succeed
+/// on a retry
+fn is_transient<E: std::error::Error + Send + Sync + 'static>(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<RusotoError<E>>() {
+        Some(RusotoError::HttpDispatch(_)) => true,
+        Some(RusotoError::Unknown(response)) => response.status.is_server_error(),
+        _ => false,
+    }
+}
+
+/// Destination errors of an object upload worth uploading the object again for
+pub fn is_retryable_upload_error(error: &anyhow::Error) -> bool {
+    is_transient::<PutObjectError>(error)
+        || is_transient::<UploadPartError>(error)
+        || is_transient::<CompleteMultipartUploadError>(error)
+}
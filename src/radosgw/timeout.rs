@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use hyper::client::HttpConnector;
+use hyper_tls::HttpsConnector;
+use rusoto_core::{
+    request::DispatchSignedRequestFuture, signature::SignedRequest, DispatchSignedRequest,
+    HttpClient,
+};
+
+use crate::tls;
+
+/// Timeouts of the requests sent to an S3 endpoint. Without them, a stalled connection hangs
+/// the sync thread waiting for it forever
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HttpTimeouts {
+    /// Time to establish the TCP connection
+    pub connect: Option<Duration>,
+    /// Time until the response headers are received, including the upload of the request body
+    pub request: Option<Duration>,
+}
+
+impl HttpTimeouts {
+    pub fn is_set(&self) -> bool {
+        self.connect.is_some() || self.request.is_some()
+    }
+}
+
+/// HTTP client applying the request timeout to the requests of the S3 client, which never sets
+/// one. A timed out request fails with an `HttpDispatch` error, like a connection reset.
+/// Also used for the TLS settings of --ca-bundle, the default client only trusts the system CAs
+pub struct TimeoutDispatcher {
+    client: HttpClient<HttpsConnector<HttpConnector>>,
+    request_timeout: Option<Duration>,
+}
+
+impl TimeoutDispatcher {
+    pub fn new(timeouts: HttpTimeouts) -> TimeoutDispatcher {
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(timeouts.connect);
+
+        TimeoutDispatcher {
+            client: HttpClient::from_connector(tls::https_connector(connector)),
+            request_timeout: timeouts.request,
+        }
+    }
+}
+
+impl DispatchSignedRequest for TimeoutDispatcher {
+    fn dispatch(
+        &self,
+        request: SignedRequest,
+        timeout: Option<Duration>,
+    ) -> DispatchSignedRequestFuture {
+        self.client
+            .dispatch(request, timeout.or(self.request_timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rusoto_core::ByteStream;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        benchmark::SyntheticProvider,
+        provider::{Provider, ProviderObject},
+        radosgw::{retry::is_retryable_upload_error, RadosGW},
+    };
+
+    #[tokio::test]
+    async fn request_to_a_stalled_destination_times_out() {
+        // Accepts the connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.push(connection);
+            }
+        });
+        let client = RadosGW::new(
+            Some(endpoint),
+            None,
+            "access_key".to_string(),
+            "secret_key".to_string(),
+            Some("bucket".to_string()),
+        )
+        .with_timeouts(HttpTimeouts {
+            connect: None,
+            request: Some(Duration::from_millis(200)),
+        });
+        let metadata = SyntheticProvider {}
+            .get_object_metadata(&ProviderObject::new("key".to_string(), 5))
+            .await
+            .unwrap();
+
+        let start = Instant::now();
+        let error = client
+            .put_object(
+                "key".to_string(),
+                &metadata,
+                5,
+                ByteStream::from(vec![0; 5]),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(is_retryable_upload_error(&error.into()));
+    }
+}
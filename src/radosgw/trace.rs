@@ -0,0 +1,97 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use hyper::{HeaderMap, StatusCode};
+use rusoto_core::{
+    request::{DispatchSignedRequestFuture, HttpResponse},
+    signature::SignedRequest,
+    ByteStream, DispatchSignedRequest,
+};
+use tracing::{event, Level};
+
+/// Set once at startup by --trace-requests, before any client is built
+static TRACE_REQUESTS: AtomicBool = AtomicBool::new(false);
+
+pub fn enable_trace_requests() {
+    TRACE_REQUESTS.store(true, Ordering::Relaxed);
+}
+
+pub fn trace_requests_enabled() -> bool {
+    TRACE_REQUESTS.load(Ordering::Relaxed)
+}
+
+/// Debugging dispatcher logging the requests the S3 clients would send, without sending them.
+/// Each request gets an empty successful response, so listings are empty and nothing is
+/// migrated. Useful to check the addressing style and the region of the requests
+#[derive(Debug, Clone, Default)]
+pub struct TraceDispatcher;
+
+impl TraceDispatcher {
+    /// Minimal bodies for the responses the clients can't do without
+    fn canned_body(request: &SignedRequest) -> &'static str {
+        match request.method() {
+            "GET" if request.params.contains_key("list-type") => {
+                "<ListBucketResult><IsTruncated>false</IsTruncated><KeyCount>0</KeyCount></ListBucketResult>"
+            }
+            "GET" if request.path() == "/" => {
+                "<ListAllMyBucketsResult><Buckets></Buckets></ListAllMyBucketsResult>"
+            }
+            "POST" if request.params.contains_key("uploads") => {
+                "<InitiateMultipartUploadResult><UploadId>trace-requests</UploadId></InitiateMultipartUploadResult>"
+            }
+            _ => "",
+        }
+    }
+}
+
+impl DispatchSignedRequest for TraceDispatcher {
+    fn dispatch(
+        &self,
+        request: SignedRequest,
+        _timeout: Option<Duration>,
+    ) -> DispatchSignedRequestFuture {
+        let mut url = format!(
+            "{}://{}{}",
+            request.scheme(),
+            request.hostname(),
+            request.canonical_path()
+        );
+        if !request.canonical_query_string.is_empty() {
+            url = format!("{}?{}", url, request.canonical_query_string);
+        }
+
+        let headers = request
+            .headers()
+            .iter()
+            .filter(|(name, _)| name.as_str() != "authorization")
+            .map(|(name, values)| {
+                let values = values
+                    .iter()
+                    .map(|value| String::from_utf8_lossy(value).to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("{}: {}", name, values)
+            })
+            .collect::<Vec<String>>();
+
+        event!(
+            Level::INFO,
+            "Trace requests | {} {} (region {}) | {}",
+            request.method(),
+            url,
+            request.region.name(),
+            headers.join(" | ")
+        );
+
+        let body = TraceDispatcher::canned_body(&request);
+        Box::pin(async move {
+            Ok(HttpResponse {
+                status: StatusCode::OK,
+                body: ByteStream::from(body.as_bytes().to_vec()),
+                headers: HeaderMap::default(),
+            })
+        })
+    }
+}
@@ -1,29 +1,190 @@
 use std::{
-    collections::VecDeque,
+    collections::{BTreeMap, HashMap, VecDeque},
     pin::Pin,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
-use bytes::Bytes;
-use futures::Stream;
+use base64::Engine;
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
 use hyper::body::HttpBody;
-use rusoto_core::ByteStream;
-use tokio::task::JoinError;
-use tracing::event;
+use md5::{Digest, Md5};
+use rusoto_core::{ByteStream, RusotoError};
+use rusoto_s3::{MultipartUpload, Part, UploadPartOutput};
+use tokio::{
+    sync::Semaphore,
+    task::{JoinError, JoinHandle},
+};
 use tracing::Level;
+use tracing::{event, instrument, Instrument};
 
-use crate::provider::{
-    Provider, ProviderObject, ProviderObjectMetadata, ProviderResponseStreamChunkWrapper,
+#[cfg(feature = "sqlite-status")]
+use crate::status_db::ObjectStatusDb;
+use crate::{
+    acl_rules::{AclRules, DestinationAcl},
+    bandwidth::BandwidthLimit,
+    checksum_manifest::ChecksumManifest,
+    circuit_breaker::CircuitBreaker,
+    content_dedup::ContentDedup,
+    encryption::EncryptionMirror,
+    etag_cache::SourceETagCache,
+    heartbeat::{PartsProgress, SyncProgress},
+    key_filter::KeyFilter,
+    md5_hasher::Md5Hasher,
+    metadata_filter::MetadataFilter,
+    object_timings::ObjectTimings,
+    provider::{
+        Provider, ProviderObject, ProviderObjectMetadata, ProviderResponseStreamChunkWrapper,
+    },
+    report::{BucketObjectsReport, ObjectStatus},
+    run_window::RunWindow,
+    state_file::StateFile,
+    storage_class::StorageClassRules,
+    timing_trace::TimingTrace,
 };
 
-use super::RadosGW;
+use super::{
+    clock_skew_message,
+    faults::SimulatedFailures,
+    is_destination_full,
+    retry::{is_retryable_upload_error, RetryPolicy},
+    RadosGW, MAX_DELETE_KEYS,
+};
 
 pub type ObjectMigrationSize = usize;
 
+/// Outcome of `Uploader::sync_object`
+#[derive(Debug)]
+pub enum ObjectSync {
+    /// With the MD5 of the uploaded bytes with --verify-checksum, in the ETag format for
+    /// multipart uploads
+    Uploaded(Option<String>),
+    /// The source answered the conditional GET with a 304, nothing was transferred
+    NotModified,
+}
+
 pub struct ThreadMigrationResult {
     pub sync_results: Vec<anyhow::Result<ObjectMigrationSize>>,
     pub delete_results: Vec<anyhow::Result<ObjectMigrationSize>>,
+    /// Source objects successfully synchronized by the thread
+    pub synchronized_objects: Vec<ProviderObject>,
+}
+
+/// Queues a sync thread takes its work from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SyncLane {
+    /// Objects then, for the first `delete_threads` threads, the objects to delete
+    Objects,
+    /// Small objects only, never deletes
+    SmallObjects,
+    /// Objects to delete only, when there are more delete threads than sync threads
+    Deletes,
+}
+
+/// Order in which the sync threads pick the objects
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectPriority {
+    Listing,
+    /// Smallest objects first, maximizes the number of objects synchronized early
+    SizeAsc,
+    /// Biggest objects first, maximizes the number of bytes synchronized early
+    SizeDesc,
+    Key,
+}
+
+impl ObjectPriority {
+    fn sort(&self, objects: &mut [ProviderObject]) {
+        match self {
+            ObjectPriority::Listing => {}
+            ObjectPriority::SizeAsc => objects.sort_by_key(|object| object.get_size()),
+            ObjectPriority::SizeDesc => {
+                objects.sort_by_key(|object| std::cmp::Reverse(object.get_size()))
+            }
+            ObjectPriority::Key => objects.sort_by_key(|object| object.get_key()),
+        }
+    }
+}
+
+impl TryFrom<&str> for ObjectPriority {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "listing" => Ok(ObjectPriority::Listing),
+            "size-asc" => Ok(ObjectPriority::SizeAsc),
+            "size-desc" => Ok(ObjectPriority::SizeDesc),
+            "key" => Ok(ObjectPriority::Key),
+            _ => Err(format!("Failed to parse priority: {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UploaderConfiguration {
+    pub threads: usize,
+    /// Objects of at least this size are uploaded using multipart
+    pub multipart_threshold: u64,
+    pub multipart_part_size: usize,
+    /// Shared by all the buckets, one permit per multipart upload in progress
+    pub multipart_budget: Option<Arc<Semaphore>>,
+    /// Multipart uploads left in progress on the destination by a previous run for the objects
+    /// to synchronize, reused instead of starting new uploads
+    pub multipart_uploads: Vec<MultipartUpload>,
+    pub small_object_threshold: Option<usize>,
+    pub small_object_threads: usize,
+    /// Threads deleting the destination objects missing from the source. Up to `threads` of
+    /// them also synchronize objects first, the others only delete
+    pub delete_threads: usize,
+    pub priority: ObjectPriority,
+    pub source_etag_cache: Option<Arc<SourceETagCache>>,
+    pub checksum_manifest: Option<Arc<ChecksumManifest>>,
+    pub state_file: Option<Arc<StateFile>>,
+    pub object_report: Option<Arc<BucketObjectsReport>>,
+    pub content_dedup: Option<Arc<ContentDedup>>,
+    pub acl_rules: Option<AclRules>,
+    /// Map the grants of the source ACL to a canned ACL instead of only keeping public-read
+    pub preserve_acl: bool,
+    /// Copy the tag set of the source objects
+    pub preserve_tags: bool,
+    pub storage_classes: StorageClassRules,
+    /// The destination bucket rejects object ACLs, public objects are uploaded without one
+    pub object_acls_disabled: bool,
+    /// Guess the Content-Type from the key extension when the source object has none
+    pub infer_content_type: bool,
+    pub progress: Arc<SyncProgress>,
+    pub truncate_bytes: Option<u64>,
+    /// Recorded as user metadata on each destination object when set
+    pub source_bucket: Option<String>,
+    pub metadata_filter: Option<MetadataFilter>,
+    /// Objects whose key doesn't match are neither synchronized nor deleted, e.g. when applying
+    /// a plan written without the filter
+    pub key_filter: Option<Arc<KeyFilter>>,
+    /// Destination objects are left unencrypted when not set
+    pub encryption_mirror: Option<Arc<EncryptionMirror>>,
+    /// Set on every destination object unless the encryption is mirrored
+    pub server_side_encryption: Option<String>,
+    pub sse_kms_key_id: Option<String>,
+    pub timing_trace: Option<Arc<TimingTrace>>,
+    /// Duration of each uploaded object, only the slowest ones are kept
+    pub object_timings: Option<Arc<ObjectTimings>>,
+    /// Stop all the threads on the first object error
+    pub fail_fast: bool,
+    pub circuit_breaker: Option<Arc<CircuitBreaker>>,
+    pub run_window: Option<Arc<RunWindow>>,
+    /// Throttle the bodies sent to the destination, a body is as fast as the strictest one
+    pub bandwidth_limits: Vec<Arc<BandwidthLimit>>,
+    /// Send the MD5 of the uploaded bytes with each object and part, and check the part ETags
+    pub verify_checksum: bool,
+    pub thread_budget: Option<Arc<Semaphore>>,
+    /// Objects failing with a transient destination error are synchronized again
+    pub max_retries: usize,
+    pub retry_base_delay: Duration,
+    pub simulated_failures: Option<SimulatedFailures>,
 }
 
 #[derive(Debug, Clone)]
@@ -31,22 +192,106 @@ pub struct Uploader {
     source_provider_client: Box<dyn Provider>,
     radosgw_client: RadosGW,
     objects: Arc<Mutex<VecDeque<ProviderObject>>>,
+    small_objects: Arc<Mutex<VecDeque<ProviderObject>>>,
     objects_to_delete: Arc<Mutex<VecDeque<ProviderObject>>>,
+    total_objects: usize,
+    total_small_objects: usize,
+    total_objects_to_delete: usize,
     threads: usize,
-    multipart_chunk_size: usize,
+    small_object_threads: usize,
+    delete_threads: usize,
+    multipart_threshold: u64,
+    multipart_part_size: usize,
+    multipart_budget: Option<Arc<Semaphore>>,
+    source_etag_cache: Option<Arc<SourceETagCache>>,
+    checksum_manifest: Option<Arc<ChecksumManifest>>,
+    state_file: Option<Arc<StateFile>>,
+    object_report: Option<Arc<BucketObjectsReport>>,
+    content_dedup: Option<Arc<ContentDedup>>,
+    acl_rules: Option<AclRules>,
+    preserve_acl: bool,
+    preserve_tags: bool,
+    storage_classes: StorageClassRules,
+    object_acls_disabled: bool,
+    infer_content_type: bool,
+    progress: Arc<SyncProgress>,
+    truncate_bytes: Option<u64>,
+    source_bucket: Option<String>,
+    metadata_filter: Option<MetadataFilter>,
+    encryption_mirror: Option<Arc<EncryptionMirror>>,
+    server_side_encryption: Option<String>,
+    sse_kms_key_id: Option<String>,
+    timing_trace: Option<Arc<TimingTrace>>,
+    object_timings: Option<Arc<ObjectTimings>>,
+    /// Start of the queue wait of the objects
+    created_at: Instant,
+    simulated_failures: Option<SimulatedFailures>,
+    destination_full: Arc<AtomicBool>,
+    fail_fast: bool,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    run_window: Option<Arc<RunWindow>>,
+    bandwidth_limits: Vec<Arc<BandwidthLimit>>,
+    verify_checksum: bool,
+    /// Shared with the buckets migrated at the same time
+    thread_budget: Option<Arc<Semaphore>>,
+    retry_policy: RetryPolicy,
+    /// Multipart uploads interrupted by a retryable error or found on the destination, by
+    /// destination key
+    resumable_multiparts: Arc<Mutex<HashMap<String, ResumableMultipart>>>,
+    multipart_uploads: Vec<MultipartUpload>,
+    /// Set by the first failed object when fail_fast is enabled
+    failed: Arc<AtomicBool>,
+    #[cfg(feature = "sqlite-status")]
+    status_db: Option<Arc<ObjectStatusDb>>,
+}
+
+/// Parts already uploaded by a failed attempt or by a previous run, so only the missing parts
+/// of the object are uploaded to the same upload
+#[derive(Debug)]
+struct ResumableMultipart {
+    upload_id: String,
+    parts: BTreeMap<usize, UploadPartOutput>,
+    /// MD5 of the parts hashed by --verify-checksum
+    digests: BTreeMap<usize, [u8; 16]>,
 }
 
 impl Uploader {
     pub fn new(
         source_provider_client: Box<dyn Provider>,
         radosgw_client: RadosGW,
-        objects: Vec<ProviderObject>,
-        objects_to_delete: Vec<ProviderObject>,
-        threads: usize,
-        multipart_chunk_size: usize,
+        mut objects: Vec<ProviderObject>,
+        mut objects_to_delete: Vec<ProviderObject>,
+        conf: UploaderConfiguration,
     ) -> Uploader {
+        if let Some(key_filter) = &conf.key_filter {
+            objects.retain(|object| key_filter.matches(&object.get_key()));
+            objects_to_delete.retain(|object| key_filter.matches(&object.get_key()));
+        }
+
+        // The queues are popped from the front, sorting them once is enough
+        conf.priority.sort(&mut objects);
+
+        // Small objects get their own lane so they don't wait behind big objects and
+        // their request latency can be hidden by a higher concurrency
+        let (small_objects, objects): (Vec<ProviderObject>, Vec<ProviderObject>) =
+            match conf.small_object_threshold {
+                Some(threshold) => objects
+                    .into_iter()
+                    .partition(|object| (object.get_size() as usize) < threshold),
+                None => (Vec::new(), objects),
+            };
+
+        conf.progress.objects_to_sync(
+            objects.len() + small_objects.len(),
+            objects
+                .iter()
+                .chain(small_objects.iter())
+                .map(|object| object.get_size() as usize)
+                .sum(),
+        );
+
         let sync_len = objects.len() + objects_to_delete.len();
-        if sync_len < threads {
+        if sync_len < conf.threads {
             event!(
                 Level::WARN,
                 "There are more threads than files to synchronize. I'll only start {} threads",
@@ -57,150 +302,573 @@ impl Uploader {
         Uploader {
             source_provider_client,
             radosgw_client,
+            total_objects: objects.len(),
+            total_small_objects: small_objects.len(),
+            total_objects_to_delete: objects_to_delete.len(),
+            small_object_threads: std::cmp::min(conf.small_object_threads, small_objects.len()),
             objects: Arc::new(Mutex::new(VecDeque::from(objects))),
+            small_objects: Arc::new(Mutex::new(VecDeque::from(small_objects))),
             objects_to_delete: Arc::new(Mutex::new(VecDeque::from(objects_to_delete))),
-            threads: std::cmp::min(threads, sync_len),
-            multipart_chunk_size,
+            threads: std::cmp::min(conf.threads, sync_len),
+            delete_threads: conf.delete_threads,
+            multipart_threshold: conf.multipart_threshold,
+            multipart_part_size: conf.multipart_part_size,
+            multipart_budget: conf.multipart_budget,
+            source_etag_cache: conf.source_etag_cache,
+            checksum_manifest: conf.checksum_manifest,
+            state_file: conf.state_file,
+            object_report: conf.object_report,
+            content_dedup: conf.content_dedup,
+            acl_rules: conf.acl_rules,
+            preserve_acl: conf.preserve_acl,
+            preserve_tags: conf.preserve_tags,
+            storage_classes: conf.storage_classes,
+            object_acls_disabled: conf.object_acls_disabled,
+            infer_content_type: conf.infer_content_type,
+            progress: conf.progress,
+            truncate_bytes: conf.truncate_bytes,
+            source_bucket: conf.source_bucket,
+            metadata_filter: conf.metadata_filter,
+            encryption_mirror: conf.encryption_mirror,
+            server_side_encryption: conf.server_side_encryption,
+            sse_kms_key_id: conf.sse_kms_key_id,
+            timing_trace: conf.timing_trace,
+            object_timings: conf.object_timings,
+            created_at: Instant::now(),
+            simulated_failures: conf.simulated_failures,
+            destination_full: Arc::new(AtomicBool::new(false)),
+            fail_fast: conf.fail_fast,
+            circuit_breaker: conf.circuit_breaker,
+            run_window: conf.run_window,
+            bandwidth_limits: conf.bandwidth_limits,
+            verify_checksum: conf.verify_checksum,
+            thread_budget: conf.thread_budget,
+            retry_policy: RetryPolicy {
+                max_retries: conf.max_retries,
+                base_delay: conf.retry_base_delay,
+            },
+            resumable_multiparts: Arc::new(Mutex::new(HashMap::new())),
+            multipart_uploads: conf.multipart_uploads,
+            failed: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "sqlite-status")]
+            status_db: None,
         }
     }
 
+    /// Records the outcome of each synchronized object
+    #[cfg(feature = "sqlite-status")]
+    pub fn set_status_db(&mut self, status_db: Option<Arc<ObjectStatusDb>>) {
+        self.status_db = status_db;
+    }
+
     pub async fn sync(&mut self) -> Vec<Result<ThreadMigrationResult, JoinError>> {
+        if !self.multipart_uploads.is_empty() {
+            self.find_resumable_multiparts().await;
+        }
+
         event!(Level::INFO, "Starting {} sync threads", self.threads);
         let mut handles = Vec::new();
-        let total_files = self.objects.clone().lock().unwrap().len();
-        let total_files_to_delete = self.objects_to_delete.clone().lock().unwrap().len();
 
         for thread_id in 0..self.threads {
-            let riak_client = self.source_provider_client.clone();
-            let radosgw_client = self.radosgw_client.clone();
-            let files = self.objects.clone();
-            let files_to_delete = self.objects_to_delete.clone();
-            let multipart_chunk_size = self.multipart_chunk_size;
-            let handle = tokio::spawn(async move {
-                let mut results = Vec::new();
-                let mut delete_results = Vec::new();
-                loop {
-                    let (object, remaining) = {
-                        let mut files = files.lock().unwrap();
-                        let object = files.pop_front();
-                        let remaining = files.len();
-                        (object, remaining)
+            handles.push(self.spawn_sync_thread(thread_id, SyncLane::Objects));
+        }
+
+        if self.small_object_threads > 0 {
+            event!(
+                Level::INFO,
+                "Starting {} sync threads for small objects",
+                self.small_object_threads
+            );
+            for thread_id in self.threads..self.threads + self.small_object_threads {
+                handles.push(self.spawn_sync_thread(thread_id, SyncLane::SmallObjects));
+            }
+        }
+
+        // No more threads than batches to delete
+        let delete_batches = (self.total_objects_to_delete as f64 / MAX_DELETE_KEYS as f64).ceil();
+        let delete_only_threads = std::cmp::min(
+            self.delete_threads.saturating_sub(self.threads),
+            delete_batches as usize,
+        );
+        if delete_only_threads > 0 {
+            event!(
+                Level::INFO,
+                "Starting {} threads only deleting objects",
+                delete_only_threads
+            );
+            let first_thread_id = self.threads + self.small_object_threads;
+            for thread_id in first_thread_id..first_thread_id + delete_only_threads {
+                handles.push(self.spawn_sync_thread(thread_id, SyncLane::Deletes));
+            }
+        }
+
+        futures::future::join_all(handles).await
+    }
+
+    fn spawn_sync_thread(
+        &self,
+        thread_id: usize,
+        lane: SyncLane,
+    ) -> JoinHandle<ThreadMigrationResult> {
+        let uploader = self.clone();
+        let (files, total_files) = match lane {
+            SyncLane::Objects => (self.objects.clone(), self.total_objects),
+            SyncLane::SmallObjects => (self.small_objects.clone(), self.total_small_objects),
+            SyncLane::Deletes => (Arc::new(Mutex::new(VecDeque::new())), 0),
+        };
+        let deletes = match lane {
+            SyncLane::Objects => thread_id < self.delete_threads,
+            SyncLane::SmallObjects => false,
+            SyncLane::Deletes => true,
+        };
+        let files_to_delete = self.objects_to_delete.clone();
+        let total_files_to_delete = self.total_objects_to_delete;
+        // Spawned tasks don't inherit the current span, attach it manually so per object
+        // spans keep their bucket as parent
+        let thread_span = tracing::debug_span!("sync_thread", thread_id, lane = ?lane);
+        let thread_task = async move {
+            let mut results = Vec::new();
+            let mut delete_results = Vec::new();
+            let mut synchronized_objects = Vec::new();
+            uploader.progress.worker_started();
+            loop {
+                if uploader.destination_full.load(Ordering::SeqCst) {
+                    event!(
+                        Level::ERROR,
+                        "Thread {} | Destination is full, stopping synchronization",
+                        thread_id
+                    );
+                    break;
+                }
+
+                if let Some(run_window) = &uploader.run_window {
+                    run_window.wait().await;
+                }
+
+                if let Some(circuit_breaker) = &uploader.circuit_breaker {
+                    circuit_breaker.wait().await;
+                }
+
+                if uploader.cancelled() {
+                    event!(
+                        Level::ERROR,
+                        "Thread {} | An object failed and --fail-fast is set, stopping synchronization",
+                        thread_id
+                    );
+                    break;
+                }
+
+                // Held until the end of the iteration
+                let _permit = match &uploader.thread_budget {
+                    Some(thread_budget) => Some(
+                        thread_budget
+                            .acquire()
+                            .await
+                            .expect("Thread budget semaphore should never be closed"),
+                    ),
+                    None => None,
+                };
+
+                let (object, remaining) = {
+                    let mut files = files.lock().unwrap();
+                    let object = files.pop_front();
+                    let remaining = files.len();
+                    (object, remaining)
+                };
+
+                if let Some(object) = object {
+                    event!(
+                        Level::INFO,
+                        key = %object.get_key(),
+                        size = object.get_size(),
+                        "Thread {} | ({}/{}) Starting to sync object {}",
+                        thread_id,
+                        total_files - remaining,
+                        total_files,
+                        object.get_key()
+                    );
+
+                    uploader.progress.object_started();
+                    let object_start = Instant::now();
+                    // The source body is streamed to the destination, it can't be sent again
+                    // without downloading the object again
+                    let result = uploader
+                        .retry_policy
+                        .retry(
+                            &format!(
+                                "Thread {} | Synchronization of object {}",
+                                thread_id,
+                                object.get_key()
+                            ),
+                            is_retryable_upload_error,
+                            || uploader.sync_object(&object, thread_id),
+                        )
+                        .await;
+                    uploader.abort_resumable_multipart(&object, thread_id).await;
+                    let (result, checksum) = match result {
+                        Ok(ObjectSync::Uploaded(checksum)) => {
+                            (Ok(object.get_size() as usize), checksum)
+                        }
+                        Ok(ObjectSync::NotModified) => {
+                            uploader.progress.object_finished(true);
+                            uploader
+                                .progress
+                                .object_not_modified(object.get_size() as usize);
+
+                            if let Some(object_report) = &uploader.object_report {
+                                object_report.record(
+                                    object.get_key(),
+                                    object.get_size(),
+                                    ObjectStatus::Skipped,
+                                    None,
+                                    None,
+                                );
+                            }
+
+                            #[cfg(feature = "sqlite-status")]
+                            if let Some(status_db) = &uploader.status_db {
+                                if let Err(error) = status_db.record(&object.get_key(), &Ok(())) {
+                                    event!(
+                                        Level::WARN,
+                                        "Thread {} | Failed to record the status of object {}: {:?}",
+                                        thread_id,
+                                        object.get_key(),
+                                        error
+                                    );
+                                }
+                            }
+
+                            continue;
+                        }
+                        Err(error) => (Err(error), None),
                     };
+                    uploader.progress.object_finished(result.is_ok());
 
-                    if let Some(object) = object {
-                        event!(
-                            Level::INFO,
-                            "Thread {} | ({}/{}) Starting to sync object {}",
-                            thread_id,
-                            total_files - remaining,
-                            total_files,
-                            object.get_key()
+                    if let (Some(object_timings), Ok(size)) = (&uploader.object_timings, &result) {
+                        object_timings.record(
+                            object.get_key(),
+                            *size as u64,
+                            object_start.elapsed(),
+                            uploader.number_of_parts(object.get_size()),
                         );
+                    }
 
-                        let result = Uploader::sync_object(
-                            &*riak_client,
-                            &radosgw_client,
-                            &object,
-                            thread_id,
-                            multipart_chunk_size,
-                        )
-                        .await
-                        .map(|_| object.get_size() as usize);
+                    if matches!(&result, Err(error) if error.is::<DestinationFull>()) {
+                        uploader.destination_full.store(true, Ordering::SeqCst);
+                    }
 
-                        results.push(result);
-                    } else {
-                        let (object_to_delete, remaining) = {
-                            let mut files = files_to_delete.lock().unwrap();
-                            let object = files.pop_front();
-                            let remaining = files.len();
-                            (object, remaining)
+                    if let Some(object_report) = &uploader.object_report {
+                        let (status, reason) = match &result {
+                            Ok(_) => (ObjectStatus::Uploaded, None),
+                            Err(error) => (ObjectStatus::Failed, Some(format!("{:#}", error))),
                         };
+                        object_report.record(
+                            object.get_key(),
+                            object.get_size(),
+                            status,
+                            reason,
+                            checksum,
+                        );
+                    }
+
+                    // Only the first error is reported, the objects interrupted because of it
+                    // are not
+                    if uploader.fail_fast
+                        && result.is_err()
+                        && uploader.failed.swap(true, Ordering::SeqCst)
+                    {
+                        event!(
+                            Level::WARN,
+                            "Thread {} | Synchronization of object {} interrupted: {:?}",
+                            thread_id,
+                            object.get_key(),
+                            result
+                        );
+                        continue;
+                    }
 
-                        if let Some(object_to_delete) = object_to_delete {
+                    #[cfg(feature = "sqlite-status")]
+                    if let Some(status_db) = &uploader.status_db {
+                        if let Err(error) = status_db.record(&object.get_key(), &result) {
                             event!(
-                                Level::INFO,
-                                "Thread {} | ({}/{}) Deleting object {} on destination bucket",
+                                Level::WARN,
+                                "Thread {} | Failed to record the status of object {}: {:?}",
                                 thread_id,
-                                total_files_to_delete - remaining,
-                                total_files_to_delete,
-                                object_to_delete.get_key()
+                                object.get_key(),
+                                error
                             );
+                        }
+                    }
 
-                            let result = Uploader::delete_destination_object(
-                                &radosgw_client,
-                                object_to_delete,
-                                thread_id,
-                            )
-                            .await
-                            .map(|object| object.get_size() as usize);
+                    if let Ok(size) = &result {
+                        uploader.progress.object_done(*size);
 
-                            delete_results.push(result);
-                        } else {
-                            event!(
-                                Level::INFO,
-                                "Thread {} | No more objects to synchronize, quitting..",
-                                thread_id
-                            );
-                            break;
+                        if let Some(state_file) = &uploader.state_file {
+                            if let Err(error) = state_file.record(&object.get_key()) {
+                                event!(
+                                    Level::WARN,
+                                    "Thread {} | Failed to record object {} in the state file: {:?}",
+                                    thread_id,
+                                    object.get_key(),
+                                    error
+                                );
+                            }
                         }
+
+                        synchronized_objects.push(object);
                     }
-                }
 
-                ThreadMigrationResult {
-                    sync_results: results,
-                    delete_results,
+                    results.push(result);
+                } else {
+                    // Deleted in batches, a single request deletes up to MAX_DELETE_KEYS objects
+                    let (objects_to_delete, remaining) = if !deletes {
+                        (Vec::new(), 0)
+                    } else {
+                        let mut files = files_to_delete.lock().unwrap();
+                        let batch_len = std::cmp::min(files.len(), MAX_DELETE_KEYS);
+                        let objects: Vec<ProviderObject> = files.drain(..batch_len).collect();
+                        let remaining = files.len();
+                        (objects, remaining)
+                    };
+
+                    if !objects_to_delete.is_empty() {
+                        event!(
+                            Level::INFO,
+                            "Thread {} | ({}/{}) Deleting {} objects on destination bucket",
+                            thread_id,
+                            total_files_to_delete - remaining,
+                            total_files_to_delete,
+                            objects_to_delete.len()
+                        );
+
+                        let results = Uploader::delete_destination_objects(
+                            &uploader.radosgw_client,
+                            objects_to_delete,
+                            thread_id,
+                        )
+                        .await;
+
+                        for result in results {
+                            if uploader.fail_fast
+                                && result.is_err()
+                                && uploader.failed.swap(true, Ordering::SeqCst)
+                            {
+                                continue;
+                            }
+
+                            delete_results.push(result);
+                        }
+                    } else {
+                        event!(
+                            Level::INFO,
+                            "Thread {} | No more objects to synchronize, quitting..",
+                            thread_id
+                        );
+                        break;
+                    }
                 }
-            });
+            }
+            uploader.progress.worker_stopped();
 
-            handles.push(handle);
-        }
+            ThreadMigrationResult {
+                sync_results: results,
+                delete_results,
+                synchronized_objects,
+            }
+        };
 
-        futures::future::join_all(handles).await
+        tokio::spawn(thread_task.instrument(thread_span))
     }
 
+    #[instrument(skip_all, level = "debug", fields(key = %object.get_key(), size = object.get_size()))]
     pub async fn sync_object(
-        source_provider_client: &dyn Provider,
-        radosgw_client: &RadosGW,
+        &self,
         object: &ProviderObject,
         thread_id: usize,
-        multipart_chunk_size: usize,
-    ) -> anyhow::Result<()> {
-        let object_metadata = source_provider_client.get_object_metadata(object).await?;
-        let mut response = source_provider_client.get_object(object).await?;
+    ) -> anyhow::Result<ObjectSync> {
+        let original_size = object.get_size();
+        let truncated_object;
+        let object = match self.truncate_bytes {
+            Some(truncate_bytes) if original_size > truncate_bytes => {
+                truncated_object = object.truncated(truncate_bytes);
+                &truncated_object
+            }
+            _ => object,
+        };
+        let truncated = object.get_size() < original_size;
+
+        // Hold a permit for the whole lifetime of the multipart upload so we never
+        // have more than --max-concurrent-multipart sessions opened on the destination.
+        // It is taken before the source download starts so we don't keep idle connections open.
+        let _multipart_permit = match &self.multipart_budget {
+            Some(semaphore) if self.is_multipart(object.get_size() as usize) => {
+                event!(
+                    Level::DEBUG,
+                    "Thread {} | Waiting for a multipart upload slot for object {}",
+                    thread_id,
+                    object.get_key()
+                );
+                Some(semaphore.acquire().await?)
+            }
+            _ => None,
+        };
+
+        let download_start = Instant::now();
+        self.record_timing(object, "queue", thread_id, self.created_at, download_start);
+
+        if let Some(simulated_failures) = &self.simulated_failures {
+            simulated_failures.before_object(object).await?;
+        }
+
+        if let Some(copy_source) = self
+            .content_dedup
+            .as_ref()
+            .filter(|_| !truncated)
+            .and_then(|content_dedup| content_dedup.find(object))
+        {
+            return self
+                .copy_duplicate(object, copy_source, original_size, thread_id)
+                .await
+                .map(|_| ObjectSync::Uploaded(None));
+        }
+
+        // The parts already uploaded by a previous attempt aren't downloaded again
+        let resume_offset = self.resume_offset(object);
+        let ranged = truncated || resume_offset > 0;
+
+        let mut cached_etag = self
+            .source_etag_cache
+            .as_ref()
+            .and_then(|cache| cache.get(&object.get_key()));
+        // A 304 only tells that the source didn't change, the object was queued because its
+        // destination copy is missing or different unless it was copied from this source version
+        if let Some(etag) = cached_etag.filter(|_| !ranged) {
+            if !self.destination_copied_from(object, etag).await {
+                cached_etag = None;
+            }
+        }
+        let mut response = match cached_etag {
+            _ if ranged => {
+                self.source_provider_client
+                    .get_object_range(object, resume_offset, object.get_size() - resume_offset)
+                    .await?
+            }
+            Some(etag) => {
+                match self
+                    .source_provider_client
+                    .get_object_if_none_match(object, etag)
+                    .await?
+                {
+                    Some(response) => response,
+                    None => {
+                        event!(
+                            Level::INFO,
+                            "Thread {} | Source object {} hasn't changed since it was synchronized, skipping",
+                            thread_id,
+                            object.get_key()
+                        );
+                        return Ok(ObjectSync::NotModified);
+                    }
+                }
+            }
+            None => self.source_provider_client.get_object(object).await?,
+        };
+        let object_metadata = self
+            .destination_metadata(object, original_size, thread_id)
+            .await?;
         if response.success() {
-            let start = std::time::Instant::now();
+            let start = Instant::now();
+            self.record_timing(object, "download", thread_id, download_start, start);
             let object_size = object.get_size() as usize;
+            let checksum;
 
-            if object_size < multipart_chunk_size {
-                let body = ByteStream::new(response.body());
-                Uploader::sync_object_singlepart(
-                    radosgw_client,
-                    object,
-                    &object_metadata,
-                    body,
+            if response.is_chunked() && resume_offset > 0 {
+                // Only the multipart path with a known size resumes, the next attempt starts over
+                self.abort_resumable_multipart(object, thread_id).await;
+                anyhow::bail!(
+                    "Source response for the missing parts of object {} has no Content-Length, its multipart upload can't be resumed",
+                    object.get_key()
+                );
+            } else if response.is_chunked() {
+                event!(
+                    Level::DEBUG,
+                    "Thread {} | Source response for object {} has no Content-Length, buffering it before upload",
                     thread_id,
-                )
-                .await?;
+                    object.get_key()
+                );
+
+                if !self.is_multipart(object_size) {
+                    let body = match response.consume_body().await {
+                        Some(body) => body?,
+                        None => Bytes::new(),
+                    };
+                    checksum = self
+                        .sync_object_singlepart(
+                            object,
+                            &object_metadata,
+                            body.len(),
+                            ByteStream::new(futures::stream::once(async move { Ok(body) })),
+                            thread_id,
+                        )
+                        .await?;
+                } else {
+                    checksum = self
+                        .sync_object_multipart_streamed(
+                            object,
+                            &object_metadata,
+                            response.body(),
+                            thread_id,
+                        )
+                        .await?;
+                }
+            } else if !self.is_multipart(object_size) {
+                let body = ByteStream::new(response.body());
+                checksum = self
+                    .sync_object_singlepart(object, &object_metadata, object_size, body, thread_id)
+                    .await?;
             } else {
-                let body = response.body_chunked(multipart_chunk_size);
-                Uploader::sync_object_multipart(
-                    radosgw_client,
-                    object,
-                    &object_metadata,
-                    Box::pin(body),
-                    multipart_chunk_size,
-                    thread_id,
-                )
-                .await?;
+                let body = response.body_chunked(self.multipart_part_size);
+                checksum = self
+                    .sync_object_multipart(
+                        object,
+                        &object_metadata,
+                        Box::pin(body),
+                        resume_offset as usize,
+                        thread_id,
+                    )
+                    .await?;
             }
             event!(
                 Level::INFO,
+                duration_ms = start.elapsed().as_millis() as u64,
                 "Thread {} | Object {} has been put in {:?}",
                 thread_id,
                 object.get_key(),
                 start.elapsed()
             );
-            Ok(())
+            self.record_timing(object, "upload", thread_id, start, Instant::now());
+
+            if let Some(content_dedup) = &self.content_dedup {
+                if !truncated {
+                    content_dedup.record_upload(
+                        object,
+                        self.radosgw_client.copy_source(&object.get_destination_key()),
+                    );
+                }
+            }
+
+            if let Some(cache) = &self.source_etag_cache {
+                if let Err(error) = cache.record(&object.get_key(), object.get_etag()) {
+                    event!(
+                        Level::WARN,
+                        "Thread {} | Failed to cache the ETag of object {}: {:?}",
+                        thread_id,
+                        object.get_key(),
+                        error
+                    );
+                }
+            }
+            Ok(ObjectSync::Uploaded(checksum))
         } else if let Some(body) = response.consume_body().await {
             match body {
                 Ok(bytes) => Err(anyhow::Error::from(DownloadError {
@@ -223,83 +891,367 @@ impl Uploader {
         }
     }
 
-    pub async fn sync_object_singlepart(
-        radosgw_client: &RadosGW,
+    /// Whether the destination object has the size of the source object and was copied from the
+    /// source object having this ETag
+    async fn destination_copied_from(&self, object: &ProviderObject, etag: &str) -> bool {
+        let destination_object =
+            ProviderObject::new(object.get_destination_key(), object.get_size());
+        match self
+            .radosgw_client
+            .get_object_metadata(&destination_object)
+            .await
+        {
+            Ok(metadata) => {
+                metadata.content_length == Some(object.get_size() as i64)
+                    && ProviderObjectMetadata::from(metadata)
+                        .source_etag
+                        .as_deref()
+                        == Some(etag.trim_matches('"'))
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Server side copy of an object whose content has already been uploaded during the run
+    async fn copy_duplicate(
+        &self,
         object: &ProviderObject,
-        object_metadata: &ProviderObjectMetadata,
-        body: ByteStream,
+        copy_source: String,
+        original_size: u64,
         thread_id: usize,
     ) -> anyhow::Result<()> {
-        let response = radosgw_client
-            .put_object(
-                object.get_key(),
-                object_metadata,
-                object.get_size() as i64,
-                body,
-            )
+        let object_metadata = self
+            .destination_metadata(object, original_size, thread_id)
+            .await?;
+
+        event!(
+            Level::INFO,
+            "Thread {} | Object {} has the same content as {}, copying it server side",
+            thread_id,
+            object.get_key(),
+            copy_source
+        );
+        let response = self
+            .radosgw_client
+            .copy_object(copy_source, object.get_destination_key(), &object_metadata)
             .await;
+        self.record_destination_response(&response);
+        response.map_err(|error| destination_error(object, error))?;
 
-        match response {
-            Ok(put_object_output) => {
-                event!(
-                    Level::TRACE,
-                    "Thread {} | {:#?}",
-                    thread_id,
-                    put_object_output
-                );
-                Ok(())
-            }
-            Err(error) => Err(anyhow::anyhow!(format!(
-                "Failed to put object {}: {:?}",
-                object.get_key(),
-                error
-            ))),
+        if let Some(content_dedup) = &self.content_dedup {
+            content_dedup.record_copy(object);
         }
+
+        Ok(())
     }
 
-    pub async fn sync_object_multipart(
-        radosgw_client: &RadosGW,
+    /// Metadata of the destination object, from the source object metadata and the options
+    async fn destination_metadata(
+        &self,
         object: &ProviderObject,
-        object_metadata: &ProviderObjectMetadata,
-        body: Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>,
-        multipart_chunk_size: usize,
+        original_size: u64,
         thread_id: usize,
-    ) -> anyhow::Result<()> {
-        let total_parts = (object.get_size() as f64 / multipart_chunk_size as f64).ceil() as usize;
-        event!(Level::DEBUG, "Thread {} | Initiating multipart upload for object {}. object_size={}, part_size={}, total_parts={}", thread_id, object.get_key(), object.get_size(), multipart_chunk_size, total_parts);
-        let multipart_upload = radosgw_client
-            .create_multipart_upload(object.get_key(), object_metadata)
+    ) -> anyhow::Result<ProviderObjectMetadata> {
+        let truncated = object.get_size() < original_size;
+        let mut object_metadata = self
+            .source_provider_client
+            .get_object_metadata(object)
             .await?;
-        let multipart_upload_id = multipart_upload
-            .upload_id
-            .expect("Multipart upload should have an upload id");
-        let body_wrapper = Arc::new(Mutex::new(body));
-        let mut completed_parts = Vec::with_capacity(total_parts);
-
-        for part_number in 0..total_parts {
-            let total_uploaded = part_number * multipart_chunk_size;
-            let radosgw_part_number = part_number + 1;
-            let remaining = object.get_size() as usize - total_uploaded;
-            let part_size = std::cmp::min(remaining, multipart_chunk_size);
-            event!(
-                Level::DEBUG,
-                "Thread {} | Object {}, total_uploaded={}, remaining={}, part_size={}",
-                thread_id,
-                object.get_key(),
-                total_uploaded,
-                remaining,
-                part_size
-            );
-
-            let upload_part_response = radosgw_client
-                .put_object_part(
+        if self.infer_content_type
+            && object_metadata
+                .content_type
+                .as_deref()
+                .unwrap_or_default()
+                .is_empty()
+        {
+            if let Some(content_type) = infer_content_type(&object.get_key()) {
+                event!(
+                    Level::DEBUG,
+                    "Thread {} | Object {} has no Content-Type, using {}",
+                    thread_id,
                     object.get_key(),
-                    part_size as i64,
+                    content_type
+                );
+                object_metadata.content_type = Some(content_type);
+            }
+        }
+        if let Some(md5) = self
+            .checksum_manifest
+            .as_ref()
+            .and_then(|manifest| manifest.content_md5(&object.get_key()))
+        {
+            object_metadata.content_md5 = Some(md5.to_string());
+        }
+        if truncated {
+            // The checksum of the whole object would be rejected by the destination
+            object_metadata.content_md5 = None;
+            object_metadata.truncated_from = Some(original_size);
+        } else {
+            object_metadata.source_etag = Some(object.get_etag().trim_matches('"').to_string());
+        }
+        object_metadata.source_bucket = self.source_bucket.clone();
+        if let Some(metadata_filter) = &self.metadata_filter {
+            metadata_filter.apply(&mut object_metadata);
+        }
+        match &self.encryption_mirror {
+            Some(encryption_mirror) => {
+                if let Some(reason) =
+                    encryption_mirror.apply(&object.get_key(), &mut object_metadata)
+                {
+                    event!(
+                        Level::WARN,
+                        "Thread {} | Encryption of object {} can't be mirrored: {}",
+                        thread_id,
+                        object.get_key(),
+                        reason
+                    );
+                }
+            }
+            None => {
+                object_metadata.server_side_encryption = self.server_side_encryption.clone();
+                object_metadata.ssekms_key_id = self.sse_kms_key_id.clone();
+            }
+        }
+        if self.preserve_acl {
+            let grants = self.source_provider_client.get_object_acl(object).await?;
+            let (acl, unmapped) = DestinationAcl::from_grants(&grants);
+            if !unmapped.is_empty() {
+                event!(
+                    Level::WARN,
+                    "Thread {} | Grants of object {} can't be expressed with a canned ACL, it will be {}: {}",
+                    thread_id,
+                    object.get_key(),
+                    acl.canned(),
+                    unmapped.join(", ")
+                );
+            }
+            object_metadata.acl_public = acl.is_public();
+            object_metadata.acl = Some(acl);
+        }
+        let tags = if self.preserve_tags || self.acl_rules.is_some() {
+            self.source_provider_client.get_object_tags(object).await?
+        } else {
+            HashMap::new()
+        };
+        if let Some(acl_rules) = &self.acl_rules {
+            match acl_rules.matching_acl(&tags) {
+                Some(acl) => {
+                    object_metadata.acl_public = acl.is_public();
+                    object_metadata.acl = Some(acl);
+                }
+                None => {
+                    event!(
+                        Level::WARN,
+                        "Thread {} | Tags of object {} didn't match any ACL rule: {:?}",
+                        thread_id,
+                        object.get_key(),
+                        tags
+                    );
+                    if let Some(acl) = acl_rules.default {
+                        object_metadata.acl_public = acl.is_public();
+                        object_metadata.acl = Some(acl);
+                    }
+                }
+            }
+        }
+        if self.preserve_tags {
+            object_metadata.tags = tags;
+        }
+        object_metadata.storage_class = self
+            .storage_classes
+            .storage_class(&object.get_key())
+            .map(|storage_class| storage_class.to_string());
+        let acl_granted = match object_metadata.acl {
+            Some(acl) => acl != DestinationAcl::Private,
+            None => object_metadata.acl_public,
+        };
+        if self.object_acls_disabled && acl_granted {
+            event!(
+                Level::WARN,
+                "Thread {} | Object {} is shared but object ACLs are disabled on the destination bucket, it will only be readable through the bucket policy",
+                thread_id,
+                object.get_key()
+            );
+            object_metadata.acl_public = false;
+            object_metadata.acl = None;
+        }
+
+        Ok(object_metadata)
+    }
+
+    /// With --verify-checksum, the body is hashed while it is sent and the returned ETag is checked
+    /// against its MD5, which is returned
+    pub async fn sync_object_singlepart(
+        &self,
+        object: &ProviderObject,
+        object_metadata: &ProviderObjectMetadata,
+        size: usize,
+        body: ByteStream,
+        thread_id: usize,
+    ) -> anyhow::Result<Option<String>> {
+        let hasher = self.verify_checksum.then(Md5Hasher::new);
+        let body = match &hasher {
+            Some(hasher) => ByteStream::new(hasher.hash(body)),
+            None => body,
+        };
+        let response = self
+            .radosgw_client
+            .put_object(
+                object.get_destination_key(),
+                object_metadata,
+                size as i64,
+                self.throttle(body),
+            )
+            .await;
+
+        self.record_destination_response(&response);
+
+        match response {
+            Ok(put_object_output) => {
+                event!(
+                    Level::TRACE,
+                    "Thread {} | {:#?}",
+                    thread_id,
+                    put_object_output
+                );
+                self.progress.bytes_sent(size);
+                match hasher {
+                    Some(hasher) => {
+                        let digest = hasher.finish().await?;
+                        check_etag(object_metadata, &digest, put_object_output.e_tag.as_deref())
+                            .map_err(|reason| {
+                                anyhow::anyhow!(
+                                    "Object {} doesn't match its checksum: {}",
+                                    object.get_key(),
+                                    reason
+                                )
+                            })?;
+                        Ok(Some(hex_digest(&digest)))
+                    }
+                    None => Ok(None),
+                }
+            }
+            Err(error) if is_destination_full(&error) => {
+                Err(destination_full_error(object, error))
+            }
+            Err(error) if clock_skew_message(&error).is_some() => {
+                Err(destination_error(object, error))
+            }
+            Err(error) => Err(anyhow::Error::from(error)
+                .context(format!("Failed to put object {}", object.get_key()))),
+        }
+    }
+
+    /// Each part request streams its slice of the source body, parts are never buffered: the
+    /// memory used by a thread doesn't depend on the part or object size. The body starts at
+    /// `body_offset`, the offset returned by `resume_offset`. With --verify-checksum, returns the
+    /// checksum of the object in the multipart ETag format
+    pub async fn sync_object_multipart(
+        &self,
+        object: &ProviderObject,
+        object_metadata: &ProviderObjectMetadata,
+        body: Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>,
+        body_offset: usize,
+        thread_id: usize,
+    ) -> anyhow::Result<Option<String>> {
+        let radosgw_client = &self.radosgw_client;
+        let multipart_part_size = self.multipart_part_size;
+        let total_parts = (object.get_size() as f64 / multipart_part_size as f64).ceil() as usize;
+        let resumable = self
+            .resumable_multiparts
+            .lock()
+            .unwrap()
+            .remove(&object.get_destination_key());
+        let (multipart_upload_id, mut uploaded_parts, mut uploaded_digests) = match resumable {
+            Some(resumable) => {
+                event!(
+                    Level::INFO,
+                    "Thread {} | Resuming multipart upload of object {}, {}/{} parts already uploaded",
+                    thread_id,
+                    object.get_key(),
+                    resumable.parts.len(),
+                    total_parts
+                );
+                (resumable.upload_id, resumable.parts, resumable.digests)
+            }
+            None => {
+                event!(Level::DEBUG, "Thread {} | Initiating multipart upload for object {}. object_size={}, part_size={}, total_parts={}", thread_id, object.get_key(), object.get_size(), multipart_part_size, total_parts);
+                let multipart_upload = radosgw_client
+                    .create_multipart_upload(object.get_destination_key(), object_metadata)
+                    .await;
+                self.record_destination_response(&multipart_upload);
+                let multipart_upload =
+                    multipart_upload.map_err(|error| destination_error(object, error))?;
+                let upload_id = multipart_upload
+                    .upload_id
+                    .expect("Multipart upload should have an upload id");
+                (upload_id, BTreeMap::new(), BTreeMap::new())
+            }
+        };
+        let body_wrapper = Arc::new(Mutex::new(body));
+        let mut completed_parts = Vec::with_capacity(total_parts);
+        let mut parts_digests = BTreeMap::new();
+        let mut parts_progress = PartsProgress::new(&self.progress);
+
+        for part_number in 0..total_parts {
+            if self.cancelled() {
+                event!(
+                    Level::DEBUG,
+                    "Thread {} | Multipart upload aborted for {}",
+                    thread_id,
+                    object.get_key()
+                );
+                radosgw_client
+                    .abort_multipart_upload(object.get_destination_key(), multipart_upload_id)
+                    .await?;
+                anyhow::bail!("Synchronization of object {} was cancelled", object.get_key());
+            }
+
+            let total_uploaded = part_number * multipart_part_size;
+            let radosgw_part_number = part_number + 1;
+            let remaining = object.get_size() as usize - total_uploaded;
+            let part_size = std::cmp::min(remaining, multipart_part_size);
+            event!(
+                Level::DEBUG,
+                "Thread {} | Object {}, total_uploaded={}, remaining={}, part_size={}",
+                thread_id,
+                object.get_key(),
+                total_uploaded,
+                remaining,
+                part_size
+            );
+
+            if let Some(response) = uploaded_parts.remove(&radosgw_part_number) {
+                if total_uploaded >= body_offset {
+                    if let Err(error) = skip_part(&body_wrapper, part_size).await {
+                        radosgw_client
+                            .abort_multipart_upload(
+                                object.get_destination_key(),
+                                multipart_upload_id,
+                            )
+                            .await?;
+                        return Err(error);
+                    }
+                }
+                parts_progress.part_resumed(part_size);
+                completed_parts.push((radosgw_part_number, response));
+                if let Some(digest) = uploaded_digests.remove(&radosgw_part_number) {
+                    parts_digests.insert(radosgw_part_number, digest);
+                }
+                continue;
+            }
+
+            let upload_part_response = self
+                .upload_part(
+                    object,
+                    object_metadata,
+                    &multipart_upload_id,
+                    radosgw_part_number,
+                    part_size,
                     ByteStream::new(ProviderResponseStreamChunkWrapper::new(
                         body_wrapper.clone(),
                     )),
-                    multipart_upload_id.clone(),
-                    radosgw_part_number as i64,
+                    None,
                 )
                 .await;
 
@@ -311,8 +1263,32 @@ impl Uploader {
             );
 
             match upload_part_response {
-                Ok(response) => {
+                Ok((response, digest)) => {
+                    parts_progress.part_done(part_size);
                     completed_parts.push((radosgw_part_number, response));
+                    if let Some(digest) = digest {
+                        parts_digests.insert(radosgw_part_number, digest);
+                    }
+                }
+                Err(error) if is_retryable_upload_error(&error) => {
+                    // The upload is aborted if the object isn't retried
+                    event!(
+                        Level::DEBUG,
+                        "Thread {} | Keeping {} uploaded parts of {} for a retry",
+                        thread_id,
+                        completed_parts.len(),
+                        object.get_key()
+                    );
+                    self.resumable_multiparts.lock().unwrap().insert(
+                        object.get_destination_key(),
+                        ResumableMultipart {
+                            upload_id: multipart_upload_id,
+                            parts: completed_parts.into_iter().collect(),
+                            digests: parts_digests,
+                        },
+                    );
+
+                    return Err(error.context(format!("Failed to put object {}", object.get_key())));
                 }
                 Err(error) => {
                     event!(
@@ -322,26 +1298,452 @@ impl Uploader {
                         object.get_key()
                     );
                     radosgw_client
-                        .abort_multipart_upload(object.get_key(), multipart_upload_id)
+                        .abort_multipart_upload(object.get_destination_key(), multipart_upload_id)
                         .await?;
 
-                    return Err(anyhow::anyhow!(format!(
-                        "Failed to put object {}: {:?}",
+                    if error.is::<DestinationFull>() {
+                        return Err(error);
+                    }
+
+                    // Keeps the destination error so the object can be retried
+                    return Err(error.context(format!("Failed to put object {}", object.get_key())));
+                }
+            }
+        }
+
+        // The parts of an upload found on the destination have no known MD5
+        let checksum = (self.verify_checksum && parts_digests.len() == total_parts)
+            .then(|| multipart_checksum(&parts_digests.into_values().collect::<Vec<[u8; 16]>>()));
+        self.complete_multipart_upload(object, multipart_upload_id, completed_parts, thread_id)
+            .await?;
+
+        Ok(checksum)
+    }
+
+    /// Reuses the multipart uploads left on the destination by an interrupted run for the objects
+    /// to synchronize, so the parts already uploaded aren't uploaded again even if the previous
+    /// run kept no state. Only the most recent upload of each object is reused
+    async fn find_resumable_multiparts(&mut self) {
+        let uploads = std::mem::take(&mut self.multipart_uploads);
+
+        let objects: HashMap<String, ProviderObject> = {
+            let objects = self.objects.lock().unwrap();
+            let small_objects = self.small_objects.lock().unwrap();
+            objects
+                .iter()
+                .chain(small_objects.iter())
+                .filter(|object| self.is_multipart(object.get_size() as usize))
+                .map(|object| (object.get_destination_key(), object.clone()))
+                .collect()
+        };
+
+        let mut latest_uploads: HashMap<String, (DateTime<Utc>, String)> = HashMap::new();
+        for upload in uploads {
+            let (key, upload_id, initiated) = match (upload.key, upload.upload_id, upload.initiated)
+            {
+                (Some(key), Some(upload_id), Some(initiated)) => (key, upload_id, initiated),
+                _ => continue,
+            };
+            let initiated = match DateTime::parse_from_rfc3339(&initiated) {
+                Ok(initiated) => initiated.with_timezone(&Utc),
+                Err(_) => continue,
+            };
+
+            // The parts of an upload started before the source object was last modified hold
+            // its previous content
+            let started_after_source = objects
+                .get(&key)
+                .is_some_and(|object| initiated > *object.get_last_modified());
+            let latest = latest_uploads
+                .get(&key)
+                .is_none_or(|(latest_initiated, _)| initiated > *latest_initiated);
+            if started_after_source && latest {
+                latest_uploads.insert(key, (initiated, upload_id));
+            }
+        }
+
+        for (key, (_, upload_id)) in latest_uploads {
+            let object = &objects[&key];
+            let parts = match self
+                .radosgw_client
+                .list_parts(key.clone(), upload_id.clone())
+                .await
+            {
+                Ok(parts) => parts,
+                Err(error) => {
+                    event!(
+                        Level::WARN,
+                        "Failed to list the parts of the multipart upload of object {}, it won't be resumed: {:?}",
                         object.get_key(),
                         error
-                    )));
+                    );
+                    continue;
+                }
+            };
+
+            match resumable_parts(&parts, object.get_size(), self.multipart_part_size) {
+                Some(parts) => {
+                    event!(
+                        Level::INFO,
+                        "Found a multipart upload of object {} on the destination, {} parts already uploaded",
+                        object.get_key(),
+                        parts.len()
+                    );
+                    self.resumable_multiparts
+                        .lock()
+                        .unwrap()
+                        .insert(
+                            key,
+                            ResumableMultipart {
+                                upload_id,
+                                parts,
+                                digests: BTreeMap::new(),
+                            },
+                        );
                 }
+                None => event!(
+                    Level::DEBUG,
+                    "Multipart upload {} of object {} was started with another part size, it won't be resumed",
+                    upload_id,
+                    object.get_key()
+                ),
             }
         }
+    }
 
-        match radosgw_client
-            .complete_multipart_upload(
+    /// Offset of the first part missing from the multipart upload kept for the object, 0 if
+    /// there is none. The last part is downloaded again if none is missing, the upload failed
+    /// before it was completed
+    fn resume_offset(&self, object: &ProviderObject) -> u64 {
+        let total_parts = self.number_of_parts(object.get_size());
+        self.resumable_multiparts
+            .lock()
+            .unwrap()
+            .get(&object.get_destination_key())
+            .map(|resumable| {
+                let first_missing = (1..total_parts)
+                    .find(|part_number| !resumable.parts.contains_key(part_number))
+                    .unwrap_or(total_parts);
+                ((first_missing - 1) * self.multipart_part_size) as u64
+            })
+            .unwrap_or(0)
+    }
+
+    /// Aborts the multipart upload kept for a retry of the object which didn't happen, because
+    /// the retries are exhausted or the retry synchronized the object another way
+    async fn abort_resumable_multipart(&self, object: &ProviderObject, thread_id: usize) {
+        let resumable = self
+            .resumable_multiparts
+            .lock()
+            .unwrap()
+            .remove(&object.get_destination_key());
+
+        if let Some(resumable) = resumable {
+            event!(
+                Level::DEBUG,
+                "Thread {} | Multipart upload aborted for {}",
+                thread_id,
+                object.get_key()
+            );
+            if let Err(error) = self
+                .radosgw_client
+                .abort_multipart_upload(object.get_destination_key(), resumable.upload_id)
+                .await
+            {
+                event!(
+                    Level::WARN,
+                    "Thread {} | Failed to abort the multipart upload of object {}: {:?}",
+                    thread_id,
+                    object.get_key(),
+                    error
+                );
+            }
+        }
+    }
+
+    /// Multipart upload for sources streaming the object without a Content-Length.
+    /// We can't trust the listing size to split the parts, so each part is buffered in memory
+    /// until it reaches the part size or the source stream ends.
+    /// With --verify-checksum, the MD5 of each buffered part is sent as its Content-MD5. Returns
+    /// the checksum of the object in the multipart ETag format in that case
+    pub async fn sync_object_multipart_streamed(
+        &self,
+        object: &ProviderObject,
+        object_metadata: &ProviderObjectMetadata,
+        mut body: Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>,
+        thread_id: usize,
+    ) -> anyhow::Result<Option<String>> {
+        let radosgw_client = &self.radosgw_client;
+        let multipart_part_size = self.multipart_part_size;
+        event!(
+            Level::DEBUG,
+            "Thread {} | Initiating streamed multipart upload for object {}. part_size={}",
+            thread_id,
+            object.get_key(),
+            multipart_part_size
+        );
+        let multipart_upload = radosgw_client
+            .create_multipart_upload(object.get_destination_key(), object_metadata)
+            .await;
+        self.record_destination_response(&multipart_upload);
+        let multipart_upload = multipart_upload.map_err(|error| destination_error(object, error))?;
+        let multipart_upload_id = multipart_upload
+            .upload_id
+            .expect("Multipart upload should have an upload id");
+        let mut completed_parts = Vec::new();
+        let mut parts_progress = PartsProgress::new(&self.progress);
+        let mut part = BytesMut::with_capacity(multipart_part_size);
+        // The ETag of the object is the MD5 of the MD5 of its parts
+        let mut parts_digests = Vec::new();
+        let mut eof = false;
+
+        loop {
+            while !eof && part.len() < multipart_part_size {
+                match body.next().await {
+                    Some(Ok(bytes)) => part.extend_from_slice(&bytes),
+                    Some(Err(error)) => {
+                        event!(
+                            Level::DEBUG,
+                            "Thread {} | Multipart upload aborted for {}",
+                            thread_id,
+                            object.get_key()
+                        );
+                        radosgw_client
+                            .abort_multipart_upload(object.get_destination_key(), multipart_upload_id)
+                            .await?;
+
+                        return Err(anyhow::anyhow!(format!(
+                            "Failed to read object {} from source: {:?}",
+                            object.get_key(),
+                            error
+                        )));
+                    }
+                    None => eof = true,
+                }
+            }
+
+            // The object size was a multiple of the part size, we already sent everything
+            if part.is_empty() && !completed_parts.is_empty() {
+                break;
+            }
+
+            let part_body = part
+                .split_to(std::cmp::min(part.len(), multipart_part_size))
+                .freeze();
+            let part_size = part_body.len();
+            let radosgw_part_number = completed_parts.len() + 1;
+            event!(
+                Level::DEBUG,
+                "Thread {} | Object {}, part_number={}, part_size={}",
+                thread_id,
+                object.get_key(),
+                radosgw_part_number,
+                part_size
+            );
+
+            let part_digest = if self.verify_checksum {
+                let hasher = Md5Hasher::new();
+                hasher.update(part_body.clone());
+                Some(hasher.finish().await?)
+            } else {
+                None
+            };
+            let upload_part_response = self
+                .upload_part(
+                    object,
+                    object_metadata,
+                    &multipart_upload_id,
+                    radosgw_part_number,
+                    part_size,
+                    ByteStream::new(futures::stream::once(async move { Ok(part_body) })),
+                    part_digest,
+                )
+                .await;
+
+            match upload_part_response {
+                Ok((response, digest)) => {
+                    parts_progress.part_done(part_size);
+                    completed_parts.push((radosgw_part_number, response));
+                    parts_digests.extend(digest);
+                }
+                Err(error) => {
+                    event!(
+                        Level::DEBUG,
+                        "Thread {} | Multipart upload aborted for {}",
+                        thread_id,
+                        object.get_key()
+                    );
+                    radosgw_client
+                        .abort_multipart_upload(object.get_destination_key(), multipart_upload_id)
+                        .await?;
+
+                    if error.is::<DestinationFull>() {
+                        return Err(error);
+                    }
+
+                    // Keeps the destination error so the object can be retried
+                    return Err(error.context(format!("Failed to put object {}", object.get_key())));
+                }
+            }
+
+            if eof && part.is_empty() {
+                break;
+            }
+        }
+
+        let checksum = self
+            .verify_checksum
+            .then(|| multipart_checksum(&parts_digests));
+        self.complete_multipart_upload(object, multipart_upload_id, completed_parts, thread_id)
+            .await?;
+
+        Ok(checksum)
+    }
+
+    /// With --verify-checksum, the ETag of the part is checked against its MD5, which is returned
+    /// with the response. The MD5 of a buffered part is given to be sent as its Content-MD5,
+    /// otherwise it is computed while the part is sent
+    #[allow(clippy::too_many_arguments)]
+    async fn upload_part(
+        &self,
+        object: &ProviderObject,
+        object_metadata: &ProviderObjectMetadata,
+        multipart_upload_id: &str,
+        part_number: usize,
+        part_size: usize,
+        body: ByteStream,
+        digest: Option<[u8; 16]>,
+    ) -> anyhow::Result<(UploadPartOutput, Option<[u8; 16]>)> {
+        if let Some(simulated_failures) = &self.simulated_failures {
+            simulated_failures.before_part(object, part_number)?;
+        }
+
+        let hasher = (self.verify_checksum && digest.is_none()).then(Md5Hasher::new);
+        let body = match &hasher {
+            Some(hasher) => ByteStream::new(hasher.hash(body)),
+            None => body,
+        };
+        let response = self
+            .radosgw_client
+            .put_object_part(
+                object.get_destination_key(),
+                part_size as i64,
+                self.throttle(body),
+                multipart_upload_id.to_string(),
+                part_number as i64,
+                digest.as_ref().map(|digest| base64_digest(digest)),
+            )
+            .await;
+        self.record_destination_response(&response);
+        let response = response.map_err(|error| destination_error(object, error))?;
+
+        let digest = match hasher {
+            Some(hasher) => Some(hasher.finish().await?),
+            None => digest,
+        };
+        if let Some(digest) = &digest {
+            check_etag(object_metadata, digest, response.e_tag.as_deref()).map_err(|reason| {
+                anyhow::anyhow!(
+                    "Part {} of object {} doesn't match its checksum: {}",
+                    part_number,
+                    object.get_key(),
+                    reason
+                )
+            })?;
+        }
+
+        Ok((response, digest))
+    }
+
+    /// Empty objects are always put in a single request, even with a multipart threshold of 0:
+    /// a multipart upload needs at least one part
+    fn is_multipart(&self, object_size: usize) -> bool {
+        object_size > 0 && object_size as u64 >= self.multipart_threshold
+    }
+
+    fn number_of_parts(&self, object_size: u64) -> usize {
+        if self.is_multipart(object_size as usize) {
+            (object_size as f64 / self.multipart_part_size as f64).ceil() as usize
+        } else {
+            1
+        }
+    }
+
+    fn throttle(&self, body: ByteStream) -> ByteStream {
+        self.bandwidth_limits
+            .iter()
+            .fold(body, |body, bandwidth_limit| {
+                ByteStream::new(bandwidth_limit.throttle(body))
+            })
+    }
+
+    /// Feeds the circuit breaker with the outcome of a destination request
+    fn record_destination_response<T, E>(&self, response: &Result<T, RusotoError<E>>) {
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.record(matches!(
+                response,
+                Err(RusotoError::Unknown(response)) if response.status.is_server_error()
+            ));
+        }
+    }
+
+    /// Another object failed and --fail-fast is set
+    fn cancelled(&self) -> bool {
+        self.fail_fast && self.failed.load(Ordering::SeqCst)
+    }
+
+    fn record_timing(
+        &self,
+        object: &ProviderObject,
+        phase: &str,
+        thread_id: usize,
+        start: Instant,
+        end: Instant,
+    ) {
+        if let Some(timing_trace) = &self.timing_trace {
+            timing_trace.record(&object.get_key(), phase, thread_id, start, end);
+        }
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        object: &ProviderObject,
+        multipart_upload_id: String,
+        completed_parts: Vec<(usize, UploadPartOutput)>,
+        thread_id: usize,
+    ) -> anyhow::Result<()> {
+        let radosgw_client = &self.radosgw_client;
+
+        if let Err(reason) = check_completed_parts(&completed_parts) {
+            event!(
+                Level::ERROR,
+                "Thread {} | Invalid parts list for object {}: {}",
+                thread_id,
+                object.get_key(),
+                reason
+            );
+            radosgw_client
+                .abort_multipart_upload(object.get_destination_key(), multipart_upload_id)
+                .await?;
+            anyhow::bail!(
+                "Failed to complete the multipart upload of object {}: {}",
                 object.get_key(),
+                reason
+            );
+        }
+
+        let start = Instant::now();
+        let result = radosgw_client
+            .complete_multipart_upload(
+                object.get_destination_key(),
                 multipart_upload_id.clone(),
                 completed_parts,
             )
-            .await
-        {
+            .await;
+        self.record_timing(object, "complete", thread_id, start, Instant::now());
+        self.record_destination_response(&result);
+
+        match result {
             Ok(_) => {}
             Err(error) => {
                 event!(
@@ -352,7 +1754,7 @@ impl Uploader {
                     error
                 );
                 radosgw_client
-                    .abort_multipart_upload(object.get_key(), multipart_upload_id)
+                    .abort_multipart_upload(object.get_destination_key(), multipart_upload_id)
                     .await?;
                 return Err(anyhow::Error::from(error));
             }
@@ -368,22 +1770,204 @@ impl Uploader {
         Ok(())
     }
 
-    pub async fn delete_destination_object(
+    /// One result per object, in the same order, with the size of the deleted objects
+    pub async fn delete_destination_objects(
         radosgw_client: &RadosGW,
-        object: ProviderObject,
+        objects: Vec<ProviderObject>,
         thread_id: usize,
-    ) -> anyhow::Result<ProviderObject> {
-        event!(
-            Level::DEBUG,
-            "Thread {} | Delete object {}",
-            thread_id,
-            object.get_key()
-        );
+    ) -> Vec<anyhow::Result<ObjectMigrationSize>> {
+        for object in &objects {
+            event!(
+                Level::DEBUG,
+                "Thread {} | Delete object {}",
+                thread_id,
+                object.get_key()
+            );
+        }
 
-        radosgw_client
-            .delete_object(object)
-            .await
-            .map_err(|err| anyhow::anyhow!(err))
+        let mut failed = radosgw_client
+            .delete_objects(objects.iter().map(|object| object.get_key()).collect())
+            .await;
+
+        objects
+            .into_iter()
+            .map(|object| match failed.remove(&object.get_key()) {
+                Some(reason) => Err(anyhow::anyhow!(
+                    "Failed to delete object {}: {}",
+                    object.get_key(),
+                    reason
+                )),
+                None => Ok(object.get_size() as usize),
+            })
+            .collect()
+    }
+}
+
+/// Part numbers start at 1 and the destination rejects a completion with gaps or unsorted parts
+fn check_completed_parts(parts: &[(usize, UploadPartOutput)]) -> Result<(), String> {
+    if parts.is_empty() {
+        return Err("no part was uploaded".to_string());
+    }
+
+    match parts
+        .iter()
+        .enumerate()
+        .find(|(index, (part_number, _))| *part_number != index + 1)
+    {
+        Some((index, (part_number, _))) => Err(format!(
+            "part {} found at position {}, parts should be numbered from 1 without gaps",
+            part_number,
+            index + 1
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Parts of a multipart upload found on the destination, only reused if they were all uploaded
+/// with the current part size: a part of another size would shift the following ones
+fn resumable_parts(
+    parts: &[Part],
+    object_size: u64,
+    part_size: usize,
+) -> Option<BTreeMap<usize, UploadPartOutput>> {
+    let part_size = part_size as u64;
+    let total_parts = (object_size as f64 / part_size as f64).ceil() as usize;
+
+    parts
+        .iter()
+        .map(|part| {
+            let part_number = part.part_number? as usize;
+            if part_number == 0 || part_number > total_parts {
+                return None;
+            }
+
+            let expected_size = std::cmp::min(
+                part_size,
+                object_size - (part_number as u64 - 1) * part_size,
+            );
+            (part.size? as u64 == expected_size).then(|| {
+                (
+                    part_number,
+                    UploadPartOutput {
+                        e_tag: part.e_tag.clone(),
+                        ..Default::default()
+                    },
+                )
+            })
+        })
+        .collect()
+}
+
+/// Content-Type of the key extension. None for keys without an extension or with an unknown
+/// one, the destination then defaults to application/octet-stream
+fn infer_content_type(key: &str) -> Option<String> {
+    mime_guess::from_path(key)
+        .first()
+        .map(|mime| mime.essence_str().to_string())
+}
+
+/// Reads the source bytes of a part already uploaded by a previous attempt after a missing part,
+/// the body is downloaded from the first missing part
+async fn skip_part(
+    body: &Arc<Mutex<Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>>>,
+    part_size: usize,
+) -> anyhow::Result<()> {
+    let mut part = ProviderResponseStreamChunkWrapper::new(body.clone());
+    let mut skipped = 0;
+    while skipped < part_size {
+        match part.next().await {
+            Some(bytes) => skipped += bytes?.len(),
+            None => anyhow::bail!("Source body ended before the end of the part"),
+        }
+    }
+
+    Ok(())
+}
+
+/// As expected by Content-MD5
+fn base64_digest(digest: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+/// As returned in the ETag of an object
+fn hex_digest(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Checksum of a multipart upload in the ETag format, from the MD5 of its parts
+fn multipart_checksum(parts_digests: &[[u8; 16]]) -> String {
+    format!(
+        "{}-{}",
+        hex_digest(&Md5::digest(parts_digests.concat())),
+        parts_digests.len()
+    )
+}
+
+/// The ETag of an object or a part put in a single request is the MD5 of its body, except when
+/// it is encrypted with SSE-KMS
+fn check_etag(
+    object_metadata: &ProviderObjectMetadata,
+    digest: &[u8],
+    etag: Option<&str>,
+) -> Result<(), String> {
+    if object_metadata.server_side_encryption.as_deref() == Some("aws:kms") {
+        return Ok(());
+    }
+
+    let expected = hex_digest(digest);
+    match etag.map(|etag| etag.trim_matches('"')) {
+        Some(etag) if etag != expected => Err(format!("expected ETag {}, got {}", expected, etag)),
+        _ => Ok(()),
+    }
+}
+
+/// Keeps the type of the error when the destination is full so the workers can stop,
+/// other errors are returned as is
+fn destination_error<E: std::error::Error + Send + Sync + 'static>(
+    object: &ProviderObject,
+    error: RusotoError<E>,
+) -> anyhow::Error {
+    if is_destination_full(&error) {
+        destination_full_error(object, error)
+    } else if let Some(message) = clock_skew_message(&error) {
+        anyhow::anyhow!(message)
+    } else {
+        anyhow::Error::from(error)
+    }
+}
+
+fn destination_full_error<E: std::error::Error + 'static>(
+    object: &ProviderObject,
+    error: RusotoError<E>,
+) -> anyhow::Error {
+    anyhow::Error::from(DestinationFull {
+        object: object.clone(),
+        reason: format!("{:?}", error),
+    })
+}
+
+/// The destination refused an upload because its storage quota has been exceeded or it ran out of space.
+/// Every following upload would fail the same way.
+#[derive(Debug, Clone)]
+pub struct DestinationFull {
+    pub object: ProviderObject,
+    pub reason: String,
+}
+
+impl std::error::Error for DestinationFull {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+impl std::fmt::Display for DestinationFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Destination is full or its quota has been exceeded while uploading object {}: {}",
+            self.object.get_key(),
+            self.reason
+        )
     }
 }
 
@@ -439,3 +2023,802 @@ impl Stream for RiakResponseStream {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        acl_rules::ObjectGrant,
+        benchmark::SyntheticProvider,
+        provider::ProviderResponse,
+        radosgw::mock_destination::{
+            accept_uploads, error_response, etag_response, Concurrency, MockDestination,
+            ReceivedRequest,
+        },
+    };
+    use hyper::Method;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Uploads without any option, in parts of 10 bytes from 10 bytes
+    fn configuration() -> UploaderConfiguration {
+        UploaderConfiguration {
+            threads: 1,
+            multipart_threshold: 10,
+            multipart_part_size: 10,
+            multipart_budget: None,
+            multipart_uploads: Vec::new(),
+            small_object_threshold: None,
+            small_object_threads: 0,
+            delete_threads: 1,
+            priority: ObjectPriority::Listing,
+            source_etag_cache: None,
+            checksum_manifest: None,
+            state_file: None,
+            object_report: None,
+            content_dedup: None,
+            acl_rules: None,
+            preserve_acl: false,
+            preserve_tags: false,
+            storage_classes: StorageClassRules::default(),
+            infer_content_type: false,
+            object_acls_disabled: false,
+            progress: Arc::new(SyncProgress::default()),
+            truncate_bytes: None,
+            source_bucket: None,
+            metadata_filter: None,
+            key_filter: None,
+            encryption_mirror: None,
+            server_side_encryption: None,
+            sse_kms_key_id: None,
+            timing_trace: None,
+            object_timings: None,
+            fail_fast: false,
+            circuit_breaker: None,
+            run_window: None,
+            bandwidth_limits: Vec::new(),
+            verify_checksum: false,
+            thread_budget: None,
+            max_retries: 0,
+            retry_base_delay: Duration::ZERO,
+            simulated_failures: None,
+        }
+    }
+
+    /// Uploader of the zero filled objects of `SyntheticProvider`
+    fn uploader(destination: &MockDestination, conf: UploaderConfiguration) -> Uploader {
+        Uploader::new(
+            Box::new(SyntheticProvider {}),
+            destination.client(),
+            Vec::new(),
+            Vec::new(),
+            conf,
+        )
+    }
+
+    fn verifying_checksums() -> UploaderConfiguration {
+        UploaderConfiguration {
+            verify_checksum: true,
+            ..configuration()
+        }
+    }
+
+    async fn object_metadata(object: &ProviderObject) -> ProviderObjectMetadata {
+        SyntheticProvider {}
+            .get_object_metadata(object)
+            .await
+            .unwrap()
+    }
+
+    fn body(data: &'static [u8]) -> ByteStream {
+        ByteStream::new(futures::stream::once(async move {
+            Ok(Bytes::from_static(data))
+        }))
+    }
+
+    #[tokio::test]
+    async fn part_with_a_wrong_checksum_is_rejected_by_the_destination() {
+        let destination = MockDestination::start(accept_uploads);
+        let uploader = uploader(&destination, verifying_checksums());
+        let object = ProviderObject::new("key".to_string(), 5);
+        let wrong_digest = Md5::digest(b"world").into();
+
+        let response = uploader
+            .upload_part(
+                &object,
+                &object_metadata(&object).await,
+                "upload",
+                1,
+                5,
+                body(b"hello"),
+                Some(wrong_digest),
+            )
+            .await;
+
+        let error = response.unwrap_err();
+        assert!(format!("{:?}", error).contains("BadDigest"), "{:?}", error);
+        let requests = destination.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].header("Content-MD5"),
+            Some(base64_digest(&wrong_digest).as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn streamed_part_is_checked_against_its_etag() {
+        let destination =
+            MockDestination::start(|_| etag_response(&hex_digest(&Md5::digest(b"world"))));
+        let uploader = uploader(&destination, verifying_checksums());
+        let object = ProviderObject::new("key".to_string(), 5);
+
+        let error = uploader
+            .upload_part(
+                &object,
+                &object_metadata(&object).await,
+                "upload",
+                1,
+                5,
+                body(b"hello"),
+                None,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(
+            error.to_string().contains("doesn't match its checksum"),
+            "{}",
+            error
+        );
+        // The MD5 of a streamed part isn't known before it is sent
+        assert_eq!(destination.requests()[0].header("Content-MD5"), None);
+        assert_eq!(destination.requests()[0].body, Bytes::from_static(b"hello"));
+    }
+
+    #[tokio::test]
+    async fn object_is_hashed_while_it_is_uploaded() {
+        let destination = MockDestination::start(accept_uploads);
+        let uploader = uploader(&destination, verifying_checksums());
+
+        let sync = uploader
+            .sync_object(&ProviderObject::new("key".to_string(), 5), 0)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            sync,
+            ObjectSync::Uploaded(Some(checksum)) if checksum == hex_digest(&Md5::digest([0; 5]))
+        ));
+    }
+
+    #[tokio::test]
+    async fn multipart_object_is_hashed_part_by_part() {
+        let destination = MockDestination::start(accept_uploads);
+        let uploader = uploader(&destination, verifying_checksums());
+
+        let sync = uploader
+            .sync_object(&ProviderObject::new("key".to_string(), 25), 0)
+            .await
+            .unwrap();
+
+        let parts_digests: Vec<[u8; 16]> = [10, 10, 5]
+            .iter()
+            .map(|size| Md5::digest(vec![0; *size]).into())
+            .collect();
+        assert!(matches!(
+            sync,
+            ObjectSync::Uploaded(Some(checksum)) if checksum == multipart_checksum(&parts_digests)
+        ));
+        let parts = destination
+            .requests()
+            .into_iter()
+            .filter(|request| request.uri.contains("partNumber"))
+            .map(|request| request.body.len())
+            .collect::<Vec<usize>>();
+        assert_eq!(parts, [10, 10, 5]);
+    }
+
+    #[tokio::test]
+    async fn last_part_is_smaller_than_the_minimum_part_size() {
+        const PART_SIZE: usize = 5 * 1024 * 1024;
+        let destination = MockDestination::start(accept_uploads);
+        let uploader = uploader(
+            &destination,
+            UploaderConfiguration {
+                multipart_threshold: PART_SIZE as u64,
+                multipart_part_size: PART_SIZE,
+                ..configuration()
+            },
+        );
+        assert_eq!(uploader.number_of_parts(PART_SIZE as u64), 1);
+        assert_eq!(uploader.number_of_parts(PART_SIZE as u64 + 1), 2);
+
+        let object = ProviderObject::new("key".to_string(), PART_SIZE as u64 + 1);
+        uploader.sync_object(&object, 0).await.unwrap();
+
+        let parts = destination
+            .requests()
+            .into_iter()
+            .filter(|request| request.uri.contains("partNumber"))
+            .map(|request| request.body.len())
+            .collect::<Vec<usize>>();
+        assert_eq!(parts, [PART_SIZE, 1]);
+    }
+
+    /// `SyntheticProvider` recording the ranges requested with `get_object_range`
+    #[derive(Debug, Clone, Default)]
+    struct RangesProvider {
+        ranges: Arc<Mutex<Vec<(u64, u64)>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Provider for RangesProvider {
+        async fn get_buckets(&self) -> anyhow::Result<Vec<String>> {
+            unimplemented!()
+        }
+
+        fn list_objects(
+            &self,
+            _: Option<usize>,
+            _: Option<String>,
+        ) -> Pin<Box<dyn Stream<Item = anyhow::Result<Vec<ProviderObject>>> + '_>> {
+            unimplemented!()
+        }
+
+        async fn get_object_metadata(
+            &self,
+            object: &ProviderObject,
+        ) -> anyhow::Result<ProviderObjectMetadata> {
+            SyntheticProvider {}.get_object_metadata(object).await
+        }
+
+        async fn get_object(
+            &self,
+            object: &ProviderObject,
+        ) -> anyhow::Result<Box<dyn ProviderResponse>> {
+            SyntheticProvider {}.get_object(object).await
+        }
+
+        async fn get_object_range(
+            &self,
+            object: &ProviderObject,
+            start: u64,
+            length: u64,
+        ) -> anyhow::Result<Box<dyn ProviderResponse>> {
+            self.ranges.lock().unwrap().push((start, length));
+            SyntheticProvider {}
+                .get_object_range(object, start, length)
+                .await
+        }
+
+        async fn get_object_if_none_match(
+            &self,
+            _: &ProviderObject,
+            _: &str,
+        ) -> anyhow::Result<Option<Box<dyn ProviderResponse>>> {
+            unimplemented!()
+        }
+
+        async fn get_object_tags(
+            &self,
+            _: &ProviderObject,
+        ) -> anyhow::Result<HashMap<String, String>> {
+            unimplemented!()
+        }
+
+        async fn get_object_acl(&self, _: &ProviderObject) -> anyhow::Result<Vec<ObjectGrant>> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_only_uploads_the_missing_parts() {
+        let part_failed = AtomicBool::new(false);
+        let destination = MockDestination::start(move |request| {
+            if request.uri.contains("partNumber=2") && !part_failed.swap(true, Ordering::SeqCst) {
+                error_response(500, "InternalError")
+            } else {
+                accept_uploads(request)
+            }
+        });
+        let source = RangesProvider::default();
+        let uploader = Uploader::new(
+            Box::new(source.clone()),
+            destination.client(),
+            Vec::new(),
+            Vec::new(),
+            configuration(),
+        );
+        let object = ProviderObject::new("key".to_string(), 25);
+
+        let error = uploader.sync_object(&object, 0).await.unwrap_err();
+        assert!(is_retryable_upload_error(&error), "{:?}", error);
+        uploader.sync_object(&object, 0).await.unwrap();
+
+        let requests = destination.requests();
+        let parts = requests
+            .iter()
+            .filter(|request| request.uri.contains("partNumber"))
+            .map(|request| (request.uri.contains("partNumber=2"), request.body.len()))
+            .collect::<Vec<(bool, usize)>>();
+        assert_eq!(parts, [(false, 10), (true, 10), (true, 10), (false, 5)]);
+        let uploads = requests
+            .iter()
+            .filter(|request| request.uri.contains("?uploads"))
+            .count();
+        assert_eq!(uploads, 1);
+        // The retry downloads the object from the first missing part
+        assert_eq!(*source.ranges.lock().unwrap(), [(10, 15)]);
+        assert_eq!(requests.last().unwrap().method, Method::POST);
+    }
+
+    /// Answers the first `failures` requests matching `fails` with `status`
+    fn failing(
+        failures: usize,
+        status: u16,
+        fails: fn(&ReceivedRequest) -> bool,
+    ) -> MockDestination {
+        let failed = AtomicUsize::new(0);
+        MockDestination::start(move |request| {
+            if fails(request) && failed.fetch_add(1, Ordering::SeqCst) < failures {
+                error_response(status, "Error")
+            } else {
+                accept_uploads(request)
+            }
+        })
+    }
+
+    async fn sync(destination: &MockDestination, object: ProviderObject) -> anyhow::Result<usize> {
+        let mut uploader = Uploader::new(
+            Box::new(SyntheticProvider {}),
+            destination.client(),
+            vec![object],
+            Vec::new(),
+            UploaderConfiguration {
+                max_retries: 2,
+                ..configuration()
+            },
+        );
+        let mut results = uploader.sync().await.pop().unwrap().unwrap();
+        results.sync_results.pop().unwrap()
+    }
+
+    fn count(destination: &MockDestination, method: Method) -> usize {
+        destination
+            .requests()
+            .iter()
+            .filter(|request| request.method == method)
+            .count()
+    }
+
+    #[tokio::test]
+    async fn upload_failing_twice_is_retried() {
+        let destination = failing(2, 503, |request| request.method == Method::PUT);
+
+        let size = sync(&destination, ProviderObject::new("key".to_string(), 5)).await;
+
+        assert_eq!(size.unwrap(), 5);
+        assert_eq!(count(&destination, Method::PUT), 3);
+    }
+
+    #[tokio::test]
+    async fn completion_failing_twice_is_retried() {
+        let destination = failing(2, 500, |request| {
+            request.method == Method::POST && request.uri.contains("uploadId")
+        });
+
+        let size = sync(&destination, ProviderObject::new("key".to_string(), 25)).await;
+
+        assert_eq!(size.unwrap(), 25);
+        // The failed uploads are aborted, each attempt uploads the object again
+        assert_eq!(count(&destination, Method::DELETE), 2);
+        assert_eq!(count(&destination, Method::POST), 6);
+    }
+
+    #[tokio::test]
+    async fn access_denied_upload_is_not_retried() {
+        let destination = failing(1, 403, |request| request.method == Method::PUT);
+
+        let size = sync(&destination, ProviderObject::new("key".to_string(), 5)).await;
+
+        assert!(size.is_err());
+        assert_eq!(count(&destination, Method::PUT), 1);
+    }
+
+    fn objects(keys: &[&str]) -> Vec<ProviderObject> {
+        keys.iter()
+            .map(|key| ProviderObject::new(key.to_string(), 5))
+            .collect()
+    }
+
+    fn uploaded_keys(destination: &MockDestination) -> Vec<String> {
+        destination
+            .requests()
+            .iter()
+            .filter(|request| request.method == Method::PUT)
+            .map(|request| request.uri.trim_start_matches("/bucket/").to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn second_run_only_uploads_the_objects_missing_from_the_state_file() {
+        let path = std::env::temp_dir().join(format!("state-{}.jsonl", std::process::id()));
+        let destination = MockDestination::start(accept_uploads);
+
+        // The third object fails and stops the run before the last one
+        let mut first_run = Uploader::new(
+            Box::new(SyntheticProvider {}),
+            destination.client(),
+            objects(&["a", "b", "c", "d"]),
+            Vec::new(),
+            UploaderConfiguration {
+                state_file: Some(Arc::new(StateFile::open(&path, "bucket").unwrap())),
+                simulated_failures: Some("key=c".parse().unwrap()),
+                fail_fast: true,
+                ..configuration()
+            },
+        );
+        first_run.sync().await;
+        assert_eq!(uploaded_keys(&destination), ["a", "b"]);
+
+        let state_file = Arc::new(StateFile::open(&path, "bucket").unwrap());
+        let remaining = objects(&["a", "b", "c", "d"])
+            .into_iter()
+            .filter(|object| !state_file.is_completed(&object.get_key()))
+            .collect();
+        let mut second_run = Uploader::new(
+            Box::new(SyntheticProvider {}),
+            destination.client(),
+            remaining,
+            Vec::new(),
+            UploaderConfiguration {
+                state_file: Some(state_file),
+                ..configuration()
+            },
+        );
+        second_run.sync().await;
+        let completed = StateFile::open(&path, "bucket").unwrap().completed_count();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(uploaded_keys(&destination), ["a", "b", "c", "d"]);
+        assert_eq!(completed, 4);
+    }
+
+    #[tokio::test]
+    async fn empty_object_is_put_in_a_single_request() {
+        let destination = MockDestination::start(accept_uploads);
+        // Every other object is uploaded using multipart
+        let mut uploader = Uploader::new(
+            Box::new(SyntheticProvider {}),
+            destination.client(),
+            vec![ProviderObject::new("empty".to_string(), 0)],
+            Vec::new(),
+            UploaderConfiguration {
+                multipart_threshold: 0,
+                ..configuration()
+            },
+        );
+
+        let mut results = uploader.sync().await.pop().unwrap().unwrap();
+        assert_eq!(results.sync_results.pop().unwrap().unwrap(), 0);
+
+        let requests = destination.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, Method::PUT);
+        assert_eq!(requests[0].uri, "/bucket/empty");
+        assert_eq!(requests[0].header("Content-Length"), Some("0"));
+        assert_eq!(
+            requests[0].header("Content-Type"),
+            Some("application/octet-stream")
+        );
+        assert!(requests[0].body.is_empty());
+    }
+
+    /// Requests sent to upload an object of `size` bytes, with a multipart threshold of 10 bytes
+    async fn upload_requests(size: u64) -> Vec<(Method, String)> {
+        let destination = MockDestination::start(accept_uploads);
+        sync(&destination, ProviderObject::new("key".to_string(), size))
+            .await
+            .unwrap();
+        destination
+            .requests()
+            .into_iter()
+            .map(|request| (request.method, request.uri))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn object_under_the_multipart_threshold_is_put_in_a_single_request() {
+        assert_eq!(
+            upload_requests(9).await,
+            [(Method::PUT, "/bucket/key".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn object_of_the_multipart_threshold_is_uploaded_using_multipart() {
+        assert_eq!(
+            upload_requests(10).await,
+            [
+                (Method::POST, "/bucket/key?uploads=".to_string()),
+                (
+                    Method::PUT,
+                    "/bucket/key?partNumber=1&uploadId=upload".to_string()
+                ),
+                (Method::POST, "/bucket/key?uploadId=upload".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn content_type_of_the_key_extension() {
+        assert_eq!(
+            infer_content_type("site/index.html").as_deref(),
+            Some("text/html")
+        );
+        assert_eq!(
+            infer_content_type("data.json").as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(infer_content_type("site/README"), None);
+        assert_eq!(infer_content_type("archive.unknown-extension"), None);
+    }
+
+    #[tokio::test]
+    async fn content_type_of_the_source_is_kept() {
+        let destination = MockDestination::start(accept_uploads);
+        let mut uploader = Uploader::new(
+            Box::new(SyntheticProvider {}),
+            destination.client(),
+            vec![ProviderObject::new("index.html".to_string(), 5)],
+            Vec::new(),
+            UploaderConfiguration {
+                infer_content_type: true,
+                ..configuration()
+            },
+        );
+        uploader.sync().await;
+
+        let requests = destination.requests();
+        assert_eq!(
+            requests[0].header("Content-Type"),
+            Some("application/octet-stream")
+        );
+    }
+
+    #[tokio::test]
+    async fn storage_class_is_set_on_the_uploads() {
+        let destination = MockDestination::start(accept_uploads);
+        let mut uploader = Uploader::new(
+            Box::new(SyntheticProvider {}),
+            destination.client(),
+            vec![
+                ProviderObject::new("archive/large".to_string(), 20),
+                ProviderObject::new("small".to_string(), 5),
+            ],
+            Vec::new(),
+            UploaderConfiguration {
+                storage_classes: StorageClassRules {
+                    rules: vec!["archive/*=GLACIER".try_into().unwrap()],
+                    default: Some("STANDARD_IA".to_string()),
+                },
+                ..configuration()
+            },
+        );
+        uploader.sync().await;
+
+        let storage_classes: Vec<(String, Option<String>)> = destination
+            .requests()
+            .iter()
+            .filter(|request| {
+                (request.method == Method::PUT && !request.uri.contains("partNumber"))
+                    || request.query("uploads").is_some()
+            })
+            .map(|request| {
+                (
+                    request.uri.clone(),
+                    request.header("x-amz-storage-class").map(str::to_string),
+                )
+            })
+            .collect();
+        assert_eq!(
+            storage_classes,
+            [
+                (
+                    "/bucket/archive/large?uploads=".to_string(),
+                    Some("GLACIER".to_string())
+                ),
+                ("/bucket/small".to_string(), Some("STANDARD_IA".to_string())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn timing_of_each_uploaded_object_is_recorded() {
+        let destination = MockDestination::start(accept_uploads);
+        let object_timings = Arc::new(ObjectTimings::new(10));
+        let mut uploader = Uploader::new(
+            Box::new(SyntheticProvider {}),
+            destination.client(),
+            vec![
+                ProviderObject::new("large".to_string(), 25),
+                ProviderObject::new("small".to_string(), 5),
+            ],
+            Vec::new(),
+            UploaderConfiguration {
+                object_timings: Some(object_timings.clone()),
+                ..configuration()
+            },
+        );
+        uploader.sync().await;
+
+        let mut timings: Vec<(String, u64, usize)> = object_timings
+            .slowest()
+            .into_iter()
+            .map(|timing| (timing.key, timing.size, timing.number_of_parts))
+            .collect();
+        timings.sort();
+        assert_eq!(
+            timings,
+            [("large".to_string(), 25, 3), ("small".to_string(), 5, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn encryption_is_set_on_the_uploads_of_each_mode() {
+        let modes = [
+            (None, None),
+            (Some("AES256"), None),
+            (Some("aws:kms"), None),
+            (Some("aws:kms"), Some("key-id")),
+        ];
+
+        for (server_side_encryption, sse_kms_key_id) in modes {
+            let destination = MockDestination::start(accept_uploads);
+            // A single PUT and a multipart upload
+            let mut uploader = Uploader::new(
+                Box::new(SyntheticProvider {}),
+                destination.client(),
+                vec![
+                    ProviderObject::new("small".to_string(), 5),
+                    ProviderObject::new("large".to_string(), 20),
+                ],
+                Vec::new(),
+                UploaderConfiguration {
+                    server_side_encryption: server_side_encryption.map(str::to_string),
+                    sse_kms_key_id: sse_kms_key_id.map(str::to_string),
+                    ..configuration()
+                },
+            );
+            uploader.sync().await;
+
+            let creations: Vec<ReceivedRequest> = destination
+                .requests()
+                .into_iter()
+                .filter(|request| {
+                    (request.method == Method::PUT && !request.uri.contains("partNumber"))
+                        || request.query("uploads").is_some()
+                })
+                .collect();
+            assert_eq!(creations.len(), 2);
+            for request in creations {
+                assert_eq!(
+                    request.header("x-amz-server-side-encryption"),
+                    server_side_encryption,
+                    "{}",
+                    request.uri
+                );
+                assert_eq!(
+                    request.header("x-amz-server-side-encryption-aws-kms-key-id"),
+                    sse_kms_key_id,
+                    "{}",
+                    request.uri
+                );
+            }
+        }
+    }
+
+    fn parts(part_numbers: &[usize]) -> Vec<(usize, UploadPartOutput)> {
+        part_numbers
+            .iter()
+            .map(|part_number| (*part_number, UploadPartOutput::default()))
+            .collect()
+    }
+
+    #[test]
+    fn completed_parts_numbered_from_one() {
+        assert!(check_completed_parts(&parts(&[1])).is_ok());
+        assert!(check_completed_parts(&parts(&[1, 2, 3])).is_ok());
+    }
+
+    #[test]
+    fn completed_parts_without_parts() {
+        assert!(check_completed_parts(&[]).is_err());
+    }
+
+    #[test]
+    fn completed_parts_numbered_from_zero() {
+        let error = check_completed_parts(&parts(&[0, 1, 2])).unwrap_err();
+        assert!(error.contains("part 0 found at position 1"), "{}", error);
+    }
+
+    #[test]
+    fn completed_parts_with_a_gap() {
+        let error = check_completed_parts(&parts(&[1, 2, 4])).unwrap_err();
+        assert!(error.contains("part 4 found at position 3"), "{}", error);
+    }
+
+    #[test]
+    fn completed_parts_unsorted() {
+        let error = check_completed_parts(&parts(&[2, 1, 3])).unwrap_err();
+        assert!(error.contains("part 2 found at position 1"), "{}", error);
+    }
+
+    fn uploaded_part(part_number: i64, size: i64) -> Part {
+        Part {
+            part_number: Some(part_number),
+            size: Some(size),
+            e_tag: Some(format!("\"etag-{}\"", part_number)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resumable_parts_of_the_part_size() {
+        let parts = [uploaded_part(1, 10), uploaded_part(3, 5)];
+        let resumable = resumable_parts(&parts, 25, 10).unwrap();
+        assert_eq!(resumable.keys().collect::<Vec<_>>(), [&1, &3]);
+        assert_eq!(resumable[&3].e_tag.as_deref(), Some("\"etag-3\""));
+    }
+
+    #[test]
+    fn resumable_parts_of_another_part_size() {
+        assert!(resumable_parts(&[uploaded_part(1, 8)], 25, 10).is_none());
+        assert!(resumable_parts(&[uploaded_part(3, 10)], 25, 10).is_none());
+    }
+
+    #[test]
+    fn resumable_parts_out_of_the_object() {
+        assert!(resumable_parts(&[uploaded_part(0, 10)], 25, 10).is_none());
+        assert!(resumable_parts(&[uploaded_part(4, 5)], 25, 10).is_none());
+    }
+
+    /// Highest number of batches deleted at the same time by an uploader of `threads` threads,
+    /// `delete_threads` of them deleting
+    async fn concurrent_deletes(threads: usize, delete_threads: usize) -> usize {
+        let concurrency = Arc::new(Concurrency::default());
+        let answered = concurrency.clone();
+        let destination = MockDestination::start(move |_| {
+            answered.respond(Duration::from_millis(50), hyper::Response::default())
+        });
+        let objects_to_delete = (0..6 * MAX_DELETE_KEYS)
+            .map(|i| ProviderObject::new(format!("key-{}", i), 10))
+            .collect();
+        let conf = UploaderConfiguration {
+            threads,
+            delete_threads,
+            ..configuration()
+        };
+        let mut uploader = Uploader::new(
+            Box::new(SyntheticProvider {}),
+            destination.client(),
+            Vec::new(),
+            objects_to_delete,
+            conf,
+        );
+
+        uploader.sync().await;
+
+        assert_eq!(destination.requests().len(), 6);
+        concurrency.max()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn fewer_delete_threads_than_sync_threads() {
+        assert_eq!(concurrent_deletes(4, 2).await, 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn more_delete_threads_than_sync_threads() {
+        assert_eq!(concurrent_deletes(1, 3).await, 3);
+    }
+}
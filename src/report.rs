@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::migrate::{migration_stats, BucketMigrationError, MultiBucketMigrationReport};
+
+/// Bumped on incompatible changes of the report. Adding fields isn't one
+const REPORT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ObjectStatus {
+    Uploaded,
+    /// Already synchronized, not copied
+    Skipped,
+    /// Not copied because of --min-object-size
+    TooSmall,
+    /// Not copied because of --max-object-size
+    TooLarge,
+    /// Not copied because of --modified-since or --modified-before
+    NotModifiedInRange,
+    Failed,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ObjectReport {
+    key: String,
+    size: u64,
+    status: ObjectStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    /// Hex MD5 computed while uploading with --verify-checksum, in the multipart ETag format
+    /// for objects uploaded in parts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    md5: Option<String>,
+}
+
+/// Outcome of each source object of a bucket
+#[derive(Debug, Default)]
+pub struct BucketObjectsReport {
+    objects: Mutex<Vec<ObjectReport>>,
+}
+
+impl BucketObjectsReport {
+    pub fn record(
+        &self,
+        key: String,
+        size: u64,
+        status: ObjectStatus,
+        reason: Option<String>,
+        md5: Option<String>,
+    ) {
+        self.objects.lock().unwrap().push(ObjectReport {
+            key,
+            size,
+            status,
+            reason,
+            md5,
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum BucketStatus {
+    Synchronized,
+    Failed,
+    /// Not started because the destination is full or a bucket failed with fail fast
+    Skipped,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct BucketReport {
+    bucket: String,
+    status: BucketStatus,
+    duration_seconds: f64,
+    synchronized_bytes: usize,
+    synchronized_objects: usize,
+    deleted_bytes: usize,
+    deleted_objects: usize,
+    /// Source objects out of --min-object-size and --max-object-size
+    size_excluded_objects: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resume_token: Option<String>,
+    errors: Vec<String>,
+    verification_errors: Vec<String>,
+    objects: Vec<ObjectReport>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct MigrationReportFile {
+    schema_version: u32,
+    dry_run: bool,
+    duration_seconds: f64,
+    synchronized_bytes: usize,
+    synchronized_objects: usize,
+    deleted_bytes: usize,
+    deleted_objects: usize,
+    failed_buckets: usize,
+    buckets: Vec<BucketReport>,
+}
+
+/// JSON report of the run written with `--report-json`, for tools parsing the outcome of a
+/// migration instead of its logs
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    buckets: Mutex<HashMap<String, Arc<BucketObjectsReport>>>,
+}
+
+impl MigrationReport {
+    pub fn bucket(&self, bucket: &str) -> Arc<BucketObjectsReport> {
+        self.buckets
+            .lock()
+            .unwrap()
+            .entry(bucket.to_string())
+            .or_default()
+            .clone()
+    }
+
+    fn take_objects(&self, bucket: &str) -> Vec<ObjectReport> {
+        self.buckets
+            .lock()
+            .unwrap()
+            .get(bucket)
+            .map(|report| std::mem::take(&mut *report.objects.lock().unwrap()))
+            .unwrap_or_default()
+    }
+
+    pub fn write(
+        &self,
+        path: &Path,
+        migration_report: &MultiBucketMigrationReport,
+        duration: Duration,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let mut buckets = Vec::new();
+        for (bucket, result) in &migration_report.buckets {
+            let stats = migration_stats(result);
+            let (errors, verification_errors) = match result {
+                Ok(_) => (Vec::new(), Vec::new()),
+                Err(error) => match error.downcast_ref::<BucketMigrationError>() {
+                    Some(error) => (error.errors.clone(), error.verification_errors.clone()),
+                    None => (vec![format!("{:#}", error)], Vec::new()),
+                },
+            };
+
+            buckets.push(BucketReport {
+                bucket: bucket.clone(),
+                status: if result.is_ok() {
+                    BucketStatus::Synchronized
+                } else {
+                    BucketStatus::Failed
+                },
+                duration_seconds: stats
+                    .map(|stats| stats.synchronization_time.as_secs_f64())
+                    .unwrap_or_default(),
+                synchronized_bytes: stats
+                    .map(|stats| stats.synchronization_size)
+                    .unwrap_or_default(),
+                synchronized_objects: stats
+                    .map(|stats| stats.total_files_sync)
+                    .unwrap_or_default(),
+                deleted_bytes: stats.map(|stats| stats.delete_size).unwrap_or_default(),
+                deleted_objects: stats
+                    .map(|stats| stats.total_files_delete)
+                    .unwrap_or_default(),
+                size_excluded_objects: stats
+                    .map(|stats| stats.total_files_size_excluded)
+                    .unwrap_or_default(),
+                resume_token: stats.and_then(|stats| stats.resume_token.clone()),
+                errors,
+                verification_errors,
+                objects: self.take_objects(bucket),
+            });
+        }
+
+        for bucket in &migration_report.skipped_buckets {
+            buckets.push(BucketReport {
+                bucket: bucket.clone(),
+                status: BucketStatus::Skipped,
+                duration_seconds: 0.0,
+                synchronized_bytes: 0,
+                synchronized_objects: 0,
+                deleted_bytes: 0,
+                deleted_objects: 0,
+                size_excluded_objects: 0,
+                resume_token: None,
+                errors: Vec::new(),
+                verification_errors: Vec::new(),
+                objects: Vec::new(),
+            });
+        }
+
+        let report = MigrationReportFile {
+            schema_version: REPORT_SCHEMA_VERSION,
+            dry_run,
+            duration_seconds: duration.as_secs_f64(),
+            synchronized_bytes: buckets.iter().map(|bucket| bucket.synchronized_bytes).sum(),
+            synchronized_objects: buckets
+                .iter()
+                .map(|bucket| bucket.synchronized_objects)
+                .sum(),
+            deleted_bytes: buckets.iter().map(|bucket| bucket.deleted_bytes).sum(),
+            deleted_objects: buckets.iter().map(|bucket| bucket.deleted_objects).sum(),
+            failed_buckets: migration_report.failed_buckets().len(),
+            buckets,
+        };
+
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create JSON report {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, &report)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrate::BucketMigrationStats;
+
+    use super::*;
+
+    fn stats(bucket: &str) -> BucketMigrationStats {
+        BucketMigrationStats {
+            bucket: bucket.to_string(),
+            synchronization_time: Duration::from_secs(2),
+            synchronization_size: 30,
+            per_object_timings: Vec::new(),
+            delete_size: 0,
+            total_files_sync: 2,
+            total_files_delete: 0,
+            total_files_in_sync: 0,
+            total_files_size_excluded: 0,
+            resume_token: None,
+        }
+    }
+
+    #[test]
+    fn object_status_round_trip() {
+        for status in [
+            ObjectStatus::Uploaded,
+            ObjectStatus::Skipped,
+            ObjectStatus::TooSmall,
+            ObjectStatus::TooLarge,
+            ObjectStatus::NotModifiedInRange,
+            ObjectStatus::Failed,
+        ] {
+            let json = serde_json::to_string(&status).unwrap();
+            assert_eq!(serde_json::from_str::<ObjectStatus>(&json).unwrap(), status);
+        }
+        assert_eq!(
+            serde_json::to_string(&ObjectStatus::NotModifiedInRange).unwrap(),
+            "\"not-modified-in-range\""
+        );
+    }
+
+    #[test]
+    fn report_round_trip() {
+        let report = MigrationReport::default();
+        let objects = report.bucket("source");
+        objects.record("a".to_string(), 10, ObjectStatus::Uploaded, None, None);
+        objects.record(
+            "b".to_string(),
+            20,
+            ObjectStatus::Failed,
+            Some("AccessDenied".to_string()),
+            None,
+        );
+        let migration_report = MultiBucketMigrationReport {
+            buckets: vec![("source".to_string(), Ok(stats("source")))],
+            skipped_buckets: vec!["skipped".to_string()],
+        };
+
+        let path = std::env::temp_dir().join(format!("report-{}.json", std::process::id()));
+        report
+            .write(&path, &migration_report, Duration::from_secs(3), false)
+            .unwrap();
+        let written: MigrationReportFile =
+            serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(written.schema_version, REPORT_SCHEMA_VERSION);
+        assert_eq!(written.synchronized_bytes, 30);
+        assert_eq!(written.failed_buckets, 0);
+        assert_eq!(written.buckets[0].status, BucketStatus::Synchronized);
+        assert_eq!(written.buckets[0].objects.len(), 2);
+        assert_eq!(written.buckets[0].objects[1].status, ObjectStatus::Failed);
+        assert_eq!(
+            written.buckets[0].objects[1].reason.as_deref(),
+            Some("AccessDenied")
+        );
+        assert_eq!(written.buckets[1].status, BucketStatus::Skipped);
+
+        let json = serde_json::to_string(&written).unwrap();
+        assert_eq!(
+            serde_json::from_str::<MigrationReportFile>(&json).unwrap(),
+            written
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use base64::Engine;
+use ring::digest;
+
+use crate::migrate::BucketMigrationConfiguration;
+
+const RESUME_TOKEN_VERSION: &str = "v1";
+
+/// Opaque handle given at the end of a partial run so the next run can continue where it stopped.
+/// It contains the listing cursor of the source bucket and a hash of the configuration the run
+/// was started with, so a token can't be used with a different source or destination
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResumeToken {
+    pub config_hash: String,
+    pub bucket: String,
+    pub start_after: String,
+}
+
+impl ResumeToken {
+    pub fn new(conf: &BucketMigrationConfiguration, start_after: String) -> ResumeToken {
+        ResumeToken {
+            config_hash: config_hash(conf),
+            bucket: conf.source_bucket.clone(),
+            start_after,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let token = format!(
+            "{}\n{}\n{}\n{}",
+            RESUME_TOKEN_VERSION, self.config_hash, self.bucket, self.start_after
+        );
+
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(token)
+    }
+
+    /// Makes sure the token has been emitted by a run with the same configuration
+    pub fn validate(&self, conf: &BucketMigrationConfiguration) -> Result<(), String> {
+        if self.bucket != conf.source_bucket {
+            return Err(format!(
+                "Resume token is for bucket {} but bucket {} is being synchronized",
+                self.bucket, conf.source_bucket
+            ));
+        }
+
+        if self.config_hash != config_hash(conf) {
+            return Err("Resume token has been emitted with a different configuration".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<&str> for ResumeToken {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let decoded = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(value.trim())
+            .map_err(|error| format!("Failed to decode resume token: {}", error))?;
+        let decoded = String::from_utf8(decoded)
+            .map_err(|error| format!("Failed to decode resume token: {}", error))?;
+
+        // The cursor is last as object keys may contain new lines
+        let mut parts = decoded.splitn(4, '\n');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(RESUME_TOKEN_VERSION), Some(config_hash), Some(bucket), Some(start_after)) => {
+                Ok(ResumeToken {
+                    config_hash: config_hash.to_string(),
+                    bucket: bucket.to_string(),
+                    start_after: start_after.to_string(),
+                })
+            }
+            _ => Err("Invalid resume token".to_string()),
+        }
+    }
+}
+
+/// Only the options that change which objects are copied, and where, are part of the hash.
+/// Tuning options (threads, chunk size, ..) can be changed between two runs
+fn config_hash(conf: &BucketMigrationConfiguration) -> String {
+    let material = format!(
+        "{:?}|{:?}|{:?}|{}|{:?}|{:?}|{:?}|{}|{}|{:?}|{}|{}|{}",
+        conf.source_provider,
+        conf.source_endpoint,
+        conf.source_region,
+        conf.source_bucket,
+        conf.include_prefix,
+        conf.key_filter,
+        conf.key_transform,
+        conf.destination_endpoint,
+        conf.destination_bucket,
+        conf.compare,
+        conf.trust_size_for_multipart,
+        conf.recopy_storage_class_changes,
+        conf.delete_destination_files
+    );
+
+    digest::digest(&digest::SHA256, material.as_bytes())
+        .as_ref()
+        .iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>()
+}
@@ -5,6 +5,8 @@ use hyper::{Body, Response};
 
 use serde_derive::Deserialize;
 
+use crate::acl_rules::{Grantee, ObjectGrant};
+
 #[derive(Debug, Deserialize, PartialEq, Clone)]
 pub struct ObjectContents {
     #[serde(rename(deserialize = "Key"))]
@@ -88,6 +90,7 @@ pub struct ObjectMetadata {
     pub content_language: Option<String>,
     pub content_md5: Option<String>,
     pub expires: Option<String>,
+    pub website_redirect_location: Option<String>,
 }
 
 impl ObjectMetadata {
@@ -124,6 +127,10 @@ impl From<Response<Body>> for ObjectMetadata {
             content_language: Self::extract_header(&response, "content-language"),
             content_md5: Self::extract_header(&response, "content-md5"),
             expires: Self::extract_header(&response, "expires"),
+            website_redirect_location: Self::extract_header(
+                &response,
+                "x-amz-website-redirect-location",
+            ),
         }
     }
 }
@@ -151,3 +158,64 @@ impl ListBucketsResult {
         self.buckets.bucket.clone().unwrap_or_default()
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct AccessControlPolicy {
+    #[serde(rename(deserialize = "Owner"))]
+    owner: AclOwner,
+    #[serde(rename(deserialize = "AccessControlList"))]
+    access_control_list: AccessControlList,
+}
+
+#[derive(Debug, Deserialize)]
+struct AclOwner {
+    #[serde(rename(deserialize = "ID"))]
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessControlList {
+    #[serde(rename(deserialize = "Grant"), default)]
+    grants: Vec<AclGrant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AclGrant {
+    #[serde(rename(deserialize = "Grantee"))]
+    grantee: AclGrantee,
+    #[serde(rename(deserialize = "Permission"))]
+    permission: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AclGrantee {
+    #[serde(rename(deserialize = "ID"))]
+    id: Option<String>,
+    #[serde(rename(deserialize = "URI"))]
+    uri: Option<String>,
+    #[serde(rename(deserialize = "EmailAddress"))]
+    email_address: Option<String>,
+}
+
+impl AccessControlPolicy {
+    pub fn get_grants(&self) -> Vec<ObjectGrant> {
+        self.access_control_list
+            .grants
+            .iter()
+            .filter_map(|grant| {
+                let grantee = &grant.grantee;
+                let grantee = match (&grantee.uri, &grantee.id, &grantee.email_address) {
+                    (Some(uri), _, _) => Grantee::from_uri(uri),
+                    (None, Some(id), _) if id == &self.owner.id => Grantee::Owner,
+                    (None, Some(id), _) => Grantee::Other(id.clone()),
+                    (None, None, Some(email)) => Grantee::Other(email.clone()),
+                    (None, None, None) => return None,
+                };
+                Some(ObjectGrant {
+                    grantee,
+                    permission: grant.permission.clone(),
+                })
+            })
+            .collect()
+    }
+}
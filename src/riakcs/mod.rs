@@ -1,6 +1,6 @@
 pub mod dto;
 
-use std::pin::Pin;
+use std::{collections::HashMap, pin::Pin};
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -9,23 +9,29 @@ use bytes::{BufMut, BytesMut};
 use chrono::{DateTime, Duration, Utc};
 use dto::{ListObjectResponse, ObjectContents};
 use futures::Stream;
-use hyper::{body::HttpBody, Body, Client, Method, Response, StatusCode};
-use hyper_tls::HttpsConnector;
+use hyper::{
+    body::HttpBody,
+    client::HttpConnector,
+    header::{CONTENT_LENGTH, IF_NONE_MATCH, RANGE, TRANSFER_ENCODING},
+    Body, Client, Method, Response, StatusCode,
+};
 use ring::hmac;
 use serde::Deserialize;
 use serde_xml_rs::{de::Deserializer, ParserConfig};
 use tracing::{event, instrument, Level};
 
 use crate::{
+    acl_rules::ObjectGrant,
     provider::{
-        Provider, ProviderObject, ProviderObjectMetadata, ProviderResponse,
+        quote_etag, Provider, ProviderObject, ProviderObjectMetadata, ProviderResponse,
         ProviderResponseStreamChunk,
     },
     radosgw::uploader::RiakResponseStream,
     riakcs::dto::ListBucketsResult,
+    tls,
 };
 
-use self::dto::{ListBucket, ObjectMetadata, ObjectMetadataResponse};
+use self::dto::{AccessControlPolicy, ListBucket, ObjectMetadata, ObjectMetadataResponse};
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -39,6 +45,10 @@ impl RiakCSError {
     pub fn new(uri: String, code: u16, body: Option<String>) -> RiakCSError {
         RiakCSError { uri, code, body }
     }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
 }
 
 impl std::error::Error for RiakCSError {
@@ -67,8 +77,14 @@ pub struct RiakCS {
     access_key: String,
     secret_key: String,
     bucket: Option<String>,
+    requester_pays: bool,
+    /// Only the keys starting with it are listed
+    prefix: Option<String>,
 }
 
+/// Requester pays buckets reject the requests without this header
+const REQUEST_PAYER_HEADER: &str = "x-amz-request-payer";
+
 impl RiakCS {
     pub fn new(
         endpoint: String,
@@ -81,6 +97,30 @@ impl RiakCS {
             access_key,
             secret_key,
             bucket,
+            requester_pays: false,
+            prefix: None,
+        }
+    }
+
+    pub fn with_requester_pays(self, requester_pays: bool) -> RiakCS {
+        RiakCS {
+            requester_pays,
+            ..self
+        }
+    }
+
+    pub fn with_prefix(self, prefix: Option<String>) -> RiakCS {
+        RiakCS { prefix, ..self }
+    }
+
+    fn with_request_payer(
+        &self,
+        builder: hyper::http::request::Builder,
+    ) -> hyper::http::request::Builder {
+        if self.requester_pays {
+            builder.header(REQUEST_PAYER_HEADER, "requester")
+        } else {
+            builder
         }
     }
 
@@ -124,17 +164,29 @@ impl RiakCS {
                 .to_string(),
         );
 
-        for (header_name, header_value) in req.headers() {
-            if header_name.as_str().starts_with("x-amz-") {
-                to_sign.push(format!(
+        // The x-amz-* headers are signed sorted by name
+        let mut amz_headers = req
+            .headers()
+            .iter()
+            .filter(|(header_name, _)| header_name.as_str().starts_with("x-amz-"))
+            // User metadata can be UTF-8, which `to_str` rejects
+            .map(|(header_name, header_value)| {
+                format!(
                     "{}:{}",
                     header_name.as_str(),
-                    header_value.to_str().unwrap()
-                ));
-            }
+                    String::from_utf8_lossy(header_value.as_bytes())
+                )
+            })
+            .collect::<Vec<String>>();
+        amz_headers.sort();
+        to_sign.extend(amz_headers);
+
+        // The ?acl sub-resource is part of the signed resource, unlike the other query parameters
+        let mut resource = req.uri().path().to_string();
+        if req.uri().query() == Some("acl") {
+            resource.push_str("?acl");
         }
-
-        to_sign.push(req.uri().path().to_string());
+        to_sign.push(resource);
 
         let encoded_sha1 = self.sign_string(to_sign.join("\n"));
 
@@ -148,8 +200,13 @@ impl RiakCS {
 
     fn sign_url(&self, object: &ProviderObject, expiry: DateTime<Utc>) -> String {
         let to_sign = format!(
-            "GET\n\n\n{}\n/{}/{}",
+            "GET\n\n\n{}\n{}/{}/{}",
             expiry.timestamp(),
+            if self.requester_pays {
+                format!("{}:requester\n", REQUEST_PAYER_HEADER)
+            } else {
+                String::new()
+            },
             self.bucket.as_ref().unwrap_or(&String::new()),
             urlencoding::encode(&object.get_key())
         );
@@ -157,10 +214,16 @@ impl RiakCS {
         self.sign_string(to_sign)
     }
 
+    /// An endpoint without a scheme is reached over HTTPS
     fn get_uri(&self) -> String {
+        let endpoint = if self.endpoint.contains("://") {
+            self.endpoint.clone()
+        } else {
+            format!("https://{}", self.endpoint)
+        };
         format!(
-            "https://{}/{}",
-            self.endpoint,
+            "{}/{}",
+            endpoint,
             self.bucket.as_ref().unwrap_or(&String::new())
         )
     }
@@ -197,7 +260,7 @@ impl RiakCS {
 
     #[instrument(skip(self, req), level = "debug")]
     async fn send_request(&self, req: hyper::Request<Body>) -> Result<Response<Body>> {
-        let https = HttpsConnector::new();
+        let https = tls::https_connector(HttpConnector::new());
         let client = Client::builder().build::<_, hyper::Body>(https);
 
         event!(
@@ -221,9 +284,13 @@ impl RiakCS {
         let mut results = Vec::new();
         loop {
             let uri = format!(
-                "{}?max-keys={}{}",
+                "{}?max-keys={}{}{}",
                 self.get_uri(),
                 std::cmp::max(max_keys.unwrap_or(1000), 1000),
+                self.prefix
+                    .as_ref()
+                    .map(|prefix| format!("&prefix={}", urlencoding::encode(prefix)))
+                    .unwrap_or_else(String::new),
                 marker
                     .take()
                     .map(|m| format!("&marker={}", urlencoding::encode(&m)))
@@ -231,7 +298,8 @@ impl RiakCS {
             );
 
             event!(Level::TRACE, "Build request with uri: {}", uri);
-            let mut req = hyper::Request::builder()
+            let mut req = self
+                .with_request_payer(hyper::Request::builder())
                 .method(Method::GET)
                 .uri(uri)
                 .body(Body::empty())?;
@@ -282,7 +350,8 @@ impl RiakCS {
     pub async fn get_object(&self, object: &ProviderObject) -> Result<Response<Body>> {
         let url = self.get_download_url(object);
 
-        let req = hyper::Request::builder()
+        let req = self
+            .with_request_payer(hyper::Request::builder())
             .method(Method::GET)
             .uri(url)
             .body(Body::empty())?;
@@ -290,47 +359,82 @@ impl RiakCS {
         self.send_request(req).await
     }
 
-    #[allow(dead_code)]
-    pub async fn get_object_acl(&self, object: &ObjectContents) -> Result<()> {
-        let uri = format!("{}/{}?acl", self.get_uri(), object.get_key());
-        let mut req = hyper::Request::builder()
+    pub async fn get_object_range(
+        &self,
+        object: &ProviderObject,
+        start: u64,
+        length: u64,
+    ) -> Result<Response<Body>> {
+        let url = self.get_download_url(object);
+
+        let req = self
+            .with_request_payer(hyper::Request::builder())
             .method(Method::GET)
-            .uri(uri)
+            .uri(url)
+            .header(
+                RANGE,
+                format!("bytes={}-{}", start, (start + length).saturating_sub(1)),
+            )
             .body(Body::empty())?;
 
-        self.sign_request(&mut req);
+        self.send_request(req).await
+    }
 
-        let mut response = self.send_request(req).await?;
-        let mut body = BytesMut::new();
-        while let Some(data) = response.body_mut().data().await {
-            body.put(data?);
-        }
+    pub async fn get_object_if_none_match(
+        &self,
+        object: &ProviderObject,
+        etag: &str,
+    ) -> Result<Option<Response<Body>>> {
+        let url = self.get_download_url(object);
 
-        let data_str = String::from_utf8_lossy(&body[..]);
-        event!(Level::TRACE, "{}", data_str);
+        let req = self
+            .with_request_payer(hyper::Request::builder())
+            .method(Method::GET)
+            .uri(url)
+            .header(IF_NONE_MATCH, quote_etag(etag))
+            .body(Body::empty())?;
 
-        Ok(())
+        let response = self.send_request(req).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            Ok(None)
+        } else {
+            Ok(Some(response))
+        }
     }
 
     #[instrument(skip(self), level = "debug")]
-    async fn _get_object_metadata(
-        &self,
-        object: &ProviderObject,
-        with_signature: bool,
-    ) -> Result<ObjectMetadataResponse> {
+    pub async fn get_object_acl(&self, object: &ProviderObject) -> Result<Vec<ObjectGrant>> {
         let uri = format!(
-            "{}/{}",
+            "{}/{}?acl",
             self.get_uri(),
             urlencoding::encode(&object.get_key())
         );
-        let mut use_signature = with_signature;
+        let mut req = self
+            .with_request_payer(hyper::Request::builder())
+            .method(Method::GET)
+            .uri(uri)
+            .body(Body::empty())?;
+
+        self.sign_request(&mut req);
+
+        let policy: AccessControlPolicy = self.send_request_deser(req).await?;
+        Ok(policy.get_grants())
+    }
+
+    /// Metadata of a single object from a HEAD request, without listing the bucket. The object
+    /// is first requested anonymously to know if its ACL is public
+    #[instrument(skip(self), level = "debug")]
+    pub async fn head_object(&self, key: &str) -> Result<ObjectMetadataResponse> {
+        let uri = format!("{}/{}", self.get_uri(), urlencoding::encode(key));
+        let mut use_signature = false;
 
         // Loop or else it will complain about "recursion in an `async fn` requires boxing"
         // and it didn't want to handle this.
         // So the loop will only loop once maximum
         // See rustc --explain E0733
         loop {
-            let mut req = hyper::Request::builder()
+            let mut req = self
+                .with_request_payer(hyper::Request::builder())
                 .method(Method::HEAD)
                 .uri(uri.clone())
                 .body(Body::empty())?;
@@ -348,6 +452,14 @@ impl RiakCS {
             } else if !use_signature && response.status().as_u16() == 403 {
                 use_signature = true;
                 continue;
+            } else if response.status() == StatusCode::NOT_FOUND {
+                // HEAD responses have no body telling the object is missing
+                let error = RiakCSError::new(uri.clone(), 404, None);
+                return Err(anyhow::Error::new(error).context(format!(
+                    "Object {} doesn't exist in bucket {}",
+                    key,
+                    self.bucket.as_deref().unwrap_or_default()
+                )));
             } else {
                 return Err(anyhow::Error::new(RiakCSError::new(
                     uri.clone(),
@@ -363,7 +475,7 @@ impl RiakCS {
         &self,
         object: &ProviderObject,
     ) -> Result<ObjectMetadataResponse> {
-        self._get_object_metadata(object, false).await
+        self.head_object(&object.get_key()).await
     }
 
     pub async fn list_buckets(&self) -> Result<Vec<ListBucket>> {
@@ -437,20 +549,68 @@ impl Provider for RiakCS {
             x
         })
     }
+    async fn get_object_range(
+        &self,
+        object: &ProviderObject,
+        start: u64,
+        length: u64,
+    ) -> anyhow::Result<Box<dyn ProviderResponse>> {
+        self.get_object_range(object, start, length)
+            .await
+            .map(|res| {
+                let x: Box<dyn ProviderResponse> = Box::new(RiakCSResponse::new(res));
+                x
+            })
+    }
+    async fn get_object_if_none_match(
+        &self,
+        object: &ProviderObject,
+        etag: &str,
+    ) -> anyhow::Result<Option<Box<dyn ProviderResponse>>> {
+        self.get_object_if_none_match(object, etag)
+            .await
+            .map(|res| {
+                res.map(|res| {
+                    let x: Box<dyn ProviderResponse> = Box::new(RiakCSResponse::new(res));
+                    x
+                })
+            })
+    }
+    async fn get_object_tags(
+        &self,
+        _object: &ProviderObject,
+    ) -> anyhow::Result<HashMap<String, String>> {
+        // Riak CS doesn't support object tagging
+        Ok(HashMap::new())
+    }
+
+    async fn get_object_acl(&self, object: &ProviderObject) -> anyhow::Result<Vec<ObjectGrant>> {
+        self.get_object_acl(object).await
+    }
 }
 
 #[derive(Debug)]
 struct RiakCSResponse {
     response: Option<Response<Body>>,
     status: StatusCode,
+    chunked: bool,
 }
 
 impl RiakCSResponse {
     pub fn new(response: Response<Body>) -> RiakCSResponse {
         let status = response.status();
+        let headers = response.headers();
+        let chunked = headers.get(CONTENT_LENGTH).is_none()
+            || headers
+                .get(TRANSFER_ENCODING)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.contains("chunked"))
+                .unwrap_or(false);
+
         RiakCSResponse {
             response: Some(response),
             status,
+            chunked,
         }
     }
 }
@@ -460,6 +620,10 @@ impl ProviderResponse for RiakCSResponse {
         self.status.as_u16()
     }
 
+    fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+
     fn body(
         &mut self,
     ) -> std::pin::Pin<
@@ -484,3 +648,117 @@ impl ProviderResponse for RiakCSResponse {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::radosgw::mock_destination::{error_response, MockDestination};
+
+    fn client(mock: &MockDestination) -> RiakCS {
+        RiakCS::new(
+            mock.endpoint(),
+            "access_key".to_string(),
+            "secret_key".to_string(),
+            Some("bucket".to_string()),
+        )
+    }
+
+    fn metadata_response() -> Response<Body> {
+        Response::builder()
+            .header("Last-Modified", "Wed, 31 Jan 2024 10:00:00 GMT")
+            .header("ETag", "\"etag\"")
+            .header("Content-Type", "image/png")
+            .header("Content-Length", "42")
+            .header("Cache-Control", "max-age=3600")
+            .header("Content-Disposition", "attachment")
+            .header("Content-Encoding", "gzip")
+            .header("Content-Language", "fr")
+            .header("Content-MD5", "md5")
+            .header("Expires", "Thu, 01 Feb 2024 10:00:00 GMT")
+            .header("x-amz-website-redirect-location", "/other")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn endpoint_without_scheme_is_reached_over_https() {
+        let client = RiakCS::new(
+            "cellar.example.com".to_string(),
+            "access_key".to_string(),
+            "secret_key".to_string(),
+            Some("bucket".to_string()),
+        );
+
+        assert_eq!(client.get_uri(), "https://cellar.example.com/bucket");
+    }
+
+    #[tokio::test]
+    async fn head_object_maps_the_headers() {
+        // Anonymous requests are denied, the object is private
+        let mock = MockDestination::start(|request| match request.header("Authorization") {
+            Some(_) => metadata_response(),
+            None => error_response(403, "AccessDenied"),
+        });
+
+        let response = client(&mock).head_object("dir/key").await.unwrap();
+
+        assert!(!response.acl_public);
+        let metadata = response.metadata;
+        assert_eq!(
+            metadata.last_modified.map(|date| date.to_rfc3339()),
+            Some("2024-01-31T10:00:00+00:00".to_string())
+        );
+        assert_eq!(metadata.etag.as_deref(), Some("etag"));
+        assert_eq!(metadata.content_type.as_deref(), Some("image/png"));
+        assert_eq!(metadata.content_length, 42);
+        assert_eq!(metadata.cache_control.as_deref(), Some("max-age=3600"));
+        assert_eq!(metadata.content_disposition.as_deref(), Some("attachment"));
+        assert_eq!(metadata.content_encoding.as_deref(), Some("gzip"));
+        assert_eq!(metadata.content_language.as_deref(), Some("fr"));
+        assert_eq!(metadata.content_md5.as_deref(), Some("md5"));
+        assert_eq!(
+            metadata.expires.as_deref(),
+            Some("Thu, 01 Feb 2024 10:00:00 GMT")
+        );
+        assert_eq!(
+            metadata.website_redirect_location.as_deref(),
+            Some("/other")
+        );
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|request| request.method == Method::HEAD && request.uri == "/bucket/dir%2Fkey"));
+        assert!(requests[1].header("Authorization").is_some());
+    }
+
+    #[tokio::test]
+    async fn head_object_of_a_public_object() {
+        let mock = MockDestination::start(|_| metadata_response());
+
+        let response = client(&mock).head_object("key").await.unwrap();
+
+        assert!(response.acl_public);
+        assert_eq!(mock.requests().len(), 1);
+        assert!(mock.requests()[0].header("Authorization").is_none());
+    }
+
+    #[tokio::test]
+    async fn head_object_of_a_missing_object() {
+        let mock = MockDestination::start(|_| {
+            Response::builder().status(404).body(Body::empty()).unwrap()
+        });
+
+        let error = client(&mock).head_object("key").await.unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Object key doesn't exist in bucket bucket"
+        );
+        assert_eq!(
+            error.downcast_ref::<RiakCSError>().map(RiakCSError::code),
+            Some(404)
+        );
+    }
+}
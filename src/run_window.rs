@@ -0,0 +1,112 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{Local, NaiveTime};
+use tracing::{event, Level};
+
+/// The window is checked again at least this often while paused, in case the clock changed
+const MAX_PAUSE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct PauseState {
+    paused_since: Option<Instant>,
+    total: Duration,
+}
+
+/// Daily time window, in the local time of the host, outside which no new object is
+/// synchronized. The objects in flight when the window closes are finished
+#[derive(Debug)]
+pub struct RunWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+    pause: Mutex<PauseState>,
+}
+
+impl TryFrom<&str> for RunWindow {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid run window {}. Expected HH:MM-HH:MM", value);
+        let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|_| invalid())?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").map_err(|_| invalid())?;
+
+        if start == end {
+            return Err(format!(
+                "Invalid run window {}. Start and end must differ",
+                value
+            ));
+        }
+
+        Ok(RunWindow {
+            start,
+            end,
+            pause: Mutex::new(PauseState::default()),
+        })
+    }
+}
+
+impl RunWindow {
+    /// Windows ending before they start span midnight, e.g. 22:00-06:00
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    fn until_start(&self, time: NaiveTime) -> Duration {
+        let until_start = (self.start - time).num_seconds().rem_euclid(24 * 60 * 60);
+        Duration::from_secs(until_start as u64)
+    }
+
+    /// Returns once a new object can be dispatched
+    pub async fn wait(&self) {
+        loop {
+            let now = Local::now().time();
+            let sleep = {
+                let mut pause = self.pause.lock().unwrap();
+                if self.contains(now) {
+                    if let Some(paused_since) = pause.paused_since.take() {
+                        pause.total += paused_since.elapsed();
+                        event!(
+                            Level::INFO,
+                            "Run window | Window {}-{} opened, resuming synchronization",
+                            self.start.format("%H:%M"),
+                            self.end.format("%H:%M")
+                        );
+                    }
+                    return;
+                }
+
+                let until_start = self.until_start(now);
+                if pause.paused_since.is_none() {
+                    pause.paused_since = Some(Instant::now());
+                    event!(
+                        Level::INFO,
+                        "Run window | Outside of window {}-{}, pausing synchronization for {:?}",
+                        self.start.format("%H:%M"),
+                        self.end.format("%H:%M"),
+                        until_start
+                    );
+                }
+
+                std::cmp::min(until_start, MAX_PAUSE_CHECK_INTERVAL)
+            };
+
+            tokio::time::sleep(sleep).await;
+        }
+    }
+
+    /// Time spent waiting for the window to open
+    pub fn paused_time(&self) -> Duration {
+        let pause = self.pause.lock().unwrap();
+        pause.total
+            + pause
+                .paused_since
+                .map(|paused_since| paused_since.elapsed())
+                .unwrap_or_default()
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use serde_derive::{Deserialize, Serialize};
+use tracing::{event, Level};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CompletedObject {
+    bucket: String,
+    key: String,
+}
+
+/// Objects successfully synchronized by previous runs, stored in a file shared by all the
+/// buckets. Each line is a `{"bucket":"...","key":"..."}` JSON object appended as soon as an
+/// object is synchronized, so a crash only loses the line being written. The next runs skip
+/// these objects without comparing them to the destination
+#[derive(Debug)]
+pub struct StateFile {
+    bucket: String,
+    completed: HashSet<String>,
+    file: Mutex<File>,
+}
+
+impl StateFile {
+    pub fn open(path: &Path, bucket: &str) -> anyhow::Result<StateFile> {
+        let mut completed = HashSet::new();
+
+        if path.exists() {
+            let file = File::open(path)
+                .with_context(|| format!("Failed to open state file {}", path.display()))?;
+
+            for (index, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<CompletedObject>(&line) {
+                    Ok(object) if object.bucket == bucket => {
+                        completed.insert(object.key);
+                    }
+                    Ok(_) => {}
+                    // Most likely the last line, partially written when the previous run stopped
+                    Err(error) => event!(
+                        Level::WARN,
+                        "Ignoring line {} of state file {}: {}",
+                        index + 1,
+                        path.display(),
+                        error
+                    ),
+                }
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open state file {}", path.display()))?;
+        // Terminates a partially written last line so the next entry isn't appended to it
+        writeln!(file)?;
+
+        Ok(StateFile {
+            bucket: bucket.to_string(),
+            completed,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn is_completed(&self, key: &str) -> bool {
+        self.completed.contains(key)
+    }
+
+    pub fn completed_count(&self) -> usize {
+        self.completed.len()
+    }
+
+    pub fn record(&self, key: &str) -> anyhow::Result<()> {
+        let line = serde_json::to_string(&CompletedObject {
+            bucket: self.bucket.clone(),
+            key: key.to_string(),
+        })?;
+
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use std::{path::Path, sync::Mutex, time::Duration};
+
+use anyhow::Context;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::provider::ProviderObject;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS objects (
+        bucket TEXT NOT NULL,
+        key TEXT NOT NULL,
+        status TEXT NOT NULL CHECK (status IN ('pending', 'done', 'failed')),
+        attempts INTEGER NOT NULL DEFAULT 0,
+        error TEXT,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (bucket, key)
+    ) WITHOUT ROWID;
+    CREATE INDEX IF NOT EXISTS objects_status ON objects (bucket, status);
+";
+
+/// Status of each object of a bucket, stored in a SQLite database shared by all the buckets so it
+/// scales to tens of millions of objects and can be queried after the run. Objects are `pending`
+/// from the moment they are found different until they are `done` or `failed`, a crashed run
+/// leaves them `pending`
+#[derive(Debug)]
+pub struct ObjectStatusDb {
+    bucket: String,
+    /// Only synchronize the objects `pending` or `failed` in the database
+    retry_only: bool,
+    connection: Mutex<Connection>,
+}
+
+impl ObjectStatusDb {
+    pub fn open(path: &Path, bucket: &str, retry_only: bool) -> anyhow::Result<ObjectStatusDb> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to open status database {}", path.display()))?;
+        // Buckets migrated at the same time write to the same database
+        connection.busy_timeout(Duration::from_secs(30))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection
+            .execute_batch(SCHEMA)
+            .with_context(|| format!("Failed to create status database {}", path.display()))?;
+
+        Ok(ObjectStatusDb {
+            bucket: bucket.to_string(),
+            retry_only,
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn retry_only(&self) -> bool {
+        self.retry_only
+    }
+
+    /// Whether the object was left `pending` or `failed` by a previous run
+    pub fn needs_retry(&self, key: &str) -> anyhow::Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        let status: Option<String> = connection
+            .query_row(
+                "SELECT status FROM objects WHERE bucket = ?1 AND key = ?2",
+                params![self.bucket, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(matches!(status.as_deref(), Some("pending" | "failed")))
+    }
+
+    /// Done in a single transaction, a page of objects would be slow to insert one by one
+    pub fn mark_pending(&self, objects: &[ProviderObject]) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO objects (bucket, key, status, updated_at) VALUES (?1, ?2, 'pending', ?3)
+                 ON CONFLICT (bucket, key) DO UPDATE SET status = 'pending', updated_at = ?3",
+            )?;
+            let now = Utc::now().to_rfc3339();
+            for object in objects {
+                statement.execute(params![self.bucket, object.get_key(), now])?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Each synchronization counts as an attempt, the error of the last failed one is kept
+    pub fn record<T>(&self, key: &str, result: &anyhow::Result<T>) -> anyhow::Result<()> {
+        let (status, error) = match result {
+            Ok(_) => ("done", None),
+            Err(error) => ("failed", Some(format!("{:#}", error))),
+        };
+
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO objects (bucket, key, status, attempts, error, updated_at)
+             VALUES (?1, ?2, ?3, 1, ?4, ?5)
+             ON CONFLICT (bucket, key) DO UPDATE SET
+                status = ?3, attempts = attempts + 1, error = ?4, updated_at = ?5",
+            params![self.bucket, key, status, error, Utc::now().to_rfc3339()],
+        )?;
+
+        Ok(())
+    }
+}
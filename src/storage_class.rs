@@ -0,0 +1,140 @@
+use regex::Regex;
+
+/// Storage classes of the S3 API. Destinations may only support some of them, an unsupported
+/// one is rejected on the first upload
+const STORAGE_CLASSES: [&str; 9] = [
+    "STANDARD",
+    "REDUCED_REDUNDANCY",
+    "STANDARD_IA",
+    "ONEZONE_IA",
+    "INTELLIGENT_TIERING",
+    "GLACIER",
+    "GLACIER_IR",
+    "DEEP_ARCHIVE",
+    "OUTPOSTS",
+];
+
+/// Uppercased storage class, unknown ones are rejected before the migration starts
+pub fn parse_storage_class(value: &str) -> Result<String, String> {
+    let storage_class = value.to_uppercase();
+    if STORAGE_CLASSES.contains(&storage_class.as_str()) {
+        Ok(storage_class)
+    } else {
+        Err(format!(
+            "Unknown storage class {}. Valid values are: {}",
+            value,
+            STORAGE_CLASSES.join(", ")
+        ))
+    }
+}
+
+/// `<key pattern>=<storage class>`, e.g. `archive/*=GLACIER`. In the pattern, `*` matches any
+/// characters, including `/`, and `?` a single character
+#[derive(Debug, Clone)]
+pub struct StorageClassRule {
+    pattern: Regex,
+    storage_class: String,
+}
+
+impl TryFrom<&str> for StorageClassRule {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (pattern, storage_class) = value.rsplit_once('=').ok_or(format!(
+            "Invalid storage class rule {}. Expected <key pattern>=<storage class>",
+            value
+        ))?;
+
+        let regex = pattern
+            .split('*')
+            .map(|part| {
+                part.split('?')
+                    .map(regex::escape)
+                    .collect::<Vec<String>>()
+                    .join(".")
+            })
+            .collect::<Vec<String>>()
+            .join(".*");
+
+        Ok(StorageClassRule {
+            pattern: Regex::new(&format!("^{}$", regex))
+                .map_err(|error| format!("Invalid storage class rule {}: {}", value, error))?,
+            storage_class: parse_storage_class(storage_class)?,
+        })
+    }
+}
+
+/// Storage class of each destination object, from its source key. Rules are evaluated in order
+/// and the first matching rule wins
+#[derive(Debug, Clone, Default)]
+pub struct StorageClassRules {
+    pub rules: Vec<StorageClassRule>,
+    /// Storage class of the objects no rule matches. The destination default if not set
+    pub default: Option<String>,
+}
+
+impl StorageClassRules {
+    pub fn storage_class(&self, key: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|rule| rule.pattern.is_match(key))
+            .map(|rule| rule.storage_class.as_str())
+            .or(self.default.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &[&str], default: Option<&str>) -> StorageClassRules {
+        StorageClassRules {
+            rules: rules
+                .iter()
+                .map(|rule| StorageClassRule::try_from(*rule).unwrap())
+                .collect(),
+            default: default.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn storage_classes_are_uppercased() {
+        assert_eq!(parse_storage_class("glacier"), Ok("GLACIER".to_string()));
+        assert!(parse_storage_class("COLD").is_err());
+        assert!(StorageClassRule::try_from("archive/*=COLD").is_err());
+        assert!(StorageClassRule::try_from("archive/*").is_err());
+    }
+
+    #[test]
+    fn global_storage_class() {
+        let rules = rules(&[], Some("STANDARD_IA"));
+
+        assert_eq!(rules.storage_class("a"), Some("STANDARD_IA"));
+        assert_eq!(StorageClassRules::default().storage_class("a"), None);
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = rules(
+            &["archive/*.log=DEEP_ARCHIVE", "archive/*=GLACIER"],
+            Some("STANDARD"),
+        );
+
+        assert_eq!(
+            rules.storage_class("archive/2023/app.log"),
+            Some("DEEP_ARCHIVE")
+        );
+        assert_eq!(rules.storage_class("archive/2023/app.txt"), Some("GLACIER"));
+        assert_eq!(rules.storage_class("data/archive/a"), Some("STANDARD"));
+    }
+
+    #[test]
+    fn question_mark_matches_a_single_character() {
+        let rules = rules(&["log-?.txt=GLACIER"], None);
+
+        assert_eq!(rules.storage_class("log-1.txt"), Some("GLACIER"));
+        assert_eq!(rules.storage_class("log-10.txt"), None);
+        // The dot isn't a regex wildcard
+        assert_eq!(rules.storage_class("log-1_txt"), None);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use tokio::task::JoinHandle;
+
+use crate::heartbeat::SyncProgress;
+
+#[derive(Debug, Clone, Copy)]
+struct ThroughputSample {
+    /// Since the start of the run
+    elapsed: Duration,
+    bytes_per_second: u64,
+}
+
+/// Throughput of the run sampled at a fixed interval, to tell a steady slow run from a run
+/// which ramped up, degraded or stalled
+#[derive(Debug)]
+pub struct ThroughputReport {
+    start: Instant,
+    /// Bytes synchronized by the previous passes of --continuous
+    bytes_done_before: usize,
+    interval: Duration,
+    samples: Mutex<Vec<ThroughputSample>>,
+}
+
+impl ThroughputReport {
+    pub fn new(interval: Duration, bytes_done_before: usize) -> ThroughputReport {
+        ThroughputReport {
+            start: Instant::now(),
+            bytes_done_before,
+            interval,
+            samples: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Samples the bytes synchronized by all the sync threads until the task is aborted
+    pub fn spawn_sampler(self: &Arc<Self>, progress: Arc<SyncProgress>) -> JoinHandle<()> {
+        let report = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(report.interval);
+            // The first tick completes immediately
+            ticker.tick().await;
+
+            let mut last_tick = Instant::now();
+            let mut last_bytes_done = report.bytes_done_before;
+            loop {
+                ticker.tick().await;
+
+                let bytes_done = progress.bytes_done();
+                let rate =
+                    (bytes_done - last_bytes_done) as f64 / last_tick.elapsed().as_secs_f64();
+
+                report.samples.lock().unwrap().push(ThroughputSample {
+                    elapsed: report.start.elapsed(),
+                    bytes_per_second: rate as u64,
+                });
+
+                last_tick = Instant::now();
+                last_bytes_done = bytes_done;
+            }
+        })
+    }
+
+    /// The average is computed over the whole run, not over the samples, so a run shorter than
+    /// the interval still gets one
+    pub fn write(&self, path: &Path, progress: &SyncProgress) -> anyhow::Result<()> {
+        let samples = self.samples.lock().unwrap();
+        let elapsed = self.start.elapsed();
+        let bytes_done = progress.bytes_done() - self.bytes_done_before;
+        let average = bytes_done as f64 / elapsed.as_secs_f64();
+        let peak = samples
+            .iter()
+            .map(|sample| sample.bytes_per_second)
+            .max()
+            .unwrap_or(average as u64);
+
+        let samples = samples
+            .iter()
+            .map(|sample| {
+                format!(
+                    "{{\"elapsed_seconds\":{},\"bytes_per_second\":{}}}",
+                    sample.elapsed.as_secs(),
+                    sample.bytes_per_second
+                )
+            })
+            .collect::<Vec<String>>();
+
+        let mut file = File::create(path)
+            .with_context(|| format!("Failed to create throughput report {}", path.display()))?;
+        writeln!(
+            file,
+            "{{\"interval_seconds\":{},\"duration_seconds\":{},\"bytes_done\":{},\"average_bytes_per_second\":{},\"peak_bytes_per_second\":{},\"samples\":[{}]}}",
+            self.interval.as_secs(),
+            elapsed.as_secs(),
+            bytes_done,
+            average as u64,
+            peak,
+            samples.join(",")
+        )?;
+
+        Ok(())
+    }
+}
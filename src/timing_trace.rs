@@ -0,0 +1,63 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::Mutex,
+    time::Instant,
+};
+
+use anyhow::Context;
+
+/// Per object phase timings written in the Chrome trace event format, which can be loaded in
+/// chrome://tracing or https://ui.perfetto.dev. Each sync thread is displayed as its own track.
+/// The JSON array is never closed, trace viewers accept it so events can be written as they come
+#[derive(Debug)]
+pub struct TimingTrace {
+    start: Instant,
+    file: Mutex<File>,
+}
+
+impl TimingTrace {
+    pub fn create(path: &Path) -> anyhow::Result<TimingTrace> {
+        let mut file = File::create(path)
+            .with_context(|| format!("Failed to create timing trace {}", path.display()))?;
+        writeln!(file, "[")?;
+
+        Ok(TimingTrace {
+            start: Instant::now(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// `phase` is one of queue, download, upload, complete
+    pub fn record(&self, key: &str, phase: &str, thread_id: usize, start: Instant, end: Instant) {
+        let timestamp = start.saturating_duration_since(self.start).as_micros();
+        let duration = end.saturating_duration_since(start).as_micros();
+        let event = format!(
+            "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":1,\"tid\":{},\"args\":{{\"key\":\"{}\"}}}},",
+            phase,
+            phase,
+            timestamp,
+            duration,
+            thread_id,
+            escape_json(key)
+        );
+
+        // Timings are a debugging help, losing some of them isn't worth failing the object
+        let mut file = self.file.lock().unwrap();
+        let _ = writeln!(file, "{}", event);
+    }
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
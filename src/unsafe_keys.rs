@@ -0,0 +1,182 @@
+use tracing::{event, Level};
+
+use crate::provider::ProviderObject;
+
+/// What to do with keys ending with a whitespace or containing control characters. Gateways
+/// don't all handle them the same way so their source and destination keys may never compare
+/// equal and these objects would be copied again on each run
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnsafeKeysPolicy {
+    /// Copy them under the same key
+    Copy,
+    Skip,
+    /// Copy them without trailing whitespaces and with control characters replaced by `_`
+    Sanitize,
+}
+
+impl TryFrom<&str> for UnsafeKeysPolicy {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "copy" => Ok(UnsafeKeysPolicy::Copy),
+            "skip" => Ok(UnsafeKeysPolicy::Skip),
+            "sanitize" => Ok(UnsafeKeysPolicy::Sanitize),
+            _ => Err(format!("Failed to parse unsafe keys policy: {}", value)),
+        }
+    }
+}
+
+pub fn is_unsafe_key(key: &str) -> bool {
+    key.ends_with(char::is_whitespace) || key.chars().any(char::is_control)
+}
+
+pub fn sanitize_key(key: &str) -> String {
+    let sanitized = key
+        .chars()
+        .map(|c| if c.is_control() { '_' } else { c })
+        .collect::<String>();
+    let sanitized = sanitized.trim_end();
+
+    if sanitized.is_empty() {
+        "_".to_string()
+    } else {
+        sanitized.to_string()
+    }
+}
+
+/// Returns the objects to diff with the destination. Renamed objects keep their source key and
+/// get a destination key
+pub fn apply_unsafe_keys_policy(
+    policy: &UnsafeKeysPolicy,
+    bucket: &str,
+    objects: &[ProviderObject],
+) -> Vec<ProviderObject> {
+    objects
+        .iter()
+        .filter_map(|object| {
+            let key = object.get_key();
+            if *policy == UnsafeKeysPolicy::Copy || !is_unsafe_key(&key) {
+                return Some(object.clone());
+            }
+
+            match policy {
+                UnsafeKeysPolicy::Skip => {
+                    event!(
+                        Level::WARN,
+                        "{} | Skipping object {:?}: its key ends with a whitespace or contains control characters",
+                        bucket,
+                        key
+                    );
+                    None
+                }
+                _ => {
+                    let destination_key = sanitize_key(&key);
+                    event!(
+                        Level::WARN,
+                        "{} | Object {:?} is renamed to {:?} on the destination bucket",
+                        bucket,
+                        key,
+                        destination_key
+                    );
+                    Some(object.with_destination_key(destination_key))
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATHOLOGICAL_KEYS: [&str; 5] = [
+        "trailing-space ",
+        "trailing-tab\t",
+        "embedded\nnewline",
+        "escape\u{1b}[0m",
+        "delete\u{7f}",
+    ];
+
+    fn objects(keys: &[&str]) -> Vec<ProviderObject> {
+        keys.iter()
+            .map(|key| ProviderObject::new(key.to_string(), 1))
+            .collect()
+    }
+
+    #[test]
+    fn unsafe_keys() {
+        for key in PATHOLOGICAL_KEYS {
+            assert!(is_unsafe_key(key), "{:?}", key);
+        }
+        assert!(!is_unsafe_key("dir/file.txt"));
+        assert!(!is_unsafe_key(" leading-space"));
+        assert!(!is_unsafe_key("inner space"));
+        assert!(!is_unsafe_key(""));
+    }
+
+    #[test]
+    fn sanitized_keys() {
+        assert_eq!(sanitize_key("trailing-space "), "trailing-space");
+        assert_eq!(sanitize_key("trailing-spaces   "), "trailing-spaces");
+        assert_eq!(sanitize_key("trailing-tab\t"), "trailing-tab_");
+        assert_eq!(sanitize_key("embedded\nnewline"), "embedded_newline");
+        assert_eq!(sanitize_key("escape\u{1b}[0m"), "escape_[0m");
+        assert_eq!(sanitize_key(" leading-space"), " leading-space");
+        assert_eq!(sanitize_key("   "), "_");
+        for key in PATHOLOGICAL_KEYS {
+            assert!(!is_unsafe_key(&sanitize_key(key)), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn copy_policy_keeps_the_keys() {
+        let objects = apply_unsafe_keys_policy(
+            &UnsafeKeysPolicy::Copy,
+            "bucket",
+            &objects(&PATHOLOGICAL_KEYS),
+        );
+        assert_eq!(objects.len(), PATHOLOGICAL_KEYS.len());
+        for (object, key) in objects.iter().zip(PATHOLOGICAL_KEYS) {
+            assert_eq!(object.get_destination_key(), key);
+        }
+    }
+
+    #[test]
+    fn skip_policy_drops_the_unsafe_keys() {
+        let objects = apply_unsafe_keys_policy(
+            &UnsafeKeysPolicy::Skip,
+            "bucket",
+            &objects(&["safe", "unsafe "]),
+        );
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].get_key(), "safe");
+    }
+
+    #[test]
+    fn sanitize_policy_renames_the_unsafe_keys() {
+        let objects = apply_unsafe_keys_policy(
+            &UnsafeKeysPolicy::Sanitize,
+            "bucket",
+            &objects(&["safe", "embedded\nnewline "]),
+        );
+        assert_eq!(objects[0].get_destination_key(), "safe");
+        assert_eq!(objects[1].get_key(), "embedded\nnewline ");
+        assert_eq!(objects[1].get_destination_key(), "embedded_newline");
+    }
+
+    #[test]
+    fn sanitized_keys_converge() {
+        let objects = apply_unsafe_keys_policy(
+            &UnsafeKeysPolicy::Sanitize,
+            "bucket",
+            &objects(&["unsafe "]),
+        );
+        let destination = ProviderObject::new("unsafe".to_string(), 1);
+        assert!(objects[0].matches_destination(&destination));
+    }
+
+    #[test]
+    fn unknown_policy() {
+        assert!(UnsafeKeysPolicy::try_from("rename").is_err());
+    }
+}